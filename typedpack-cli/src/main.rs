@@ -1,30 +1,41 @@
 use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
     io::Read as _,
     path::{Path, PathBuf},
     process::ExitCode,
 };
 
-use clap::{Arg, ArgAction, Command, builder::PathBufValueParser};
+use clap::{Arg, ArgAction, ArgMatches, Command, builder::PathBufValueParser};
 
 use typedpack_codegen::{
-    ParseError, Type,
-    typescript::{FILE_HEADER, Options, OptionsInput},
+    Diagnostics, Module, ModuleImport, ResolveError, Sources, Type, compat, rust, typescript,
 };
 
+enum Options {
+    Rust(rust::Options),
+    TypeScript(typescript::Options),
+}
+
 #[derive(Debug, thiserror::Error)]
 enum Error {
     #[error("failed to create output directory: {0}")]
     CreateDir(std::io::Error),
     #[error("when the input path is a directory, the output path must be as well")]
     OutputNotDir,
-    #[error("error parsing file {0}:\n{1}")]
-    Parse(String, ParseError),
+    #[error("{0}")]
+    Parse(Diagnostics),
     #[error("failed to read directory: {0}")]
     ReadDir(std::io::Error),
     #[error("failed to read file: {0}")]
     ReadFile(std::io::Error),
     #[error("failed to read from standard input: {0}")]
     ReadStdin(std::io::Error),
+    #[error("{0}")]
+    Resolve(ResolveError),
+    #[error(
+        "cannot generate Rust code for {0}, since 'mod.rs' files are reserved for module declarations"
+    )]
+    RustModName(PathBuf),
     #[error("failed to write output file: {0}")]
     Write(std::io::Error),
 }
@@ -34,6 +45,7 @@ enum Error {
 // typedpack-cli - OUT_FILE
 // typedpack-cli FILE -
 // typedpack-cli - -
+// typedpack-cli check-compat OLD_FILE NEW_FILE
 
 fn main() -> ExitCode {
     let mut matches = Command::new(clap::crate_name!())
@@ -44,7 +56,48 @@ fn main() -> ExitCode {
 will be created) if and only if <INPUT> is also a directory.
 In this case, code from all files with the '.tp' extension under <INPUT>
 will be generated into an identical directory structure under <OUTPUT>,
-with the file extensions replaced with '.ts'.",
+with the file extensions replaced with '.ts' or '.rs'. Files may then `import`
+other files under <INPUT>.
+
+When generating Rust, a 'mod.rs' file declaring the generated modules is
+written to <OUTPUT>, so that the directory can be used as a module with e.g.
+`#[path = \"generated/mod.rs\"] mod generated;`.",
+        )
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
+        .subcommand(
+            Command::new("check-compat")
+                .about("Check whether data can be exchanged between two versions of a schema.")
+                .after_help(
+                    "\
+Types are matched by name, and struct fields and enum variants by ID. The
+types declared in or imported by each file are compared.
+
+Exits with status 1 if any change is breaking, and with status 2 if a file
+cannot be read or parsed.",
+                )
+                .arg(
+                    Arg::new("old")
+                        .help("The old version of the schema.")
+                        .required(true)
+                        .value_name("OLD")
+                        .value_parser(PathBufValueParser::new()),
+                )
+                .arg(
+                    Arg::new("new")
+                        .help("The new version of the schema.")
+                        .required(true)
+                        .value_name("NEW")
+                        .value_parser(PathBufValueParser::new()),
+                )
+                .arg(
+                    Arg::new("format")
+                        .help("The format of the report.")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .value_parser(["text", "json"])
+                        .default_value("text"),
+                ),
         )
         .arg(
             Arg::new("input")
@@ -60,48 +113,71 @@ with the file extensions replaced with '.ts'.",
                 .value_name("OUTPUT")
                 .value_parser(PathBufValueParser::new()),
         )
+        .arg(
+            Arg::new("lang")
+                .help("The language to generate code in.")
+                .long("lang")
+                .value_name("LANG")
+                .value_parser(["rust", "typescript"])
+                .default_value("typescript")
+        )
         .arg(
             Arg::new("types_namespace")
-                .help("Change the name of the namespace containing the types (default `Types`).")
+                .help("TypeScript only: change the name of the namespace containing the types (default `Types`).")
                 .long("types-namespace")
                 .value_name("NAME")
         )
         .arg(
             Arg::new("encode_namespace")
-                .help("Change the name of the namespace containing the encoding functions (default `Encode`).")
+                .help("TypeScript only: change the name of the namespace containing the encoding functions (default `Encode`).")
                 .long("encode-namespace")
                 .value_name("NAME")
         )
         .arg(
             Arg::new("decode_namespace")
-                .help("Change the name of the namespace containing the decoding functions (default `Decode`).")
+                .help("TypeScript only: change the name of the namespace containing the decoding functions (default `Decode`).")
                 .long("decode-namespace")
                 .value_name("NAME")
         )
         .arg(
             Arg::new("encode_array_namespace")
-                .help("Change the name of the namespace containing the array encoding functions (default `EncodeArray`).")
+                .help("TypeScript only: change the name of the namespace containing the array encoding functions (default `EncodeArray`).")
                 .long("encode-array-namespace")
                 .value_name("NAME")
         )
         .arg(
             Arg::new("decode_array_namespace")
-                .help("Change the name of the namespace containing the array decoding functions (default `DecodeArray`).")
+                .help("TypeScript only: change the name of the namespace containing the array decoding functions (default `DecodeArray`).")
                 .long("decode-array-namespace")
                 .value_name("NAME")
         )
         .arg(
             Arg::new("export_decode_internal_namespace")
                 .action(ArgAction::SetTrue)
-                .help("Export the `TypedpackDecodeInternal` namespace. This is primarily meant for testing.")
+                .help("TypeScript only: export the `TypedpackDecodeInternal` namespace. This is primarily meant for testing.")
                 .long("export-decode-internal-namespace")
         )
+        .arg(
+            Arg::new("reject_unknown_fields")
+                .action(ArgAction::SetTrue)
+                .help("Reject struct fields which are not in the schema when decoding, instead of ignoring them.")
+                .long("reject-unknown-fields")
+        )
         .get_matches();
 
+    // `check-compat` is the only subcommand
+    if let Some((_, matches)) = matches.remove_subcommand() {
+        return check_compat(matches);
+    }
+
     let input: PathBuf = matches.remove_one("input").expect("required argument");
     let output: PathBuf = matches.remove_one("output").expect("required argument");
 
-    let mut options = OptionsInput::default();
+    let lang: String = matches
+        .remove_one("lang")
+        .expect("argument with default value");
+
+    let mut options = typescript::OptionsInput::default();
     if let Some(types_namespace) = matches.remove_one("types_namespace") {
         options.types_namespace = Some(types_namespace);
     }
@@ -120,7 +196,23 @@ with the file extensions replaced with '.ts'.",
     if matches.get_flag("export_decode_internal_namespace") {
         options.export_decode_internal_namespace = Some(true);
     }
-    let options = match options.try_into() {
+    let reject_unknown_fields = matches.get_flag("reject_unknown_fields").then_some(true);
+    options.reject_unknown_fields = reject_unknown_fields;
+    let options = if lang == "rust" {
+        rust::OptionsInput {
+            reject_unknown_fields,
+            ..Default::default()
+        }
+        .try_into()
+        .map(Options::Rust)
+        .map_err(|error: rust::OptionsError| error.to_string())
+    } else {
+        options
+            .try_into()
+            .map(Options::TypeScript)
+            .map_err(|error: typescript::OptionsError| error.to_string())
+    };
+    let options = match options {
         Ok(options) => options,
         Err(error) => {
             eprintln!("{error}");
@@ -142,19 +234,162 @@ fn run(input: &Path, output: &Path, options: &Options) -> Result<(), Error> {
             return Err(Error::OutputNotDir);
         }
 
+        let mut sources = Sources::new(input).map_err(Error::Resolve)?;
         for input_path in scan_dir(input)? {
-            let path_suffix = input_path
-                .strip_prefix(input)
-                .expect("not a file path inside `input` directory")
-                .with_extension("ts");
-            write_single_file(&input_path, &output.join(path_suffix), true, options)?;
+            sources.add(&input_path).map_err(Error::Resolve)?;
+        }
+        let modules = sources.parse().map_err(Error::Resolve)?;
+        for module in &modules {
+            print_warnings(module.path(), module.source(), module.types(), options);
+        }
+        let options = match options {
+            Options::Rust(options) => return write_rust_modules(&modules, output, options),
+            Options::TypeScript(options) => options,
+        };
+        let imported_module_paths: HashSet<_> = modules
+            .iter()
+            .flat_map(|module| module.imports().iter().map(ModuleImport::module_path))
+            .collect();
+
+        for module in &modules {
+            let mut output_path = output.join(module.module_path().iter().collect::<PathBuf>());
+            output_path.as_mut_os_string().push(".ts");
+            std::fs::create_dir_all(
+                output_path
+                    .parent()
+                    .expect("not a file path inside a directory"),
+            )
+            .map_err(Error::CreateDir)?;
+
+            let mut output_string =
+                typescript_code(module.types(), &module.typescript_imports(options), options);
+            if imported_module_paths.contains(module.module_path()) {
+                output_string.push_str(&module.typescript_exports(options));
+                output_string.push('\n');
+            }
+            std::fs::write(output_path, output_string).map_err(Error::Write)?;
         }
     } else {
-        write_single_file(input, output, false, options)?;
+        let input_string = if input == "-" {
+            let mut string = String::new();
+            std::io::stdin()
+                .read_to_string(&mut string)
+                .map_err(Error::ReadStdin)?;
+            string
+        } else {
+            std::fs::read_to_string(input).map_err(Error::ReadFile)?
+        };
+
+        let types =
+            typedpack_codegen::parse_with_diagnostics(&input_string).map_err(|diagnostics| {
+                Error::Parse(if input == "-" {
+                    diagnostics.with_path("<stdin>")
+                } else {
+                    diagnostics.with_path(input)
+                })
+            })?;
+        print_warnings(
+            if input == "-" {
+                Path::new("<stdin>")
+            } else {
+                input
+            },
+            &input_string,
+            &types,
+            options,
+        );
+        let output_string = match options {
+            Options::Rust(options) => rust_code(&types, "", options),
+            Options::TypeScript(options) => typescript_code(&types, "", options),
+        };
+
+        if output == "-" {
+            print!("{output_string}");
+        } else {
+            std::fs::write(output, output_string).map_err(Error::Write)?;
+        }
     }
     Ok(())
 }
 
+/// Prints a warning with a snippet of `source` for every annotation argument which the generated
+/// code ignores.
+fn print_warnings(path: &Path, source: &str, types: &[Type<'_>], options: &Options) {
+    let warnings = match options {
+        Options::Rust(_) => rust::annotation_warnings(types),
+        Options::TypeScript(_) => typescript::annotation_warnings(types),
+    };
+    let diagnostics = Diagnostics::new(Vec::new(), source).with_path(path);
+    for warning in warnings {
+        eprintln!("{}\n", diagnostics.render_warning(&warning));
+    }
+}
+
+fn check_compat(mut matches: ArgMatches) -> ExitCode {
+    let old: PathBuf = matches.remove_one("old").expect("required argument");
+    let new: PathBuf = matches.remove_one("new").expect("required argument");
+    let format: String = matches
+        .remove_one("format")
+        .expect("argument with default value");
+
+    let sources = Sources::load(&old).and_then(|old| Ok((old, Sources::load(&new)?)));
+    let (old_sources, new_sources) = match sources {
+        Ok(sources) => sources,
+        Err(error) => {
+            eprintln!("{error}");
+            return ExitCode::from(2);
+        }
+    };
+    let modules = old_sources
+        .parse()
+        .and_then(|old| Ok((old, new_sources.parse()?)));
+    let (old_modules, new_modules) = match modules {
+        Ok(modules) => modules,
+        Err(error) => {
+            eprintln!("{error}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let report = compat::check(&visible_types(&old_modules), &visible_types(&new_modules));
+    if format == "json" {
+        print!("{}", report.to_json());
+    } else if report.changes().is_empty() {
+        println!("no changes");
+    } else {
+        print!("{report}");
+    }
+
+    if report.is_breaking() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Returns the types declared in or imported by the file which was loaded with
+/// [`Sources::load`], which is the last module.
+fn visible_types<'a>(modules: &[Module<'a>]) -> Vec<Type<'a>> {
+    let Some(module) = modules.last() else {
+        return Vec::new();
+    };
+    let mut types = module.types().to_vec();
+    for import in module.imports() {
+        let imported = modules
+            .iter()
+            .find(|other| other.module_path() == import.module_path())
+            .expect("imported modules are parsed");
+        types.extend(
+            imported
+                .types()
+                .iter()
+                .filter(|r#type| import.names().contains(&r#type.name()))
+                .cloned(),
+        );
+    }
+    types
+}
+
 fn scan_dir(root: &Path) -> Result<Vec<PathBuf>, Error> {
     let mut paths = Vec::new();
     for entry in root.read_dir().map_err(Error::ReadDir)? {
@@ -168,31 +403,123 @@ fn scan_dir(root: &Path) -> Result<Vec<PathBuf>, Error> {
     Ok(paths)
 }
 
-fn write_single_file(
-    input: &Path,
+/// Writes each module into a `.rs` file, and declares the modules in `mod.rs` files.
+///
+/// A module declares the modules in the directory of the same name, e.g. `users.rs` declares
+/// `users/email.rs`. Directories without a corresponding module get a `mod.rs` file instead,
+/// including `output` itself.
+fn write_rust_modules(
+    modules: &[Module<'_>],
     output: &Path,
-    create_dir: bool,
-    options: &Options,
+    options: &rust::Options,
 ) -> Result<(), Error> {
-    if create_dir {
-        std::fs::create_dir_all(output.parent().expect("not a file path inside a directory"))
-            .map_err(Error::CreateDir)?;
+    if let Some(module) = modules.iter().find(|module| {
+        module
+            .module_path()
+            .last()
+            .is_some_and(|name| name == "mod")
+    }) {
+        return Err(Error::RustModName(module.path().to_owned()));
     }
 
-    let input_string = if input == "-" {
-        let mut string = String::new();
-        std::io::stdin()
-            .read_to_string(&mut string)
-            .map_err(Error::ReadStdin)?;
-        string
-    } else {
-        std::fs::read_to_string(input).map_err(Error::ReadFile)?
-    };
+    // the names of the modules and directories inside each directory
+    let mut children: BTreeMap<&[String], BTreeSet<&str>> = BTreeMap::new();
+    children.insert(&[], BTreeSet::new());
+    for module in modules {
+        let module_path = module.module_path();
+        for len in 1..=module_path.len() {
+            children
+                .entry(&module_path[..len - 1])
+                .or_default()
+                .insert(&module_path[len - 1]);
+        }
+    }
+    let modules: BTreeMap<_, _> = modules
+        .iter()
+        .map(|module| (module.module_path(), module))
+        .collect();
+
+    for (dir, names) in &children {
+        if dir.is_empty() || !modules.contains_key(dir) {
+            let mut output_string = String::from(rust::FILE_HEADER);
+            output_string.push('\n');
+            output_string.push_str(&rust_mod_declarations(dir, names, &modules, ""));
+            let output_path = output.join(dir.iter().collect::<PathBuf>()).join("mod.rs");
+            write_file(&output_path, &output_string)?;
+        }
+    }
+    for (module_path, module) in &modules {
+        let mut output_string = rust_code(module.types(), &module.rust_imports(), options);
+        if let Some(names) = children.get(module_path) {
+            output_string.push('\n');
+            // paths are relative to the directory containing the file, since the file itself is
+            // declared with a `path` attribute
+            let dir_name = format!("{}/", module_path.last().expect("module path is not empty"));
+            output_string.push_str(&rust_mod_declarations(
+                module_path,
+                names,
+                &modules,
+                &dir_name,
+            ));
+        }
+        let mut output_path = output.join(module_path.iter().collect::<PathBuf>());
+        output_path.as_mut_os_string().push(".rs");
+        write_file(&output_path, &output_string)?;
+    }
+    Ok(())
+}
+
+fn rust_mod_declarations(
+    dir: &[String],
+    names: &BTreeSet<&str>,
+    modules: &BTreeMap<&[String], &Module<'_>>,
+    path_prefix: &str,
+) -> String {
+    let mut s = String::new();
+    for name in names {
+        let module_path: Vec<_> = dir.iter().map(String::as_str).chain([*name]).collect();
+        let file_name = if modules.keys().any(|path| *path == module_path.as_slice()) {
+            format!("{path_prefix}{name}.rs")
+        } else {
+            format!("{path_prefix}{name}/mod.rs")
+        };
+        s.push_str(&format!(
+            "#[path = {file_name:?}]\npub mod r#{};\n",
+            rust::rust_module_name(name),
+        ));
+    }
+    s
+}
 
-    let types = typedpack_codegen::parse(&input_string)
-        .map_err(|error| Error::Parse(input.to_string_lossy().to_string(), error))?;
+fn write_file(path: &Path, contents: &str) -> Result<(), Error> {
+    std::fs::create_dir_all(path.parent().expect("not a file path inside a directory"))
+        .map_err(Error::CreateDir)?;
+    std::fs::write(path, contents).map_err(Error::Write)
+}
 
-    let output_string = if types.is_empty() {
+fn rust_code(types: &[Type<'_>], imports: &str, options: &rust::Options) -> String {
+    let mut output_string = String::from(rust::FILE_HEADER);
+    if !imports.is_empty() {
+        output_string.push('\n');
+        output_string.push_str(imports);
+    }
+    for r#type in types {
+        output_string.push('\n');
+        match r#type {
+            Type::Enum(r#enum) => {
+                output_string.push_str(&r#enum.rust_enum(options));
+            }
+            Type::Struct(r#struct) => {
+                output_string.push_str(&r#struct.rust_struct(options));
+            }
+        }
+        output_string.push('\n');
+    }
+    output_string
+}
+
+fn typescript_code(types: &[Type<'_>], imports: &str, options: &typescript::Options) -> String {
+    if types.is_empty() && imports.is_empty() {
         let mut s = String::from("export namespace ");
         s.push_str(options.types_namespace());
         s.push_str(" {}\nexport namespace ");
@@ -209,7 +536,11 @@ fn write_single_file(
         s.push_str("{}\n");
         s
     } else {
-        let mut output_string = String::from(FILE_HEADER);
+        let mut output_string = String::from(typescript::FILE_HEADER);
+        if !imports.is_empty() {
+            output_string.push_str("\n\n");
+            output_string.push_str(imports.trim_end());
+        }
         for r#type in types {
             output_string.push_str("\n\n");
             match r#type {
@@ -223,12 +554,5 @@ fn write_single_file(
         }
         output_string.push('\n');
         output_string
-    };
-
-    if output == "-" {
-        print!("{output_string}");
-    } else {
-        std::fs::write(output, output_string).map_err(Error::Write)?;
     }
-    Ok(())
 }
@@ -0,0 +1,197 @@
+use std::path::{Path, PathBuf};
+
+use crate::{Diagnostics, ResolveError, Sources, rust};
+
+/// The error type which is returned by [`Builder::compile`].
+#[derive(Debug, thiserror::Error)]
+pub enum BuildError {
+    #[error(
+        "no output directory; the OUT_DIR environment variable is only set for build scripts, use Builder::out_dir otherwise"
+    )]
+    NoOutDir,
+    #[error("failed to read directory {}: {error}", .path.display())]
+    ReadDir {
+        error: std::io::Error,
+        path: PathBuf,
+    },
+    #[error("{0}")]
+    Resolve(ResolveError),
+    #[error("failed to write file {}: {error}", .path.display())]
+    Write {
+        error: std::io::Error,
+        path: PathBuf,
+    },
+}
+
+/// Generates Rust code from typedpack files in a build script.
+///
+/// ```no_run
+/// # fn main() -> Result<(), typedpack_codegen::BuildError> {
+/// typedpack_codegen::Builder::new()
+///     .file("api.tp")
+///     .dir("schemas/")
+///     .compile()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The code for every file and every file they import is written into a single file in
+/// `OUT_DIR`, `typedpack.rs` by default, as done by [`rust_modules`](crate::rust_modules). Each
+/// file is placed in a module according to its path relative to the base directory, e.g.
+/// `schemas/users.tp` becomes `schemas::users`. The generated file can then be included with:
+///
+/// ```ignore
+/// include!(concat!(env!("OUT_DIR"), "/typedpack.rs"));
+/// ```
+///
+/// `cargo::rerun-if-changed` is printed for every file, so that the code is regenerated when any
+/// of them change, and `cargo::warning` for every annotation which the generated code ignores.
+#[derive(Clone, Debug)]
+pub struct Builder {
+    base_dir: PathBuf,
+    files: Vec<PathBuf>,
+    dirs: Vec<PathBuf>,
+    rust_options: Option<rust::Options>,
+    out_dir: Option<PathBuf>,
+    output_file: PathBuf,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Builder {
+    /// Creates a builder without any files. The base directory is the current directory, which
+    /// is the directory containing `Cargo.toml` when running a build script.
+    pub fn new() -> Self {
+        Self {
+            base_dir: PathBuf::from("."),
+            files: Vec::new(),
+            dirs: Vec::new(),
+            rust_options: None,
+            out_dir: None,
+            output_file: PathBuf::from("typedpack.rs"),
+        }
+    }
+
+    /// Sets the base directory, which every file must be inside.
+    pub fn base_dir(mut self, path: impl AsRef<Path>) -> Self {
+        self.base_dir = path.as_ref().to_owned();
+        self
+    }
+
+    /// Adds a file, along with every file it imports.
+    pub fn file(mut self, path: impl AsRef<Path>) -> Self {
+        self.files.push(path.as_ref().to_owned());
+        self
+    }
+
+    /// Adds every file with the `.tp` extension in a directory and its subdirectories.
+    pub fn dir(mut self, path: impl AsRef<Path>) -> Self {
+        self.dirs.push(path.as_ref().to_owned());
+        self
+    }
+
+    /// Sets the options for generating the code.
+    pub fn rust_options(mut self, options: rust::Options) -> Self {
+        self.rust_options = Some(options);
+        self
+    }
+
+    /// Sets the directory the code is written to instead of `OUT_DIR`.
+    pub fn out_dir(mut self, path: impl AsRef<Path>) -> Self {
+        self.out_dir = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// Sets the name of the generated file (default `typedpack.rs`), relative to the output
+    /// directory.
+    pub fn output_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.output_file = path.as_ref().to_owned();
+        self
+    }
+
+    /// Generates the code and returns the path of the written file.
+    pub fn compile(&self) -> Result<PathBuf, BuildError> {
+        let out_dir = match &self.out_dir {
+            Some(out_dir) => out_dir.clone(),
+            None => std::env::var_os("OUT_DIR")
+                .map(PathBuf::from)
+                .ok_or(BuildError::NoOutDir)?,
+        };
+
+        let mut sources = Sources::new(&self.base_dir).map_err(BuildError::Resolve)?;
+        for path in &self.files {
+            sources.add(path).map_err(BuildError::Resolve)?;
+        }
+        for dir in &self.dirs {
+            // also rerun when files are added to the directory
+            println!("cargo::rerun-if-changed={}", dir.display());
+            for path in scan_dir(dir)? {
+                sources.add(&path).map_err(BuildError::Resolve)?;
+            }
+        }
+        for path in sources.paths() {
+            println!("cargo::rerun-if-changed={}", path.display());
+        }
+        let modules = sources.parse().map_err(BuildError::Resolve)?;
+        for module in &modules {
+            let diagnostics =
+                Diagnostics::new(Vec::new(), module.source()).with_path(module.path());
+            for warning in rust::annotation_warnings(module.types()) {
+                // a `cargo::warning` is a single line
+                let rendered = diagnostics.render_warning(&warning);
+                for line in rendered
+                    .strip_prefix("warning: ")
+                    .unwrap_or(&rendered)
+                    .lines()
+                {
+                    println!("cargo::warning={line}");
+                }
+            }
+        }
+
+        let code = match &self.rust_options {
+            Some(options) => rust::rust_modules(&modules, options),
+            None => rust::rust_modules(
+                &modules,
+                &rust::OptionsInput::default()
+                    .try_into()
+                    .expect("default options are valid"),
+            ),
+        };
+        let path = out_dir.join(&self.output_file);
+        std::fs::write(&path, code).map_err(|error| BuildError::Write {
+            error,
+            path: path.clone(),
+        })?;
+        Ok(path)
+    }
+}
+
+fn scan_dir(dir: &Path) -> Result<Vec<PathBuf>, BuildError> {
+    let read_dir_error = |error| BuildError::ReadDir {
+        error,
+        path: dir.to_owned(),
+    };
+    let mut entries = dir
+        .read_dir()
+        .map_err(read_dir_error)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(read_dir_error)?;
+    // sort for reproducible output
+    entries.sort();
+
+    let mut paths = Vec::new();
+    for path in entries {
+        if path.is_dir() {
+            paths.extend(scan_dir(&path)?);
+        } else if path.extension() == Some("tp".as_ref()) && path.is_file() {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
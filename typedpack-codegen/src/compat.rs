@@ -0,0 +1,651 @@
+//! Checks whether data can be exchanged between two versions of a schema.
+//!
+//! [`check`] compares the types of an old and a new version of a schema and classifies every
+//! change as [`Compatible`](Compatibility::Compatible) or [`Breaking`](Compatibility::Breaking).
+//! Fields and variants are matched by ID, since that is what is encoded, so renaming one is
+//! compatible while giving it a different ID is not.
+//!
+//! ```
+//! use typedpack_codegen::{compat, parse};
+//!
+//! let old = parse("struct User {\n    string name = 0;\n}").unwrap();
+//! let new = parse("struct User {\n    string name = 0;\n    uint32 age = 1;\n}").unwrap();
+//! let report = compat::check(&old, &new);
+//! assert!(report.is_breaking());
+//! assert_eq!(
+//!     report.to_string(),
+//!     "breaking: User.age: required field 'age' = 1 was added, so data from the old schema lacks it\n",
+//! );
+//! ```
+
+use std::fmt;
+
+use crate::{
+    Constraints, DefaultValue, Enum, Reserved, Struct, StructField, StructFieldType, Type,
+};
+
+/// Whether data encoded with one version of a schema can be decoded with the other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Compatibility {
+    /// Data can be exchanged between both versions in either direction.
+    Compatible,
+    /// Data encoded with one version can fail to decode with the other, or decode to a different
+    /// value.
+    Breaking,
+}
+
+impl Compatibility {
+    /// Returns the name used in reports, `compatible` or `breaking`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Compatible => "compatible",
+            Self::Breaking => "breaking",
+        }
+    }
+}
+
+/// What changed between two versions of a schema.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChangeKind {
+    TypeAdded,
+    TypeRemoved,
+    /// A `struct` became an `enum` or the other way around, or a tagged `enum` became untagged
+    /// or the other way around.
+    TypeKindChanged,
+    FieldAdded,
+    FieldRemoved,
+    FieldRenamed,
+    /// A field kept its name but got a different ID.
+    FieldRenumbered,
+    FieldTypeChanged,
+    FieldMadeOptional,
+    FieldMadeRequired,
+    FieldNullableChanged,
+    FieldDefaultChanged,
+    FieldConstraintsChanged,
+    VariantAdded,
+    VariantRemoved,
+    VariantRenamed,
+    /// A variant kept its name but got a different ID.
+    VariantRenumbered,
+    /// The value a tagged `enum` variant contains has a different type, or a unit variant got a
+    /// value or the other way around.
+    VariantTypeChanged,
+}
+
+impl ChangeKind {
+    /// Returns the name used in reports, e.g. `field_type_changed`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::TypeAdded => "type_added",
+            Self::TypeRemoved => "type_removed",
+            Self::TypeKindChanged => "type_kind_changed",
+            Self::FieldAdded => "field_added",
+            Self::FieldRemoved => "field_removed",
+            Self::FieldRenamed => "field_renamed",
+            Self::FieldRenumbered => "field_renumbered",
+            Self::FieldTypeChanged => "field_type_changed",
+            Self::FieldMadeOptional => "field_made_optional",
+            Self::FieldMadeRequired => "field_made_required",
+            Self::FieldNullableChanged => "field_nullable_changed",
+            Self::FieldDefaultChanged => "field_default_changed",
+            Self::FieldConstraintsChanged => "field_constraints_changed",
+            Self::VariantAdded => "variant_added",
+            Self::VariantRemoved => "variant_removed",
+            Self::VariantRenamed => "variant_renamed",
+            Self::VariantRenumbered => "variant_renumbered",
+            Self::VariantTypeChanged => "variant_type_changed",
+        }
+    }
+}
+
+/// A single change between two versions of a schema.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Change {
+    kind: ChangeKind,
+    compatibility: Compatibility,
+    path: String,
+    message: String,
+}
+
+impl Change {
+    fn new(
+        kind: ChangeKind,
+        compatibility: Compatibility,
+        path: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            kind,
+            compatibility,
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Returns what changed.
+    pub fn kind(&self) -> ChangeKind {
+        self.kind
+    }
+
+    /// Returns whether data can still be exchanged despite the change.
+    pub fn compatibility(&self) -> Compatibility {
+        self.compatibility
+    }
+
+    /// Returns the changed type, field or variant, e.g. `User`, `User.name` or `Role::Admin`. For
+    /// fields and variants, the name is the one in the new schema if it is still there.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns a description of the change.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// The changes between two versions of a schema, as returned by [`check`].
+///
+/// The [`Display`](fmt::Display) implementation writes one line per change, e.g.
+/// `breaking: User.name: type changed from string to uint64`, and [`to_json`](Self::to_json)
+/// writes a JSON object for tools.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Report {
+    changes: Vec<Change>,
+}
+
+impl Report {
+    /// Returns the changes, ordered by the types, fields and variants of the old schema, followed
+    /// by the ones only in the new schema.
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// Returns `true` if any change is [`Breaking`](Compatibility::Breaking).
+    pub fn is_breaking(&self) -> bool {
+        self.changes
+            .iter()
+            .any(|change| change.compatibility == Compatibility::Breaking)
+    }
+
+    /// Returns the report as a JSON object of the form:
+    ///
+    /// ```json
+    /// {
+    ///   "breaking": true,
+    ///   "changes": [
+    ///     {
+    ///       "kind": "field_type_changed",
+    ///       "compatibility": "breaking",
+    ///       "path": "User.name",
+    ///       "message": "type changed from string to uint64"
+    ///     }
+    ///   ]
+    /// }
+    /// ```
+    pub fn to_json(&self) -> String {
+        let mut s = String::from("{\n  \"breaking\": ");
+        s.push_str(if self.is_breaking() { "true" } else { "false" });
+        s.push_str(",\n  \"changes\": [");
+        for (i, change) in self.changes.iter().enumerate() {
+            if i != 0 {
+                s.push(',');
+            }
+            s.push_str("\n    {\n      \"kind\": ");
+            push_json_string(&mut s, change.kind.as_str());
+            s.push_str(",\n      \"compatibility\": ");
+            push_json_string(&mut s, change.compatibility.as_str());
+            s.push_str(",\n      \"path\": ");
+            push_json_string(&mut s, &change.path);
+            s.push_str(",\n      \"message\": ");
+            push_json_string(&mut s, &change.message);
+            s.push_str("\n    }");
+        }
+        if !self.changes.is_empty() {
+            s.push_str("\n  ");
+        }
+        s.push_str("]\n}\n");
+        s
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(
+                f,
+                "{}: {}: {}",
+                change.compatibility.as_str(),
+                change.path,
+                change.message,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn push_json_string(s: &mut String, value: &str) {
+    s.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            c if c.is_control() => s.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => s.push(c),
+        }
+    }
+    s.push('"');
+}
+
+/// Compares the types of two versions of a schema. Types are matched by name, so renaming a
+/// type is reported as removing it and adding another one.
+pub fn check(old: &[Type<'_>], new: &[Type<'_>]) -> Report {
+    use Compatibility::{Breaking, Compatible};
+
+    let mut changes = Vec::new();
+    for old_type in old {
+        let name = old_type.name();
+        let Some(new_type) = new.iter().find(|new_type| new_type.name() == name) else {
+            changes.push(Change::new(
+                ChangeKind::TypeRemoved,
+                Breaking,
+                name,
+                format!("type '{name}' was removed"),
+            ));
+            continue;
+        };
+        match (old_type, new_type) {
+            (Type::Struct(old_struct), Type::Struct(new_struct)) => {
+                check_struct(old_struct, new_struct, &mut changes);
+            }
+            (Type::Enum(old_enum), Type::Enum(new_enum))
+                if matches!(
+                    (old_enum, new_enum),
+                    (Enum::Tagged { .. }, Enum::Tagged { .. })
+                        | (Enum::Untagged { .. }, Enum::Untagged { .. })
+                ) =>
+            {
+                check_enum(old_enum, new_enum, &mut changes);
+            }
+            _ => changes.push(Change::new(
+                ChangeKind::TypeKindChanged,
+                Breaking,
+                name,
+                format!(
+                    "type '{name}' changed from {} to {}",
+                    type_kind(old_type),
+                    type_kind(new_type),
+                ),
+            )),
+        }
+    }
+    for new_type in new {
+        let name = new_type.name();
+        if !old.iter().any(|old_type| old_type.name() == name) {
+            changes.push(Change::new(
+                ChangeKind::TypeAdded,
+                Compatible,
+                name,
+                format!("type '{name}' was added"),
+            ));
+        }
+    }
+    Report { changes }
+}
+
+fn type_kind(r#type: &Type<'_>) -> &'static str {
+    match r#type {
+        Type::Struct(_) => "a struct",
+        Type::Enum(Enum::Tagged { .. }) => "a tagged enum",
+        Type::Enum(Enum::Untagged { .. }) => "an untagged enum",
+    }
+}
+
+/// How a field or variant of the old schema relates to the new schema, or the other way around.
+enum Match<'m, T> {
+    /// Both have the same ID.
+    Same(&'m T, &'m T),
+    /// Both have the same name, but different IDs.
+    Renumbered(&'m T, &'m T),
+    Removed(&'m T),
+    Added(&'m T),
+}
+
+/// Matches fields or variants by name if their IDs differ, and otherwise by ID, in the order of
+/// `old` followed by the ones only in `new`.
+///
+/// Matching by name first means that swapped IDs are reported as renumbered members instead of
+/// as two renames.
+fn match_members<'m, T>(
+    old: &'m [T],
+    new: &'m [T],
+    id: impl Fn(&T) -> u16,
+    name: impl Fn(&T) -> &str,
+) -> Vec<Match<'m, T>> {
+    // a member on both sides with the same name but a different ID
+    let renumbered = |member: &T, others: &'m [T]| {
+        others
+            .iter()
+            .find(|other| name(other) == name(member) && id(other) != id(member))
+    };
+    // a member on the other side with the same ID which is not renumbered itself
+    let same = |member: &T, others: &'m [T], members: &'m [T]| {
+        others
+            .iter()
+            .find(|other| id(other) == id(member) && renumbered(other, members).is_none())
+    };
+
+    let mut matches = Vec::new();
+    for old_member in old {
+        matches.push(match renumbered(old_member, new) {
+            Some(new_member) => Match::Renumbered(old_member, new_member),
+            None => match same(old_member, new, old) {
+                Some(new_member) => Match::Same(old_member, new_member),
+                None => Match::Removed(old_member),
+            },
+        });
+    }
+    for new_member in new {
+        if renumbered(new_member, old).is_none() && same(new_member, old, new).is_none() {
+            matches.push(Match::Added(new_member));
+        }
+    }
+    matches
+}
+
+fn check_struct(old: &Struct<'_>, new: &Struct<'_>, changes: &mut Vec<Change>) {
+    use Compatibility::{Breaking, Compatible};
+
+    let path = |field: &StructField<'_>| format!("{}.{}", new.name, field.name);
+    for r#match in match_members(
+        &old.fields,
+        &new.fields,
+        |field| field.id,
+        |field| field.name,
+    ) {
+        match r#match {
+            Match::Same(old_field, new_field) => {
+                if old_field.name != new_field.name {
+                    changes.push(Change::new(
+                        ChangeKind::FieldRenamed,
+                        Compatible,
+                        path(new_field),
+                        format!(
+                            "field '{}' was renamed to '{}'",
+                            old_field.name, new_field.name,
+                        ),
+                    ));
+                }
+                check_field(old_field, new_field, &path(new_field), changes);
+            }
+            Match::Renumbered(old_field, new_field) => changes.push(Change::new(
+                ChangeKind::FieldRenumbered,
+                Breaking,
+                path(new_field),
+                format!("field ID changed from {} to {}", old_field.id, new_field.id,),
+            )),
+            Match::Removed(field) => {
+                let mut message = if field.optional {
+                    format!("optional field '{}' = {} was removed", field.name, field.id)
+                } else {
+                    format!(
+                        "required field '{}' = {} was removed, so the old schema cannot decode data which lacks it",
+                        field.name, field.id,
+                    )
+                };
+                push_reserve_hint(&mut message, field.id, &new.reserved);
+                changes.push(Change::new(
+                    ChangeKind::FieldRemoved,
+                    if field.optional { Compatible } else { Breaking },
+                    path(field),
+                    message,
+                ));
+            }
+            Match::Added(field) => changes.push(if field.optional {
+                Change::new(
+                    ChangeKind::FieldAdded,
+                    Compatible,
+                    path(field),
+                    format!("optional field '{}' = {} was added", field.name, field.id),
+                )
+            } else {
+                Change::new(
+                    ChangeKind::FieldAdded,
+                    Breaking,
+                    path(field),
+                    format!(
+                        "required field '{}' = {} was added, so data from the old schema lacks it",
+                        field.name, field.id,
+                    ),
+                )
+            }),
+        }
+    }
+}
+
+fn check_field(
+    old: &StructField<'_>,
+    new: &StructField<'_>,
+    path: &str,
+    changes: &mut Vec<Change>,
+) {
+    use Compatibility::Breaking;
+
+    if old.r#type != new.r#type {
+        let message = if old.name == new.name {
+            format!("type changed from {} to {}", old.r#type, new.r#type)
+        } else {
+            format!(
+                "ID {} was reused for a field of type {}, but field '{}' had type {}",
+                new.id, new.r#type, old.name, old.r#type,
+            )
+        };
+        changes.push(Change::new(
+            ChangeKind::FieldTypeChanged,
+            Breaking,
+            path,
+            message,
+        ));
+    }
+    match (old.optional, new.optional) {
+        (true, false) => changes.push(Change::new(
+            ChangeKind::FieldMadeRequired,
+            Breaking,
+            path,
+            "field is no longer optional, so data from the old schema can lack it",
+        )),
+        (false, true) => changes.push(Change::new(
+            ChangeKind::FieldMadeOptional,
+            Breaking,
+            path,
+            "field became optional, so data from the new schema can lack it",
+        )),
+        _ => {}
+    }
+    if old.nullable != new.nullable {
+        changes.push(Change::new(
+            ChangeKind::FieldNullableChanged,
+            Breaking,
+            path,
+            if new.nullable {
+                "field became nullable, so data from the new schema can be null"
+            } else {
+                "field is no longer nullable, so data from the old schema can be null"
+            },
+        ));
+    }
+    // encoders leave out values equal to the default, which decoders fill in
+    if old.optional && new.optional && old.default != new.default {
+        let default = |default: &Option<DefaultValue<'_>>| match default {
+            Some(default) => format!("default value {}", default_value_str(default)),
+            None => String::from("no default value"),
+        };
+        changes.push(Change::new(
+            ChangeKind::FieldDefaultChanged,
+            Breaking,
+            path,
+            format!(
+                "field changed from {} to {}, so omitted values are decoded differently",
+                default(&old.default),
+                default(&new.default),
+            ),
+        ));
+    }
+    // decoding rejects values which do not satisfy the constraints, so loosening them is breaking
+    // as well
+    if old.constraints.options() != new.constraints.options() {
+        let constraints = |constraints: &Constraints<'_>| {
+            if constraints.is_empty() {
+                String::from("none")
+            } else {
+                format!("[{constraints}]")
+            }
+        };
+        changes.push(Change::new(
+            ChangeKind::FieldConstraintsChanged,
+            Breaking,
+            path,
+            format!(
+                "constraints changed from {} to {}, so values accepted by one schema can be rejected by the other",
+                constraints(&old.constraints),
+                constraints(&new.constraints),
+            ),
+        ));
+    }
+}
+
+fn default_value_str(default: &DefaultValue<'_>) -> String {
+    match default {
+        DefaultValue::Bool(value) => value.to_string(),
+        DefaultValue::Integer(value) => value.to_string(),
+        DefaultValue::Float(value) => value.clone(),
+        DefaultValue::String(value) => format!("\"{value}\""),
+        DefaultValue::Variant(value) => (*value).to_owned(),
+    }
+}
+
+/// A variant of a tagged or untagged `enum`.
+struct Variant<'a, 'b> {
+    id: u16,
+    name: &'a str,
+    r#type: Option<&'b StructFieldType<'a>>,
+}
+
+fn variants<'a, 'b>(r#enum: &'b Enum<'a>) -> Vec<Variant<'a, 'b>> {
+    match r#enum {
+        Enum::Tagged { variants, .. } => variants
+            .iter()
+            .map(|variant| Variant {
+                id: variant.id,
+                name: variant.name,
+                r#type: variant.r#type.as_ref(),
+            })
+            .collect(),
+        Enum::Untagged { variants, .. } => variants
+            .iter()
+            .map(|variant| Variant {
+                id: variant.id,
+                name: variant.name,
+                r#type: None,
+            })
+            .collect(),
+    }
+}
+
+fn check_enum(old: &Enum<'_>, new: &Enum<'_>, changes: &mut Vec<Change>) {
+    use Compatibility::{Breaking, Compatible};
+
+    let path = |variant: &Variant<'_, '_>| format!("{}::{}", new.name(), variant.name);
+    let old_variants = variants(old);
+    let new_variants = variants(new);
+    let matches = match_members(
+        &old_variants,
+        &new_variants,
+        |variant| variant.id,
+        |variant| variant.name,
+    );
+    for r#match in matches {
+        match r#match {
+            Match::Same(old_variant, new_variant) => {
+                if old_variant.name != new_variant.name {
+                    changes.push(Change::new(
+                        ChangeKind::VariantRenamed,
+                        Compatible,
+                        path(new_variant),
+                        format!(
+                            "variant '{}' was renamed to '{}'",
+                            old_variant.name, new_variant.name,
+                        ),
+                    ));
+                }
+                if old_variant.r#type != new_variant.r#type {
+                    let type_str = |r#type: Option<&StructFieldType<'_>>| match r#type {
+                        Some(r#type) => r#type.to_string(),
+                        None => String::from("no value"),
+                    };
+                    changes.push(Change::new(
+                        ChangeKind::VariantTypeChanged,
+                        Breaking,
+                        path(new_variant),
+                        format!(
+                            "value changed from {} to {}",
+                            type_str(old_variant.r#type),
+                            type_str(new_variant.r#type),
+                        ),
+                    ));
+                }
+            }
+            Match::Renumbered(old_variant, new_variant) => changes.push(Change::new(
+                ChangeKind::VariantRenumbered,
+                Breaking,
+                path(new_variant),
+                format!(
+                    "variant ID changed from {} to {}",
+                    old_variant.id, new_variant.id,
+                ),
+            )),
+            // unknown variants are only an error for enums which are not `open`
+            Match::Removed(variant) => {
+                let mut message =
+                    format!("variant '{}' = {} was removed", variant.name, variant.id);
+                message.push_str(if new.open() {
+                    ", which the new schema keeps as an unknown variant"
+                } else {
+                    ", so the new schema cannot decode it"
+                });
+                push_reserve_hint(&mut message, variant.id, new.reserved());
+                changes.push(Change::new(
+                    ChangeKind::VariantRemoved,
+                    if new.open() { Compatible } else { Breaking },
+                    path(variant),
+                    message,
+                ));
+            }
+            Match::Added(variant) => changes.push(Change::new(
+                ChangeKind::VariantAdded,
+                if old.open() { Compatible } else { Breaking },
+                path(variant),
+                format!(
+                    "variant '{}' = {} was added{}",
+                    variant.name,
+                    variant.id,
+                    if old.open() {
+                        ", which the old schema keeps as an unknown variant"
+                    } else {
+                        ", so the old schema cannot decode it"
+                    },
+                ),
+            )),
+        }
+    }
+}
+
+fn push_reserve_hint(message: &mut String, id: u16, reserved: &Reserved<'_>) {
+    if !reserved.contains_id(id) {
+        message.push_str(&format!(
+            "; consider `reserved {id};` so that the ID is not reused"
+        ));
+    }
+}
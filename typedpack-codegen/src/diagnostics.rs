@@ -0,0 +1,211 @@
+use std::{
+    fmt::{self, Write as _},
+    path::{Path, PathBuf},
+};
+
+use crate::{ParseError, Position, Warning};
+
+/// Every [`ParseError`] found in a typedpack file, along with the file's source.
+///
+/// [`Warning`]s about the same file can be rendered the same way with
+/// [`render_warning`](Self::render_warning).
+///
+/// The [`Display`](fmt::Display) implementation renders each error rustc-style, with the offending
+/// source line and a caret pointing at the error:
+///
+/// ```text
+/// error: unknown type 'Usr'
+///  --> users.tp:3:5
+///   |
+/// 3 |     Usr owner = 0;
+///   |     ^^^
+///   = help: did you mean 'User'?
+/// ```
+#[derive(Clone, Debug)]
+pub struct Diagnostics {
+    errors: Vec<ParseError>,
+    source: String,
+    path: Option<PathBuf>,
+}
+
+impl Diagnostics {
+    /// Creates the diagnostics for `errors` found in `source`, which can be empty to only render
+    /// warnings.
+    pub fn new(errors: Vec<ParseError>, source: &str) -> Self {
+        Self {
+            errors,
+            source: source.to_owned(),
+            path: None,
+        }
+    }
+
+    /// Sets the path of the file, which is shown in front of each error's position.
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Returns the errors in the order they were found.
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    /// Returns the errors in the order they were found.
+    pub fn into_errors(self) -> Vec<ParseError> {
+        self.errors
+    }
+
+    /// Returns the source of the file.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns the path of the file, if one was set with [`with_path`](Self::with_path).
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Renders a single error with a snippet of the source, as done for each error by the
+    /// [`Display`](fmt::Display) implementation.
+    pub fn render(&self, error: &ParseError) -> String {
+        let mut s = String::new();
+        self.write_error(&mut s, error)
+            .expect("writing to a string does not fail");
+        s.truncate(s.trim_end().len());
+        s
+    }
+
+    /// Renders a warning about the file with a snippet of the source, like an error but starting
+    /// with `warning:`.
+    pub fn render_warning(&self, warning: &Warning) -> String {
+        let mut s = String::new();
+        self.write_snippet(
+            &mut s,
+            "warning",
+            warning.message(),
+            Some(warning.pos()),
+            false,
+        )
+        .expect("writing to a string does not fail");
+        s.truncate(s.trim_end().len());
+        s
+    }
+
+    fn write_error(&self, f: &mut String, error: &ParseError) -> fmt::Result {
+        let message = error.to_string();
+        let pos = error.pos();
+        let message = match pos {
+            Some(pos) => message
+                .strip_prefix(&format!("line {}, column {}: ", pos.line, pos.column))
+                .unwrap_or(&message),
+            None => &message,
+        };
+        let at_end = matches!(error, ParseError::UnexpectedEnd { .. });
+        self.write_snippet(f, "error", message, pos, at_end)
+    }
+
+    /// Writes `message` labeled with `level`, followed by the source line at `pos` with the token
+    /// there underlined. Without a position, the end of the source is underlined if `at_end` is
+    /// `true`, and no source is shown otherwise.
+    fn write_snippet(
+        &self,
+        f: &mut String,
+        level: &str,
+        message: &str,
+        pos: Option<Position>,
+        at_end: bool,
+    ) -> fmt::Result {
+        let mut lines = message.lines();
+        writeln!(f, "{level}: {}", lines.next().unwrap_or_default())?;
+
+        let (pos, width) = match (pos, at_end) {
+            (Some(pos), _) => (pos, token_width(&self.source[pos.offset..])),
+            // point just past the last token
+            (None, true) => (position_at(&self.source, self.source.trim_end().len()), 1),
+            (None, false) => {
+                if let Some(path) = &self.path {
+                    writeln!(f, " --> {}", path.display())?;
+                }
+                for line in lines {
+                    writeln!(f, " = {line}")?;
+                }
+                return Ok(());
+            }
+        };
+
+        let gutter = " ".repeat(pos.line.to_string().len());
+        match &self.path {
+            Some(path) => writeln!(
+                f,
+                "{gutter}--> {}:{}:{}",
+                path.display(),
+                pos.line,
+                pos.column,
+            )?,
+            None => writeln!(f, "{gutter}--> line {}, column {}", pos.line, pos.column)?,
+        }
+        writeln!(f, "{gutter} |")?;
+        let source_line = self.source.lines().nth(pos.line - 1).unwrap_or_default();
+        writeln!(f, "{} | {}", pos.line, source_line.trim_end())?;
+        writeln!(
+            f,
+            "{gutter} | {}{}",
+            " ".repeat(pos.column - 1),
+            "^".repeat(width),
+        )?;
+        for line in lines {
+            writeln!(f, "{gutter} = {line}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.errors.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n\n")?;
+            }
+            f.write_str(&self.render(error))?;
+        }
+        match self.errors.len() {
+            0 | 1 => Ok(()),
+            n => write!(f, "\n\nerror: found {n} errors"),
+        }
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+/// Returns the position of the byte `offset` in `source`.
+fn position_at(source: &str, offset: usize) -> Position {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position {
+        line: before.matches('\n').count() + 1,
+        column: before[line_start..].chars().count() + 1,
+        offset,
+    }
+}
+
+/// Returns the width in characters of the token at the start of `s`, which is underlined when
+/// rendering an error.
+fn token_width(s: &str) -> usize {
+    let line = s.lines().next().unwrap_or_default();
+    let is_word_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    match line.chars().next() {
+        None => 1,
+        Some(quote @ ('"' | '\'')) => {
+            let mut escaped = false;
+            let end = line.char_indices().skip(1).find(|&(_, c)| {
+                let is_end = !escaped && c == quote;
+                escaped = !escaped && c == '\\';
+                is_end
+            });
+            end.map_or(line.chars().count(), |(i, _)| line[..=i].chars().count())
+        }
+        Some('#') => line.trim_end().chars().count(),
+        Some(c) if is_word_char(c) => line.find(|c| !is_word_char(c)).unwrap_or(line.len()),
+        Some(_) => 1,
+    }
+}
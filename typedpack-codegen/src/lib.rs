@@ -1,10 +1,23 @@
-use std::rc::Rc;
+use std::{
+    borrow::Cow,
+    fmt,
+    ops::{Range, RangeInclusive},
+    rc::Rc,
+};
 
+mod builder;
+pub mod compat;
+mod diagnostics;
 mod parse;
-mod rust;
+mod resolve;
+pub mod rust;
 pub mod typescript;
 
-pub use parse::{ParseError, parse};
+pub use builder::{BuildError, Builder};
+pub use diagnostics::Diagnostics;
+pub use parse::{ParseError, Position, parse, parse_with_diagnostics};
+pub use resolve::{Module, ModuleImport, ResolveError, Sources};
+pub use rust::rust_modules;
 
 /// The type of a typedpack `struct` field.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -21,19 +34,119 @@ pub enum StructFieldType<'a> {
     F32,
     F64,
     String,
-    Bytes { len: Option<u32> },
-    Array { items: Rc<StructFieldType<'a>> },
-    Reference { name: &'a str },
+    Bytes {
+        len: Option<u32>,
+    },
+    /// A point in time, encoded with the MessagePack timestamp extension.
+    Timestamp,
+    /// A non-negative length of time, encoded like a timestamp of that long after the Unix epoch.
+    Duration,
+    Array {
+        items: Rc<StructFieldType<'a>>,
+    },
+    Map {
+        key: Rc<StructFieldType<'a>>,
+        value: Rc<StructFieldType<'a>>,
+    },
+    Reference {
+        name: &'a str,
+    },
+}
+
+/// The default value of an optional typedpack `struct` field.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DefaultValue<'a> {
+    Bool(bool),
+    Integer(i128),
+    /// A floating-point literal of the form `-?[0-9]+(\.[0-9]+)?([eE][+-]?[0-9]+)?`.
+    Float(String),
+    /// The contents of a string literal, with any escapes replaced.
+    String(Cow<'a, str>),
+    /// A variant of an untagged `enum`.
+    Variant(&'a str),
 }
 
 /// A typedpack `struct` field.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct StructField<'a> {
-    id: u8,
+    id: u16,
     name: &'a str,
     r#type: StructFieldType<'a>,
     optional: bool,
     nullable: bool,
+    default: Option<DefaultValue<'a>>,
+    constraints: Constraints<'a>,
+    deprecated: Option<Deprecated<'a>>,
+    annotations: Vec<Annotation<'a>>,
+    doc: Vec<&'a str>,
+    span: Range<usize>,
+}
+
+/// The constraints on the value of a typedpack `struct` field, given as field options, e.g.
+/// `[min_len = 3, max_len = 32]`. They only apply to values which are present and not null.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Constraints<'a> {
+    min: Option<Bound>,
+    max: Option<Bound>,
+    min_len: Option<u32>,
+    max_len: Option<u32>,
+    len: Option<u32>,
+    pattern: Option<Cow<'a, str>>,
+    span: Range<usize>,
+}
+
+/// The value of a `min` or `max` constraint.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Bound {
+    Integer(i128),
+    /// A floating-point literal, see [`DefaultValue::Float`].
+    Float(String),
+}
+
+/// The IDs and names listed in the `reserved` declarations of a `struct` or `enum`, which its
+/// fields or variants cannot use.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Reserved<'a> {
+    ids: Vec<RangeInclusive<u16>>,
+    names: Vec<Cow<'a, str>>,
+    span: Range<usize>,
+}
+
+/// A `deprecated` modifier on a type, `struct` field or `enum` variant.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Deprecated<'a> {
+    note: Option<Cow<'a, str>>,
+    span: Range<usize>,
+}
+
+/// An annotation on a type, `struct` field or `enum` variant, e.g. `@rust(derive = "Default")`,
+/// which passes settings to a code generator.
+///
+/// The Rust and TypeScript generators read `@rust` and `@ts` annotations respectively and ignore
+/// any others, so that other tools can use their own.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Annotation<'a> {
+    name: &'a str,
+    args: Vec<AnnotationArg<'a>>,
+    pos: Position,
+    span: Range<usize>,
+}
+
+/// A `key = "value"` argument of an [`Annotation`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AnnotationArg<'a> {
+    key: &'a str,
+    value: Cow<'a, str>,
+    pos: Position,
+    span: Range<usize>,
+}
+
+/// A problem in a typedpack file which does not prevent generating code, e.g. an annotation key
+/// which the code generator does not know.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Warning {
+    message: String,
+    pos: Position,
 }
 
 /// A typedpack `struct`.
@@ -41,21 +154,43 @@ pub struct StructField<'a> {
 pub struct Struct<'a> {
     name: &'a str,
     fields: Vec<StructField<'a>>,
+    preserve_unknown: bool,
+    reserved: Reserved<'a>,
+    deprecated: Option<Deprecated<'a>>,
+    annotations: Vec<Annotation<'a>>,
+    doc: Vec<&'a str>,
+    span: Range<usize>,
+    contains_floats: bool,
 }
 
 /// A variant of a typedpack tagged `enum`.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct TaggedEnumVariant<'a> {
-    id: u8,
+    id: u16,
     name: &'a str,
-    r#type: &'a str,
+    r#type: Option<StructFieldType<'a>>,
+    deprecated: Option<Deprecated<'a>>,
+    annotations: Vec<Annotation<'a>>,
+    doc: Vec<&'a str>,
+    span: Range<usize>,
 }
 
 /// A variant of a typedpack untagged `enum`.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct UntaggedEnumVariant<'a> {
-    id: u8,
+    id: u16,
     name: &'a str,
+    deprecated: Option<Deprecated<'a>>,
+    annotations: Vec<Annotation<'a>>,
+    doc: Vec<&'a str>,
+    span: Range<usize>,
+}
+
+/// The integer type of the IDs of a typedpack `enum`'s variants.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EnumRepr {
+    U8,
+    U16,
 }
 
 /// A typedpack `enum`.
@@ -63,14 +198,37 @@ pub struct UntaggedEnumVariant<'a> {
 pub enum Enum<'a> {
     Tagged {
         name: &'a str,
+        open: bool,
+        repr: EnumRepr,
         variants: Vec<TaggedEnumVariant<'a>>,
+        reserved: Reserved<'a>,
+        deprecated: Option<Deprecated<'a>>,
+        annotations: Vec<Annotation<'a>>,
+        doc: Vec<&'a str>,
+        span: Range<usize>,
+        contains_floats: bool,
     },
     Untagged {
         name: &'a str,
+        open: bool,
+        repr: EnumRepr,
         variants: Vec<UntaggedEnumVariant<'a>>,
+        reserved: Reserved<'a>,
+        deprecated: Option<Deprecated<'a>>,
+        annotations: Vec<Annotation<'a>>,
+        doc: Vec<&'a str>,
+        span: Range<usize>,
     },
 }
 
+/// A typedpack `import` statement.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct Import<'a> {
+    path: Cow<'a, str>,
+    names: Option<Vec<&'a str>>,
+    pos: Position,
+}
+
 /// A typedpack `enum` or `struct`.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Type<'a> {
@@ -78,12 +236,578 @@ pub enum Type<'a> {
     Struct(Struct<'a>),
 }
 
-impl Type<'_> {
+/// Formats the type as it is written in a typedpack file, e.g. `map<string, User[]>`.
+impl fmt::Display for StructFieldType<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool => f.write_str("bool"),
+            Self::U8 => f.write_str("uint8"),
+            Self::I8 => f.write_str("int8"),
+            Self::U16 => f.write_str("uint16"),
+            Self::I16 => f.write_str("int16"),
+            Self::U32 => f.write_str("uint32"),
+            Self::I32 => f.write_str("int32"),
+            Self::U64 => f.write_str("uint64"),
+            Self::I64 => f.write_str("int64"),
+            Self::F32 => f.write_str("float32"),
+            Self::F64 => f.write_str("float64"),
+            Self::String => f.write_str("string"),
+            Self::Bytes { len: None } => f.write_str("bytes"),
+            Self::Bytes { len: Some(len) } => write!(f, "bytes{len}"),
+            Self::Timestamp => f.write_str("timestamp"),
+            Self::Duration => f.write_str("duration"),
+            Self::Array { items } => write!(f, "{items}[]"),
+            Self::Map { key, value } => write!(f, "map<{key}, {value}>"),
+            Self::Reference { name } => f.write_str(name),
+        }
+    }
+}
+
+impl<'a> StructField<'a> {
+    /// Returns the field ID, which is used as the key in the encoded map.
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Returns the name of the field.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns the type of the field.
+    pub fn r#type(&self) -> &StructFieldType<'a> {
+        &self.r#type
+    }
+
+    /// Returns `true` if the field is `optional`.
+    pub fn optional(&self) -> bool {
+        self.optional
+    }
+
+    /// Returns `true` if the field is `nullable`.
+    pub fn nullable(&self) -> bool {
+        self.nullable
+    }
+
+    /// Returns the default value of the field, if it has one.
+    pub fn default(&self) -> Option<&DefaultValue<'a>> {
+        self.default.as_ref()
+    }
+
+    /// Returns the constraints on the field's value.
+    pub fn constraints(&self) -> &Constraints<'a> {
+        &self.constraints
+    }
+
+    /// Returns the field's `deprecated` modifier, if it has one.
+    pub fn deprecated(&self) -> Option<&Deprecated<'a>> {
+        self.deprecated.as_ref()
+    }
+
+    /// Returns the field's annotations in declaration order.
+    pub fn annotations(&self) -> &[Annotation<'a>] {
+        &self.annotations
+    }
+
+    /// Returns the lines of the field's doc comment.
+    pub fn doc(&self) -> &[&'a str] {
+        &self.doc
+    }
+
+    /// Returns the byte range of the field in the source, excluding its doc comment.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+impl<'a> Reserved<'a> {
+    /// Returns the reserved ID ranges in declaration order. A single ID is a range of one.
+    pub fn ids(&self) -> &[RangeInclusive<u16>] {
+        &self.ids
+    }
+
+    /// Returns the reserved names in declaration order.
+    pub fn names(&self) -> &[Cow<'a, str>] {
+        &self.names
+    }
+
+    /// Returns `true` if `id` is in any of the reserved ranges.
+    pub fn contains_id(&self, id: u16) -> bool {
+        self.ids.iter().any(|range| range.contains(&id))
+    }
+
+    /// Returns `true` if `name` is reserved.
+    pub fn contains_name(&self, name: &str) -> bool {
+        self.names.iter().any(|reserved| reserved == name)
+    }
+
+    /// Returns the byte range of the `reserved` declarations in the source, from the first
+    /// `reserved` to the `;` of the last one. It is empty if there are no declarations.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+impl<'a> Constraints<'a> {
+    /// Returns the inclusive lower bound of a number.
+    pub fn min(&self) -> Option<&Bound> {
+        self.min.as_ref()
+    }
+
+    /// Returns the inclusive upper bound of a number.
+    pub fn max(&self) -> Option<&Bound> {
+        self.max.as_ref()
+    }
+
+    /// Returns the minimum length of a string, binary data, array or map. The length of a string
+    /// is its number of characters.
+    pub fn min_len(&self) -> Option<u32> {
+        self.min_len
+    }
+
+    /// Returns the maximum length of a string, binary data, array or map.
+    pub fn max_len(&self) -> Option<u32> {
+        self.max_len
+    }
+
+    /// Returns the exact length of a string, binary data, array or map.
+    pub fn len(&self) -> Option<u32> {
+        self.len
+    }
+
+    /// Returns the regular expression a string must contain a match of. Use `^` and `$` to match
+    /// the whole string.
+    pub fn pattern(&self) -> Option<&str> {
+        self.pattern.as_deref()
+    }
+
+    /// Returns `true` if there are no constraints.
+    pub fn is_empty(&self) -> bool {
+        self.options().is_empty()
+    }
+
+    /// Returns the byte range of the constraints in the field options in the source, from the
+    /// name of the first one to the end of the value of the last one. It is empty if there are no
+    /// constraints.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// Returns every constraint as written in a field option, e.g. `max_len = 32`.
+    fn options(&self) -> Vec<String> {
+        let mut options = Vec::new();
+        if let Some(min) = &self.min {
+            options.push(format!("min = {min}"));
+        }
+        if let Some(max) = &self.max {
+            options.push(format!("max = {max}"));
+        }
+        if let Some(min_len) = self.min_len {
+            options.push(format!("min_len = {min_len}"));
+        }
+        if let Some(max_len) = self.max_len {
+            options.push(format!("max_len = {max_len}"));
+        }
+        if let Some(len) = self.len {
+            options.push(format!("len = {len}"));
+        }
+        if let Some(pattern) = &self.pattern {
+            options.push(format!("pattern = \"{pattern}\""));
+        }
+        options
+    }
+}
+
+impl fmt::Display for Constraints<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.options().join(", "))
+    }
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Integer(value) => write!(f, "{value}"),
+            Self::Float(value) => f.write_str(value),
+        }
+    }
+}
+
+impl<'a> Deprecated<'a> {
+    /// Returns the contents of the string literal given after `deprecated`, if there is one.
+    pub fn note(&self) -> Option<&str> {
+        self.note.as_deref()
+    }
+
+    /// Returns the byte range of the modifier in the source, from `deprecated` to the closing `)`
+    /// or to the keyword itself if there is no note.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+impl<'a> Annotation<'a> {
+    /// Returns the name of the annotation, e.g. `rust` for `@rust(...)`.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns the arguments in declaration order. A key can be given more than once.
+    pub fn args(&self) -> &[AnnotationArg<'a>] {
+        &self.args
+    }
+
+    /// Returns the position of the `@` in the source.
+    pub fn pos(&self) -> Position {
+        self.pos
+    }
+
+    /// Returns the byte range of the annotation in the source, from the `@` to the closing `)`
+    /// or to the name if there are no arguments.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+impl<'a> AnnotationArg<'a> {
+    /// Returns the key of the argument.
+    pub fn key(&self) -> &'a str {
+        self.key
+    }
+
+    /// Returns the contents of the string literal given as the value, with any escapes replaced.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Returns the position of the key in the source.
+    pub fn pos(&self) -> Position {
+        self.pos
+    }
+
+    /// Returns the byte range of the argument in the source, from the key to the end of the
+    /// string literal.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+/// Returns the arguments of the `name` annotations, e.g. `rust`, in declaration order.
+fn annotation_args<'b, 'a>(
+    annotations: &'b [Annotation<'a>],
+    name: &'b str,
+) -> impl Iterator<Item = &'b AnnotationArg<'a>> {
+    annotations
+        .iter()
+        .filter(move |annotation| annotation.name == name)
+        .flat_map(|annotation| &annotation.args)
+}
+
+impl Warning {
+    /// Returns the message, without the position.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the position in the source the warning is about.
+    pub fn pos(&self) -> Position {
+        self.pos
+    }
+}
+
+/// Formats the warning as `line 3, column 5: message`, like [`ParseError`].
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.pos.line, self.pos.column, self.message
+        )
+    }
+}
+
+/// Checks the arguments of the `name` annotations of a type, field or variant, adding a warning
+/// for every key which is not in `keys` and every value which `is_valid` rejects. `target`
+/// describes what is annotated, e.g. `a struct field`.
+fn check_annotations(
+    annotations: &[Annotation<'_>],
+    name: &str,
+    target: &str,
+    keys: &[&str],
+    is_valid: impl Fn(&str, &str) -> Result<(), &'static str>,
+    warnings: &mut Vec<Warning>,
+) {
+    for arg in annotation_args(annotations, name) {
+        if !keys.contains(&arg.key) {
+            let expected = match keys {
+                [] => String::from("no keys are supported there"),
+                [key] => format!("expected `{key}`"),
+                _ => format!(
+                    "expected one of {}",
+                    keys.iter()
+                        .map(|key| format!("`{key}`"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            };
+            warnings.push(Warning {
+                message: format!(
+                    "unknown key '{key}' in `@{name}` annotation on {target}; {expected}",
+                    key = arg.key,
+                ),
+                pos: arg.pos,
+            });
+        } else if let Err(expected) = is_valid(arg.key, &arg.value) {
+            warnings.push(Warning {
+                message: format!(
+                    "invalid value \"{value}\" for '{key}' in `@{name}` annotation; expected {expected}",
+                    value = arg.value,
+                    key = arg.key,
+                ),
+                pos: arg.pos,
+            });
+        }
+    }
+}
+
+impl<'a> Struct<'a> {
+    /// Returns the name of the `struct`.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns the fields of the `struct` in declaration order.
+    pub fn fields(&self) -> &[StructField<'a>] {
+        &self.fields
+    }
+
+    /// Returns `true` if the `struct` is declared with `preserve_unknown`, so that fields which
+    /// are not in the schema are kept when decoding and written back when encoding.
+    pub fn preserve_unknown(&self) -> bool {
+        self.preserve_unknown
+    }
+
+    /// Returns the IDs and names the `struct`'s fields cannot use.
+    pub fn reserved(&self) -> &Reserved<'a> {
+        &self.reserved
+    }
+
+    /// Returns the `struct`'s `deprecated` modifier, if it has one.
+    pub fn deprecated(&self) -> Option<&Deprecated<'a>> {
+        self.deprecated.as_ref()
+    }
+
+    /// Returns the `struct`'s annotations in declaration order.
+    pub fn annotations(&self) -> &[Annotation<'a>] {
+        &self.annotations
+    }
+
+    /// Returns the lines of the `struct`'s doc comment.
+    pub fn doc(&self) -> &[&'a str] {
+        &self.doc
+    }
+
+    /// Returns the byte range of the `struct` in the source, excluding its doc comment.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// Returns `true` if the `struct` contains `float32` or `float64` values, directly or
+    /// through the types it references. Such types cannot implement `Eq` or `Hash` in Rust.
+    pub fn contains_floats(&self) -> bool {
+        self.contains_floats
+    }
+}
+
+impl<'a> TaggedEnumVariant<'a> {
+    /// Returns the variant ID, which is used as the tag in the encoded data.
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Returns the name of the variant.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns the type of the value the variant contains, or `None` if it is a unit variant.
+    pub fn r#type(&self) -> Option<&StructFieldType<'a>> {
+        self.r#type.as_ref()
+    }
+
+    /// Returns the variant's `deprecated` modifier, if it has one.
+    pub fn deprecated(&self) -> Option<&Deprecated<'a>> {
+        self.deprecated.as_ref()
+    }
+
+    /// Returns the variant's annotations in declaration order.
+    pub fn annotations(&self) -> &[Annotation<'a>] {
+        &self.annotations
+    }
+
+    /// Returns the lines of the variant's doc comment.
+    pub fn doc(&self) -> &[&'a str] {
+        &self.doc
+    }
+
+    /// Returns the byte range of the variant in the source, excluding its doc comment.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+impl<'a> UntaggedEnumVariant<'a> {
+    /// Returns the variant ID, which is the encoded value of the variant.
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Returns the name of the variant.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns the variant's `deprecated` modifier, if it has one.
+    pub fn deprecated(&self) -> Option<&Deprecated<'a>> {
+        self.deprecated.as_ref()
+    }
+
+    /// Returns the variant's annotations in declaration order.
+    pub fn annotations(&self) -> &[Annotation<'a>] {
+        &self.annotations
+    }
+
+    /// Returns the lines of the variant's doc comment.
+    pub fn doc(&self) -> &[&'a str] {
+        &self.doc
+    }
+
+    /// Returns the byte range of the variant in the source, excluding its doc comment.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+impl<'a> Enum<'a> {
+    /// Returns the name of the `enum`.
+    pub fn name(&self) -> &'a str {
+        match self {
+            Self::Tagged { name, .. } | Self::Untagged { name, .. } => name,
+        }
+    }
+
+    /// Returns `true` if the `enum` is declared with `open`, so that variants which are not in
+    /// the schema are kept when decoding and written back when encoding.
+    pub fn open(&self) -> bool {
+        match self {
+            Self::Tagged { open, .. } | Self::Untagged { open, .. } => *open,
+        }
+    }
+
+    /// Returns the integer type of the variant IDs, which is the one given after the name of the
+    /// `enum`, or the smallest one which fits every variant ID if there is none.
+    pub fn repr(&self) -> EnumRepr {
+        match self {
+            Self::Tagged { repr, .. } | Self::Untagged { repr, .. } => *repr,
+        }
+    }
+
+    /// Returns the IDs and names the `enum`'s variants cannot use.
+    pub fn reserved(&self) -> &Reserved<'a> {
+        match self {
+            Self::Tagged { reserved, .. } | Self::Untagged { reserved, .. } => reserved,
+        }
+    }
+
+    /// Returns the `enum`'s `deprecated` modifier, if it has one.
+    pub fn deprecated(&self) -> Option<&Deprecated<'a>> {
+        match self {
+            Self::Tagged { deprecated, .. } | Self::Untagged { deprecated, .. } => {
+                deprecated.as_ref()
+            }
+        }
+    }
+
+    /// Returns the `enum`'s annotations in declaration order.
+    pub fn annotations(&self) -> &[Annotation<'a>] {
+        match self {
+            Self::Tagged { annotations, .. } | Self::Untagged { annotations, .. } => annotations,
+        }
+    }
+
+    /// Returns the lines of the `enum`'s doc comment.
+    pub fn doc(&self) -> &[&'a str] {
+        match self {
+            Self::Tagged { doc, .. } | Self::Untagged { doc, .. } => doc,
+        }
+    }
+
+    /// Returns the byte range of the `enum` in the source, excluding its doc comment.
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            Self::Tagged { span, .. } | Self::Untagged { span, .. } => span.clone(),
+        }
+    }
+
+    /// Returns `true` if the `enum` contains `float32` or `float64` values through the structs
+    /// its variants contain. Such types cannot implement `Eq` or `Hash` in Rust.
+    pub fn contains_floats(&self) -> bool {
+        match self {
+            Self::Tagged {
+                contains_floats, ..
+            } => *contains_floats,
+            Self::Untagged { .. } => false,
+        }
+    }
+}
+
+impl<'a> Type<'a> {
     /// Returns the name of the type.
-    pub fn name(&self) -> &str {
+    pub fn name(&self) -> &'a str {
         match self {
-            Self::Enum(Enum::Tagged { name, .. }) | Self::Enum(Enum::Untagged { name, .. }) => name,
+            Self::Enum(r#enum) => r#enum.name(),
             Self::Struct(r#struct) => r#struct.name,
         }
     }
+
+    /// Returns the lines of the type's doc comment.
+    pub fn doc(&self) -> &[&'a str] {
+        match self {
+            Self::Enum(r#enum) => r#enum.doc(),
+            Self::Struct(r#struct) => &r#struct.doc,
+        }
+    }
+
+    /// Returns the type's `deprecated` modifier, if it has one.
+    pub fn deprecated(&self) -> Option<&Deprecated<'a>> {
+        match self {
+            Self::Enum(r#enum) => r#enum.deprecated(),
+            Self::Struct(r#struct) => r#struct.deprecated(),
+        }
+    }
+
+    /// Returns the type's annotations in declaration order.
+    pub fn annotations(&self) -> &[Annotation<'a>] {
+        match self {
+            Self::Enum(r#enum) => r#enum.annotations(),
+            Self::Struct(r#struct) => &r#struct.annotations,
+        }
+    }
+
+    /// Returns the byte range of the type in the source, excluding its doc comment.
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            Self::Enum(r#enum) => r#enum.span(),
+            Self::Struct(r#struct) => r#struct.span(),
+        }
+    }
+
+    /// Returns `true` if the type contains `float32` or `float64` values, directly or through
+    /// the types it references.
+    pub fn contains_floats(&self) -> bool {
+        match self {
+            Self::Enum(r#enum) => r#enum.contains_floats(),
+            Self::Struct(r#struct) => r#struct.contains_floats,
+        }
+    }
 }
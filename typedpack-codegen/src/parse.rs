@@ -1,30 +1,61 @@
-use std::collections::HashSet;
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    iter::Peekable,
+    ops::Range,
+    str::Chars,
+};
 
 use crate::{
-    Enum, Struct, StructField, StructFieldType, TaggedEnumVariant, Type, UntaggedEnumVariant,
+    Annotation, AnnotationArg, Bound, Constraints, DefaultValue, Deprecated, Diagnostics, Enum,
+    EnumRepr, Import, Reserved, Struct, StructField, StructFieldType, TaggedEnumVariant, Type,
+    UntaggedEnumVariant, rust::rust_regex,
 };
 
 /// The error type which is returned from [`parse`].
 #[derive(Clone, Debug, thiserror::Error)]
 pub enum ParseError {
+    #[error("line {line}, column {column}: only optional fields can have a default value", line = pos.line, column = pos.column)]
+    DefaultValueOnRequiredField { pos: Position },
     #[error("line {line}, column {column}: duplicate enum variant ID '{id}'", line = pos.line, column = pos.column)]
-    DuplicateEnumVariantId { id: u8, pos: Position },
+    DuplicateEnumVariantId { id: u16, pos: Position },
     #[error("line {line}, column {column}: duplicate enum variant name '{name}'", line = pos.line, column = pos.column)]
     DuplicateEnumVariantName { name: String, pos: Position },
+    #[error("line {line}, column {column}: duplicate field option '{name}'", line = pos.line, column = pos.column)]
+    DuplicateFieldOption { name: String, pos: Position },
     #[error("line {line}, column {column}: duplicate struct field ID '{id}'", line = pos.line, column = pos.column)]
-    DuplicateStructFieldId { id: u8, pos: Position },
+    DuplicateStructFieldId { id: u16, pos: Position },
     #[error("line {line}, column {column}: duplicate struct field name '{name}'", line = pos.line, column = pos.column)]
     DuplicateStructFieldName { name: String, pos: Position },
     #[error("line {line}, column {column}: duplicate type name '{name}'", line = pos.line, column = pos.column)]
     DuplicateTypeName { name: String, pos: Position },
-    #[error("line {line}, column {column}: invalid enum variant ID '{id}'\nnote: ID must be between 0 and 127 inclusive and leading zeros are not allowed", line = pos.line, column = pos.column)]
+    #[error("line {line}, column {column}: invalid value '{value}' for constraint '{name}'\nnote: `min` and `max` must fit in the field type and cannot conflict, lengths must be between 0 and 4294967295 and cannot conflict, and `pattern` must be a valid regular expression which only uses syntax that Rust and JavaScript share", line = pos.line, column = pos.column)]
+    InvalidConstraint {
+        name: String,
+        value: String,
+        pos: Position,
+    },
+    #[error("line {line}, column {column}: invalid default value '{value}' for the field type", line = pos.line, column = pos.column)]
+    InvalidDefaultValue { value: String, pos: Position },
+    #[error("line {line}, column {column}: invalid enum variant ID '{id}'\nnote: ID must be between 0 and 65535 inclusive, or 255 for `uint8` enums, and leading zeros are not allowed", line = pos.line, column = pos.column)]
     InvalidEnumVariantId { id: String, pos: Position },
-    #[error("line {line}, column {column}: invalid struct field ID '{id}'\nnote: ID must be between 0 and 127 inclusive and leading zeros are not allowed", line = pos.line, column = pos.column)]
+    #[error("line {line}, column {column}: invalid map key type\nnote: map keys must be integers, strings or untagged enums", line = pos.line, column = pos.column)]
+    InvalidMapKeyType { pos: Position },
+    #[error("line {line}, column {column}: invalid reserved ID range '{range}'\nnote: IDs must be between 0 and 65535 inclusive, a range cannot end before it starts, and leading zeros are not allowed", line = pos.line, column = pos.column)]
+    InvalidReservedRange { range: String, pos: Position },
+    #[error("line {line}, column {column}: invalid struct field ID '{id}'\nnote: ID must be between 0 and 65535 inclusive and leading zeros are not allowed", line = pos.line, column = pos.column)]
     InvalidStructFieldId { id: String, pos: Position },
-    #[error("line {line}, column {column}: cannot mix tagged and untagged enum variants", line = pos.line, column = pos.column)]
-    MixedTaggedAndUntaggedEnumVariants { pos: Position },
     #[error("line {line}, column {column}: unexpected non-ASCII character '{char}'", line = pos.line, column = pos.column)]
     NonAsciiCharacter { char: char, pos: Position },
+    #[error("line {line}, column {column}: enum variant name '{name}' is reserved\nnote: `open` enums use it for the unknown variants", line = pos.line, column = pos.column)]
+    ReservedEnumVariantName { name: String, pos: Position },
+    #[error("line {line}, column {column}: ID '{id}' is reserved\nnote: it is listed in a `reserved` declaration, usually because a removed field or variant used it", line = pos.line, column = pos.column)]
+    ReservedId { id: u16, pos: Position },
+    #[error("line {line}, column {column}: name '{name}' is reserved\nnote: it is listed in a `reserved` declaration, usually because a removed field or variant used it", line = pos.line, column = pos.column)]
+    ReservedName { name: String, pos: Position },
+    #[error("line {line}, column {column}: struct field name '{name}' is reserved\nnote: `preserve_unknown` structs use it for the unknown fields", line = pos.line, column = pos.column)]
+    ReservedStructFieldName { name: String, pos: Position },
     #[error("unexpected end of input: expected {expected}")]
     UnexpectedEnd { expected: &'static str },
     #[error("line {line}, column {column}: unexpected token '{unexpected}'; expected {expected}", line = pos.line, column = pos.column)]
@@ -33,15 +64,68 @@ pub enum ParseError {
         expected: &'static str,
         pos: Position,
     },
-    #[error("unknown tagged enum variant type '{0}'")]
-    UnknownTaggedEnumVariantType(String),
+    #[error("line {line}, column {column}: unknown field option '{name}'", line = pos.line, column = pos.column)]
+    UnknownFieldOption { name: String, pos: Position },
+    #[error("line {line}, column {column}: unknown type '{name}'{help}", line = pos.line, column = pos.column, help = suggestion.as_ref().map(|suggestion| format!("\nhelp: did you mean '{suggestion}'?")).unwrap_or_default())]
+    UnknownType {
+        name: String,
+        suggestion: Option<String>,
+        pos: Position,
+    },
+    #[error("line {line}, column {column}: `import` is not supported when parsing a single file\nnote: use `Sources` to load files with imports", line = pos.line, column = pos.column)]
+    UnresolvedImport { pos: Position },
+    #[error("line {line}, column {column}: constraint '{name}' cannot be used on a field of type {type}\nnote: `min` and `max` apply to numbers, `min_len`, `max_len` and `len` to strings, `bytes`, arrays and maps, and `pattern` to strings", line = pos.line, column = pos.column)]
+    UnsupportedConstraint {
+        name: String,
+        r#type: String,
+        pos: Position,
+    },
+    #[error("line {line}, column {column}: unterminated string literal", line = pos.line, column = pos.column)]
+    UnterminatedString { pos: Position },
 }
 
-/// The location a [`ParseError`] occurred at in the source file.
+impl ParseError {
+    /// Returns the position in the source where the error occurred, if it is known.
+    pub fn pos(&self) -> Option<Position> {
+        match self {
+            Self::DefaultValueOnRequiredField { pos }
+            | Self::DuplicateEnumVariantId { pos, .. }
+            | Self::DuplicateEnumVariantName { pos, .. }
+            | Self::DuplicateFieldOption { pos, .. }
+            | Self::DuplicateStructFieldId { pos, .. }
+            | Self::DuplicateStructFieldName { pos, .. }
+            | Self::DuplicateTypeName { pos, .. }
+            | Self::InvalidConstraint { pos, .. }
+            | Self::InvalidDefaultValue { pos, .. }
+            | Self::InvalidEnumVariantId { pos, .. }
+            | Self::InvalidMapKeyType { pos }
+            | Self::InvalidReservedRange { pos, .. }
+            | Self::InvalidStructFieldId { pos, .. }
+            | Self::NonAsciiCharacter { pos, .. }
+            | Self::ReservedEnumVariantName { pos, .. }
+            | Self::ReservedId { pos, .. }
+            | Self::ReservedName { pos, .. }
+            | Self::ReservedStructFieldName { pos, .. }
+            | Self::UnexpectedToken { pos, .. }
+            | Self::UnknownFieldOption { pos, .. }
+            | Self::UnknownType { pos, .. }
+            | Self::UnresolvedImport { pos }
+            | Self::UnsupportedConstraint { pos, .. }
+            | Self::UnterminatedString { pos } => Some(*pos),
+            Self::UnexpectedEnd { .. } => None,
+        }
+    }
+}
+
+/// A location in the source file, e.g. where a [`ParseError`] occurred.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Position {
-    line: usize,
-    column: usize,
+    /// The line number, starting from 1.
+    pub line: usize,
+    /// The column number in characters, starting from 1.
+    pub column: usize,
+    /// The byte offset from the start of the source.
+    pub offset: usize,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -50,18 +134,63 @@ struct Token<'a> {
     pos: Position,
 }
 
-fn tokenize(s: &str) -> Result<Vec<Token<'_>>, ParseError> {
+impl Token<'_> {
+    /// Returns the byte range from the start of `self` to the end of `end`.
+    fn span_to(&self, end: &Token<'_>) -> Range<usize> {
+        self.pos.offset..end.pos.offset + end.str.len()
+    }
+}
+
+/// Splits `s` into tokens, skipping over any invalid characters and unterminated strings after
+/// adding an error for them to `errors`. Each doc comment line is a single token.
+fn tokenize<'a>(s: &'a str, errors: &mut Vec<ParseError>) -> Vec<Token<'a>> {
     let mut tokens = Vec::new();
 
     let mut current_token_start_idx_and_column = None;
+    let mut current_string_start_idx_and_column = None;
+    let mut escaped = false;
     let mut inside_comment = false;
+    let mut current_doc_comment_start_idx_and_column = None;
     let mut line = 1;
+    let mut line_start_idx = 0;
     let mut column = 0;
     for (i, char) in s.char_indices() {
         if inside_comment && char != '\n' {
             column += 1;
             continue;
         }
+        if let Some((idx, column_start)) = current_string_start_idx_and_column {
+            if char == '\n' {
+                errors.push(ParseError::UnterminatedString {
+                    pos: Position {
+                        line,
+                        column: column_start,
+                        offset: idx,
+                    },
+                });
+                current_string_start_idx_and_column = None;
+                escaped = false;
+            } else {
+                column += 1;
+                // a string ends at the same kind of quote which starts it, unless it is escaped
+                if escaped {
+                    escaped = false;
+                } else if char == '\\' {
+                    escaped = true;
+                } else if s[idx..].starts_with(char) {
+                    tokens.push(Token {
+                        str: &s[idx..i + 1],
+                        pos: Position {
+                            line,
+                            column: column_start,
+                            offset: idx,
+                        },
+                    });
+                    current_string_start_idx_and_column = None;
+                }
+                continue;
+            }
+        }
         if char.is_ascii_alphanumeric() || char == '_' {
             column += 1;
             if current_token_start_idx_and_column.is_none() {
@@ -72,49 +201,961 @@ fn tokenize(s: &str) -> Result<Vec<Token<'_>>, ParseError> {
             if let Some((idx, column)) = current_token_start_idx_and_column {
                 tokens.push(Token {
                     str: &s[idx..i],
-                    pos: Position { line, column },
+                    pos: Position {
+                        line,
+                        column,
+                        offset: idx,
+                    },
                 });
                 current_token_start_idx_and_column = None;
             }
             if !char.is_ascii() {
-                return Err(ParseError::NonAsciiCharacter {
+                errors.push(ParseError::NonAsciiCharacter {
                     char,
-                    pos: Position { line, column },
+                    pos: Position {
+                        line,
+                        column,
+                        offset: i,
+                    },
                 });
-            }
-            if char == '#' {
+            } else if char == '#' {
                 inside_comment = true;
+                // `##` after other tokens on the same line starts an ordinary comment
+                if s[i + 1..].starts_with('#') && s[line_start_idx..i].trim().is_empty() {
+                    current_doc_comment_start_idx_and_column = Some((i, column));
+                }
+            } else if char == '"' || char == '\'' {
+                current_string_start_idx_and_column = Some((i, column));
             } else if char == '\n' {
+                if let Some((idx, column)) = current_doc_comment_start_idx_and_column {
+                    tokens.push(Token {
+                        str: &s[idx..i],
+                        pos: Position {
+                            line,
+                            column,
+                            offset: idx,
+                        },
+                    });
+                    current_doc_comment_start_idx_and_column = None;
+                }
                 line += 1;
+                line_start_idx = i + 1;
                 column = 0;
                 inside_comment = false;
             } else if !char.is_whitespace() {
                 tokens.push(Token {
                     str: &s[i..i + 1],
-                    pos: Position { line, column },
+                    pos: Position {
+                        line,
+                        column,
+                        offset: i,
+                    },
                 });
             }
         }
     }
 
-    if let Some((start, column)) = current_token_start_idx_and_column {
+    if let Some((start, column)) = current_string_start_idx_and_column {
+        errors.push(ParseError::UnterminatedString {
+            pos: Position {
+                line,
+                column,
+                offset: start,
+            },
+        });
+    }
+    if let Some((start, column)) =
+        current_token_start_idx_and_column.or(current_doc_comment_start_idx_and_column)
+    {
         tokens.push(Token {
             str: &s[start..],
-            pos: Position { line, column },
+            pos: Position {
+                line,
+                column,
+                offset: start,
+            },
+        });
+    }
+
+    remove_dangling_doc_comments(&mut tokens);
+    tokens
+}
+
+/// Removes the doc comments which are not followed by a type, struct field or enum variant, so
+/// that they are ordinary comments.
+fn remove_dangling_doc_comments(tokens: &mut Vec<Token<'_>>) {
+    let mut dangling = vec![false; tokens.len()];
+    // whether the next token which is not a doc comment is `}` or the end
+    let mut followed_by_end = true;
+    for (i, token) in tokens.iter().enumerate().rev() {
+        if doc_comment(token.str).is_some() {
+            dangling[i] = followed_by_end;
+        } else {
+            followed_by_end = token.str == "}";
+        }
+    }
+    let mut dangling = dangling.into_iter();
+    tokens.retain(|_| !dangling.next().is_some_and(|dangling| dangling));
+}
+
+/// Returns the text of a doc comment token, i.e. a line starting with `##`.
+fn doc_comment(s: &str) -> Option<&str> {
+    let text = s.strip_prefix("##")?;
+    Some(text.strip_prefix(' ').unwrap_or(text).trim_end())
+}
+
+/// Parses the doc comment lines preceding a type, struct field or enum variant.
+fn parse_doc_comment<'a>(tokens: &mut &[Token<'a>]) -> Vec<&'a str> {
+    let mut doc = Vec::new();
+    while let Some(token) = tokens.first() {
+        let Some(text) = doc_comment(token.str) else {
+            break;
+        };
+        doc.push(text);
+        tokens.split_off_first();
+    }
+    doc
+}
+
+/// Parses the `@name` or `@name(key = "value", ...)` annotations preceding a type, struct field
+/// or enum variant.
+fn parse_annotations<'a>(tokens: &mut &[Token<'a>]) -> Result<Vec<Annotation<'a>>, ParseError> {
+    let mut annotations = Vec::new();
+    while let Some(at @ Token { str: "@", .. }) = tokens.first() {
+        tokens.split_off_first();
+        let name = match tokens.split_off_first() {
+            Some(name) if is_identifier(name.str) => name,
+            Some(token) => {
+                return Err(ParseError::UnexpectedToken {
+                    unexpected: token.str.into(),
+                    expected: "an annotation name",
+                    pos: token.pos,
+                });
+            }
+            None => {
+                return Err(ParseError::UnexpectedEnd {
+                    expected: "an annotation name",
+                });
+            }
+        };
+
+        let mut args = Vec::new();
+        let mut end = name;
+        if tokens.first().is_some_and(|token| token.str == "(") {
+            tokens.split_off_first();
+            // the arguments may be empty or end with a trailing comma
+            while tokens.first().is_none_or(|token| token.str != ")") {
+                let key = match tokens.split_off_first() {
+                    Some(key) if is_identifier(key.str) => key,
+                    Some(token) => {
+                        return Err(ParseError::UnexpectedToken {
+                            unexpected: token.str.into(),
+                            expected: "an annotation key",
+                            pos: token.pos,
+                        });
+                    }
+                    None => {
+                        return Err(ParseError::UnexpectedEnd {
+                            expected: "an annotation key",
+                        });
+                    }
+                };
+                match tokens.split_off_first() {
+                    Some(Token { str: "=", .. }) => {}
+                    Some(token) => {
+                        return Err(ParseError::UnexpectedToken {
+                            unexpected: token.str.into(),
+                            expected: "`=`",
+                            pos: token.pos,
+                        });
+                    }
+                    None => {
+                        return Err(ParseError::UnexpectedEnd { expected: "`=`" });
+                    }
+                }
+                let (value, value_token) = match tokens.split_off_first() {
+                    Some(token) => match string_literal(token.str) {
+                        Some(value) => (value, token),
+                        None => {
+                            return Err(ParseError::UnexpectedToken {
+                                unexpected: token.str.into(),
+                                expected: "a string literal",
+                                pos: token.pos,
+                            });
+                        }
+                    },
+                    None => {
+                        return Err(ParseError::UnexpectedEnd {
+                            expected: "a string literal",
+                        });
+                    }
+                };
+                args.push(AnnotationArg {
+                    key: key.str,
+                    value,
+                    pos: key.pos,
+                    span: key.span_to(value_token),
+                });
+
+                match tokens.first() {
+                    Some(Token { str: ",", .. }) => {
+                        tokens.split_off_first();
+                    }
+                    Some(Token { str: ")", .. }) => {}
+                    Some(token) => {
+                        return Err(ParseError::UnexpectedToken {
+                            unexpected: token.str.into(),
+                            expected: "`,` or `)`",
+                            pos: token.pos,
+                        });
+                    }
+                    None => {
+                        return Err(ParseError::UnexpectedEnd {
+                            expected: "`,` or `)`",
+                        });
+                    }
+                }
+            }
+            end = tokens.split_off_first().unwrap_or(end);
+        }
+        annotations.push(Annotation {
+            name: name.str,
+            args,
+            pos: at.pos,
+            span: at.span_to(end),
+        });
+    }
+    Ok(annotations)
+}
+
+/// Parses a `deprecated` or `deprecated("note")` modifier, if there is one.
+fn parse_deprecated<'a>(tokens: &mut &[Token<'a>]) -> Result<Option<Deprecated<'a>>, ParseError> {
+    if tokens.first().is_none_or(|token| token.str != "deprecated") {
+        return Ok(None);
+    }
+    let Some(keyword) = tokens.split_off_first() else {
+        return Ok(None);
+    };
+    if tokens.first().is_none_or(|token| token.str != "(") {
+        return Ok(Some(Deprecated {
+            note: None,
+            span: keyword.span_to(keyword),
+        }));
+    }
+    tokens.split_off_first();
+
+    let note = match tokens.split_off_first() {
+        Some(token) => match string_literal(token.str) {
+            Some(note) => note,
+            None => {
+                return Err(ParseError::UnexpectedToken {
+                    unexpected: token.str.into(),
+                    expected: "a string literal",
+                    pos: token.pos,
+                });
+            }
+        },
+        None => {
+            return Err(ParseError::UnexpectedEnd {
+                expected: "a string literal",
+            });
+        }
+    };
+    let end = match tokens.split_off_first() {
+        Some(end @ Token { str: ")", .. }) => end,
+        Some(token) => {
+            return Err(ParseError::UnexpectedToken {
+                unexpected: token.str.into(),
+                expected: "`)`",
+                pos: token.pos,
+            });
+        }
+        None => {
+            return Err(ParseError::UnexpectedEnd { expected: "`)`" });
+        }
+    };
+    Ok(Some(Deprecated {
+        note: Some(note),
+        span: keyword.span_to(end),
+    }))
+}
+
+/// A reference to another type by name in a struct field type.
+#[derive(Clone, Copy, Debug)]
+struct Reference<'a> {
+    token: Token<'a>,
+    is_map_key: bool,
+    /// The variant given as the field's default value, which must belong to the referenced type.
+    default_variant: Option<Token<'a>>,
+}
+
+fn parse_primitive_type(name: &str) -> Option<StructFieldType<'_>> {
+    if name == "bytes" {
+        return Some(StructFieldType::Bytes { len: None });
+    } else if name == "bytes0" {
+        return Some(StructFieldType::Bytes { len: Some(0) });
+    } else if let Some(len_str) = name.strip_prefix("bytes") {
+        return match len_str.chars().next() {
+            Some('1'..='9') => match len_str.parse() {
+                Ok(len) => Some(StructFieldType::Bytes { len: Some(len) }),
+                Err(_) => None,
+            },
+            _ => None,
+        };
+    }
+    Some(match name {
+        "bool" => StructFieldType::Bool,
+        "uint8" => StructFieldType::U8,
+        "int8" => StructFieldType::I8,
+        "uint16" => StructFieldType::U16,
+        "int16" => StructFieldType::I16,
+        "uint32" => StructFieldType::U32,
+        "int32" => StructFieldType::I32,
+        "uint64" => StructFieldType::U64,
+        "int64" => StructFieldType::I64,
+        "float32" => StructFieldType::F32,
+        "float64" => StructFieldType::F64,
+        "string" => StructFieldType::String,
+        "timestamp" => StructFieldType::Timestamp,
+        "duration" => StructFieldType::Duration,
+        _ => return None,
+    })
+}
+
+/// Parses a struct field type starting with the token `type_name`, including the parameters of
+/// `map<K, V>` and any `[]` suffixes.
+fn parse_struct_field_type<'a>(
+    type_name: &Token<'a>,
+    tokens: &mut &[Token<'a>],
+    references: &mut Vec<Reference<'a>>,
+    is_map_key: bool,
+) -> Result<StructFieldType<'a>, ParseError> {
+    let mut r#type = if type_name.str == "map" && tokens.first().is_some_and(|t| t.str == "<") {
+        tokens.split_off_first();
+
+        let Some(key_name) = tokens.split_off_first() else {
+            return Err(ParseError::UnexpectedEnd {
+                expected: "a map key type",
+            });
+        };
+        if !is_identifier(key_name.str) {
+            return Err(ParseError::UnexpectedToken {
+                unexpected: key_name.str.into(),
+                expected: "a map key type",
+                pos: key_name.pos,
+            });
+        }
+        let key = parse_struct_field_type(key_name, tokens, references, true)?;
+
+        match tokens.split_off_first() {
+            Some(Token { str: ",", .. }) => {}
+            Some(token) => {
+                return Err(ParseError::UnexpectedToken {
+                    unexpected: token.str.into(),
+                    expected: "`,`",
+                    pos: token.pos,
+                });
+            }
+            None => {
+                return Err(ParseError::UnexpectedEnd { expected: "`,`" });
+            }
+        }
+
+        let Some(value_name) = tokens.split_off_first() else {
+            return Err(ParseError::UnexpectedEnd {
+                expected: "a map value type",
+            });
+        };
+        if !is_identifier(value_name.str) {
+            return Err(ParseError::UnexpectedToken {
+                unexpected: value_name.str.into(),
+                expected: "a map value type",
+                pos: value_name.pos,
+            });
+        }
+        let value = parse_struct_field_type(value_name, tokens, references, false)?;
+
+        match tokens.split_off_first() {
+            Some(Token { str: ">", .. }) => {}
+            Some(token) => {
+                return Err(ParseError::UnexpectedToken {
+                    unexpected: token.str.into(),
+                    expected: "`[` or `>`",
+                    pos: token.pos,
+                });
+            }
+            None => {
+                return Err(ParseError::UnexpectedEnd {
+                    expected: "`[` or `>`",
+                });
+            }
+        }
+
+        StructFieldType::Map {
+            key: key.into(),
+            value: value.into(),
+        }
+    } else if let Some(r#type) = parse_primitive_type(type_name.str) {
+        r#type
+    } else {
+        references.push(Reference {
+            token: *type_name,
+            is_map_key,
+            default_variant: None,
         });
+        StructFieldType::Reference {
+            name: type_name.str,
+        }
+    };
+
+    while tokens.first().is_some_and(|token| token.str == "[") {
+        tokens.split_off_first();
+        match tokens.split_off_first() {
+            Some(Token { str: "]", .. }) => {}
+            Some(token) => {
+                return Err(ParseError::UnexpectedToken {
+                    unexpected: token.str.into(),
+                    expected: "`]`",
+                    pos: token.pos,
+                });
+            }
+            None => {
+                return Err(ParseError::UnexpectedEnd { expected: "`]`" });
+            }
+        }
+        r#type = StructFieldType::Array {
+            items: r#type.into(),
+        };
+    }
+
+    if is_map_key
+        && !matches!(
+            r#type,
+            StructFieldType::U8
+                | StructFieldType::I8
+                | StructFieldType::U16
+                | StructFieldType::I16
+                | StructFieldType::U32
+                | StructFieldType::I32
+                | StructFieldType::U64
+                | StructFieldType::I64
+                | StructFieldType::String
+                | StructFieldType::Reference { .. }
+        )
+    {
+        return Err(ParseError::InvalidMapKeyType { pos: type_name.pos });
+    }
+
+    Ok(r#type)
+}
+
+/// Parses a `[name = value, ...]` list of field options. Each value consists of the tokens up to
+/// the next `,` or `]`.
+fn parse_field_options<'a>(
+    tokens: &mut &[Token<'a>],
+) -> Result<Vec<(Token<'a>, Vec<Token<'a>>)>, ParseError> {
+    // skip `[`
+    tokens.split_off_first();
+
+    let mut options: Vec<(Token<'a>, Vec<Token<'a>>)> = Vec::new();
+    loop {
+        let Some(name) = tokens.split_off_first() else {
+            return Err(ParseError::UnexpectedEnd {
+                expected: "a field option name",
+            });
+        };
+        if !is_identifier(name.str) {
+            return Err(ParseError::UnexpectedToken {
+                unexpected: name.str.into(),
+                expected: "a field option name",
+                pos: name.pos,
+            });
+        }
+        if options
+            .iter()
+            .any(|(other_name, _)| other_name.str == name.str)
+        {
+            return Err(ParseError::DuplicateFieldOption {
+                name: name.str.into(),
+                pos: name.pos,
+            });
+        }
+
+        match tokens.split_off_first() {
+            Some(Token { str: "=", .. }) => {}
+            Some(token) => {
+                return Err(ParseError::UnexpectedToken {
+                    unexpected: token.str.into(),
+                    expected: "`=`",
+                    pos: token.pos,
+                });
+            }
+            None => {
+                return Err(ParseError::UnexpectedEnd { expected: "`=`" });
+            }
+        }
+
+        let mut value = Vec::new();
+        while let Some(token) = tokens.first() {
+            if token.str == "," || token.str == "]" {
+                break;
+            }
+            value.push(*token);
+            tokens.split_off_first();
+        }
+        match tokens.split_off_first() {
+            Some(token) if value.is_empty() => {
+                return Err(ParseError::UnexpectedToken {
+                    unexpected: token.str.into(),
+                    expected: "a field option value",
+                    pos: token.pos,
+                });
+            }
+            Some(Token { str: ",", .. }) => {
+                options.push((*name, value));
+            }
+            Some(_) => {
+                options.push((*name, value));
+                return Ok(options);
+            }
+            None => {
+                return Err(ParseError::UnexpectedEnd {
+                    expected: "`,` or `]`",
+                });
+            }
+        }
     }
+}
+
+/// Joins tokens which are not separated by whitespace, e.g. `-`, `1`, `.` and `5` into `-1.5`.
+fn join_adjacent_tokens(tokens: &[Token<'_>]) -> Option<String> {
+    let mut joined = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if i != 0 {
+            let previous = tokens[i - 1];
+            if token.pos.line != previous.pos.line
+                || token.pos.column != previous.pos.column + previous.str.chars().count()
+            {
+                return None;
+            }
+        }
+        joined.push_str(token.str);
+    }
+    Some(joined)
+}
+
+/// Checks that `s` is of the form `-?[0-9]+(\.[0-9]+)?([eE][+-]?[0-9]+)?`.
+fn is_float_literal(s: &str) -> bool {
+    fn strip_digits(s: &str) -> Option<&str> {
+        let rest = s.trim_start_matches(|c: char| c.is_ascii_digit());
+        (rest.len() != s.len()).then_some(rest)
+    }
+
+    let s = s.strip_prefix('-').unwrap_or(s);
+    let Some(mut rest) = strip_digits(s) else {
+        return false;
+    };
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let Some(after_fraction) = strip_digits(fraction) else {
+            return false;
+        };
+        rest = after_fraction;
+    }
+    if let Some(exponent) = rest.strip_prefix(['e', 'E']) {
+        let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        let Some(after_exponent) = strip_digits(exponent) else {
+            return false;
+        };
+        rest = after_exponent;
+    }
+    rest.is_empty()
+}
+
+/// Parses the value of a `default` field option for a field of type `r#type`.
+fn parse_default_value<'a>(
+    r#type: &StructFieldType<'a>,
+    tokens: &[Token<'a>],
+) -> Result<DefaultValue<'a>, ParseError> {
+    let invalid = || ParseError::InvalidDefaultValue {
+        value: tokens
+            .iter()
+            .map(|token| token.str)
+            .collect::<Vec<_>>()
+            .join(" "),
+        pos: tokens[0].pos,
+    };
+
+    let integer_range = match r#type {
+        StructFieldType::Bool => {
+            return match tokens {
+                [Token { str: "true", .. }] => Ok(DefaultValue::Bool(true)),
+                [Token { str: "false", .. }] => Ok(DefaultValue::Bool(false)),
+                _ => Err(invalid()),
+            };
+        }
+        StructFieldType::U8 => (0, u8::MAX.into()),
+        StructFieldType::I8 => (i8::MIN.into(), i8::MAX.into()),
+        StructFieldType::U16 => (0, u16::MAX.into()),
+        StructFieldType::I16 => (i16::MIN.into(), i16::MAX.into()),
+        StructFieldType::U32 => (0, u32::MAX.into()),
+        StructFieldType::I32 => (i32::MIN.into(), i32::MAX.into()),
+        StructFieldType::U64 => (0, u64::MAX.into()),
+        StructFieldType::I64 => (i64::MIN.into(), i64::MAX.into()),
+        StructFieldType::F32 | StructFieldType::F64 => {
+            return match join_adjacent_tokens(tokens) {
+                Some(literal) if is_float_literal(&literal) => Ok(DefaultValue::Float(literal)),
+                _ => Err(invalid()),
+            };
+        }
+        StructFieldType::String => {
+            return match tokens {
+                [token] => string_literal(token.str)
+                    .map(DefaultValue::String)
+                    .ok_or_else(invalid),
+                _ => Err(invalid()),
+            };
+        }
+        StructFieldType::Reference { .. } => {
+            return match tokens {
+                [token] if is_identifier(token.str) => Ok(DefaultValue::Variant(token.str)),
+                _ => Err(invalid()),
+            };
+        }
+        StructFieldType::Bytes { .. }
+        | StructFieldType::Timestamp
+        | StructFieldType::Duration
+        | StructFieldType::Array { .. }
+        | StructFieldType::Map { .. } => {
+            return Err(invalid());
+        }
+    };
 
-    Ok(tokens)
+    let literal = join_adjacent_tokens(tokens).ok_or_else(invalid)?;
+    let digits = literal.strip_prefix('-').unwrap_or(&literal);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let (min, max): (i128, i128) = integer_range;
+    match literal.parse() {
+        Ok(value) if (min..=max).contains(&value) => Ok(DefaultValue::Integer(value)),
+        Ok(_) | Err(_) => Err(invalid()),
+    }
+}
+
+/// Compares two bounds, or returns `None` if either is NaN.
+fn compare_bounds(a: &Bound, b: &Bound) -> Option<Ordering> {
+    let float = |bound: &Bound| match bound {
+        Bound::Integer(value) => *value as f64,
+        Bound::Float(value) => value.parse::<f64>().unwrap_or(f64::NAN),
+    };
+    match (a, b) {
+        (Bound::Integer(a), Bound::Integer(b)) => Some(a.cmp(b)),
+        _ => float(a).partial_cmp(&float(b)),
+    }
+}
+
+/// Returns `true` if the default value of a field satisfies its constraints.
+fn satisfies_constraints(default: &DefaultValue<'_>, constraints: &Constraints<'_>) -> bool {
+    let number = match default {
+        DefaultValue::Integer(value) => Bound::Integer(*value),
+        DefaultValue::Float(value) => Bound::Float(value.clone()),
+        DefaultValue::String(value) => {
+            let len = value.chars().count();
+            return constraints
+                .min_len
+                .is_none_or(|min_len| len >= min_len as usize)
+                && constraints
+                    .max_len
+                    .is_none_or(|max_len| len <= max_len as usize)
+                && constraints
+                    .len
+                    .is_none_or(|exact_len| len == exact_len as usize)
+                && constraints.pattern.as_deref().is_none_or(|pattern| {
+                    regex::Regex::new(&rust_regex(pattern)).is_ok_and(|regex| regex.is_match(value))
+                });
+        }
+        DefaultValue::Bool(_) | DefaultValue::Variant(_) => return true,
+    };
+    constraints.min.as_ref().is_none_or(|min| {
+        compare_bounds(&number, min).is_some_and(|ordering| ordering != Ordering::Less)
+    }) && constraints.max.as_ref().is_none_or(|max| {
+        compare_bounds(&number, max).is_some_and(|ordering| ordering != Ordering::Greater)
+    })
+}
+
+/// Returns `true` if `pattern` only uses regular expression syntax which means the same to the
+/// Rust `regex` crate, once [`rust_regex`] has translated it, and to JavaScript with the `u` flag.
+///
+/// This rejects inline flags like `(?i)`, escapes like `\A` and `\p{L}`, nested classes and set
+/// operations in classes, and repeated quantifiers like `a**`, which only Rust accepts or which
+/// mean something else in JavaScript. Other syntax errors are left to the `regex` crate.
+fn is_portable_pattern(pattern: &str) -> bool {
+    let mut chars = pattern.chars().peekable();
+    let mut in_class = false;
+    // whether the previous token can be followed by a quantifier
+    let mut can_repeat = false;
+    while let Some(c) = chars.next() {
+        if in_class {
+            match c {
+                ']' => {
+                    in_class = false;
+                    can_repeat = true;
+                }
+                '[' => return false,
+                '&' | '-' | '~' if chars.peek() == Some(&c) => return false,
+                '\\' => match chars.next() {
+                    Some('b' | 'B') | None => return false,
+                    Some('-') => {}
+                    Some(escaped) => {
+                        if !is_portable_escape(escaped, &mut chars) {
+                            return false;
+                        }
+                    }
+                },
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '\\' => {
+                let Some(escaped) = chars.next() else {
+                    return false;
+                };
+                // `\b{start}` and the like are word boundaries in Rust
+                if matches!(escaped, 'b' | 'B') && chars.peek() != Some(&'{') {
+                    can_repeat = false;
+                    continue;
+                }
+                if !is_portable_escape(escaped, &mut chars) {
+                    return false;
+                }
+                can_repeat = true;
+            }
+            '[' => {
+                in_class = true;
+                chars.next_if_eq(&'^');
+                // `[]` is an empty class in JavaScript, but `]` is a literal at the start in Rust
+                if chars.peek() == Some(&']') {
+                    return false;
+                }
+            }
+            '(' => {
+                if chars.next_if_eq(&'?').is_some() {
+                    match chars.next() {
+                        Some(':') => {}
+                        Some('<') => {
+                            if chars
+                                .next_if(|c| c.is_ascii_alphabetic() || *c == '_')
+                                .is_none()
+                            {
+                                return false;
+                            }
+                            while chars
+                                .next_if(|c| c.is_ascii_alphanumeric() || *c == '_')
+                                .is_some()
+                            {}
+                            if chars.next() != Some('>') {
+                                return false;
+                            }
+                        }
+                        _ => return false,
+                    }
+                }
+                can_repeat = false;
+            }
+            '*' | '+' | '?' | '{' => {
+                if !can_repeat {
+                    return false;
+                }
+                if c == '{' {
+                    let mut digits = 0;
+                    while chars.next_if(char::is_ascii_digit).is_some() {
+                        digits += 1;
+                    }
+                    if chars.next_if_eq(&',').is_some() {
+                        while chars.next_if(char::is_ascii_digit).is_some() {}
+                    }
+                    if digits == 0 || chars.next() != Some('}') {
+                        return false;
+                    }
+                }
+                // a quantifier can only be made lazy, not repeated
+                chars.next_if_eq(&'?');
+                can_repeat = false;
+            }
+            '|' | '^' | '$' => can_repeat = false,
+            // JavaScript requires these to be escaped with the `u` flag
+            ']' | '}' => return false,
+            _ => can_repeat = true,
+        }
+    }
+    !in_class
+}
+
+/// Returns `true` if the escape `\` `escaped` in a pattern means the same in Rust and JavaScript,
+/// consuming the hexadecimal digits of `\x` and `\u` escapes from `chars`.
+fn is_portable_escape(escaped: char, chars: &mut Peekable<Chars<'_>>) -> bool {
+    fn hex_digits(chars: &mut Peekable<Chars<'_>>, count: usize) -> bool {
+        (0..count).all(|_| chars.next_if(char::is_ascii_hexdigit).is_some())
+    }
+
+    match escaped {
+        'd' | 'D' | 'w' | 'W' | 's' | 'S' | 't' | 'n' | 'r' | 'f' | 'v' => true,
+        'x' => hex_digits(chars, 2),
+        'u' => {
+            if chars.next_if_eq(&'{').is_some() {
+                while chars.next_if(char::is_ascii_hexdigit).is_some() {}
+                chars.next() == Some('}')
+            } else {
+                hex_digits(chars, 4)
+            }
+        }
+        _ => "^$\\.*+?()[]{}|/".contains(escaped),
+    }
+}
+
+/// Parses the value of a constraint field option for a field of type `r#type` into
+/// `constraints`. The constraint cannot conflict with the ones parsed before it or with the
+/// default value of the field.
+fn parse_constraint<'a>(
+    constraints: &mut Constraints<'a>,
+    r#type: &StructFieldType<'a>,
+    default: Option<&DefaultValue<'a>>,
+    name: &Token<'a>,
+    tokens: &[Token<'a>],
+) -> Result<(), ParseError> {
+    let invalid = || ParseError::InvalidConstraint {
+        name: name.str.into(),
+        value: tokens
+            .iter()
+            .map(|token| token.str)
+            .collect::<Vec<_>>()
+            .join(" "),
+        pos: tokens[0].pos,
+    };
+    let unsupported = || ParseError::UnsupportedConstraint {
+        name: name.str.into(),
+        r#type: r#type.to_string(),
+        pos: name.pos,
+    };
+
+    match name.str {
+        "min" | "max" => {
+            if !matches!(
+                r#type,
+                StructFieldType::U8
+                    | StructFieldType::I8
+                    | StructFieldType::U16
+                    | StructFieldType::I16
+                    | StructFieldType::U32
+                    | StructFieldType::I32
+                    | StructFieldType::U64
+                    | StructFieldType::I64
+                    | StructFieldType::F32
+                    | StructFieldType::F64
+            ) {
+                return Err(unsupported());
+            }
+            let bound = match parse_default_value(r#type, tokens) {
+                Ok(DefaultValue::Integer(value)) => Bound::Integer(value),
+                Ok(DefaultValue::Float(value)) => Bound::Float(value),
+                _ => return Err(invalid()),
+            };
+            if name.str == "min" {
+                constraints.min = Some(bound);
+            } else {
+                constraints.max = Some(bound);
+            }
+            if let (Some(min), Some(max)) = (&constraints.min, &constraints.max)
+                && matches!(compare_bounds(min, max), Some(Ordering::Greater) | None)
+            {
+                return Err(invalid());
+            }
+        }
+        "min_len" | "max_len" | "len" => {
+            if !matches!(
+                r#type,
+                StructFieldType::String
+                    | StructFieldType::Bytes { len: None }
+                    | StructFieldType::Array { .. }
+                    | StructFieldType::Map { .. }
+            ) {
+                return Err(unsupported());
+            }
+            let len = match tokens {
+                [token] if token.str == "0" || !token.str.starts_with('0') => {
+                    token.str.parse::<u32>().ok()
+                }
+                _ => None,
+            }
+            .ok_or_else(invalid)?;
+            match name.str {
+                "min_len" => constraints.min_len = Some(len),
+                "max_len" => constraints.max_len = Some(len),
+                _ => constraints.len = Some(len),
+            }
+            let min_len = constraints.min_len.unwrap_or(0);
+            let max_len = constraints.max_len.unwrap_or(u32::MAX);
+            if min_len > max_len
+                || constraints
+                    .len
+                    .is_some_and(|len| len < min_len || len > max_len)
+            {
+                return Err(invalid());
+            }
+        }
+        "pattern" => {
+            if *r#type != StructFieldType::String {
+                return Err(unsupported());
+            }
+            let pattern = match tokens {
+                [token] => string_literal(token.str),
+                _ => None,
+            }
+            // ranges cannot end in a class like `\d`, which is only checked before translating it
+            .filter(|pattern| {
+                is_portable_pattern(pattern)
+                    && regex::Regex::new(pattern).is_ok()
+                    && regex::Regex::new(&rust_regex(pattern)).is_ok()
+            })
+            .ok_or_else(invalid)?;
+            constraints.pattern = Some(pattern);
+        }
+        _ => unreachable!("unknown field options are rejected before parsing constraints"),
+    }
+
+    if default.is_some_and(|default| !satisfies_constraints(default, constraints)) {
+        return Err(invalid());
+    }
+    let start = if constraints.span.is_empty() {
+        name.pos.offset
+    } else {
+        constraints.span.start
+    };
+    constraints.span = start..name.span_to(tokens.last().unwrap_or(name)).end;
+    Ok(())
 }
 
 fn parse_struct_field<'a>(
     tokens: &mut &[Token<'a>],
-    field_ids: &HashSet<u8>,
+    field_ids: &HashSet<u16>,
     field_names: &HashSet<&str>,
+    reserved: &Reserved<'_>,
+    preserve_unknown: bool,
+    references: &mut Vec<Reference<'a>>,
 ) -> Result<StructField<'a>, ParseError> {
+    let doc = parse_doc_comment(tokens);
     let mut optional = false;
     let mut nullable = false;
 
+    if tokens.is_empty() {
+        return Err(ParseError::UnexpectedEnd {
+            expected: "`optional`, `nullable` or a struct field type",
+        });
+    }
+    let start = tokens[0];
+    let annotations = parse_annotations(tokens)?;
+    let deprecated = parse_deprecated(tokens)?;
     if tokens.is_empty() {
         return Err(ParseError::UnexpectedEnd {
             expected: "`optional`, `nullable` or a struct field type",
@@ -159,48 +1200,12 @@ fn parse_struct_field<'a>(
         });
     }
 
-    let mut array_depth = 0;
-    let mut open_array_bracket = false;
+    let r#type = parse_struct_field_type(type_name, tokens, references, false)?;
 
-    let name = loop {
-        match tokens.split_off_first() {
-            Some(Token { str: "[", pos }) => {
-                if open_array_bracket {
-                    return Err(ParseError::UnexpectedToken {
-                        unexpected: "[".into(),
-                        expected: "`]`",
-                        pos: *pos,
-                    });
-                }
-                array_depth += 1;
-                open_array_bracket = true;
-            }
-            Some(Token { str: "]", pos }) => {
-                if !open_array_bracket {
-                    return Err(ParseError::UnexpectedToken {
-                        unexpected: "]".into(),
-                        expected: "`[` or a struct field name",
-                        pos: *pos,
-                    });
-                }
-                open_array_bracket = false;
-            }
-            Some(field_name) => {
-                if open_array_bracket {
-                    return Err(ParseError::UnexpectedToken {
-                        unexpected: field_name.str.into(),
-                        expected: "`]`",
-                        pos: field_name.pos,
-                    });
-                }
-                break field_name;
-            }
-            None => {
-                return Err(ParseError::UnexpectedEnd {
-                    expected: "`[` or a struct field name",
-                });
-            }
-        }
+    let Some(name) = tokens.split_off_first() else {
+        return Err(ParseError::UnexpectedEnd {
+            expected: "`[` or a struct field name",
+        });
     };
     if !name
         .str
@@ -218,6 +1223,18 @@ fn parse_struct_field<'a>(
             pos: name.pos,
         });
     }
+    if reserved.contains_name(name.str) {
+        return Err(ParseError::ReservedName {
+            name: name.str.into(),
+            pos: name.pos,
+        });
+    }
+    if preserve_unknown && name.str == "unknown_fields" {
+        return Err(ParseError::ReservedStructFieldName {
+            name: name.str.into(),
+            pos: name.pos,
+        });
+    }
 
     match tokens.split_off_first() {
         Some(Token { str: "=", .. }) => {}
@@ -235,13 +1252,13 @@ fn parse_struct_field<'a>(
 
     let Some(id) = tokens.split_off_first() else {
         return Err(ParseError::UnexpectedEnd {
-            expected: "an integer between 0 and 127 inclusive",
+            expected: "an integer between 0 and 65535 inclusive",
         });
     };
     if !id.str.chars().all(|c| c.is_ascii_digit()) {
         return Err(ParseError::UnexpectedToken {
             unexpected: id.str.into(),
-            expected: "an integer between 0 and 127 inclusive",
+            expected: "an integer between 0 and 65535 inclusive",
             pos: id.pos,
         });
     }
@@ -253,8 +1270,8 @@ fn parse_struct_field<'a>(
     }
     let id_pos = id.pos;
     let id = match id.str.parse() {
-        Ok(id @ ..128) => id,
-        Ok(_) | Err(_) => {
+        Ok(id) => id,
+        Err(_) => {
             return Err(ParseError::InvalidStructFieldId {
                 id: id.str.into(),
                 pos: id.pos,
@@ -264,170 +1281,172 @@ fn parse_struct_field<'a>(
     if field_ids.contains(&id) {
         return Err(ParseError::DuplicateStructFieldId { id, pos: id_pos });
     }
+    if reserved.contains_id(id) {
+        return Err(ParseError::ReservedId { id, pos: id_pos });
+    }
 
     if tokens.is_empty() {
         return Err(ParseError::UnexpectedEnd { expected: ";" });
     }
 
-    match tokens.split_off_first() {
-        Some(Token { str: ";", .. }) => {}
+    let has_options = tokens[0].str == "[";
+    let mut default = None;
+    let mut constraints = Constraints::default();
+    if has_options {
+        let options = parse_field_options(tokens)?;
+        for (option_name, value_tokens) in &options {
+            match option_name.str {
+                "default" => {
+                    if !optional {
+                        return Err(ParseError::DefaultValueOnRequiredField {
+                            pos: option_name.pos,
+                        });
+                    }
+                    let value = parse_default_value(&r#type, value_tokens)?;
+                    if let DefaultValue::Variant(_) = value {
+                        // the field type is a single reference, which was pushed last
+                        if let Some(reference) = references.last_mut() {
+                            reference.default_variant = Some(value_tokens[0]);
+                        }
+                    }
+                    default = Some(value);
+                }
+                "min" | "max" | "min_len" | "max_len" | "len" | "pattern" => {}
+                _ => {
+                    return Err(ParseError::UnknownFieldOption {
+                        name: option_name.str.into(),
+                        pos: option_name.pos,
+                    });
+                }
+            }
+        }
+        // constraints are parsed after the default value, which has to satisfy them
+        for (option_name, value_tokens) in &options {
+            if option_name.str != "default" {
+                parse_constraint(
+                    &mut constraints,
+                    &r#type,
+                    default.as_ref(),
+                    option_name,
+                    value_tokens,
+                )?;
+            }
+        }
+    }
+
+    let span = match tokens.split_off_first() {
+        Some(end @ Token { str: ";", .. }) => start.span_to(end),
         Some(token) => {
             return Err(ParseError::UnexpectedToken {
                 unexpected: token.str.into(),
-                expected: "`;`",
+                expected: if has_options { "`;`" } else { "`[` or `;`" },
                 pos: token.pos,
             });
         }
         None => {
             return Err(ParseError::UnexpectedEnd { expected: "`;`" });
         }
-    }
-
-    let bytes_type = {
-        if type_name.str == "bytes" {
-            Some(StructFieldType::Bytes { len: None })
-        } else if type_name.str == "bytes0" {
-            Some(StructFieldType::Bytes { len: Some(0) })
-        } else if let Some(len_str) = type_name.str.strip_prefix("bytes") {
-            match len_str.chars().next() {
-                Some('1'..='9') => match len_str.parse() {
-                    Ok(len) => Some(StructFieldType::Bytes { len: Some(len) }),
-                    Err(_) => None,
-                },
-                _ => None,
-            }
-        } else {
-            None
-        }
     };
-    let mut r#type = if let Some(bytes_type) = bytes_type {
-        bytes_type
-    } else {
-        match type_name.str {
-            "bool" => StructFieldType::Bool,
-            "uint8" => StructFieldType::U8,
-            "int8" => StructFieldType::I8,
-            "uint16" => StructFieldType::U16,
-            "int16" => StructFieldType::I16,
-            "uint32" => StructFieldType::U32,
-            "int32" => StructFieldType::I32,
-            "uint64" => StructFieldType::U64,
-            "int64" => StructFieldType::I64,
-            "float32" => StructFieldType::F32,
-            "float64" => StructFieldType::F64,
-            "string" => StructFieldType::String,
-            name => StructFieldType::Reference { name },
-        }
-    };
-
-    for _ in 0..array_depth {
-        r#type = StructFieldType::Array {
-            items: r#type.into(),
-        };
-    }
+
     Ok(StructField {
         id,
         name: name.str,
         r#type,
         optional,
         nullable,
+        default,
+        constraints,
+        deprecated,
+        annotations,
+        doc,
+        span,
     })
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-enum EnumVariant<'a> {
-    Tagged(TaggedEnumVariant<'a>),
-    Untagged(UntaggedEnumVariant<'a>),
-}
-
+/// Parses an enum variant, which has a type unless it is a unit variant. An enum is tagged if any
+/// of its variants has a type.
 fn parse_enum_variant<'a>(
     tokens: &mut &[Token<'a>],
-    variant_ids: &HashSet<u8>,
+    references: &mut Vec<Reference<'a>>,
+    variant_ids: &HashSet<u16>,
     variant_names: &HashSet<&str>,
-    is_tagged: Option<bool>,
-) -> Result<EnumVariant<'a>, ParseError> {
+    reserved: &Reserved<'_>,
+    open: bool,
+    repr: Option<EnumRepr>,
+) -> Result<TaggedEnumVariant<'a>, ParseError> {
+    let doc = parse_doc_comment(tokens);
+    let Some(&start) = tokens.first() else {
+        return Err(ParseError::UnexpectedEnd {
+            expected: "a type or an enum variant name",
+        });
+    };
+    let annotations = parse_annotations(tokens)?;
+    // a unit variant can be named `deprecated`
+    let deprecated = if tokens.get(1).is_some_and(|token| token.str == "=") {
+        None
+    } else {
+        parse_deprecated(tokens)?
+    };
     let Some(type_or_name) = tokens.split_off_first() else {
         return Err(ParseError::UnexpectedEnd {
-            expected: match is_tagged {
-                Some(true) => "a struct name",
-                Some(false) => "an enum variant name",
-                None => "a struct name or an enum variant name",
-            },
+            expected: "a type or an enum variant name",
         });
     };
-
-    if !type_or_name
-        .str
-        .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
-    {
+    if !is_identifier(type_or_name.str) {
         return Err(ParseError::UnexpectedToken {
             unexpected: type_or_name.str.into(),
-            expected: match is_tagged {
-                Some(true) => "a struct name",
-                Some(false) => "an enum variant name",
-                None => "a struct name or an enum variant name",
-            },
+            expected: "a type or an enum variant name",
             pos: type_or_name.pos,
         });
     }
 
-    let (r#type, name) = match tokens.split_off_first() {
-        Some(Token { str: "=", pos }) => {
-            if is_tagged == Some(true) {
-                return Err(ParseError::UnexpectedToken {
-                    unexpected: "=".into(),
-                    expected: "an enum variant name",
-                    pos: *pos,
-                });
-            }
-            (None, type_or_name)
-        }
-        Some(token) => {
-            if !token
-                .str
-                .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
-            {
-                return Err(ParseError::UnexpectedToken {
-                    unexpected: token.str.into(),
-                    expected: match is_tagged {
-                        Some(true) => "an enum variant name",
-                        Some(false) => "`=`",
-                        None => "an enum variant name or `=`",
-                    },
-                    pos: token.pos,
-                });
-            }
-            (Some(type_or_name), token)
-        }
-        None => {
+    let (r#type, name) = if tokens.first().is_some_and(|token| token.str == "=") {
+        (None, type_or_name)
+    } else {
+        let r#type = parse_struct_field_type(type_or_name, tokens, references, false)?;
+        let Some(name) = tokens.split_off_first() else {
             return Err(ParseError::UnexpectedEnd {
-                expected: match is_tagged {
-                    Some(true) => "an enum variant name",
-                    Some(false) => "`=`",
-                    None => "an enum variant name or `=`",
-                },
+                expected: "an enum variant name",
+            });
+        };
+        if !is_identifier(name.str) {
+            return Err(ParseError::UnexpectedToken {
+                unexpected: name.str.into(),
+                expected: "an enum variant name",
+                pos: name.pos,
             });
         }
+        (Some(r#type), name)
     };
 
-    if r#type.is_some() {
-        match tokens.split_off_first() {
-            Some(Token { str: "=", .. }) => {}
-            Some(token) => {
-                return Err(ParseError::UnexpectedToken {
-                    unexpected: token.str.into(),
-                    expected: "`=`",
-                    pos: token.pos,
-                });
-            }
-            None => {
-                return Err(ParseError::UnexpectedEnd { expected: "`=`" });
-            }
+    match tokens.split_off_first() {
+        Some(Token { str: "=", .. }) => {}
+        Some(token) => {
+            return Err(ParseError::UnexpectedToken {
+                unexpected: token.str.into(),
+                expected: "`=`",
+                pos: token.pos,
+            });
         }
+        None => {
+            return Err(ParseError::UnexpectedEnd { expected: "`=`" });
+        }
+    }
+
+    if variant_names.contains(&name.str) {
+        return Err(ParseError::DuplicateEnumVariantName {
+            name: name.str.into(),
+            pos: name.pos,
+        });
+    }
+    if reserved.contains_name(name.str) {
+        return Err(ParseError::ReservedName {
+            name: name.str.into(),
+            pos: name.pos,
+        });
     }
-
-    if variant_names.contains(&name.str) {
-        return Err(ParseError::DuplicateEnumVariantName {
+    if open && name.str == "Unknown" {
+        return Err(ParseError::ReservedEnumVariantName {
             name: name.str.into(),
             pos: name.pos,
         });
@@ -435,13 +1454,13 @@ fn parse_enum_variant<'a>(
 
     let Some(id) = tokens.split_off_first() else {
         return Err(ParseError::UnexpectedEnd {
-            expected: "an integer between 0 and 127 inclusive",
+            expected: "an integer between 0 and 65535 inclusive",
         });
     };
     if !id.str.chars().all(|c| c.is_ascii_digit()) {
         return Err(ParseError::UnexpectedToken {
             unexpected: id.str.into(),
-            expected: "an integer between 0 and 127 inclusive",
+            expected: "an integer between 0 and 65535 inclusive",
             pos: id.pos,
         });
     }
@@ -452,8 +1471,8 @@ fn parse_enum_variant<'a>(
         });
     }
     let id_pos = id.pos;
-    let id = match id.str.parse() {
-        Ok(id @ ..128) => id,
+    let id = match id.str.parse::<u16>() {
+        Ok(id) if repr != Some(EnumRepr::U8) || id <= u8::MAX.into() => id,
         Ok(_) | Err(_) => {
             return Err(ParseError::InvalidEnumVariantId {
                 id: id.str.into(),
@@ -464,9 +1483,12 @@ fn parse_enum_variant<'a>(
     if variant_ids.contains(&id) {
         return Err(ParseError::DuplicateEnumVariantId { id, pos: id_pos });
     }
+    if reserved.contains_id(id) {
+        return Err(ParseError::ReservedId { id, pos: id_pos });
+    }
 
-    match tokens.split_off_first() {
-        Some(Token { str: ";", .. }) => {}
+    let span = match tokens.split_off_first() {
+        Some(end @ Token { str: ";", .. }) => start.span_to(end),
         Some(token) => {
             return Err(ParseError::UnexpectedToken {
                 unexpected: token.str.into(),
@@ -477,22 +1499,37 @@ fn parse_enum_variant<'a>(
         None => {
             return Err(ParseError::UnexpectedEnd { expected: "`;`" });
         }
-    }
+    };
 
-    Ok(match r#type {
-        Some(r#type) => EnumVariant::Tagged(TaggedEnumVariant {
-            id,
-            name: name.str,
-            r#type: r#type.str,
-        }),
-        None => EnumVariant::Untagged(UntaggedEnumVariant { id, name: name.str }),
+    Ok(TaggedEnumVariant {
+        id,
+        name: name.str,
+        r#type,
+        deprecated,
+        annotations,
+        doc,
+        span,
     })
 }
 
 fn parse_enum<'a>(
     tokens: &mut &[Token<'a>],
+    prefix: TypePrefix<'a>,
     type_names: &HashSet<&str>,
+    references: &mut Vec<Reference<'a>>,
+    errors: &mut Vec<ParseError>,
 ) -> Result<Enum<'a>, ParseError> {
+    let TypePrefix {
+        start,
+        doc,
+        annotations,
+        deprecated,
+    } = prefix;
+    let open = tokens.first().is_some_and(|token| token.str == "open");
+    if open {
+        tokens.split_off_first();
+    }
+
     match tokens.split_off_first() {
         Some(Token { str: "enum", .. }) => {}
         Some(token) => {
@@ -529,52 +1566,91 @@ fn parse_enum<'a>(
         });
     }
 
+    let mut repr = None;
+    if tokens.first().is_some_and(|token| token.str == ":") {
+        tokens.split_off_first();
+        repr = match tokens.split_off_first() {
+            Some(Token { str: "uint8", .. }) => Some(EnumRepr::U8),
+            Some(Token { str: "uint16", .. }) => Some(EnumRepr::U16),
+            Some(token) => {
+                return Err(ParseError::UnexpectedToken {
+                    unexpected: token.str.into(),
+                    expected: "`uint8` or `uint16`",
+                    pos: token.pos,
+                });
+            }
+            None => {
+                return Err(ParseError::UnexpectedEnd {
+                    expected: "`uint8` or `uint16`",
+                });
+            }
+        };
+    }
+
     match tokens.split_off_first() {
         Some(Token { str: "{", .. }) => {}
         Some(token) => {
             return Err(ParseError::UnexpectedToken {
                 unexpected: token.str.into(),
-                expected: "`{`",
+                expected: if repr.is_some() { "`{`" } else { "`:` or `{`" },
                 pos: token.pos,
             });
         }
         None => {
-            return Err(ParseError::UnexpectedEnd { expected: "`{`" });
+            return Err(ParseError::UnexpectedEnd {
+                expected: if repr.is_some() { "`{`" } else { "`:` or `{`" },
+            });
         }
     }
 
     let mut variants = Vec::new();
     let mut variant_ids = HashSet::new();
     let mut variant_names = HashSet::new();
-    let mut is_tagged = None;
+    let errors_before = errors.len();
+    let reserved = scan_reserved(tokens);
 
     while !tokens.is_empty() && tokens[0].str != "}" {
-        let pos = tokens[0].pos;
-        let variant = parse_enum_variant(tokens, &variant_ids, &variant_names, is_tagged)?;
-        if is_tagged.is_none() {
-            is_tagged = Some(matches!(variant, EnumVariant::Tagged(_)));
-        } else if is_tagged != Some(matches!(variant, EnumVariant::Tagged(_))) {
-            return Err(ParseError::MixedTaggedAndUntaggedEnumVariants { pos });
-        }
-        match variant {
-            EnumVariant::Tagged(TaggedEnumVariant { id, name, .. })
-            | EnumVariant::Untagged(UntaggedEnumVariant { id, name }) => {
-                variant_ids.insert(id);
-                variant_names.insert(name);
+        let before = *tokens;
+        if is_reserved_declaration(tokens) {
+            // the IDs and names have been collected already, this only reports errors
+            if let Err(error) = parse_reserved(tokens, &mut Reserved::default()) {
+                errors.push(error);
+                skip_member(tokens, before);
             }
+            continue;
         }
+        let variant = match parse_enum_variant(
+            tokens,
+            references,
+            &variant_ids,
+            &variant_names,
+            &reserved,
+            open,
+            repr,
+        ) {
+            Ok(variant) => variant,
+            Err(error) => {
+                errors.push(error);
+                skip_member(tokens, before);
+                continue;
+            }
+        };
+        variant_ids.insert(variant.id);
+        variant_names.insert(variant.name);
         variants.push(variant);
     }
 
-    match tokens.split_off_first() {
-        Some(Token { str: "}", pos }) => {
-            if variants.is_empty() {
+    let span = match tokens.split_off_first() {
+        Some(end @ Token { str: "}", pos }) => {
+            // an enum whose variants all failed to parse has already been reported
+            if variants.is_empty() && errors.len() == errors_before {
                 return Err(ParseError::UnexpectedToken {
                     unexpected: '}'.into(),
                     expected: "an enum variant",
                     pos: *pos,
                 });
             }
+            start.span_to(end)
         }
         Some(token) => {
             return Err(ParseError::UnexpectedToken {
@@ -592,36 +1668,76 @@ fn parse_enum<'a>(
                 expected: "an enum variant or `}`",
             });
         }
-    }
+    };
 
-    Ok(match variants[0] {
-        EnumVariant::Tagged(_) => Enum::Tagged {
-            name: name.str,
-            variants: variants
-                .into_iter()
-                .map(|variant| match variant {
-                    EnumVariant::Tagged(variant) => variant,
-                    EnumVariant::Untagged(_) => unreachable!(),
-                })
-                .collect(),
+    let repr = repr.unwrap_or(
+        if variants.iter().all(|variant| variant.id <= u8::MAX.into()) {
+            EnumRepr::U8
+        } else {
+            EnumRepr::U16
         },
-        EnumVariant::Untagged(_) => Enum::Untagged {
+    );
+
+    // an enum is untagged unless any of its variants has a type
+    Ok(if variants.iter().any(|variant| variant.r#type.is_some()) {
+        Enum::Tagged {
             name: name.str,
+            open,
+            repr,
+            variants,
+            reserved,
+            deprecated,
+            annotations,
+            doc,
+            span,
+            // set once the referenced types are known
+            contains_floats: false,
+        }
+    } else {
+        Enum::Untagged {
+            name: name.str,
+            open,
+            repr,
             variants: variants
                 .into_iter()
-                .map(|variant| match variant {
-                    EnumVariant::Tagged(_) => unreachable!(),
-                    EnumVariant::Untagged(variant) => variant,
+                .map(|variant| UntaggedEnumVariant {
+                    id: variant.id,
+                    name: variant.name,
+                    deprecated: variant.deprecated,
+                    annotations: variant.annotations,
+                    doc: variant.doc,
+                    span: variant.span,
                 })
                 .collect(),
-        },
+            reserved,
+            deprecated,
+            annotations,
+            doc,
+            span,
+        }
     })
 }
 
 fn parse_struct<'a>(
     tokens: &mut &[Token<'a>],
+    prefix: TypePrefix<'a>,
     type_names: &HashSet<&str>,
+    references: &mut Vec<Reference<'a>>,
+    errors: &mut Vec<ParseError>,
 ) -> Result<Struct<'a>, ParseError> {
+    let TypePrefix {
+        start,
+        doc,
+        annotations,
+        deprecated,
+    } = prefix;
+    let preserve_unknown = tokens
+        .first()
+        .is_some_and(|token| token.str == "preserve_unknown");
+    if preserve_unknown {
+        tokens.split_off_first();
+    }
+
     match tokens.split_off_first() {
         Some(Token { str: "struct", .. }) => {}
         Some(token) => {
@@ -676,16 +1792,40 @@ fn parse_struct<'a>(
     let mut fields = Vec::new();
     let mut field_ids = HashSet::new();
     let mut field_names = HashSet::new();
+    let reserved = scan_reserved(tokens);
 
     while !tokens.is_empty() && tokens[0].str != "}" {
-        let field = parse_struct_field(tokens, &field_ids, &field_names)?;
+        let before = *tokens;
+        if is_reserved_declaration(tokens) {
+            // the IDs and names have been collected already, this only reports errors
+            if let Err(error) = parse_reserved(tokens, &mut Reserved::default()) {
+                errors.push(error);
+                skip_member(tokens, before);
+            }
+            continue;
+        }
+        let field = match parse_struct_field(
+            tokens,
+            &field_ids,
+            &field_names,
+            &reserved,
+            preserve_unknown,
+            references,
+        ) {
+            Ok(field) => field,
+            Err(error) => {
+                errors.push(error);
+                skip_member(tokens, before);
+                continue;
+            }
+        };
         field_ids.insert(field.id);
         field_names.insert(field.name);
         fields.push(field);
     }
 
-    match tokens.split_off_first() {
-        Some(Token { str: "}", .. }) => {}
+    let span = match tokens.split_off_first() {
+        Some(end @ Token { str: "}", .. }) => start.span_to(end),
         Some(token) => {
             return Err(ParseError::UnexpectedToken {
                 unexpected: token.str.into(),
@@ -698,27 +1838,198 @@ fn parse_struct<'a>(
                 expected: "a struct field or `}`",
             });
         }
-    }
+    };
 
     Ok(Struct {
         name: name.str,
         fields,
+        preserve_unknown,
+        reserved,
+        deprecated,
+        annotations,
+        doc,
+        span,
+        // set once the referenced types are known
+        contains_floats: false,
     })
 }
 
+/// Parses a `reserved` declaration of IDs, inclusive ID ranges and names, e.g.
+/// `reserved 3, 7..10, "old_name";`, and adds them to `reserved`.
+fn parse_reserved<'a>(
+    tokens: &mut &[Token<'a>],
+    reserved: &mut Reserved<'a>,
+) -> Result<(), ParseError> {
+    // `reserved` was checked by the caller
+    let Some(keyword) = tokens.split_off_first() else {
+        return Ok(());
+    };
+
+    loop {
+        let Some(token) = tokens.split_off_first() else {
+            return Err(ParseError::UnexpectedEnd {
+                expected: "an ID, an ID range or a string literal",
+            });
+        };
+        if let Some(name) = string_literal(token.str) {
+            reserved.names.push(name);
+        } else if token.str.starts_with(|c: char| c.is_ascii_digit()) {
+            let start = parse_reserved_id(token)?;
+            let end = match *tokens {
+                [Token { str: ".", .. }, Token { str: ".", .. }, end, ..] => {
+                    *tokens = &tokens[3..];
+                    let end_id = parse_reserved_id(end)?;
+                    if end_id < start {
+                        return Err(ParseError::InvalidReservedRange {
+                            range: format!("{}..{}", token.str, end.str),
+                            pos: token.pos,
+                        });
+                    }
+                    end_id
+                }
+                _ => start,
+            };
+            reserved.ids.push(start..=end);
+        } else {
+            return Err(ParseError::UnexpectedToken {
+                unexpected: token.str.into(),
+                expected: "an ID, an ID range or a string literal",
+                pos: token.pos,
+            });
+        }
+
+        match tokens.split_off_first() {
+            Some(Token { str: ",", .. }) => {}
+            Some(end @ Token { str: ";", .. }) => {
+                // the span covers every declaration from the first one
+                let start = if reserved.span.is_empty() {
+                    keyword.pos.offset
+                } else {
+                    reserved.span.start
+                };
+                reserved.span = start..keyword.span_to(end).end;
+                return Ok(());
+            }
+            Some(token) => {
+                return Err(ParseError::UnexpectedToken {
+                    unexpected: token.str.into(),
+                    expected: "`..`, `,` or `;`",
+                    pos: token.pos,
+                });
+            }
+            None => {
+                return Err(ParseError::UnexpectedEnd {
+                    expected: "`..`, `,` or `;`",
+                });
+            }
+        }
+    }
+}
+
+fn parse_reserved_id(token: &Token<'_>) -> Result<u16, ParseError> {
+    if !token.str.chars().all(|c| c.is_ascii_digit()) {
+        return Err(ParseError::UnexpectedToken {
+            unexpected: token.str.into(),
+            expected: "an integer between 0 and 65535 inclusive",
+            pos: token.pos,
+        });
+    }
+    match token.str.parse() {
+        Ok(id) if !token.str.starts_with('0') || token.str == "0" => Ok(id),
+        Ok(_) | Err(_) => Err(ParseError::InvalidReservedRange {
+            range: token.str.into(),
+            pos: token.pos,
+        }),
+    }
+}
+
+/// Returns `true` if `tokens` start with a `reserved` declaration rather than a unit variant
+/// named `reserved`, or a field named `reserved` when scanning a body.
+fn is_reserved_declaration(tokens: &[Token<'_>]) -> bool {
+    tokens.first().is_some_and(|token| token.str == "reserved")
+        && tokens.get(1).is_none_or(|token| token.str != "=")
+}
+
+/// Collects the `reserved` declarations of a `struct` or `enum` body up to its `}`, so that
+/// fields and variants declared before them are checked as well. Errors in the declarations are
+/// ignored here and reported when the body is parsed.
+fn scan_reserved<'a>(tokens: &[Token<'a>]) -> Reserved<'a> {
+    let end = tokens
+        .iter()
+        .position(|token| token.str == "}")
+        .unwrap_or(tokens.len());
+    let mut tokens = &tokens[..end];
+    let mut reserved = Reserved::default();
+    while !tokens.is_empty() {
+        if is_reserved_declaration(tokens) {
+            // on errors, this continues after the token which caused it
+            let _ = parse_reserved(&mut tokens, &mut reserved);
+        } else {
+            tokens.split_off_first();
+        }
+    }
+    reserved
+}
+
+/// Skips the rest of a struct field or enum variant that failed to parse, where `before` is the
+/// remaining input before the failed attempt. This advances `tokens` past the next `;`, or up to
+/// the `}` ending the type if it comes first.
+fn skip_member<'t, 'a>(tokens: &mut &'t [Token<'a>], before: &'t [Token<'a>]) {
+    *tokens = match before
+        .iter()
+        .position(|token| token.str == ";" || token.str == "}")
+    {
+        Some(i) if before[i].str == ";" => &before[i + 1..],
+        Some(i) => &before[i..],
+        None => &[],
+    };
+}
+
+/// Skips the rest of an `import` statement or type that failed to parse, where `before` is the
+/// remaining input before the failed attempt. This advances `tokens` past the next `end` token.
+fn skip_past<'t, 'a>(tokens: &mut &'t [Token<'a>], before: &'t [Token<'a>], end: &str) {
+    *tokens = match before.iter().position(|token| token.str == end) {
+        Some(i) => &before[i + 1..],
+        None => &[],
+    };
+}
+
+/// The parts of a type declaration which precede `open`, `preserve_unknown`, `enum` or `struct`.
+struct TypePrefix<'a> {
+    /// The first token after the doc comment, where the type's span starts.
+    start: Token<'a>,
+    doc: Vec<&'a str>,
+    annotations: Vec<Annotation<'a>>,
+    deprecated: Option<Deprecated<'a>>,
+}
+
 fn parse_type<'a>(
     tokens: &mut &[Token<'a>],
     type_names: &HashSet<&str>,
+    references: &mut Vec<Reference<'a>>,
+    errors: &mut Vec<ParseError>,
 ) -> Result<Type<'a>, ParseError> {
+    let doc = parse_doc_comment(tokens);
+    let Some(&start) = tokens.first() else {
+        return Err(ParseError::UnexpectedEnd {
+            expected: "`enum` or `struct`",
+        });
+    };
+    let prefix = TypePrefix {
+        start,
+        doc,
+        annotations: parse_annotations(tokens)?,
+        deprecated: parse_deprecated(tokens)?,
+    };
     if tokens.is_empty() {
         return Err(ParseError::UnexpectedEnd {
             expected: "`enum` or `struct`",
         });
     }
-    if tokens[0].str == "enum" {
-        parse_enum(tokens, type_names).map(Type::Enum)
-    } else if tokens[0].str == "struct" {
-        parse_struct(tokens, type_names).map(Type::Struct)
+    if tokens[0].str == "enum" || tokens[0].str == "open" {
+        parse_enum(tokens, prefix, type_names, references, errors).map(Type::Enum)
+    } else if tokens[0].str == "struct" || tokens[0].str == "preserve_unknown" {
+        parse_struct(tokens, prefix, type_names, references, errors).map(Type::Struct)
     } else {
         Err(ParseError::UnexpectedToken {
             unexpected: tokens[0].str.into(),
@@ -728,41 +2039,413 @@ fn parse_type<'a>(
     }
 }
 
-/// Parses a typedpack `.tp` file.
-pub fn parse(s: &str) -> Result<Vec<Type<'_>>, ParseError> {
-    let tokens = tokenize(s)?;
+fn parse_import<'a>(tokens: &mut &[Token<'a>]) -> Result<Import<'a>, ParseError> {
+    let pos = match tokens.split_off_first() {
+        Some(Token { str: "import", pos }) => *pos,
+        Some(token) => {
+            return Err(ParseError::UnexpectedToken {
+                unexpected: token.str.into(),
+                expected: "`import`",
+                pos: token.pos,
+            });
+        }
+        None => {
+            return Err(ParseError::UnexpectedEnd {
+                expected: "`import`",
+            });
+        }
+    };
+
+    let names = if tokens.first().is_some_and(|token| token.str == "{") {
+        tokens.split_off_first();
+        let mut names = Vec::new();
+        loop {
+            match tokens.split_off_first() {
+                Some(Token { str: "}", pos }) => {
+                    if names.is_empty() {
+                        return Err(ParseError::UnexpectedToken {
+                            unexpected: "}".into(),
+                            expected: "a type name",
+                            pos: *pos,
+                        });
+                    }
+                    break;
+                }
+                Some(token) => {
+                    if !is_identifier(token.str) {
+                        return Err(ParseError::UnexpectedToken {
+                            unexpected: token.str.into(),
+                            expected: if names.is_empty() {
+                                "a type name"
+                            } else {
+                                "a type name or `}`"
+                            },
+                            pos: token.pos,
+                        });
+                    }
+                    names.push(token.str);
+                }
+                None => {
+                    return Err(ParseError::UnexpectedEnd {
+                        expected: "a type name or `}`",
+                    });
+                }
+            }
+            match tokens.split_off_first() {
+                Some(Token { str: ",", .. }) => {}
+                Some(Token { str: "}", .. }) => break,
+                Some(token) => {
+                    return Err(ParseError::UnexpectedToken {
+                        unexpected: token.str.into(),
+                        expected: "`,` or `}`",
+                        pos: token.pos,
+                    });
+                }
+                None => {
+                    return Err(ParseError::UnexpectedEnd {
+                        expected: "`,` or `}`",
+                    });
+                }
+            }
+        }
+
+        match tokens.split_off_first() {
+            Some(Token { str: "from", .. }) => {}
+            Some(token) => {
+                return Err(ParseError::UnexpectedToken {
+                    unexpected: token.str.into(),
+                    expected: "`from`",
+                    pos: token.pos,
+                });
+            }
+            None => {
+                return Err(ParseError::UnexpectedEnd { expected: "`from`" });
+            }
+        }
+        Some(names)
+    } else {
+        None
+    };
+
+    let path = match tokens.split_off_first() {
+        Some(token) => match string_literal(token.str) {
+            Some(path) => path,
+            None => {
+                return Err(ParseError::UnexpectedToken {
+                    unexpected: token.str.into(),
+                    expected: if names.is_some() {
+                        "a file path string"
+                    } else {
+                        "`{` or a file path string"
+                    },
+                    pos: token.pos,
+                });
+            }
+        },
+        None => {
+            return Err(ParseError::UnexpectedEnd {
+                expected: "a file path string",
+            });
+        }
+    };
+
+    match tokens.split_off_first() {
+        Some(Token { str: ";", .. }) => {}
+        Some(token) => {
+            return Err(ParseError::UnexpectedToken {
+                unexpected: token.str.into(),
+                expected: "`;`",
+                pos: token.pos,
+            });
+        }
+        None => {
+            return Err(ParseError::UnexpectedEnd { expected: "`;`" });
+        }
+    }
+
+    Ok(Import { path, names, pos })
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+}
+
+fn string_literal(s: &str) -> Option<Cow<'_, str>> {
+    let contents = ['"', '\'']
+        .into_iter()
+        .find_map(|quote| s.strip_prefix(quote)?.strip_suffix(quote))?;
+    if !contents.contains('\\') {
+        return Some(Cow::Borrowed(contents));
+    }
+
+    // only quotes and backslashes are escaped, so that patterns like `\d` can be written as is
+    let mut unescaped = String::with_capacity(contents.len());
+    let mut chars = contents.chars();
+    while let Some(char) = chars.next() {
+        match (char, chars.clone().next()) {
+            ('\\', Some(escaped @ ('"' | '\'' | '\\'))) => {
+                unescaped.push(escaped);
+                chars.next();
+            }
+            _ => unescaped.push(char),
+        }
+    }
+    Some(Cow::Owned(unescaped))
+}
+
+/// A parsed typedpack `.tp` file whose `import` statements have not been resolved yet.
+pub(crate) struct File<'a> {
+    pub(crate) imports: Vec<Import<'a>>,
+    pub(crate) types: Vec<Type<'a>>,
+    /// The type names referenced by struct fields.
+    references: Vec<Reference<'a>>,
+}
+
+/// Parses a typedpack `.tp` file without checking any references to other types.
+///
+/// Parsing recovers from errors at `;` and `}` boundaries: every error is added to `errors`, and
+/// the returned file contains the imports and types that could still be parsed.
+pub(crate) fn parse_file<'a>(s: &'a str, errors: &mut Vec<ParseError>) -> File<'a> {
+    let mut file = File {
+        imports: Vec::new(),
+        types: Vec::new(),
+        references: Vec::new(),
+    };
+
+    let errors_before = errors.len();
+    let tokens = tokenize(s, errors);
+    if errors.len() > errors_before {
+        // the tokens around an invalid character or string would only produce more errors
+        return file;
+    }
     let mut tokens = tokens.as_slice();
 
-    let mut types = Vec::new();
+    while tokens.first().is_some_and(|token| token.str == "import") {
+        let before = tokens;
+        match parse_import(&mut tokens) {
+            Ok(import) => file.imports.push(import),
+            Err(error) => {
+                errors.push(error);
+                skip_past(&mut tokens, before, ";");
+            }
+        }
+    }
+
     let mut type_names = HashSet::new();
-    let mut struct_names = HashSet::new();
 
     while !tokens.is_empty() {
-        let r#type = parse_type(&mut tokens, &type_names)?;
-        match &r#type {
-            Type::Enum(Enum::Tagged { name, .. }) | Type::Enum(Enum::Untagged { name, .. }) => {
-                type_names.insert(name);
+        let before = tokens;
+        match parse_type(&mut tokens, &type_names, &mut file.references, errors) {
+            Ok(r#type) => {
+                type_names.insert(r#type.name());
+                file.types.push(r#type);
             }
-            Type::Struct(r#struct) => {
-                type_names.insert(r#struct.name);
-                struct_names.insert(r#struct.name);
+            Err(error) => {
+                errors.push(error);
+                skip_past(&mut tokens, before, "}");
             }
         }
-        types.push(r#type);
     }
 
-    // check that each tagged enum variant's type is present
-    for r#type in &types {
-        if let Type::Enum(Enum::Tagged { variants, .. }) = r#type {
-            for variant in variants {
-                if !struct_names.contains(variant.r#type) {
-                    return Err(ParseError::UnknownTaggedEnumVariantType(
-                        variant.r#type.to_owned(),
-                    ));
+    file
+}
+
+/// Returns the name in `candidates` that is most similar to `name`, if any is similar enough to be
+/// a likely typo.
+fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    // optimal string alignment distance (Levenshtein distance with transpositions), ignoring case
+    let distance = |a: &str, b: &str| {
+        let a = a.to_ascii_lowercase().into_bytes();
+        let b = b.to_ascii_lowercase().into_bytes();
+        let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for (i, row) in d.iter_mut().enumerate() {
+            row[0] = i;
+        }
+        d[0] = (0..=b.len()).collect();
+        for i in 1..=a.len() {
+            for j in 1..=b.len() {
+                let cost = usize::from(a[i - 1] != b[j - 1]);
+                d[i][j] = (d[i - 1][j] + 1)
+                    .min(d[i][j - 1] + 1)
+                    .min(d[i - 1][j - 1] + cost);
+                if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                    d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+                }
+            }
+        }
+        d[a.len()][b.len()]
+    };
+
+    let max_distance = (name.len() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+impl File<'_> {
+    /// Checks that every type referenced by a struct field or tagged enum variant is one of
+    /// `types`, which maps each type name visible in the file to the type.
+    pub(crate) fn check_references(
+        &self,
+        types: &HashMap<&str, &Type<'_>>,
+        errors: &mut Vec<ParseError>,
+    ) {
+        for reference in &self.references {
+            match types.get(reference.token.str) {
+                None => {
+                    let mut candidates: Vec<_> = types.keys().copied().collect();
+                    candidates.sort_unstable();
+                    errors.push(ParseError::UnknownType {
+                        name: reference.token.str.into(),
+                        suggestion: suggest(reference.token.str, candidates).map(String::from),
+                        pos: reference.token.pos,
+                    });
+                }
+                Some(Type::Enum(Enum::Untagged { variants, .. })) => {
+                    if let Some(variant) = reference.default_variant
+                        && !variants.iter().any(|other| other.name == variant.str)
+                    {
+                        errors.push(ParseError::InvalidDefaultValue {
+                            value: variant.str.into(),
+                            pos: variant.pos,
+                        });
+                    }
                 }
+                Some(_) if reference.is_map_key => {
+                    errors.push(ParseError::InvalidMapKeyType {
+                        pos: reference.token.pos,
+                    });
+                }
+                Some(_) => {
+                    if let Some(variant) = reference.default_variant {
+                        errors.push(ParseError::InvalidDefaultValue {
+                            value: variant.str.into(),
+                            pos: variant.pos,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns `true` if a struct field type contains `float32` or `float64`, directly or through the
+/// types it references, according to `is_float_type`.
+fn field_type_contains_floats(
+    r#type: &StructFieldType<'_>,
+    is_float_type: &impl Fn(&str) -> bool,
+) -> bool {
+    match r#type {
+        StructFieldType::F32 | StructFieldType::F64 => true,
+        StructFieldType::Array { items } => field_type_contains_floats(items, is_float_type),
+        StructFieldType::Map { value, .. } => field_type_contains_floats(value, is_float_type),
+        StructFieldType::Reference { name } => is_float_type(name),
+        _ => false,
+    }
+}
+
+impl<'a> File<'a> {
+    /// Returns the names of the file's types which contain floating-point numbers, directly or
+    /// through the types they reference. `types` maps each type name visible in the file to the
+    /// type, where only the imported types have been marked with [`mark_float_types`] yet.
+    pub(crate) fn float_types(&self, types: &HashMap<&str, &Type<'_>>) -> HashSet<&'a str> {
+        let mut float_types = HashSet::new();
+        // repeat until nothing changes, since types can reference each other in any order
+        loop {
+            let is_float_type = |name: &str| {
+                float_types.contains(name)
+                    || types
+                        .get(name)
+                        .is_some_and(|r#type| r#type.contains_floats())
+            };
+            let new_float_types: Vec<_> = self
+                .types
+                .iter()
+                .filter(|r#type| !float_types.contains(r#type.name()))
+                .filter(|r#type| match r#type {
+                    Type::Struct(r#struct) => r#struct
+                        .fields
+                        .iter()
+                        .any(|field| field_type_contains_floats(&field.r#type, &is_float_type)),
+                    Type::Enum(Enum::Tagged { variants, .. }) => variants.iter().any(|variant| {
+                        variant.r#type.as_ref().is_some_and(|r#type| {
+                            field_type_contains_floats(r#type, &is_float_type)
+                        })
+                    }),
+                    Type::Enum(Enum::Untagged { .. }) => false,
+                })
+                .map(Type::name)
+                .collect();
+            if new_float_types.is_empty() {
+                return float_types;
             }
+            float_types.extend(new_float_types);
         }
     }
+}
+
+/// Sets the `contains_floats` flag of each type in `float_types`.
+pub(crate) fn mark_float_types(types: &mut [Type<'_>], float_types: &HashSet<&str>) {
+    for r#type in types {
+        if float_types.contains(r#type.name()) {
+            match r#type {
+                Type::Struct(Struct {
+                    contains_floats, ..
+                })
+                | Type::Enum(Enum::Tagged {
+                    contains_floats, ..
+                }) => *contains_floats = true,
+                Type::Enum(Enum::Untagged { .. }) => {}
+            }
+        }
+    }
+}
+
+/// Parses a typedpack `.tp` file, returning the first error if it is invalid.
+///
+/// The file cannot contain `import` statements; use [`Sources`](crate::Sources) to load files
+/// that import other files. Use [`parse_with_diagnostics`] to find every error in the file.
+pub fn parse(s: &str) -> Result<Vec<Type<'_>>, ParseError> {
+    parse_with_diagnostics(s).map_err(|diagnostics| {
+        diagnostics
+            .into_errors()
+            .into_iter()
+            .next()
+            .expect("diagnostics contain at least one error")
+    })
+}
+
+/// Parses a typedpack `.tp` file, returning every error found in it if it is invalid.
+///
+/// Unlike [`parse`], this recovers from errors at `;` and `}` boundaries and keeps going. The
+/// returned [`Diagnostics`] can be displayed with a snippet of the source for each error.
+pub fn parse_with_diagnostics(s: &str) -> Result<Vec<Type<'_>>, Diagnostics> {
+    let mut errors = Vec::new();
+    let mut file = parse_file(s, &mut errors);
+    if file.imports.is_empty() {
+        let types = file
+            .types
+            .iter()
+            .map(|r#type| (r#type.name(), r#type))
+            .collect();
+        file.check_references(&types, &mut errors);
+        let float_types = file.float_types(&types);
+        mark_float_types(&mut file.types, &float_types);
+    } else {
+        // references to imported types cannot be checked without loading the imported files
+        errors.extend(
+            file.imports
+                .iter()
+                .map(|import| ParseError::UnresolvedImport { pos: import.pos }),
+        );
+    }
 
-    Ok(types)
+    if errors.is_empty() {
+        Ok(file.types)
+    } else {
+        Err(Diagnostics::new(errors, s))
+    }
 }
@@ -0,0 +1,299 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    Diagnostics, Type,
+    parse::{File, mark_float_types, parse_file},
+};
+
+/// The error type which is returned when loading or resolving [`Sources`].
+#[derive(Debug, thiserror::Error)]
+pub enum ResolveError {
+    #[error("{}: duplicate type name '{name}'", .path.display())]
+    DuplicateTypeName { name: String, path: PathBuf },
+    #[error("import cycle: {}", .cycle.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(" -> "))]
+    ImportCycle { cycle: Vec<PathBuf> },
+    #[error("{}: file is not inside the base directory {}", .path.display(), .base_dir.display())]
+    OutsideBaseDir { path: PathBuf, base_dir: PathBuf },
+    #[error("{diagnostics}")]
+    Parse {
+        diagnostics: Diagnostics,
+        path: PathBuf,
+    },
+    #[error("failed to read file {}: {error}", .path.display())]
+    ReadFile {
+        error: std::io::Error,
+        path: PathBuf,
+    },
+    #[error("{}: type '{name}' is not declared in {}", .path.display(), .imported_path.display())]
+    UnknownImportedType {
+        name: String,
+        path: PathBuf,
+        imported_path: PathBuf,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct SourceFile {
+    path: PathBuf,
+    module_path: Vec<String>,
+    source: String,
+    imports: Vec<usize>,
+}
+
+/// A set of typedpack `.tp` files loaded from disk, along with every file they import.
+///
+/// Each file is identified by its module path, which is its path relative to the base directory
+/// without the `.tp` extension, e.g. `users/email.tp` becomes `["users", "email"]`.
+#[derive(Clone, Debug)]
+pub struct Sources {
+    base_dir: PathBuf,
+    files: Vec<SourceFile>,
+    indices: HashMap<PathBuf, usize>,
+}
+
+/// A parsed typedpack file whose imports have been resolved.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Module<'a> {
+    pub(crate) path: &'a Path,
+    pub(crate) source: &'a str,
+    pub(crate) module_path: &'a [String],
+    pub(crate) imports: Vec<ModuleImport<'a>>,
+    pub(crate) types: Vec<Type<'a>>,
+}
+
+/// The types a [`Module`] imports from another module.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ModuleImport<'a> {
+    pub(crate) module_path: &'a [String],
+    pub(crate) names: Vec<&'a str>,
+}
+
+impl Sources {
+    /// Creates an empty set of sources. Files added later must be inside `base_dir`.
+    pub fn new(base_dir: impl AsRef<Path>) -> Result<Self, ResolveError> {
+        let base_dir = base_dir.as_ref();
+        Ok(Self {
+            base_dir: base_dir
+                .canonicalize()
+                .map_err(|error| ResolveError::ReadFile {
+                    error,
+                    path: base_dir.to_owned(),
+                })?,
+            files: Vec::new(),
+            indices: HashMap::new(),
+        })
+    }
+
+    /// Loads a single file and every file it imports, using the file's directory as the base
+    /// directory.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ResolveError> {
+        let path = path.as_ref();
+        let base_dir = match path.parent() {
+            Some(parent) if parent != "" => parent,
+            _ => Path::new("."),
+        };
+        let mut sources = Self::new(base_dir)?;
+        sources.add(path)?;
+        Ok(sources)
+    }
+
+    /// Loads a file and every file it imports, unless they have already been loaded.
+    pub fn add(&mut self, path: impl AsRef<Path>) -> Result<(), ResolveError> {
+        self.add_file(path.as_ref(), &mut Vec::new()).map(|_| ())
+    }
+
+    /// Returns the paths of every loaded file.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|file| file.path.as_path())
+    }
+
+    fn add_file(&mut self, path: &Path, stack: &mut Vec<PathBuf>) -> Result<usize, ResolveError> {
+        let path = path
+            .canonicalize()
+            .map_err(|error| ResolveError::ReadFile {
+                error,
+                path: path.to_owned(),
+            })?;
+        if let Some(&index) = self.indices.get(&path) {
+            return Ok(index);
+        }
+        if let Some(start) = stack.iter().position(|other| *other == path) {
+            let mut cycle = stack.split_off(start);
+            cycle.push(path);
+            return Err(ResolveError::ImportCycle { cycle });
+        }
+
+        let Ok(relative_path) = path.strip_prefix(&self.base_dir) else {
+            return Err(ResolveError::OutsideBaseDir {
+                path,
+                base_dir: self.base_dir.clone(),
+            });
+        };
+        let module_path = relative_path
+            .with_extension("")
+            .iter()
+            .map(|component| component.to_string_lossy().into_owned())
+            .collect();
+
+        let source = std::fs::read_to_string(&path).map_err(|error| ResolveError::ReadFile {
+            error,
+            path: path.clone(),
+        })?;
+        // any errors in the file are reported by `parse`, once the imported files are loaded
+        let File { imports, .. } = parse_file(&source, &mut Vec::new());
+        let dir = path.parent().expect("file path has a parent");
+        let import_paths: Vec<_> = imports
+            .into_iter()
+            .map(|import| dir.join(&*import.path))
+            .collect();
+
+        stack.push(path.clone());
+        let mut imports = Vec::with_capacity(import_paths.len());
+        for import_path in import_paths {
+            imports.push(self.add_file(&import_path, stack)?);
+        }
+        stack.pop();
+
+        let index = self.files.len();
+        self.files.push(SourceFile {
+            path: path.clone(),
+            module_path,
+            source,
+            imports,
+        });
+        self.indices.insert(path, index);
+        Ok(index)
+    }
+
+    /// Parses every loaded file and resolves its imports.
+    ///
+    /// Modules are returned in an order where each module comes after the modules it imports.
+    /// If a file is invalid, the returned [`ResolveError::Parse`] contains every error in it.
+    pub fn parse(&self) -> Result<Vec<Module<'_>>, ResolveError> {
+        let mut modules: Vec<Module<'_>> = Vec::with_capacity(self.files.len());
+
+        for file in &self.files {
+            let mut errors = Vec::new();
+            let mut parsed = parse_file(&file.source, &mut errors);
+            let types = &parsed.types;
+
+            // the index of the module each visible type is declared in, or `None` if local
+            let mut type_modules: HashMap<&str, Option<usize>> =
+                types.iter().map(|r#type| (r#type.name(), None)).collect();
+
+            let mut module_imports = Vec::with_capacity(parsed.imports.len());
+            for (import, &index) in parsed.imports.iter().zip(&file.imports) {
+                let imported = &modules[index];
+                let names = match &import.names {
+                    Some(names) => {
+                        for name in names {
+                            if !imported.types.iter().any(|r#type| r#type.name() == *name) {
+                                return Err(ResolveError::UnknownImportedType {
+                                    name: (*name).to_owned(),
+                                    path: file.path.clone(),
+                                    imported_path: imported.path.to_owned(),
+                                });
+                            }
+                        }
+                        names.clone()
+                    }
+                    None => imported.types.iter().map(Type::name).collect(),
+                };
+                let mut new_names = Vec::with_capacity(names.len());
+                for name in names {
+                    match type_modules.insert(name, Some(index)) {
+                        None => new_names.push(name),
+                        // the same type may be imported more than once
+                        Some(Some(other_index)) if other_index == index => continue,
+                        Some(_) => {
+                            return Err(ResolveError::DuplicateTypeName {
+                                name: name.to_owned(),
+                                path: file.path.clone(),
+                            });
+                        }
+                    }
+                }
+                module_imports.push(ModuleImport {
+                    module_path: imported.module_path,
+                    names: new_names,
+                });
+            }
+
+            let visible_types = type_modules
+                .iter()
+                .filter_map(|(&name, index)| {
+                    let declared_in = match index {
+                        Some(index) => &modules[*index].types,
+                        None => types,
+                    };
+                    declared_in
+                        .iter()
+                        .find(|r#type| r#type.name() == name)
+                        .map(|r#type| (name, r#type))
+                })
+                .collect();
+            parsed.check_references(&visible_types, &mut errors);
+            let float_types = parsed.float_types(&visible_types);
+            if !errors.is_empty() {
+                return Err(ResolveError::Parse {
+                    diagnostics: Diagnostics::new(errors, &file.source).with_path(&file.path),
+                    path: file.path.clone(),
+                });
+            }
+
+            mark_float_types(&mut parsed.types, &float_types);
+            modules.push(Module {
+                path: &file.path,
+                source: &file.source,
+                module_path: &file.module_path,
+                imports: module_imports,
+                types: parsed.types,
+            });
+        }
+
+        Ok(modules)
+    }
+}
+
+impl<'a> Module<'a> {
+    /// Returns the canonical path of the file this module was parsed from.
+    pub fn path(&self) -> &'a Path {
+        self.path
+    }
+
+    /// Returns the source of the file this module was parsed from.
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    /// Returns the module path, i.e. the file path relative to the base directory without the
+    /// `.tp` extension.
+    pub fn module_path(&self) -> &'a [String] {
+        self.module_path
+    }
+
+    /// Returns the module's resolved imports.
+    pub fn imports(&self) -> &[ModuleImport<'a>] {
+        &self.imports
+    }
+
+    /// Returns the types declared in the module.
+    pub fn types(&self) -> &[Type<'a>] {
+        &self.types
+    }
+}
+
+impl<'a> ModuleImport<'a> {
+    /// Returns the module path of the imported module.
+    pub fn module_path(&self) -> &'a [String] {
+        self.module_path
+    }
+
+    /// Returns the names of the imported types.
+    pub fn names(&self) -> &[&'a str] {
+        &self.names
+    }
+}
@@ -1,8 +1,381 @@
-use crate::{Enum, Struct, StructField, StructFieldType};
+use crate::{
+    Annotation, DefaultValue, Deprecated, Enum, EnumRepr, Module, Struct, StructField,
+    StructFieldType, TaggedEnumVariant, Type, Warning, annotation_args, check_annotations,
+};
+
+/// Header for generated files.
+pub const FILE_HEADER: &str = "// This file is automatically generated.\n";
+
+/// Error type for converting [`OptionsInput`] into [`Options`].
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum OptionsError {
+    #[error("invalid attribute `{0}`; expected the contents of `#[...]`, e.g. `must_use`")]
+    InvalidAttribute(String),
+    #[error("invalid derive `{0}`; expected a path, e.g. `PartialOrd` or `::std::cmp::PartialOrd`")]
+    InvalidDerive(String),
+    #[error(
+        "invalid field visibility `{0}`; expected `pub`, `pub(crate)`, `pub(super)`, `pub(self)`, `pub(in PATH)` or an empty string"
+    )]
+    InvalidFieldVisibility(String),
+    #[error(
+        "invalid module name `{0}`; module names cannot be empty, can only contain ASCII alphanumeric characters or underscores, and cannot start with a digit"
+    )]
+    InvalidModuleName(String),
+}
+
+/// Unvalidated [`Options`] for Rust code generation.
+///
+/// Use [`TryInto::try_into`] to convert this into [`Options`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct OptionsInput {
+    /// Traits to derive for every generated type in addition to the default ones, e.g.
+    /// `PartialOrd`. Traits which are already derived by default are ignored.
+    pub extra_derives: Vec<String>,
+    /// Attributes to add to every generated type, without the surrounding `#[...]`, e.g.
+    /// `must_use`.
+    pub extra_attributes: Vec<String>,
+    /// The visibility of `struct` fields (default `pub`). An empty string makes fields private.
+    pub field_visibility: Option<String>,
+    /// Use `Vec<T>` instead of `Box<[T]>` for arrays.
+    pub vec_arrays: Option<bool>,
+    /// Use `[u8; N]` instead of `serde_bytes::ByteArray<N>` for `bytesN` fields.
+    pub primitive_byte_arrays: Option<bool>,
+    /// Use `typedpack::TotalF32` and `typedpack::TotalF64` instead of `f32` and `f64`, which
+    /// compare floats by their total order so that types containing them can still implement
+    /// `Eq` and `Hash`.
+    pub total_order_floats: Option<bool>,
+    /// Reject `struct` fields which are not in the schema when decoding, instead of ignoring
+    /// them.
+    pub reject_unknown_fields: Option<bool>,
+    /// The crate whose types represent `timestamp` and `duration` values (default
+    /// [`TimeCrate::Std`]).
+    pub time_crate: Option<TimeCrate>,
+    /// Wrap all of the code generated by [`rust_modules`] in a `mod` with this name.
+    pub module: Option<String>,
+}
+
+/// Validated options for Rust code generation.
+///
+/// Use [`TryInto::try_into`] to convert [`OptionsInput`] into this type.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Options {
+    extra_derives: Vec<String>,
+    extra_attributes: Vec<String>,
+    field_visibility: String,
+    vec_arrays: bool,
+    primitive_byte_arrays: bool,
+    total_order_floats: bool,
+    reject_unknown_fields: bool,
+    time_crate: TimeCrate,
+    module: Option<String>,
+}
+
+/// The crate whose types represent `timestamp` and `duration` values in generated Rust code.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TimeCrate {
+    /// `std::time::SystemTime` and `std::time::Duration`.
+    #[default]
+    Std,
+    /// `chrono::DateTime<Utc>` and `chrono::TimeDelta`, which requires the `chrono` feature of
+    /// `typedpack`.
+    Chrono,
+    /// `time::OffsetDateTime` and `time::Duration`, which requires the `time` feature of
+    /// `typedpack`.
+    Time,
+}
+
+impl Options {
+    /// The traits derived in addition to the default ones, see [`OptionsInput::extra_derives`].
+    pub fn extra_derives(&self) -> &[String] {
+        &self.extra_derives
+    }
+
+    /// The attributes added to every type, see [`OptionsInput::extra_attributes`].
+    pub fn extra_attributes(&self) -> &[String] {
+        &self.extra_attributes
+    }
+
+    /// The visibility of `struct` fields, which is empty for private fields.
+    pub fn field_visibility(&self) -> &str {
+        &self.field_visibility
+    }
+
+    /// Whether arrays are `Vec<T>` rather than `Box<[T]>`, see [`OptionsInput::vec_arrays`].
+    pub fn vec_arrays(&self) -> bool {
+        self.vec_arrays
+    }
+
+    /// Whether `bytesN` fields are `[u8; N]`, see [`OptionsInput::primitive_byte_arrays`].
+    pub fn primitive_byte_arrays(&self) -> bool {
+        self.primitive_byte_arrays
+    }
+
+    /// Whether floats are `TotalF32` and `TotalF64`, see [`OptionsInput::total_order_floats`].
+    pub fn total_order_floats(&self) -> bool {
+        self.total_order_floats
+    }
+
+    /// Whether decoding rejects unknown fields, see [`OptionsInput::reject_unknown_fields`].
+    pub fn reject_unknown_fields(&self) -> bool {
+        self.reject_unknown_fields
+    }
+
+    /// The crate whose types represent `timestamp` and `duration` values, see
+    /// [`OptionsInput::time_crate`].
+    pub fn time_crate(&self) -> TimeCrate {
+        self.time_crate
+    }
+
+    /// The name of the `mod` wrapping the generated code, see [`OptionsInput::module`].
+    pub fn module(&self) -> Option<&str> {
+        self.module.as_deref()
+    }
+}
+
+fn is_valid_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    let Some(first_char) = chars.next() else {
+        return false;
+    };
+    if !first_char.is_ascii_alphabetic() && first_char != '_' {
+        return false;
+    }
+    name != "_" && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_valid_path(path: &str) -> bool {
+    let path = path.strip_prefix("::").unwrap_or(path);
+    path.split("::")
+        .all(|segment| is_valid_identifier(segment.strip_prefix("r#").unwrap_or(segment)))
+}
+
+/// Checks that `attribute` is the contents of an attribute, i.e. that it starts with a path and
+/// cannot close the surrounding `#[...]`.
+fn is_valid_attribute(attribute: &str) -> bool {
+    let mut depth = 0_usize;
+    let balanced = attribute.chars().all(|c| {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => match depth.checked_sub(1) {
+                Some(new_depth) => depth = new_depth,
+                None => return false,
+            },
+            _ => {}
+        }
+        c != '\n'
+    }) && depth == 0;
+    let path_len = attribute
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_' && c != ':')
+        .unwrap_or(attribute.len());
+    balanced && is_valid_path(&attribute[..path_len])
+}
+
+impl TryFrom<OptionsInput> for Options {
+    type Error = OptionsError;
+
+    fn try_from(value: OptionsInput) -> Result<Self, Self::Error> {
+        let parsed = Self {
+            extra_derives: value.extra_derives,
+            extra_attributes: value.extra_attributes,
+            field_visibility: value
+                .field_visibility
+                .unwrap_or_else(|| String::from("pub")),
+            vec_arrays: value.vec_arrays.unwrap_or(false),
+            primitive_byte_arrays: value.primitive_byte_arrays.unwrap_or(false),
+            total_order_floats: value.total_order_floats.unwrap_or(false),
+            reject_unknown_fields: value.reject_unknown_fields.unwrap_or(false),
+            time_crate: value.time_crate.unwrap_or_default(),
+            module: value.module,
+        };
+
+        for derive in &parsed.extra_derives {
+            if !is_valid_path(derive) {
+                return Err(OptionsError::InvalidDerive(derive.clone()));
+            }
+        }
+
+        for attribute in &parsed.extra_attributes {
+            if !is_valid_attribute(attribute) {
+                return Err(OptionsError::InvalidAttribute(attribute.clone()));
+            }
+        }
+
+        let field_visibility = parsed.field_visibility.as_str();
+        let is_valid_visibility = match field_visibility {
+            "" | "pub" | "pub(crate)" | "pub(super)" | "pub(self)" => true,
+            _ => field_visibility
+                .strip_prefix("pub(in ")
+                .and_then(|path| path.strip_suffix(')'))
+                .is_some_and(is_valid_path),
+        };
+        if !is_valid_visibility {
+            return Err(OptionsError::InvalidFieldVisibility(
+                parsed.field_visibility.clone(),
+            ));
+        }
+
+        if let Some(module) = &parsed.module
+            && !is_valid_identifier(module)
+        {
+            return Err(OptionsError::InvalidModuleName(module.clone()));
+        }
+
+        Ok(parsed)
+    }
+}
+
+/// The traits derived for structs and tagged enums by default.
+const DEFAULT_DERIVES: &[&str] = &[
+    "::std::clone::Clone",
+    "::std::fmt::Debug",
+    "::std::cmp::PartialEq",
+    "::std::cmp::Eq",
+    "::std::hash::Hash",
+];
+
+/// Generates the `#[derive(...)]` attribute with the `default` derives followed by
+/// [`Options::extra_derives`] and the type's `@rust(derive = "...")` annotations, and any
+/// [`Options::extra_attributes`] and `@rust(attr = "...")` annotations.
+///
+/// If the type contains floats and [`Options::total_order_floats`] is not set, `Eq`, `Ord` and
+/// `Hash` are left out, since `f32` and `f64` do not implement them.
+fn rust_derives(
+    default: &[&str],
+    contains_floats: bool,
+    annotations: &[Annotation<'_>],
+    options: &Options,
+) -> String {
+    let last_segment = |path: &str| path.rsplit("::").next().unwrap_or(path).to_owned();
+    let is_derivable = |path: &str| {
+        !contains_floats
+            || options.total_order_floats
+            || !matches!(last_segment(path).as_str(), "Eq" | "Ord" | "Hash")
+    };
+
+    let mut s = String::from("#[derive(");
+    s.push_str(
+        &default
+            .iter()
+            .copied()
+            .filter(|path| is_derivable(path))
+            .collect::<Vec<_>>()
+            .join(", "),
+    );
+    let mut derived: Vec<_> = default.iter().map(|path| last_segment(path)).collect();
+    let extra_derives = options
+        .extra_derives
+        .iter()
+        .map(String::as_str)
+        .chain(rust_annotation_values(annotations, "derive"));
+    for derive in extra_derives {
+        let name = last_segment(derive);
+        if is_derivable(derive) && !derived.contains(&name) {
+            s.push_str(", ");
+            s.push_str(derive);
+            derived.push(name);
+        }
+    }
+    s.push_str(")]\n");
+    for attribute in &options.extra_attributes {
+        s.push_str("#[");
+        s.push_str(attribute);
+        s.push_str("]\n");
+    }
+    s.push_str(&rust_annotation_attributes(annotations, ""));
+    s
+}
+
+/// Returns the values of `key` in the `@rust` annotations, leaving out invalid ones, which
+/// [`annotation_warnings`] reports.
+fn rust_annotation_values<'b, 'a>(
+    annotations: &'b [Annotation<'a>],
+    key: &'b str,
+) -> impl Iterator<Item = &'b str> {
+    annotation_args(annotations, "rust")
+        .filter(move |arg| arg.key == key && check_annotation_value(arg.key, &arg.value).is_ok())
+        .map(|arg| &*arg.value)
+}
+
+/// Generates the attributes given with `@rust(attr = "...")` annotations.
+fn rust_annotation_attributes(annotations: &[Annotation<'_>], indent: &str) -> String {
+    let mut s = String::new();
+    for attribute in rust_annotation_values(annotations, "attr") {
+        s.push_str(indent);
+        s.push_str("#[");
+        s.push_str(attribute_contents(attribute));
+        s.push_str("]\n");
+    }
+    s
+}
+
+/// Strips the surrounding `#[...]` from an attribute given in an annotation, if there is one.
+fn attribute_contents(attribute: &str) -> &str {
+    attribute
+        .strip_prefix("#[")
+        .and_then(|attribute| attribute.strip_suffix(']'))
+        .unwrap_or(attribute)
+}
+
+/// Checks the value of a `@rust` annotation argument, returning what was expected if it is
+/// invalid.
+fn check_annotation_value(key: &str, value: &str) -> Result<(), &'static str> {
+    match key {
+        "derive" if !is_valid_path(value) => Err("a path, e.g. `PartialOrd`"),
+        "attr" if !is_valid_attribute(attribute_contents(value)) => {
+            Err("an attribute, e.g. `#[must_use]`")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Returns a warning for every `@rust` annotation argument which the generated code ignores,
+/// because its key is unknown or its value is invalid.
+///
+/// Types accept `derive = "Path"`, which derives another trait, and `attr = "#[...]"`, which adds
+/// an attribute; struct fields and enum variants only accept `attr`.
+pub fn annotation_warnings(types: &[Type<'_>]) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut check = |annotations: &[Annotation<'_>], target: &str, keys: &[&str]| {
+        check_annotations(
+            annotations,
+            "rust",
+            target,
+            keys,
+            check_annotation_value,
+            &mut warnings,
+        );
+    };
+    for r#type in types {
+        match r#type {
+            Type::Struct(r#struct) => {
+                check(&r#struct.annotations, "a struct", &["derive", "attr"]);
+                for field in &r#struct.fields {
+                    check(&field.annotations, "a struct field", &["attr"]);
+                }
+            }
+            Type::Enum(r#enum) => {
+                check(r#enum.annotations(), "an enum", &["derive", "attr"]);
+                match r#enum {
+                    Enum::Tagged { variants, .. } => {
+                        for variant in variants {
+                            check(&variant.annotations, "an enum variant", &["attr"]);
+                        }
+                    }
+                    Enum::Untagged { variants, .. } => {
+                        for variant in variants {
+                            check(&variant.annotations, "an enum variant", &["attr"]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    warnings
+}
 
 impl StructFieldType<'_> {
     /// Returns the corresponding Rust type.
-    pub fn rust_type(&self) -> String {
+    pub fn rust_type(&self, options: &Options) -> String {
         match self {
             Self::Bool => String::from("::std::primitive::bool"),
             Self::U8 => String::from("::std::primitive::u8"),
@@ -13,62 +386,603 @@ impl StructFieldType<'_> {
             Self::I32 => String::from("::std::primitive::i32"),
             Self::U64 => String::from("::std::primitive::u64"),
             Self::I64 => String::from("::std::primitive::i64"),
+            Self::F32 if options.total_order_floats => String::from("::typedpack::TotalF32"),
+            Self::F64 if options.total_order_floats => String::from("::typedpack::TotalF64"),
             Self::F32 => String::from("::std::primitive::f32"),
             Self::F64 => String::from("::std::primitive::f64"),
             Self::String => String::from("::std::string::String"),
-            Self::Bytes { len } => {
-                if let Some(len) = len {
-                    format!("::typedpack::serde_bytes::ByteArray<{len}>")
-                } else {
-                    String::from("::typedpack::serde_bytes::ByteBuf")
+            Self::Bytes { len } => match len {
+                Some(len) if options.primitive_byte_arrays => {
+                    format!("[::std::primitive::u8; {len}]")
                 }
+                Some(len) => format!("::typedpack::serde_bytes::ByteArray<{len}>"),
+                None => String::from("::typedpack::serde_bytes::ByteBuf"),
+            },
+            Self::Timestamp => String::from(match options.time_crate {
+                TimeCrate::Std => "::std::time::SystemTime",
+                TimeCrate::Chrono => "::typedpack::chrono::DateTime<::typedpack::chrono::Utc>",
+                TimeCrate::Time => "::typedpack::time::OffsetDateTime",
+            }),
+            Self::Duration => String::from(match options.time_crate {
+                TimeCrate::Std => "::std::time::Duration",
+                TimeCrate::Chrono => "::typedpack::chrono::TimeDelta",
+                TimeCrate::Time => "::typedpack::time::Duration",
+            }),
+            Self::Array { items } if options.vec_arrays => {
+                format!("::std::vec::Vec<{items}>", items = items.rust_type(options))
             }
             Self::Array { items } => {
-                format!("::std::boxed::Box<[{items}]>", items = items.rust_type())
+                format!(
+                    "::std::boxed::Box<[{items}]>",
+                    items = items.rust_type(options)
+                )
             }
+            Self::Map { key, value } => format!(
+                "::std::collections::BTreeMap<{key}, {value}>",
+                key = key.rust_type(options),
+                value = value.rust_type(options)
+            ),
             Self::Reference { name } => (*name).to_owned(),
         }
     }
+
+    /// Returns `true` if the type contains an array or map inside another array or map, whose Rust
+    /// type Clippy would consider too complex.
+    fn contains_nested_collection(&self) -> bool {
+        match self {
+            Self::Array { items: inner } | Self::Map { value: inner, .. } => {
+                matches!(**inner, Self::Array { .. } | Self::Map { .. })
+                    || inner.contains_nested_collection()
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the type references another type, which might be deprecated.
+    fn contains_reference(&self) -> bool {
+        match self {
+            Self::Array { items } => items.contains_reference(),
+            Self::Map { key, value } => key.contains_reference() || value.contains_reference(),
+            Self::Reference { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Generates Rust statements which call `validate` on every value of another type in `value`,
+    /// an expression of this type. `in_path` adds the path of `value` to the error.
+    fn rust_validate_nested(
+        &self,
+        value: &str,
+        in_path: &str,
+        depth: usize,
+        indent: &str,
+    ) -> String {
+        let inner_indent = format!("{indent}    ");
+        match self {
+            Self::Array { items } if items.contains_reference() => format!(
+                "{indent}for (i{depth}, item{depth}) in {value}.iter().enumerate() {{\n{inner}{indent}}}\n",
+                inner = items.rust_validate_nested(
+                    &format!("item{depth}"),
+                    &format!(".in_segment(::typedpack::PathSegment::Index(i{depth})){in_path}"),
+                    depth + 1,
+                    &inner_indent,
+                ),
+            ),
+            // map keys cannot contain values with constraints
+            Self::Map {
+                value: map_value, ..
+            } if map_value.contains_reference() => format!(
+                "{indent}for (i{depth}, (_, value{depth})) in {value}.iter().enumerate() {{\n{inner}{indent}}}\n",
+                inner = map_value.rust_validate_nested(
+                    &format!("value{depth}"),
+                    &format!(".in_segment(::typedpack::PathSegment::MapValue(i{depth})){in_path}"),
+                    depth + 1,
+                    &inner_indent,
+                ),
+            ),
+            Self::Reference { .. } => {
+                format!(
+                    "{indent}::typedpack::Validate::validate({value}).map_err(|error| error{in_path})?;\n"
+                )
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// Returns `true` if the Rust type contains values which have to be converted before they
+    /// serialize as their typedpack type: `[u8; N]` arrays, which would serialize as arrays of
+    /// integers rather than as binary data, and timestamps and durations, which would not serialize
+    /// with the timestamp extension.
+    fn needs_conversion_to_serialize(&self, options: &Options) -> bool {
+        match self {
+            Self::Bytes { len: Some(_) } => options.primitive_byte_arrays,
+            Self::Timestamp | Self::Duration => true,
+            Self::Array { items } => items.needs_conversion_to_serialize(options),
+            Self::Map { value, .. } => value.needs_conversion_to_serialize(options),
+            _ => false,
+        }
+    }
+
+    /// Returns the tuple struct which wraps a reference to a value of this type so that it
+    /// serializes as its typedpack type, if there is one.
+    fn rust_serializable_wrapper(&self, options: &Options) -> Option<&'static str> {
+        match (self, options.time_crate) {
+            (Self::Timestamp, TimeCrate::Std) => Some("::typedpack::TimestampRef"),
+            (Self::Timestamp, TimeCrate::Chrono) => Some("::typedpack::ChronoTimestampRef"),
+            (Self::Timestamp, TimeCrate::Time) => Some("::typedpack::TimeTimestampRef"),
+            (Self::Duration, TimeCrate::Std) => Some("::typedpack::DurationRef"),
+            (Self::Duration, TimeCrate::Chrono) => Some("::typedpack::ChronoDurationRef"),
+            (Self::Duration, TimeCrate::Time) => Some("::typedpack::TimeDurationRef"),
+            _ => None,
+        }
+    }
+
+    /// Returns a Rust expression which converts `value`, an expression of this type, into a
+    /// value which serializes as its typedpack type, see [`Self::needs_conversion_to_serialize`].
+    /// `is_reference` is `true` if `value` is a reference rather than a place.
+    fn rust_serializable_value(
+        &self,
+        value: &str,
+        is_reference: bool,
+        options: &Options,
+    ) -> String {
+        match self {
+            Self::Array { items } if items.needs_conversion_to_serialize(options) => format!(
+                "{value}.iter().map({item}).collect::<::std::vec::Vec<_>>()",
+                item = items.rust_serializable_fn("item", options)
+            ),
+            Self::Map {
+                value: map_value, ..
+            } if map_value.needs_conversion_to_serialize(options) => {
+                format!(
+                    "{value}.iter().map(|(key, value)| (key, {item})).collect::<::std::collections::BTreeMap<_, _>>()",
+                    item = map_value.rust_serializable_value("value", true, options)
+                )
+            }
+            Self::Bytes { len: Some(_) } if options.primitive_byte_arrays => {
+                format!("::typedpack::serde_bytes::Bytes::new({value}.as_slice())")
+            }
+            _ => match self.rust_serializable_wrapper(options) {
+                Some(wrapper) if is_reference => format!("{wrapper}({value})"),
+                Some(wrapper) => format!("{wrapper}(&{value})"),
+                None => value.to_owned(),
+            },
+        }
+    }
+
+    /// Returns a Rust function which converts a reference to a value of this type into a value
+    /// which serializes as its typedpack type, for passing to `map`. `param` names the closure's
+    /// parameter, unless the wrapper can be passed directly.
+    fn rust_serializable_fn(&self, param: &str, options: &Options) -> String {
+        match self.rust_serializable_wrapper(options) {
+            Some(wrapper) => wrapper.to_owned(),
+            None => format!(
+                "|{param}| {value}",
+                value = self.rust_serializable_value(param, true, options)
+            ),
+        }
+    }
+}
+
+/// The characters which `\s` matches in JavaScript, as the contents of a Rust character class.
+const JAVASCRIPT_WHITESPACE: &str =
+    r"\t\n\v\f\r \u{a0}\u{1680}\u{2000}-\u{200a}\u{2028}\u{2029}\u{202f}\u{205f}\u{3000}\u{feff}";
+
+/// Returns the Rust `regex` pattern for a `pattern` constraint.
+///
+/// Patterns only use syntax which the `regex` crate and JavaScript with the `u` flag share, but
+/// `.`, `\d`, `\w`, `\s` and `\b` match different characters in Rust, so they are replaced with
+/// what they match in JavaScript, where `\d`, `\w` and `\b` only consider ASCII characters.
+pub(crate) fn rust_regex(pattern: &str) -> String {
+    let mut s = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    let mut in_class = false;
+    while let Some(c) = chars.next() {
+        match (c, in_class) {
+            ('[', false) => in_class = true,
+            (']', true) => in_class = false,
+            ('.', false) => {
+                s.push_str(r"[^\n\r\u{2028}\u{2029}]");
+                continue;
+            }
+            ('\\', _) => {
+                let Some(escaped) = chars.next() else {
+                    break;
+                };
+                let class = match escaped.to_ascii_lowercase() {
+                    'd' => "0-9",
+                    'w' => "0-9A-Za-z_",
+                    's' => JAVASCRIPT_WHITESPACE,
+                    'b' => {
+                        s.push_str(&format!(r"(?-u:\{escaped})"));
+                        continue;
+                    }
+                    _ => {
+                        s.push(c);
+                        s.push(escaped);
+                        continue;
+                    }
+                };
+                // negated classes are nested in a surrounding class
+                let negated = escaped.is_ascii_uppercase();
+                if in_class && !negated {
+                    s.push_str(class);
+                } else {
+                    s.push_str(if negated { "[^" } else { "[" });
+                    s.push_str(class);
+                    s.push(']');
+                }
+                continue;
+            }
+            _ => {}
+        }
+        s.push(c);
+    }
+    s
+}
+
+/// Generates Rust `///` doc comment lines.
+fn rust_doc_comment(doc: &[&str], indent: &str) -> String {
+    let mut s = String::new();
+    for line in doc {
+        s.push_str(indent);
+        s.push_str("///");
+        if !line.is_empty() {
+            s.push(' ');
+            s.push_str(line);
+        }
+        s.push('\n');
+    }
+    s
+}
+
+/// Generates a Rust `#[deprecated]` attribute line for a `deprecated` modifier.
+fn rust_deprecated_attribute(deprecated: Option<&Deprecated<'_>>, indent: &str) -> String {
+    match deprecated {
+        Some(Deprecated {
+            note: Some(note), ..
+        }) => {
+            format!("{indent}#[deprecated(note = {note:?})]\n")
+        }
+        Some(Deprecated { note: None, .. }) => format!("{indent}#[deprecated]\n"),
+        None => String::new(),
+    }
+}
+
+/// Returns `#[allow(deprecated)]` for the generated definition and impls of a type if they might
+/// use deprecated items, which would otherwise cause warnings in code the user cannot change.
+fn rust_allow_deprecated(uses_deprecated: bool) -> &'static str {
+    if uses_deprecated {
+        "#[allow(deprecated)]\n"
+    } else {
+        ""
+    }
+}
+
+/// Generates an implementation of `Validate`, which runs the statements in `checks`.
+fn rust_validate_impl(name: &str, checks: &str) -> String {
+    if checks.is_empty() {
+        // the provided method accepts every value
+        return format!("impl ::typedpack::Validate for r#{name} {{}}");
+    }
+    format!(
+        "impl ::typedpack::Validate for r#{name} {{
+    fn validate(&self) -> ::std::result::Result<(), ::typedpack::DecodeError> {{
+{checks}        ::std::result::Result::Ok(())
+    }}
+}}"
+    )
+}
+
+impl DefaultValue<'_> {
+    /// Returns a Rust expression for the default value of a field of type `r#type`.
+    fn rust_value(&self, r#type: &StructFieldType<'_>, options: &Options) -> String {
+        match self {
+            Self::Bool(value) => value.to_string(),
+            Self::Integer(value) => value.to_string(),
+            Self::Float(value) => match r#type {
+                StructFieldType::F32 if options.total_order_floats => {
+                    format!("::typedpack::TotalF32({value}_f32)")
+                }
+                StructFieldType::F32 => format!("{value}_f32"),
+                _ if options.total_order_floats => format!("::typedpack::TotalF64({value}_f64)"),
+                _ => format!("{value}_f64"),
+            },
+            Self::String(value) => format!("::std::string::String::from({value:?})"),
+            Self::Variant(variant) => match r#type {
+                StructFieldType::Reference { name } => format!("r#{name}::r#{variant}"),
+                _ => unreachable!(),
+            },
+        }
+    }
 }
 
 impl StructField<'_> {
     /// Generates a Rust `struct` field definition.
-    pub fn rust_struct_field(&self) -> String {
-        let mut s = format!("pub r#{name}: ", name = self.name);
-        if self.optional {
+    pub fn rust_struct_field(&self, options: &Options) -> String {
+        let mut s = options.field_visibility.clone();
+        if !s.is_empty() {
+            s.push(' ');
+        }
+        s.push_str("r#");
+        s.push_str(self.name);
+        s.push_str(": ");
+        let optional = self.optional && self.default.is_none();
+        if optional {
             s.push_str("::std::option::Option<");
         }
+        s.push_str(&self.rust_value_type(options));
+        if optional {
+            s.push('>');
+        }
+        s.push(',');
+        s
+    }
+
+    /// Returns the Rust type of the field's encoded value, which is wrapped in another `Option`
+    /// in the `struct` if the field is optional without a default value.
+    fn rust_value_type(&self, options: &Options) -> String {
         if self.nullable {
-            s.push_str("::std::option::Option<");
+            format!("::std::option::Option<{}>", self.r#type.rust_type(options))
+        } else {
+            self.r#type.rust_type(options)
         }
-        s.push_str(&self.r#type.rust_type());
+    }
+
+    /// Returns a Rust expression for the field's default value, if it has one.
+    fn rust_default_value(&self, options: &Options) -> Option<String> {
+        let value = self.default.as_ref()?.rust_value(&self.r#type, options);
         if self.nullable {
-            s.push('>');
+            Some(format!("::std::option::Option::Some({value})"))
+        } else {
+            Some(value)
         }
-        if self.optional {
-            s.push('>');
+    }
+
+    /// Returns a Rust expression for the value to serialize for the field, which converts any
+    /// `[u8; N]` arrays into binary data.
+    fn rust_serializable_value(&self, options: &Options) -> String {
+        fn unwrap_options(
+            r#type: &StructFieldType<'_>,
+            value: &str,
+            is_reference: bool,
+            option_count: usize,
+            options: &Options,
+        ) -> String {
+            if option_count == 0 {
+                r#type.rust_serializable_value(value, is_reference, options)
+            } else if option_count == 1 {
+                format!(
+                    "{value}.as_ref().map({inner})",
+                    inner = r#type.rust_serializable_fn("value", options)
+                )
+            } else {
+                format!(
+                    "{value}.as_ref().map(|value| {inner})",
+                    inner = unwrap_options(r#type, "value", true, option_count - 1, options)
+                )
+            }
+        }
+
+        let field = format!("self.r#{name}", name = self.name);
+        if !self.r#type.needs_conversion_to_serialize(options) {
+            return format!("&{field}");
+        }
+        let option_count =
+            usize::from(self.nullable) + usize::from(self.optional && self.default.is_none());
+        format!(
+            "&{}",
+            unwrap_options(&self.r#type, &field, false, option_count, options)
+        )
+    }
+
+    /// Generates Rust statements which check that `value`, a reference to a value of the field's
+    /// type, satisfies the field's constraints. `return_error` turns the error into the expression
+    /// to return.
+    fn rust_constraint_checks(
+        &self,
+        type_name: &str,
+        indent: &str,
+        return_error: impl Fn(&str) -> String,
+        options: &Options,
+    ) -> String {
+        // pairs of a condition which is `true` if the constraint is not satisfied, and the
+        // constraint
+        let mut checks = Vec::new();
+        let mut push_check = |condition: &str, constraint: String| {
+            checks.push((condition.to_owned(), constraint));
+        };
+
+        let constraints = &self.constraints;
+        let (number, suffix) = match self.r#type {
+            StructFieldType::F32 if options.total_order_floats => ("&value.0", "_f32"),
+            StructFieldType::F64 if options.total_order_floats => ("&value.0", "_f64"),
+            StructFieldType::F32 => ("value", "_f32"),
+            StructFieldType::F64 => ("value", "_f64"),
+            _ => ("value", ""),
+        };
+        if let Some(min) = &constraints.min {
+            push_check(
+                &format!("!({min}{suffix}..).contains({number})"),
+                format!("min = {min}"),
+            );
+        }
+        if let Some(max) = &constraints.max {
+            push_check(
+                &format!("!(..={max}{suffix}).contains({number})"),
+                format!("max = {max}"),
+            );
+        }
+
+        // comparing `len()` with 0 or 1 is written with `is_empty()` to avoid lints
+        let is_string = self.r#type == StructFieldType::String;
+        let len = if is_string {
+            "value.chars().count()"
+        } else {
+            "value.len()"
+        };
+        match constraints.min_len {
+            None | Some(0) => {}
+            Some(1) if !is_string => push_check("value.is_empty()", String::from("min_len = 1")),
+            Some(min_len) => push_check(
+                &format!("{len} < {min_len}"),
+                format!("min_len = {min_len}"),
+            ),
+        }
+        match constraints.max_len {
+            None => {}
+            Some(0) => push_check("!value.is_empty()", String::from("max_len = 0")),
+            Some(max_len) => push_check(
+                &format!("{len} > {max_len}"),
+                format!("max_len = {max_len}"),
+            ),
+        }
+        match constraints.len {
+            None => {}
+            Some(0) => push_check("!value.is_empty()", String::from("len = 0")),
+            Some(exact_len) => push_check(
+                &format!("{len} != {exact_len}"),
+                format!("len = {exact_len}"),
+            ),
+        }
+
+        let mut s = String::new();
+        if let Some(pattern) = &constraints.pattern {
+            s.push_str(&format!(
+                "{indent}static PATTERN: ::std::sync::LazyLock<::typedpack::regex::Regex> = ::std::sync::LazyLock::new(|| ::typedpack::regex::Regex::new({regex:?}).expect(\"the pattern is checked when parsing the schema\"));\n",
+                regex = rust_regex(pattern)
+            ));
+            push_check(
+                "!PATTERN.is_match(value)",
+                format!("pattern = \"{pattern}\""),
+            );
+        }
+        for (condition, constraint) in checks {
+            s.push_str(&format!(
+                "{indent}if {condition} {{\n{indent}    return {error};\n{indent}}}\n",
+                error = return_error(&format!(
+                    "::typedpack::DecodeError::constraint_violation({constraint:?}).in_field(\"{type_name}\", \"{name}\", {id})",
+                    name = self.name,
+                    id = self.id,
+                )),
+            ));
         }
-        s.push(',');
         s
     }
+
+    /// Generates Rust statements for the `validate` method of the `struct`, which check the
+    /// field's constraints and validate any values of other types in it.
+    fn rust_validate(&self, type_name: &str, options: &Options) -> String {
+        let checks = self.rust_constraint_checks(
+            type_name,
+            "            ",
+            |error| format!("::std::result::Result::Err({error})"),
+            options,
+        );
+        let nested = self.r#type.rust_validate_nested(
+            "value",
+            &format!(
+                ".in_field(\"{type_name}\", \"{name}\", {id})",
+                name = self.name,
+                id = self.id
+            ),
+            0,
+            "            ",
+        );
+        if checks.is_empty() && nested.is_empty() {
+            return String::new();
+        }
+        let option_count =
+            usize::from(self.nullable) + usize::from(self.optional && self.default.is_none());
+        let mut s = String::from("        ");
+        if option_count == 0 {
+            s.push_str("{\n            let value = &self.r#");
+            s.push_str(self.name);
+            s.push_str(";\n");
+        } else {
+            // a single check could only be collapsed into a let chain, which needs edition 2024
+            s.push_str("#[allow(clippy::collapsible_if)]\n        if let ");
+            s.push_str(&"::std::option::Option::Some(".repeat(option_count));
+            s.push_str("value");
+            s.push_str(&")".repeat(option_count));
+            s.push_str(" = &self.r#");
+            s.push_str(self.name);
+            s.push_str(" {\n");
+        }
+        s.push_str(&checks);
+        s.push_str(&nested);
+        s.push_str("        }\n");
+        s
+    }
+
+    /// Returns a Rust expression which checks if an optional field should be serialized.
+    fn rust_is_present(&self, options: &Options) -> String {
+        let name = self.name;
+        match &self.default {
+            // compare against a `&str` so that serializing does not allocate the default string
+            Some(DefaultValue::String(value)) if self.nullable => {
+                format!("self.r#{name}.as_deref() != ::std::option::Option::Some({value:?})")
+            }
+            Some(DefaultValue::String(value)) => format!("self.r#{name} != {value:?}"),
+            _ => match self.rust_default_value(options) {
+                Some(default) => format!("self.r#{name} != {default}"),
+                None => format!("self.r#{name}.is_some()"),
+            },
+        }
+    }
 }
 
 impl Struct<'_> {
     /// Generates a Rust `struct` definition.
-    pub fn rust_struct(&self) -> String {
-        let mut s = format!(
-            "#[derive(::std::clone::Clone, ::std::fmt::Debug, ::std::cmp::PartialEq, ::std::cmp::Eq, ::std::hash::Hash)]\npub struct r#{name} {{\n",
-            name = self.name
+    pub fn rust_struct(&self, options: &Options) -> String {
+        let allow_deprecated = rust_allow_deprecated(
+            self.deprecated.is_some()
+                || self
+                    .fields
+                    .iter()
+                    .any(|field| field.deprecated.is_some() || field.r#type.contains_reference()),
         );
+        let mut s = rust_doc_comment(&self.doc, "");
+        s.push_str(&rust_deprecated_attribute(self.deprecated.as_ref(), ""));
+        s.push_str(&rust_derives(
+            DEFAULT_DERIVES,
+            self.contains_floats,
+            &self.annotations,
+            options,
+        ));
+        s.push_str(allow_deprecated);
+        // nested arrays and maps cannot be simplified without type aliases
+        if self
+            .fields
+            .iter()
+            .any(|field| field.r#type.contains_nested_collection())
+        {
+            s.push_str("#[allow(clippy::type_complexity)]\n");
+        }
+        s.push_str(&format!("pub struct r#{name} {{\n", name = self.name));
         for field in &self.fields {
+            s.push_str(&rust_doc_comment(&field.doc, "    "));
+            s.push_str(&rust_deprecated_attribute(
+                field.deprecated.as_ref(),
+                "    ",
+            ));
+            s.push_str(&rust_annotation_attributes(&field.annotations, "    "));
             s.push_str("    ");
-            s.push_str(&field.rust_struct_field());
+            s.push_str(&field.rust_struct_field(options));
             s.push('\n');
         }
+        if self.preserve_unknown {
+            s.push_str("    /// The fields which are not in the schema, which are kept so that they can be encoded again.\n    ");
+            s.push_str(&options.field_visibility);
+            if !options.field_visibility.is_empty() {
+                s.push(' ');
+            }
+            s.push_str("r#unknown_fields: ::typedpack::UnknownFields,\n");
+        }
         s.push_str(&format!(
             "}}
 
-impl ::typedpack::serde::Serialize for r#{name} {{
+{allow_deprecated}impl ::typedpack::serde::Serialize for r#{name} {{
     fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
     where
         S: ::typedpack::serde::Serializer,
@@ -76,156 +990,621 @@ impl ::typedpack::serde::Serialize for r#{name} {{
         let map_len = ",
             name = self.name
         ));
-        let mut required_field_count = 0;
-        let mut optional_field_names = Vec::new();
+        let required_field_count = self.fields.iter().filter(|field| !field.optional).count();
+        let mut terms = Vec::new();
         for field in &self.fields {
             if field.optional {
-                optional_field_names.push(&field.name);
-            } else {
-                required_field_count += 1;
+                terms.push(format!(
+                    "if {is_present} {{ 1 }} else {{ 0 }}",
+                    is_present = field.rust_is_present(options)
+                ));
             }
         }
-        s.push_str(&format!("{required_field_count}"));
-        for optional_field_name in optional_field_names {
-            s.push_str(&format!(
-                "\n            + if self.r#{name}.is_some() {{ 1 }} else {{ 0 }}",
-                name = optional_field_name
-            ));
+        if self.preserve_unknown {
+            terms.push(String::from("self.r#unknown_fields.len()"));
+        }
+        // leave out a count of 0 unless it is the only term
+        if required_field_count > 0 || terms.is_empty() {
+            terms.insert(0, required_field_count.to_string());
         }
+        s.push_str(&terms.join("\n            + "));
         s.push_str(";\n        let ");
-        if !self.fields.is_empty() {
+        if !self.fields.is_empty() || self.preserve_unknown {
             s.push_str("mut ");
         }
         s.push_str("map = serializer.serialize_map(::std::option::Option::Some(map_len))?;\n");
         for field in &self.fields {
             if field.optional {
                 s.push_str(&format!(
-                    "        if self.r#{name}.is_some() {{\n    ",
-                    name = field.name
+                    "        if {is_present} {{\n    ",
+                    is_present = field.rust_is_present(options)
                 ));
             }
-            s.push_str(&format!("        ::typedpack::serde::ser::SerializeMap::serialize_entry(&mut map, &{id}, &self.r#{name})?;\n", id = field.id, name = field.name));
+            s.push_str(&format!("        ::typedpack::serde::ser::SerializeMap::serialize_entry(&mut map, &{id}, {value})?;\n", id = field.id, value = field.rust_serializable_value(options)));
             if field.optional {
                 s.push_str("        }\n");
             }
         }
+        if self.preserve_unknown {
+            s.push_str("        for (id, value) in self.r#unknown_fields.iter() {\n            ::typedpack::serde::ser::SerializeMap::serialize_entry(&mut map, id, value)?;\n        }\n");
+        }
         s.push_str("        ::typedpack::serde::ser::SerializeMap::end(map)\n    }\n}\n\n");
 
-        s.push_str("\nimpl<'de> ::typedpack::serde::Deserialize<'de> for r#");
+        s.push('\n');
+        s.push_str(allow_deprecated);
+        s.push_str(&rust_deserialize_impl(self.name));
+        s.push_str("\n\n");
+        s.push_str(allow_deprecated);
+        s.push_str(&rust_decode_fn_start(self.name));
+        // the visitor stores the value instead of returning it, so that large values are not
+        // copied through the deserializer's stack frames
+        s.push_str("        struct Visitor<'a>(&'a mut ::std::option::Option<r#");
         s.push_str(self.name);
-        s.push_str(" {\nfn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>\n    where\n        D: ::typedpack::serde::Deserializer<'de>,\n    {\n        ");
-
-        if !self.fields.is_empty() {
-            s.push_str("let entries = ");
-        }
-        s.push_str("deserializer.deserialize_map(::typedpack::MapVisitor)?;\n");
-
-        if self.fields.is_empty() {
-            s.push_str("        ::std::result::Result::Ok(Self {})\n    }\n}");
-            return s;
-        }
+        s.push_str(">);\n        impl<'de> ::typedpack::DecodeVisitor<'de> for Visitor<'_> {\n            type Value = ();\n            const EXPECTED: ::typedpack::MessagePackType = ::typedpack::MessagePackType::Map;\n\n            fn visit_map<A>(self, mut map: A) -> ::std::result::Result<::std::result::Result<(), ::typedpack::DecodeError>, A::Error>\n            where\n                A: ::typedpack::serde::de::MapAccess<'de>,\n            {\n");
 
+        // locals are prefixed so that they cannot clash with `map` and `id`
         for field in &self.fields {
-            s.push_str("        let mut r#");
+            s.push_str("                let mut field_");
             s.push_str(field.name);
             s.push_str(" = ::std::option::Option::None;\n");
         }
-        s.push_str("\n        for (key, value) in entries {\n            match key {\n");
-
-        for field in &self.fields {
-            s.push_str(&format!("                {id} => {{
-                    r#{name} = ::std::option::Option::Some(::typedpack::FromRmpValue::from(value).map_err(|err| ::typedpack::serde::de::Error::custom(err))?);
-                }}
-", id = field.id, name = field.name));
+        if self.preserve_unknown {
+            s.push_str("                let mut unknown_fields = ::std::vec::Vec::new();\n");
         }
-        s.push_str("                _ => {{}}\n            }\n        }\n        ::std::result::Result::Ok(Self {\n");
+        s.push_str(
+            "                let mut ids = ::typedpack::FieldIds::default();\n                ",
+        );
+        // every field is unknown, so the first one is an error
+        if self.fields.is_empty() && !self.preserve_unknown && options.reject_unknown_fields {
+            s.push_str("if");
+        } else {
+            s.push_str("while");
+        }
+        s.push_str(" let ::std::option::Option::Some(id) = ::typedpack::next_field_id(&mut map, &mut ids, \"");
+        s.push_str(self.name);
+        s.push_str("\")? {\n                    match id {\n");
         for field in &self.fields {
-            s.push_str("            r#");
-            s.push_str(field.name);
-            if field.optional {
-                s.push_str(",\n");
+            let store = if field.constraints.is_empty() {
+                format!(
+                    "field_{name} = ::std::option::Option::Some(value)",
+                    name = field.name
+                )
             } else {
-                s.push_str(": r#");
-                s.push_str(field.name);
-                s.push_str(".ok_or_else(|| ::typedpack::serde::de::Error::custom(\"missing `");
-                s.push_str(field.name);
-                s.push_str("`\"))?,\n");
+                let mut store = format!(
+                    "{{\n                                let value = &*field_{name}.insert(value);\n",
+                    name = field.name
+                );
+                let mut indent = "                                ";
+                if field.nullable {
+                    store.push_str(indent);
+                    store.push_str("#[allow(clippy::collapsible_if)]\n");
+                    store.push_str(indent);
+                    store.push_str("if let ::std::option::Option::Some(value) = value {\n");
+                    indent = "                                    ";
+                }
+                store.push_str(&field.rust_constraint_checks(
+                    self.name,
+                    indent,
+                    |error| format!("::typedpack::skip_map(map, {error})"),
+                    options,
+                ));
+                if field.nullable {
+                    store.push_str("                                }\n");
+                }
+                store.push_str("                            }");
+                store
+            };
+            s.push_str(&format!(
+                "                        ::std::result::Result::Ok({id}) => match map.next_value::<::typedpack::Decoded<{value_type}>>() {{
+                            ::std::result::Result::Ok(::typedpack::Decoded(::std::result::Result::Ok(value))) => {store},
+                            ::std::result::Result::Ok(::typedpack::Decoded(::std::result::Result::Err(error))) => return ::typedpack::skip_map(map, error.in_field(\"{type_name}\", \"{name}\", {id})),
+                            ::std::result::Result::Err(error) => return ::std::result::Result::Err(error),
+                        }},
+",
+                id = field.id,
+                name = field.name,
+                type_name = self.name,
+                value_type = field.rust_value_type(options),
+            ));
+        }
+        if self.preserve_unknown {
+            s.push_str("                        ::std::result::Result::Ok(id) => unknown_fields.push((id, map.next_value::<::typedpack::rmpv::Value>()?)),\n");
+        } else if options.reject_unknown_fields {
+            s.push_str("                        ::std::result::Result::Ok(id) => {\n                            map.next_value::<::typedpack::serde::de::IgnoredAny>()?;\n                            return ::typedpack::skip_map(map, ::typedpack::DecodeError::unknown_field(\"");
+            s.push_str(self.name);
+            s.push_str("\", id));\n                        }\n");
+        } else {
+            s.push_str("                        ::std::result::Result::Ok(_) => {\n                            map.next_value::<::typedpack::serde::de::IgnoredAny>()?;\n                        }\n");
+        }
+        s.push_str("                        ::std::result::Result::Err(error) => return ::typedpack::skip_map(map, error),\n                    }\n                }\n");
+        for field in &self.fields {
+            if !field.optional {
+                s.push_str(&format!(
+                    "                let ::std::option::Option::Some(field_{name}) = field_{name} else {{
+                    return ::std::result::Result::Ok(::std::result::Result::Err(::typedpack::DecodeError::missing_field(\"{type_name}\", \"{name}\", {id})));
+                }};
+",
+                    id = field.id,
+                    name = field.name,
+                    type_name = self.name,
+                ));
             }
         }
-        s.push_str("        })\n    }\n}\n\n");
-
-        s.push_str("impl ::typedpack::FromRmpValue for r#");
+        s.push_str("                *self.0 = ::std::option::Option::Some(r#");
         s.push_str(self.name);
-        s.push_str("{\n    fn from(value: ::typedpack::rmpv::Value) -> ::std::result::Result<Self, &'static ::std::primitive::str> {\n        ::typedpack::serde::Deserialize::deserialize(value).map_err(|_| \"could not deserialize struct\")\n    }\n}");
+        s.push_str(" {\n");
+        for field in &self.fields {
+            s.push_str("                    r#");
+            s.push_str(field.name);
+            s.push_str(": field_");
+            s.push_str(field.name);
+            if let Some(default) = field.rust_default_value(options) {
+                // only a `String` default allocates, so other defaults are evaluated eagerly
+                if let Some(DefaultValue::String(_)) = field.default {
+                    s.push_str(".unwrap_or_else(|| ");
+                } else {
+                    s.push_str(".unwrap_or(");
+                }
+                s.push_str(&default);
+                s.push(')');
+            }
+            s.push_str(",\n");
+        }
+        if self.preserve_unknown {
+            s.push_str(
+                "                    r#unknown_fields: ::typedpack::UnknownFields(unknown_fields),\n",
+            );
+        }
+        s.push_str("                });\n                ::std::result::Result::Ok(::std::result::Result::Ok(()))\n            }\n        }\n\n        let mut value = ::std::option::Option::None;\n        if let ::std::result::Result::Err(error) = ::typedpack::decode_any(deserializer, Visitor(&mut value))? {\n            return ::std::result::Result::Ok(::std::result::Result::Err(error));\n        }\n        ::std::result::Result::Ok(::std::result::Result::Ok(value.expect(\"the visitor stores the value\")))\n    }\n}\n\n");
+        s.push_str(allow_deprecated);
+        let checks = self
+            .fields
+            .iter()
+            .map(|field| field.rust_validate(self.name, options))
+            .collect::<String>();
+        s.push_str(&rust_validate_impl(self.name, &checks));
         s
     }
 }
 
 impl Enum<'_> {
     /// Generates a Rust `enum` definition.
-    pub fn rust_enum(&self) -> String {
+    pub fn rust_enum(&self, options: &Options) -> String {
         match self {
-            Self::Tagged { name, variants } => {
-                let mut s = String::from(
-                    "#[derive(::std::clone::Clone, ::std::fmt::Debug, ::std::cmp::PartialEq, ::std::cmp::Eq, ::std::hash::Hash)]\npub enum r#",
+            Self::Tagged {
+                name,
+                open,
+                repr,
+                variants,
+                deprecated,
+                annotations,
+                doc,
+                contains_floats,
+                ..
+            } => {
+                let allow_deprecated = rust_allow_deprecated(
+                    deprecated.is_some()
+                        || variants.iter().any(|variant| {
+                            variant.deprecated.is_some()
+                                || variant
+                                    .r#type
+                                    .as_ref()
+                                    .is_some_and(StructFieldType::contains_reference)
+                        }),
                 );
+                let mut s = rust_doc_comment(doc, "");
+                s.push_str(&rust_deprecated_attribute(deprecated.as_ref(), ""));
+                s.push_str(&rust_derives(
+                    DEFAULT_DERIVES,
+                    *contains_floats,
+                    annotations,
+                    options,
+                ));
+                s.push_str(allow_deprecated);
+                s.push_str("pub enum r#");
                 s.push_str(name);
                 s.push_str(" {\n");
 
                 for variant in variants {
+                    s.push_str(&rust_doc_comment(&variant.doc, "    "));
+                    s.push_str(&rust_deprecated_attribute(
+                        variant.deprecated.as_ref(),
+                        "    ",
+                    ));
+                    s.push_str(&rust_annotation_attributes(&variant.annotations, "    "));
                     s.push_str("    r#");
                     s.push_str(variant.name);
-                    s.push_str("(r#");
-                    s.push_str(variant.r#type);
-                    s.push_str("),\n");
+                    if let Some(r#type) = &variant.r#type {
+                        s.push('(');
+                        s.push_str(&r#type.rust_type(options));
+                        s.push(')');
+                    }
+                    s.push_str(",\n");
+                }
+                if *open {
+                    s.push_str("    /// A variant which is not in the schema, with its ID and data.\n    Unknown(");
+                    s.push_str(repr.rust_type());
+                    s.push_str(", ::typedpack::UnknownValue),\n");
                 }
-                s.push_str("}\n\nimpl ::typedpack::serde::Serialize for r#");
+                s.push_str("}\n\n");
+                s.push_str(allow_deprecated);
+                s.push_str("impl ::typedpack::serde::Serialize for r#");
                 s.push_str(name);
                 s.push_str(" {\n    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>\n    where\n        S: ::typedpack::serde::Serializer,\n    {\n        let mut seq = serializer.serialize_seq(::std::option::Option::Some(2))?;\n        match self {\n");
 
                 for variant in variants {
                     s.push_str("            Self::r#");
                     s.push_str(variant.name);
-                    s.push_str("(data) => {\n                ::typedpack::serde::ser::SerializeSeq::serialize_element(&mut seq, &");
+                    // unit variants are encoded with `nil` as their value
+                    let value = match &variant.r#type {
+                        Some(r#type) if r#type.needs_conversion_to_serialize(options) => {
+                            format!("&{}", r#type.rust_serializable_value("data", true, options))
+                        }
+                        Some(_) => String::from("data"),
+                        None => String::from("&()"),
+                    };
+                    if variant.r#type.is_some() {
+                        s.push_str("(data)");
+                    }
+                    s.push_str(" => {\n                ::typedpack::serde::ser::SerializeSeq::serialize_element(&mut seq, &");
                     s.push_str(&variant.id.to_string());
-                    s.push_str(")?;\n                ::typedpack::serde::ser::SerializeSeq::serialize_element(&mut seq, data)?;\n            }\n");
+                    s.push_str(")?;\n                ::typedpack::serde::ser::SerializeSeq::serialize_element(&mut seq, ");
+                    s.push_str(&value);
+                    s.push_str(")?;\n            }\n");
                 }
-                s.push_str("        }\n        ::typedpack::serde::ser::SerializeSeq::end(seq)\n    }\n}\n\nimpl<'de> ::typedpack::serde::Deserialize<'de> for r#");
-                s.push_str(name);
-                s.push_str(" {\n    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>\n    where\n        D: ::typedpack::serde::Deserializer<'de>,\n    {\n        struct Visitor;\n        impl<'de> ::typedpack::serde::de::Visitor<'de> for Visitor {\n            type Value = r#");
+                if *open {
+                    s.push_str("            Self::Unknown(id, data) => {\n                ::typedpack::serde::ser::SerializeSeq::serialize_element(&mut seq, id)?;\n                ::typedpack::serde::ser::SerializeSeq::serialize_element(&mut seq, data)?;\n            }\n");
+                }
+                s.push_str("        }\n        ::typedpack::serde::ser::SerializeSeq::end(seq)\n    }\n}\n\n");
+                s.push_str(allow_deprecated);
+                s.push_str(&rust_deserialize_impl(name));
+                s.push_str("\n\n");
+                s.push_str(allow_deprecated);
+                s.push_str(&rust_decode_fn_start(name));
+                s.push_str("        struct Visitor;\n        impl<'de> ::typedpack::DecodeVisitor<'de> for Visitor {\n            type Value = r#");
                 s.push_str(name);
-                s.push_str(";\n\n            fn expecting(&self, formatter: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {\n                formatter.write_str(\"a 2-tuple\")\n            }\n\n            fn visit_seq<A>(self, mut seq: A) -> ::std::result::Result<Self::Value, A::Error>\n             where\n                A: ::typedpack::serde::de::SeqAccess<'de>,\n            {\n                let ::std::option::Option::Some(id) = seq.next_element()? else {\n                    return ::std::result::Result::Err(::typedpack::serde::de::Error::custom(\"invalid enum tag\"));\n                };\n                let value = match id {\n");
+                s.push_str(";\n            const EXPECTED: ::typedpack::MessagePackType = ::typedpack::MessagePackType::Array;\n\n            fn visit_seq<A>(self, mut seq: A) -> ::std::result::Result<::std::result::Result<Self::Value, ::typedpack::DecodeError>, A::Error>\n            where\n                A: ::typedpack::serde::de::SeqAccess<'de>,\n            {\n                let tag = match ::typedpack::decode_tag::<_, ");
+                s.push_str(repr.rust_type());
+                s.push_str(">(&mut seq)? {\n                    ::std::result::Result::Ok(tag) => tag,\n                    ::std::result::Result::Err(error) => return ::typedpack::skip_seq(seq, error),\n                };\n                match tag {\n");
 
                 for variant in variants {
-                    s.push_str("                    ");
-                    s.push_str(&variant.id.to_string());
-                    s.push_str("u8 => r#");
+                    s.push_str(&format!(
+                        "                    {id} => ::typedpack::decode_variant(seq, \"{name}\", \"{variant_name}\", {id}, {constructor}),\n",
+                        id = variant.id,
+                        variant_name = variant.name,
+                        constructor = if variant.r#type.is_some() {
+                            format!("r#{name}::r#{variant_name}", variant_name = variant.name)
+                        } else {
+                            format!("|()| r#{name}::r#{variant_name}", variant_name = variant.name)
+                        },
+                    ));
+                }
+
+                if *open {
+                    s.push_str(&format!(
+                        "                    _ => ::typedpack::decode_variant(seq, \"{name}\", \"Unknown\", {id}, |data| r#{name}::Unknown(tag, data)),\n",
+                        id = repr.rust_id("tag"),
+                    ));
+                } else {
+                    s.push_str("                    _ => ::typedpack::skip_seq(seq, ::typedpack::DecodeError::unknown_variant(\"");
                     s.push_str(name);
-                    s.push_str("::r#");
-                    s.push_str(variant.name);
-                    s.push_str("(seq.next_element()?.ok_or_else(|| ::typedpack::serde::de::Error::custom(\"invalid tagged enum data\"))?),\n");
+                    s.push_str("\", ");
+                    s.push_str(&repr.rust_id("tag"));
+                    s.push_str(")),\n");
                 }
+                s.push_str("                }\n            }\n        }\n\n        ::typedpack::decode_any(deserializer, Visitor)\n    }\n}\n\n");
 
-                s.push_str("                    _ => {\n                        return ::std::result::Result::Err(::typedpack::serde::de::Error::custom(\"invalid enum tag\"));\n                    }\n                };\n                if seq.next_element::<::typedpack::serde::de::IgnoredAny>()?.is_some() {\n                    return ::std::result::Result::Err(::typedpack::serde::de::Error::custom(\"invalid tagged enum data\"));\n                }\n                ::std::result::Result::Ok(value)\n            }\n        }\n\n        deserializer.deserialize_tuple(2, Visitor)\n    }\n}");
+                let nested = |variant: &TaggedEnumVariant<'_>, indent: &str| match &variant.r#type {
+                    Some(r#type) => r#type.rust_validate_nested(
+                        "value",
+                        &format!(
+                            ".in_variant(\"{name}\", \"{variant_name}\", {id})",
+                            variant_name = variant.name,
+                            id = variant.id
+                        ),
+                        0,
+                        indent,
+                    ),
+                    None => String::new(),
+                };
+                let checked_variants: Vec<_> = variants
+                    .iter()
+                    .filter(|variant| !nested(variant, "").is_empty())
+                    .collect();
+                let matches_every_variant = !*open && checked_variants.len() == variants.len();
+                let mut checks = String::new();
+                if let ([variant], false) = (&checked_variants[..], matches_every_variant) {
+                    // a `match` with a single arm besides `_` would be linted by Clippy
+                    checks.push_str("        if let Self::r#");
+                    checks.push_str(variant.name);
+                    checks.push_str("(value) = self {\n");
+                    checks.push_str(&nested(variant, "            "));
+                    checks.push_str("        }\n");
+                } else if !checked_variants.is_empty() {
+                    checks.push_str("        match self {\n");
+                    for variant in &checked_variants {
+                        checks.push_str("            Self::r#");
+                        checks.push_str(variant.name);
+                        checks.push_str("(value) => {\n");
+                        checks.push_str(&nested(variant, "                "));
+                        checks.push_str("            }\n");
+                    }
+                    if !matches_every_variant {
+                        checks.push_str("            _ => {}\n");
+                    }
+                    checks.push_str("        }\n");
+                }
+                s.push_str(allow_deprecated);
+                s.push_str(&rust_validate_impl(name, &checks));
                 s
             }
-            Self::Untagged { name, variants } => {
-                let mut s = String::from(
-                    "#[derive(::std::clone::Clone, ::std::fmt::Debug, ::std::cmp::PartialEq, ::std::cmp::Eq, ::std::hash::Hash, ::typedpack::serde_repr::Serialize_repr, ::typedpack::serde_repr::Deserialize_repr)]\n#[repr(u8)]\npub enum r#",
+            Self::Untagged {
+                name,
+                open,
+                repr,
+                variants,
+                deprecated,
+                annotations,
+                doc,
+                ..
+            } => {
+                let allow_deprecated = rust_allow_deprecated(
+                    deprecated.is_some()
+                        || variants.iter().any(|variant| variant.deprecated.is_some()),
                 );
+                let mut s = rust_doc_comment(doc, "");
+                s.push_str(&rust_deprecated_attribute(deprecated.as_ref(), ""));
+                let derives = [
+                    "::std::clone::Clone",
+                    "::std::fmt::Debug",
+                    "::std::cmp::PartialEq",
+                    "::std::cmp::Eq",
+                    "::std::cmp::PartialOrd",
+                    "::std::cmp::Ord",
+                    "::std::hash::Hash",
+                    "::typedpack::serde_repr::Serialize_repr",
+                ];
+                // open enums have a variant with data, so `Serialize` is implemented below
+                let derives = if *open {
+                    &derives[..derives.len() - 1]
+                } else {
+                    &derives[..]
+                };
+                s.push_str(&rust_derives(derives, false, annotations, options));
+                s.push_str(allow_deprecated);
+                if !*open {
+                    s.push_str(match repr {
+                        EnumRepr::U8 => "#[repr(u8)]\n",
+                        EnumRepr::U16 => "#[repr(u16)]\n",
+                    });
+                }
+                s.push_str("pub enum r#");
                 s.push_str(name);
                 s.push_str(" {\n");
 
                 for variant in variants {
+                    s.push_str(&rust_doc_comment(&variant.doc, "    "));
+                    s.push_str(&rust_deprecated_attribute(
+                        variant.deprecated.as_ref(),
+                        "    ",
+                    ));
+                    s.push_str(&rust_annotation_attributes(&variant.annotations, "    "));
                     s.push_str("    r#");
                     s.push_str(variant.name);
-                    s.push_str(" = ");
-                    s.push_str(&variant.id.to_string());
+                    if !*open {
+                        s.push_str(" = ");
+                        s.push_str(&variant.id.to_string());
+                    }
                     s.push_str(",\n");
                 }
-                s.push('}');
+                if *open {
+                    s.push_str(
+                        "    /// A variant which is not in the schema, with its ID.\n    Unknown(",
+                    );
+                    s.push_str(repr.rust_type());
+                    s.push_str("),\n");
+                }
+                s.push_str("}\n\n");
+                if *open {
+                    s.push_str(allow_deprecated);
+                    s.push_str("impl ::typedpack::serde::Serialize for r#");
+                    s.push_str(name);
+                    s.push_str(" {\n    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>\n    where\n        S: ::typedpack::serde::Serializer,\n    {\n        serializer.");
+                    s.push_str(match repr {
+                        EnumRepr::U8 => "serialize_u8",
+                        EnumRepr::U16 => "serialize_u16",
+                    });
+                    s.push_str("(match self {\n");
+                    for variant in variants {
+                        s.push_str(&format!(
+                            "            Self::r#{variant_name} => {id},\n",
+                            id = variant.id,
+                            variant_name = variant.name,
+                        ));
+                    }
+                    s.push_str("            Self::Unknown(id) => *id,\n        })\n    }\n}\n\n");
+                }
+                s.push_str(allow_deprecated);
+                s.push_str(&rust_deserialize_impl(name));
+                s.push_str("\n\n");
+                s.push_str(allow_deprecated);
+                s.push_str(&rust_decode_fn_start(name));
+                s.push_str("        ::std::result::Result::Ok(match <");
+                s.push_str(repr.rust_type());
+                s.push_str(" as ::typedpack::Decode<'de>>::decode(deserializer)? {\n");
+                for variant in variants {
+                    s.push_str(&format!(
+                        "            ::std::result::Result::Ok({id}) => ::std::result::Result::Ok(Self::r#{variant_name}),\n",
+                        id = variant.id,
+                        variant_name = variant.name,
+                    ));
+                }
+                if *open {
+                    s.push_str("            ::std::result::Result::Ok(id) => ::std::result::Result::Ok(Self::Unknown(id)),\n");
+                } else {
+                    s.push_str("            ::std::result::Result::Ok(id) => ::std::result::Result::Err(::typedpack::DecodeError::unknown_variant(\"");
+                    s.push_str(name);
+                    s.push_str("\", ");
+                    s.push_str(&repr.rust_id("id"));
+                    s.push_str(")),\n");
+                }
+                s.push_str("            ::std::result::Result::Err(error) => ::std::result::Result::Err(error),\n        })\n    }\n}\n\n");
+                s.push_str(allow_deprecated);
+                s.push_str(&rust_validate_impl(name, ""));
                 s
             }
         }
     }
 }
+
+impl EnumRepr {
+    /// Returns the Rust type of the variant IDs.
+    pub fn rust_type(self) -> &'static str {
+        match self {
+            Self::U8 => "::std::primitive::u8",
+            Self::U16 => "::std::primitive::u16",
+        }
+    }
+
+    /// Returns an expression which converts a variant ID to a `u16`, as used in `DecodeError`.
+    fn rust_id(self, value_name: &str) -> String {
+        match self {
+            Self::U8 => format!("::std::primitive::u16::from({value_name})"),
+            Self::U16 => String::from(value_name),
+        }
+    }
+}
+
+fn rust_deserialize_impl(name: &str) -> String {
+    format!(
+        "impl<'de> ::typedpack::serde::Deserialize<'de> for r#{name} {{\n    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>\n    where\n        D: ::typedpack::serde::Deserializer<'de>,\n    {{\n        ::typedpack::deserialize(deserializer)\n    }}\n}}"
+    )
+}
+
+/// Returns the start of an implementation of `typedpack::Decode`, up to the body of `decode`.
+fn rust_decode_fn_start(name: &str) -> String {
+    format!(
+        "impl<'de> ::typedpack::Decode<'de> for r#{name} {{\n    fn decode<D>(deserializer: D) -> ::std::result::Result<::std::result::Result<Self, ::typedpack::DecodeError>, D::Error>\n    where\n        D: ::typedpack::serde::Deserializer<'de>,\n    {{\n"
+    )
+}
+
+/// Converts a module path component, i.e. a file or directory name, into a Rust module name.
+///
+/// Characters other than ASCII letters and digits are replaced with underscores.
+pub fn rust_module_name(name: &str) -> String {
+    let mut s: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if s.is_empty() || s.starts_with(|c: char| c.is_ascii_digit()) || s == "_" {
+        s.insert(0, '_');
+    }
+    if matches!(s.as_str(), "crate" | "self" | "Self" | "super") {
+        s.push('_');
+    }
+    s
+}
+
+impl Module<'_> {
+    /// Returns the path of the module's `mod` item generated by [`rust_modules`], e.g.
+    /// `r#users::r#email`, relative to the [`Options::module`] wrapper if there is one.
+    pub fn rust_path(&self) -> String {
+        self.module_path
+            .iter()
+            .map(|component| format!("r#{}", rust_module_name(component)))
+            .collect::<Vec<_>>()
+            .join("::")
+    }
+
+    /// Generates Rust `use` declarations for the types the module imports.
+    ///
+    /// Paths are relative, assuming every module is placed according to its module path, as
+    /// done by [`rust_modules`].
+    pub fn rust_imports(&self) -> String {
+        let mut s = String::new();
+        for import in &self.imports {
+            for name in &import.names {
+                s.push_str("#[allow(unused_imports)]\nuse ");
+                for _ in self.module_path {
+                    s.push_str("super::");
+                }
+                for component in import.module_path {
+                    s.push_str("r#");
+                    s.push_str(&rust_module_name(component));
+                    s.push_str("::");
+                }
+                s.push_str("r#");
+                s.push_str(name);
+                s.push_str(";\n");
+            }
+        }
+        s
+    }
+
+    /// Generates the Rust contents of the module, i.e. its imports and type definitions.
+    pub fn rust_module(&self, options: &Options) -> String {
+        let mut s = self.rust_imports();
+        if !s.is_empty() {
+            s.push('\n');
+        }
+        for r#type in &self.types {
+            match r#type {
+                Type::Struct(r#struct) => {
+                    s.push_str(&r#struct.rust_struct(options));
+                }
+                Type::Enum(r#enum) => {
+                    s.push_str(&r#enum.rust_enum(options));
+                }
+            }
+            s.push_str("\n\n");
+        }
+        s
+    }
+}
+
+/// Generates Rust code for the given modules, each nested inside `mod` items according to its
+/// module path.
+///
+/// If [`Options::module`] is set, everything is additionally wrapped in a `mod` with that name.
+pub fn rust_modules(modules: &[Module<'_>], options: &Options) -> String {
+    let mut modules: Vec<_> = modules.iter().collect();
+    modules.sort_by_key(|module| module.module_path);
+    let mut s = String::new();
+    if let Some(module) = &options.module {
+        s.push_str("pub mod r#");
+        s.push_str(module);
+        s.push_str(" {\n");
+    }
+    push_rust_modules(&mut s, &modules, 0, options);
+    if options.module.is_some() {
+        s.push_str("}\n");
+    }
+    s
+}
+
+fn push_rust_modules(s: &mut String, modules: &[&Module<'_>], depth: usize, options: &Options) {
+    let mut modules = modules;
+    while let Some(first) = modules.first() {
+        let name = &first.module_path[depth];
+        let group_len = modules
+            .iter()
+            .take_while(|module| module.module_path[depth] == *name)
+            .count();
+        let (group, rest) = modules.split_at(group_len);
+
+        s.push_str("pub mod r#");
+        s.push_str(&rust_module_name(name));
+        s.push_str(" {\n");
+        let (own, children): (Vec<_>, Vec<_>) = group
+            .iter()
+            .copied()
+            .partition(|module| module.module_path.len() == depth + 1);
+        for module in own {
+            s.push_str(&module.rust_module(options));
+        }
+        push_rust_modules(s, &children, depth + 1, options);
+        s.push_str("}\n\n");
+
+        modules = rest;
+    }
+}
@@ -1,6 +1,9 @@
 use std::collections::HashSet;
 
-use crate::{Enum, Struct, StructField, StructFieldType};
+use crate::{
+    Annotation, DefaultValue, Deprecated, Enum, EnumRepr, Module, Struct, StructField,
+    StructFieldType, Type, Warning, annotation_args, check_annotations,
+};
 
 /// Necessary imports for generated code.
 pub const FILE_HEADER: &str = "\
@@ -18,7 +21,6 @@ import {
     decodeFloat64,
     decodeInt,
     decodeInt64,
-    decodeMapKey,
     decodeMapLength,
     decodeString,
     encodeArrayHeader,
@@ -82,6 +84,7 @@ pub struct OptionsInput {
     pub encode_array_namespace: Option<String>,
     pub decode_array_namespace: Option<String>,
     pub export_decode_internal_namespace: Option<bool>,
+    pub reject_unknown_fields: Option<bool>,
 }
 
 /// Validated options for TypeScript code generation.
@@ -95,6 +98,7 @@ pub struct Options {
     encode_array_namespace: String,
     decode_array_namespace: String,
     export_decode_internal_namespace: bool,
+    reject_unknown_fields: bool,
 }
 
 impl Options {
@@ -121,6 +125,10 @@ impl Options {
     pub fn export_decode_internal_namespace(&self) -> bool {
         self.export_decode_internal_namespace
     }
+
+    pub fn reject_unknown_fields(&self) -> bool {
+        self.reject_unknown_fields
+    }
 }
 
 impl TryFrom<OptionsInput> for Options {
@@ -146,6 +154,7 @@ impl TryFrom<OptionsInput> for Options {
             export_decode_internal_namespace: value
                 .export_decode_internal_namespace
                 .unwrap_or(false),
+            reject_unknown_fields: value.reject_unknown_fields.unwrap_or(false),
         };
 
         let is_valid_name = |name: &str| {
@@ -193,18 +202,279 @@ impl TryFrom<OptionsInput> for Options {
     }
 }
 
+/// A function which returns the offset after the MessagePack value at `offset`, which is used to
+/// keep the encoded values of unknown fields and variants.
+const TYPESCRIPT_SKIP_VALUE: &str =
+    "    const skipValue = (data: DataView, offset: number): number => {
+        let remaining = 1;
+        while (remaining > 0) {
+            remaining -= 1;
+            const type = data.getUint8(offset);
+            offset += 1;
+            if (type <= 0x7f || type >= 0xe0) {
+                continue;
+            } else if (type <= 0x8f) {
+                remaining += 2 * (type & 0x0f);
+                continue;
+            } else if (type <= 0x9f) {
+                remaining += type & 0x0f;
+                continue;
+            } else if (type <= 0xbf) {
+                offset += type & 0x1f;
+                continue;
+            }
+            switch (type) {
+                case 0xc0: case 0xc2: case 0xc3: break;
+                case 0xc4: case 0xd9: offset += 1 + data.getUint8(offset); break;
+                case 0xc5: case 0xda: offset += 2 + data.getUint16(offset); break;
+                case 0xc6: case 0xdb: offset += 4 + data.getUint32(offset); break;
+                case 0xc7: offset += 2 + data.getUint8(offset); break;
+                case 0xc8: offset += 3 + data.getUint16(offset); break;
+                case 0xc9: offset += 5 + data.getUint32(offset); break;
+                case 0xcc: case 0xd0: offset += 1; break;
+                case 0xcd: case 0xd1: offset += 2; break;
+                case 0xca: case 0xce: case 0xd2: offset += 4; break;
+                case 0xcb: case 0xcf: case 0xd3: offset += 8; break;
+                case 0xd4: offset += 2; break;
+                case 0xd5: offset += 3; break;
+                case 0xd6: offset += 5; break;
+                case 0xd7: offset += 9; break;
+                case 0xd8: offset += 17; break;
+                case 0xdc: remaining += data.getUint16(offset); offset += 2; break;
+                case 0xdd: remaining += data.getUint32(offset); offset += 4; break;
+                case 0xde: remaining += 2 * data.getUint16(offset); offset += 2; break;
+                case 0xdf: remaining += 2 * data.getUint32(offset); offset += 4; break;
+                default: throw new Error(`invalid MessagePack type ${type}`);
+            }
+        }
+        return offset;
+    };
+
+";
+
+/// A function which encodes seconds and nanoseconds since the Unix epoch with the MessagePack
+/// timestamp extension, in the smallest of the 32-, 64- and 96-bit formats which can hold them.
+const TYPESCRIPT_ENCODE_TIMESTAMP_EXTENSION: &str = "    const encodeTimestampExtension = (seconds: bigint, nanos: number, data: DataView, offset: number): number => {
+        if (nanos === 0 && seconds >= 0n && seconds < 4_294_967_296n) {
+            data.setUint8(offset, 0xd6);
+            data.setInt8(offset + 1, -1);
+            data.setUint32(offset + 2, Number(seconds));
+            return 6;
+        } else if (seconds >= 0n && seconds < 17_179_869_184n) {
+            data.setUint8(offset, 0xd7);
+            data.setInt8(offset + 1, -1);
+            data.setBigUint64(offset + 2, (BigInt(nanos) << 34n) | seconds);
+            return 10;
+        }
+        data.setUint8(offset, 0xc7);
+        data.setUint8(offset + 1, 12);
+        data.setInt8(offset + 2, -1);
+        data.setUint32(offset + 3, nanos);
+        data.setBigInt64(offset + 7, seconds);
+        return 15;
+    };
+
+";
+
+/// A function which encodes a `timestamp`.
+const TYPESCRIPT_ENCODE_TIMESTAMP: &str = "    const encodeTimestamp = (value: Date, data: DataView, offset: number): number => {
+        const time = value.getTime();
+        if (Number.isNaN(time)) {
+            throw new Error(\"invalid timestamp\");
+        }
+        const seconds = Math.floor(time / 1000);
+        return encodeTimestampExtension(BigInt(seconds), (time - seconds * 1000) * 1_000_000, data, offset);
+    };
+
+";
+
+/// A function which encodes a `duration`.
+const TYPESCRIPT_ENCODE_DURATION: &str = "    const encodeDuration = (value: { seconds: bigint; nanos: number }, data: DataView, offset: number): number => {
+        if (value.seconds < 0n || value.seconds >= 9_223_372_036_854_775_808n || !Number.isSafeInteger(value.nanos) || value.nanos < 0 || value.nanos >= 1_000_000_000) {
+            throw new Error(\"invalid duration\");
+        }
+        return encodeTimestampExtension(value.seconds, value.nanos, data, offset);
+    };
+
+";
+
+/// A function which decodes a MessagePack timestamp extension value into the seconds and
+/// nanoseconds since the Unix epoch and the offset after it.
+const TYPESCRIPT_DECODE_TIMESTAMP_EXTENSION: &str = "    const decodeTimestampExtension = (data: DataView, offset: number): [bigint, number, number] => {
+        const type = data.getUint8(offset);
+        let seconds: bigint;
+        let nanos: number;
+        if (type === 0xd6 && data.getInt8(offset + 1) === -1) {
+            seconds = BigInt(data.getUint32(offset + 2));
+            nanos = 0;
+            offset += 6;
+        } else if (type === 0xd7 && data.getInt8(offset + 1) === -1) {
+            const value = data.getBigUint64(offset + 2);
+            seconds = value & 0x3_ffff_ffffn;
+            nanos = Number(value >> 34n);
+            offset += 10;
+        } else if (type === 0xc7 && data.getUint8(offset + 1) === 12 && data.getInt8(offset + 2) === -1) {
+            nanos = data.getUint32(offset + 3);
+            seconds = data.getBigInt64(offset + 7);
+            offset += 15;
+        } else {
+            throw new Error(\"invalid timestamp\");
+        }
+        if (nanos >= 1_000_000_000) {
+            throw new Error(\"invalid timestamp\");
+        }
+        return [seconds, nanos, offset];
+    };
+
+";
+
+/// A function which decodes a `timestamp`. `Date` only has millisecond precision, so the
+/// nanoseconds are truncated.
+const TYPESCRIPT_DECODE_TIMESTAMP: &str =
+    "    const decodeTimestamp = (data: DataView, offset: number): [Date, number] => {
+        const [seconds, nanos, newOffset] = decodeTimestampExtension(data, offset);
+        const value = new Date(Number(seconds) * 1000 + Math.floor(nanos / 1_000_000));
+        if (Number.isNaN(value.getTime())) {
+            throw new Error(\"timestamp is out of range\");
+        }
+        return [value, newOffset];
+    };
+
+";
+
+/// A function which decodes a `duration`.
+const TYPESCRIPT_DECODE_DURATION: &str = "    const decodeDuration = (data: DataView, offset: number): [{ seconds: bigint; nanos: number }, number] => {
+        const [seconds, nanos, newOffset] = decodeTimestampExtension(data, offset);
+        if (seconds < 0n) {
+            throw new Error(\"duration is out of range\");
+        }
+        return [{ seconds, nanos }, newOffset];
+    };
+
+";
+
+/// Returns the functions needed to encode (or decode, if `encode` is false) the timestamps and
+/// durations in values of `types`.
+fn typescript_timestamp_helpers<'a>(
+    types: impl IntoIterator<Item = &'a StructFieldType<'a>>,
+    encode: bool,
+) -> String {
+    let (mut timestamps, mut durations) = (false, false);
+    for r#type in types {
+        timestamps |= r#type.contains_type(&StructFieldType::Timestamp);
+        durations |= r#type.contains_type(&StructFieldType::Duration);
+    }
+    let mut s = String::new();
+    if !timestamps && !durations {
+        return s;
+    }
+    let [extension, timestamp, duration] = if encode {
+        [
+            TYPESCRIPT_ENCODE_TIMESTAMP_EXTENSION,
+            TYPESCRIPT_ENCODE_TIMESTAMP,
+            TYPESCRIPT_ENCODE_DURATION,
+        ]
+    } else {
+        [
+            TYPESCRIPT_DECODE_TIMESTAMP_EXTENSION,
+            TYPESCRIPT_DECODE_TIMESTAMP,
+            TYPESCRIPT_DECODE_DURATION,
+        ]
+    };
+    s.push_str(extension);
+    if timestamps {
+        s.push_str(timestamp);
+    }
+    if durations {
+        s.push_str(duration);
+    }
+    s
+}
+
+/// Generates a TypeScript JSDoc comment.
+fn typescript_doc_comment(
+    doc: &[&str],
+    deprecated: Option<&Deprecated<'_>>,
+    indent: &str,
+) -> String {
+    if doc.is_empty() && deprecated.is_none() {
+        return String::new();
+    }
+    let mut s = String::from(indent);
+    s.push_str("/**\n");
+    for line in doc {
+        s.push_str(indent);
+        s.push_str(" *");
+        if !line.is_empty() {
+            s.push(' ');
+            s.push_str(&line.replace("*/", "*\\/"));
+        }
+        s.push('\n');
+    }
+    if let Some(deprecated) = deprecated {
+        if !doc.is_empty() {
+            s.push_str(indent);
+            s.push_str(" *\n");
+        }
+        s.push_str(indent);
+        s.push_str(" * @deprecated");
+        if let Some(note) = &deprecated.note {
+            s.push(' ');
+            s.push_str(&note.replace("*/", "*\\/"));
+        }
+        s.push('\n');
+    }
+    s.push_str(indent);
+    s.push_str(" */\n");
+    s
+}
+
+/// Returns the length of an unsigned integer encoded with `encodeUint`.
+fn typescript_uint_len(value: u16) -> usize {
+    match value {
+        0..128 => 1,
+        128..256 => 2,
+        _ => 3,
+    }
+}
+
+impl EnumRepr {
+    /// Returns the maximum encoded length of a variant ID.
+    fn typescript_max_len(self) -> usize {
+        match self {
+            Self::U8 => 2,
+            Self::U16 => 3,
+        }
+    }
+}
+
 impl Enum<'_> {
     /// Generates a TypeScript `enum` and encode/decode functions.
     pub fn typescript_enum(&self, options: &Options) -> String {
         match self {
-            Self::Tagged { name, variants } => {
+            Self::Tagged {
+                name,
+                open,
+                repr,
+                variants,
+                deprecated,
+                doc,
+                ..
+            } => {
                 let mut s = String::from("export namespace ");
                 s.push_str(&options.types_namespace);
-                s.push_str(" {\n    export enum ");
+                s.push_str(" {\n");
+                s.push_str(&typescript_doc_comment(doc, deprecated.as_ref(), "    "));
+                s.push_str("    export enum ");
                 s.push_str(name);
                 s.push_str(" {\n");
 
                 for variant in variants {
+                    s.push_str(&typescript_doc_comment(
+                        &variant.doc,
+                        variant.deprecated.as_ref(),
+                        "        ",
+                    ));
                     s.push_str("        ");
                     s.push_str(variant.name);
                     s.push_str(" = ");
@@ -228,11 +498,26 @@ impl Enum<'_> {
                     type_union.push('.');
                     type_union.push_str(variant.name);
                     type_union.push_str(", ");
-                    type_union.push_str(&options.types_namespace);
-                    type_union.push('.');
-                    type_union.push_str(variant.r#type);
+                    match &variant.r#type {
+                        Some(r#type) => type_union
+                            .push_str(&r#type.typescript_qualified_type(&options.types_namespace)),
+                        None => type_union.push_str("null"),
+                    }
                     type_union.push(']');
                 }
+                if *open {
+                    type_union.push_str(" | [number, Uint8Array]");
+                }
+                // the unknown variant prevents narrowing the value by the tag
+                let value_name = |r#type: &StructFieldType<'_>| {
+                    let qualified_type = r#type.typescript_qualified_type(&options.types_namespace);
+                    match r#type {
+                        _ if !*open => String::from("value"),
+                        // only passed as an argument
+                        StructFieldType::Reference { .. } => format!("value as {qualified_type}"),
+                        _ => format!("(value as {qualified_type})"),
+                    }
+                };
 
                 s.push_str(&type_union);
                 s.push_str("): number => {\n");
@@ -244,11 +529,35 @@ impl Enum<'_> {
                     s.push_str(name);
                     s.push('.');
                     s.push_str(variant.name);
-                    s.push_str(") {\n            return 2 + TypedpackMaxLength.");
-                    s.push_str(variant.r#type);
-                    s.push_str("(value);\n        }\n");
+                    s.push_str(") {\n            return ");
+                    // the array header and the tag
+                    let header_len = 1 + typescript_uint_len(variant.id);
+                    match &variant.r#type {
+                        Some(r#type) => {
+                            s.push_str(&header_len.to_string());
+                            s.push_str(" + ");
+                            s.push_str(&r#type.typescript_encoded_max_len(&value_name(r#type)));
+                        }
+                        // unit variants are encoded with `nil` as their value
+                        None => s.push_str(&(header_len + 1).to_string()),
+                    }
+                    s.push_str(";\n        }\n");
+                }
+                if *open {
+                    s.push_str("        return ");
+                    s.push_str(&(1 + repr.typescript_max_len()).to_string());
+                    s.push_str(" + (value as Uint8Array).byteLength;\n");
+                } else {
+                    s.push_str("        throw new Error(\"invalid enum tag\");\n");
                 }
-                s.push_str("        throw new Error(\"invalid enum tag\");\n    }\n}\n\nnamespace TypedpackEncodeInternal {\n    export const ");
+                s.push_str("    }\n}\n\nnamespace TypedpackEncodeInternal {\n");
+                s.push_str(&typescript_timestamp_helpers(
+                    variants
+                        .iter()
+                        .filter_map(|variant| variant.r#type.as_ref()),
+                    true,
+                ));
+                s.push_str("    export const ");
                 s.push_str(name);
                 s.push_str(" = (tagValue: ");
                 s.push_str(&type_union);
@@ -260,17 +569,52 @@ impl Enum<'_> {
                     s.push_str(name);
                     s.push('.');
                     s.push_str(variant.name);
-                    s.push_str(
-                        ") {\n            data.setUint8(offset, 0x92);\n            data.setUint8(offset + 1, ",
-                    );
-                    s.push_str(&variant.id.to_string());
-                    s.push_str(");\n            return 2 + TypedpackEncodeInternal.");
-                    s.push_str(variant.r#type);
-                    s.push_str("(value, data, offset + 2);\n        }\n");
+                    s.push_str(") {\n            data.setUint8(offset, 0x92);\n");
+                    if variant.id < 128 {
+                        s.push_str("            data.setUint8(offset + 1, ");
+                        s.push_str(&variant.id.to_string());
+                        s.push_str(");\n");
+                    } else {
+                        s.push_str("            encodeUint(");
+                        s.push_str(&variant.id.to_string());
+                        s.push_str(", data, offset + 1);\n");
+                    }
+                    let header_len = (1 + typescript_uint_len(variant.id)).to_string();
+                    match &variant.r#type {
+                        Some(r#type @ StructFieldType::Reference { name: type_name }) => {
+                            s.push_str("            return ");
+                            s.push_str(&header_len);
+                            s.push_str(" + TypedpackEncodeInternal.");
+                            s.push_str(type_name);
+                            s.push('(');
+                            s.push_str(&value_name(r#type));
+                            s.push_str(", data, offset + ");
+                            s.push_str(&header_len);
+                            s.push_str(");\n");
+                        }
+                        Some(r#type) => {
+                            s.push_str("            const originalOffset = offset;\n            offset += ");
+                            s.push_str(&header_len);
+                            s.push_str(";\n");
+                            r#type.push_typescript_encode_nested(&mut s, &value_name(r#type), 12);
+                            s.push_str("\n            return offset - originalOffset;\n");
+                        }
+                        None => {
+                            s.push_str("            data.setUint8(offset + ");
+                            s.push_str(&header_len);
+                            s.push_str(", 0xc0);\n            return ");
+                            s.push_str(&(1 + typescript_uint_len(variant.id) + 1).to_string());
+                            s.push_str(";\n");
+                        }
+                    }
+                    s.push_str("        }\n");
                 }
-                s.push_str(
-                    "        throw new Error(\"invalid enum tag\");\n    }\n}\n\nexport namespace ",
-                );
+                if *open {
+                    s.push_str("        const bytes = value as Uint8Array;\n        data.setUint8(offset, 0x92);\n        const headerLength = 1 + encodeUint(tag, data, offset + 1);\n        new Uint8Array(data.buffer, data.byteOffset + offset + headerLength, bytes.byteLength).set(bytes);\n        return headerLength + bytes.byteLength;\n");
+                } else {
+                    s.push_str("        throw new Error(\"invalid enum tag\");\n");
+                }
+                s.push_str("    }\n}\n\nexport namespace ");
                 s.push_str(&options.encode_namespace);
                 s.push_str(" {\n    export const ");
                 s.push_str(name);
@@ -284,17 +628,30 @@ impl Enum<'_> {
                 if options.export_decode_internal_namespace {
                     s.push_str("export ");
                 }
-                s.push_str("namespace TypedpackDecodeInternal {\n    export const ");
+                s.push_str("namespace TypedpackDecodeInternal {\n");
+                if *open {
+                    s.push_str(TYPESCRIPT_SKIP_VALUE);
+                }
+                s.push_str(&typescript_timestamp_helpers(
+                    variants
+                        .iter()
+                        .filter_map(|variant| variant.r#type.as_ref()),
+                    false,
+                ));
+                s.push_str("    export const ");
                 s.push_str(name);
                 s.push_str(" = (data: DataView, offset: number = 0): [");
                 s.push_str(&type_union);
-                s.push_str(", number] => {\n        if (data.getUint8(offset) != 0x92) {\n            throw new Error(\"invalid enum tag\");\n        }\n        offset += 1;\n        const tag = data.getUint8(offset);\n        offset += 1;\n        if (!");
-                s.push_str(&options.types_namespace);
-                s.push('.');
-                s.push_str(name);
-                s.push_str(
-                    "[tag]) {\n            throw new Error(\"invalid enum tag\");\n        }\n",
-                );
+                s.push_str(", number] => {\n        if (data.getUint8(offset) != 0x92) {\n            throw new Error(\"invalid enum tag\");\n        }\n        offset += 1;\n        const [tag, newOffset] = decodeInt(data, offset);\n        offset = newOffset;\n");
+                if !*open {
+                    s.push_str("        if (!");
+                    s.push_str(&options.types_namespace);
+                    s.push('.');
+                    s.push_str(name);
+                    s.push_str(
+                        "[tag]) {\n            throw new Error(\"invalid enum tag\");\n        }\n",
+                    );
+                }
 
                 for variant in variants {
                     s.push_str("        if (tag === ");
@@ -303,16 +660,35 @@ impl Enum<'_> {
                     s.push_str(name);
                     s.push('.');
                     s.push_str(variant.name);
-                    s.push_str(
-                        ") {\n            const [value, newOffset] = TypedpackDecodeInternal.",
-                    );
-                    s.push_str(variant.r#type);
-                    s.push_str("(data, offset);\n            offset = newOffset;\n            return [[tag, value], offset];\n        }\n");
+                    s.push_str(") {\n");
+                    match &variant.r#type {
+                        Some(StructFieldType::Reference { name: type_name }) => {
+                            s.push_str(
+                                "            const [value, newOffset] = TypedpackDecodeInternal.",
+                            );
+                            s.push_str(type_name);
+                            s.push_str("(data, offset);\n            offset = newOffset;\n            return [[tag, value], offset];\n");
+                        }
+                        Some(r#type) => {
+                            s.push_str("            let payload: ");
+                            s.push_str(&r#type.typescript_qualified_type(&options.types_namespace));
+                            s.push_str(";\n");
+                            r#type.push_typescript_decode_nested(&mut s, "payload", 12);
+                            s.push_str("\n            return [[tag, payload], offset];\n");
+                        }
+                        None => {
+                            s.push_str("            if (data.getUint8(offset) !== 0xc0) {\n                throw new Error(\"expected null\");\n            }\n            return [[tag, null], offset + 1];\n");
+                        }
+                    }
+                    s.push_str("        }\n");
                 }
 
-                s.push_str(
-                    "        throw new Error(\"invalid enum tag\");\n    }\n}\n\nexport namespace ",
-                );
+                if *open {
+                    s.push_str("        const end = skipValue(data, offset);\n        return [[tag, new Uint8Array(data.buffer.slice(data.byteOffset + offset, data.byteOffset + end))], end];\n");
+                } else {
+                    s.push_str("        throw new Error(\"invalid enum tag\");\n");
+                }
+                s.push_str("    }\n}\n\nexport namespace ");
                 s.push_str(&options.decode_namespace);
                 s.push_str(" {\n    export const ");
                 s.push_str(name);
@@ -341,14 +717,40 @@ impl Enum<'_> {
                 s.push_str("(data, offset);\n            offset = newOffset;\n            values.push(value);\n        }\n        return values;\n    };\n}");
                 s
             }
-            Self::Untagged { name, variants } => {
+            Self::Untagged {
+                name,
+                open,
+                repr,
+                variants,
+                deprecated,
+                doc,
+                ..
+            } => {
+                // unknown variants of open enums can have any ID which fits the repr
+                let max_len = if *open {
+                    repr.typescript_max_len()
+                } else {
+                    variants
+                        .iter()
+                        .map(|variant| typescript_uint_len(variant.id))
+                        .max()
+                        .unwrap_or(1)
+                };
+
                 let mut s = String::from("export namespace ");
                 s.push_str(&options.types_namespace);
-                s.push_str(" {\n    export enum ");
+                s.push_str(" {\n");
+                s.push_str(&typescript_doc_comment(doc, deprecated.as_ref(), "    "));
+                s.push_str("    export enum ");
                 s.push_str(name);
                 s.push_str(" {\n");
 
                 for variant in variants {
+                    s.push_str(&typescript_doc_comment(
+                        &variant.doc,
+                        variant.deprecated.as_ref(),
+                        "        ",
+                    ));
                     s.push_str("        ");
                     s.push_str(variant.name);
                     s.push_str(" = ");
@@ -362,15 +764,19 @@ impl Enum<'_> {
                 s.push_str(&options.types_namespace);
                 s.push('.');
                 s.push_str(name);
-                s.push_str(
-                    "): number => 1;\n}\n\nnamespace TypedpackEncodeInternal {\n    export const ",
-                );
+                s.push_str("): number => ");
+                s.push_str(&max_len.to_string());
+                s.push_str(";\n}\n\nnamespace TypedpackEncodeInternal {\n    export const ");
                 s.push_str(name);
                 s.push_str(" = (value: ");
                 s.push_str(&options.types_namespace);
                 s.push('.');
                 s.push_str(name);
-                s.push_str(", data: DataView, offset: number): number => {\n        data.setUint8(offset, value);\n        return 1;\n    };\n}\n\nexport namespace ");
+                if max_len == 1 {
+                    s.push_str(", data: DataView, offset: number): number => {\n        data.setUint8(offset, value);\n        return 1;\n    };\n}\n\nexport namespace ");
+                } else {
+                    s.push_str(", data: DataView, offset: number): number => encodeUint(value, data, offset);\n}\n\nexport namespace ");
+                }
                 s.push_str(&options.encode_namespace);
                 s.push_str(" {\n    export const ");
                 s.push_str(name);
@@ -378,9 +784,17 @@ impl Enum<'_> {
                 s.push_str(&options.types_namespace);
                 s.push('.');
                 s.push_str(name);
-                s.push_str("): DataView<ArrayBuffer> => {\n        const data = new DataView(new ArrayBuffer(1));\n        TypedpackEncodeInternal.");
-                s.push_str(name);
-                s.push_str("(value, data, 0);\n        return data;\n    }\n}\n\n");
+                if max_len == 1 {
+                    s.push_str("): DataView<ArrayBuffer> => {\n        const data = new DataView(new ArrayBuffer(1));\n        TypedpackEncodeInternal.");
+                    s.push_str(name);
+                    s.push_str("(value, data, 0);\n        return data;\n    }\n}\n\n");
+                } else {
+                    s.push_str("): DataView<ArrayBuffer> => {\n        const maxByteLength = ");
+                    s.push_str(&max_len.to_string());
+                    s.push_str(";\n        const data = new DataView(new ArrayBuffer(maxByteLength, { maxByteLength }));\n        const length = TypedpackEncodeInternal.");
+                    s.push_str(name);
+                    s.push_str("(value, data, 0);\n        data.buffer.resize(length);\n        return data;\n    }\n}\n\n");
+                }
                 if options.export_decode_internal_namespace {
                     s.push_str("export ");
                 }
@@ -391,16 +805,21 @@ impl Enum<'_> {
                 s.push('.');
                 s.push_str(name);
                 s.push_str(
-                    ", number] => {\n        const value = data.getUint8(offset);\n        if (!",
-                );
-                s.push_str(&options.types_namespace);
-                s.push('.');
-                s.push_str(name);
-                s.push_str(
-                    "[value]) {\n            throw new Error(`${value} is not a variant of ",
+                    ", number] => {\n        const [value, newOffset] = decodeInt(data, offset);\n",
                 );
-                s.push_str(name);
-                s.push_str("`);\n        }\n        return [value, offset + 1];\n    };\n}\n\nexport namespace ");
+                // open enums keep unknown variants as their numeric value
+                if !*open {
+                    s.push_str("        if (!");
+                    s.push_str(&options.types_namespace);
+                    s.push('.');
+                    s.push_str(name);
+                    s.push_str(
+                        "[value]) {\n            throw new Error(`${value} is not a variant of ",
+                    );
+                    s.push_str(name);
+                    s.push_str("`);\n        }\n");
+                }
+                s.push_str("        return [value, newOffset];\n    };\n}\n\nexport namespace ");
                 s.push_str(&options.decode_namespace);
                 s.push_str(" {\n    export const ");
                 s.push_str(name);
@@ -439,11 +858,83 @@ impl Enum<'_> {
     }
 }
 
-impl StructField<'_> {
+/// Checks that `name` can be used as a property name without quotes.
+fn is_valid_typescript_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Checks the value of a `@ts` annotation argument, returning what was expected if it is invalid.
+fn check_annotation_value(key: &str, value: &str) -> Result<(), &'static str> {
+    match key {
+        "name" if !is_valid_typescript_identifier(value) => Err("an identifier, e.g. `userId`"),
+        _ => Ok(()),
+    }
+}
+
+/// Returns a warning for every `@ts` annotation argument which the generated code ignores,
+/// because its key is unknown or its value is invalid.
+///
+/// Struct fields accept `name = "..."`, which renames the field in the generated `interface`.
+/// Types and enum variants accept no keys.
+pub fn annotation_warnings(types: &[Type<'_>]) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut check = |annotations: &[Annotation<'_>], target: &str, keys: &[&str]| {
+        check_annotations(
+            annotations,
+            "ts",
+            target,
+            keys,
+            check_annotation_value,
+            &mut warnings,
+        );
+    };
+    for r#type in types {
+        match r#type {
+            Type::Struct(r#struct) => {
+                check(&r#struct.annotations, "a struct", &[]);
+                for field in &r#struct.fields {
+                    check(&field.annotations, "a struct field", &["name"]);
+                }
+            }
+            Type::Enum(r#enum) => {
+                check(r#enum.annotations(), "an enum", &[]);
+                match r#enum {
+                    Enum::Tagged { variants, .. } => {
+                        for variant in variants {
+                            check(&variant.annotations, "an enum variant", &[]);
+                        }
+                    }
+                    Enum::Untagged { variants, .. } => {
+                        for variant in variants {
+                            check(&variant.annotations, "an enum variant", &[]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    warnings
+}
+
+impl<'a> StructField<'a> {
+    /// Returns the name of the field in TypeScript, which is the last valid `@ts(name = "...")`
+    /// annotation if there is one.
+    pub fn typescript_name(&self) -> &str {
+        annotation_args(&self.annotations, "ts")
+            .filter(|arg| arg.key == "name" && check_annotation_value(arg.key, &arg.value).is_ok())
+            .map(|arg| &*arg.value)
+            .last()
+            .unwrap_or(self.name)
+    }
+
     /// Generates a TypeScript `interface` field.
     pub fn typescript_interface_field(&self) -> String {
-        let mut s = self.name.to_owned();
-        if self.optional {
+        let mut s = self.typescript_name().to_owned();
+        if self.optional && self.default.is_none() {
             s.push('?');
         }
         s.push_str(": ");
@@ -454,9 +945,219 @@ impl StructField<'_> {
         s.push(';');
         s
     }
+
+    /// Generates TypeScript statements which throw an error if `value`, an expression of the
+    /// field's type, does not satisfy the field's constraints.
+    fn push_typescript_constraint_checks(
+        &self,
+        s: &mut String,
+        type_name: &str,
+        value: &str,
+        indent: &str,
+    ) {
+        let constraints = &self.constraints;
+        let suffix = match self.r#type {
+            StructFieldType::U64 | StructFieldType::I64 => "n",
+            _ => "",
+        };
+        let len = match self.r#type {
+            StructFieldType::String => format!("[...{value}].length"),
+            StructFieldType::Bytes { .. } => format!("{value}.byteLength"),
+            StructFieldType::Map { .. } => format!("{value}.size"),
+            _ => format!("{value}.length"),
+        };
+
+        // pairs of a condition which is `true` if the constraint is not satisfied, and the
+        // constraint
+        let mut checks = Vec::new();
+        if let Some(min) = &constraints.min {
+            checks.push((
+                format!("!({value} >= {min}{suffix})"),
+                format!("min = {min}"),
+            ));
+        }
+        if let Some(max) = &constraints.max {
+            checks.push((
+                format!("!({value} <= {max}{suffix})"),
+                format!("max = {max}"),
+            ));
+        }
+        if let Some(min_len) = constraints.min_len {
+            checks.push((format!("{len} < {min_len}"), format!("min_len = {min_len}")));
+        }
+        if let Some(max_len) = constraints.max_len {
+            checks.push((format!("{len} > {max_len}"), format!("max_len = {max_len}")));
+        }
+        if let Some(exact_len) = constraints.len {
+            checks.push((
+                format!("{len} !== {exact_len}"),
+                format!("len = {exact_len}"),
+            ));
+        }
+        if let Some(pattern) = &constraints.pattern {
+            checks.push((
+                format!(
+                    "!{regex}.test({value})",
+                    regex = typescript_regex_literal(pattern)
+                ),
+                format!("pattern = \"{pattern}\""),
+            ));
+        }
+
+        for (condition, constraint) in checks {
+            s.push_str(indent);
+            s.push_str("if (");
+            s.push_str(&condition);
+            s.push_str(") {\n");
+            s.push_str(indent);
+            s.push_str("    throw new Error(");
+            s.push_str(&typescript_string_literal(&format!(
+                "{type_name}.{name}: value does not satisfy `{constraint}`",
+                name = self.name
+            )));
+            s.push_str(");\n");
+            s.push_str(indent);
+            s.push_str("}\n");
+        }
+    }
+
+    /// Returns a TypeScript expression which checks if an optional field of `value` should be
+    /// encoded.
+    fn typescript_is_present(&self, options: &Options) -> String {
+        match &self.default {
+            Some(default) => format!(
+                "value.{name} !== {default}",
+                name = self.typescript_name(),
+                default = default.typescript_value(&self.r#type, options)
+            ),
+            None => format!(
+                "Object.hasOwn(value, \"{name}\")",
+                name = self.typescript_name()
+            ),
+        }
+    }
+}
+
+/// Returns a TypeScript string literal with the contents `value`.
+fn typescript_string_literal(value: &str) -> String {
+    let mut s = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                s.push('\\');
+                s.push(c);
+            }
+            c if c.is_control() => s.push_str(&format!("\\u{{{:x}}}", u32::from(c))),
+            c => s.push(c),
+        }
+    }
+    s.push('"');
+    s
+}
+
+/// Returns a TypeScript regular expression literal for a `pattern` constraint, using the `u`
+/// flag so that it matches characters rather than UTF-16 code units.
+fn typescript_regex_literal(pattern: &str) -> String {
+    let mut s = String::from("/");
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            // escaped characters are kept as they are
+            '\\' => {
+                s.push(c);
+                s.extend(chars.next());
+            }
+            '/' => s.push_str("\\/"),
+            c => s.push(c),
+        }
+    }
+    s.push_str("/u");
+    s
+}
+
+impl DefaultValue<'_> {
+    /// Returns a TypeScript expression for the default value of a field of type `r#type`.
+    fn typescript_value(&self, r#type: &StructFieldType<'_>, options: &Options) -> String {
+        match self {
+            Self::Bool(value) => value.to_string(),
+            Self::Integer(value) => match r#type {
+                StructFieldType::U64 | StructFieldType::I64 => format!("{value}n"),
+                _ => value.to_string(),
+            },
+            Self::Float(value) => value.clone(),
+            Self::String(value) => typescript_string_literal(value),
+            Self::Variant(variant) => match r#type {
+                StructFieldType::Reference { name } => {
+                    format!(
+                        "{namespace}.{name}.{variant}",
+                        namespace = options.types_namespace
+                    )
+                }
+                _ => unreachable!(),
+            },
+        }
+    }
+}
+
+/// Where generated TypeScript decoding code stores a decoded `value`.
+enum DecodeTarget<'a> {
+    /// An expression which is assigned the value, e.g. a field of the decoded object.
+    Place(&'a str),
+    /// A statement which stores `value`, e.g. in an array.
+    Statement(String),
+}
+
+impl DecodeTarget<'_> {
+    fn store(&self) -> String {
+        match self {
+            Self::Place(place) => format!("{place} = value;"),
+            Self::Statement(statement) => statement.clone(),
+        }
+    }
+}
+
+/// Returns the name of the array or map decoded at `depth`.
+fn container_name(maps: &[bool], depth: usize) -> String {
+    if maps[depth] {
+        format!("map{depth}")
+    } else {
+        format!("array{depth}")
+    }
+}
+
+/// Returns a statement which stores `value` in the array or map decoded at `depth`.
+fn store(maps: &[bool], depth: usize, value: &str) -> String {
+    if maps[depth] {
+        format!("map{depth}.set(key{depth}, {value});")
+    } else {
+        format!("array{depth}.push({value});")
+    }
 }
 
 impl StructFieldType<'_> {
+    /// Returns `true` if the type is `r#type` or an array or map containing it.
+    fn contains_type(&self, r#type: &StructFieldType<'_>) -> bool {
+        match self {
+            Self::Array { items } => items.contains_type(r#type),
+            Self::Map { key, value } => key.contains_type(r#type) || value.contains_type(r#type),
+            _ => self == r#type,
+        }
+    }
+
+    /// Returns the maximum encoded length of the type if it does not depend on the value.
+    fn typescript_encoded_fixed_max_len(&self) -> Option<u32> {
+        match self {
+            Self::Bool => Some(1),
+            Self::U8 | Self::I8 => Some(2),
+            Self::U16 | Self::I16 => Some(3),
+            Self::U32 | Self::I32 | Self::F32 => Some(5),
+            Self::U64 | Self::I64 | Self::F64 => Some(9),
+            Self::Timestamp | Self::Duration => Some(15),
+            _ => None,
+        }
+    }
+
+    /// Returns a TypeScript expression for the maximum encoded length of `name`.
     fn typescript_encoded_max_len(&self, name: &str) -> String {
         match self {
             Self::Bool => String::from("1"),
@@ -468,13 +1169,663 @@ impl StructFieldType<'_> {
             Self::Bytes { .. } => {
                 format!("bytesHeaderLength({name}.byteLength) + {name}.byteLength")
             }
-            Self::Array { .. } => unreachable!(),
+            Self::Timestamp | Self::Duration => String::from("15"),
+            Self::Array { items } => {
+                let items_len = if let Some(len) = items.typescript_encoded_fixed_max_len() {
+                    format!("{name}.length * {len}")
+                } else if let Self::Array { .. } | Self::Map { .. } = **items {
+                    format!(
+                        "{name}.reduce((sum, item) => sum + {item_len}, 0)",
+                        item_len = items.typescript_encoded_max_len("item")
+                    )
+                } else {
+                    format!(
+                        "{name}.map((item) => {item_len}).reduce((sum, item) => sum + item, 0)",
+                        item_len = items.typescript_encoded_max_len("item")
+                    )
+                };
+                format!("arrayHeaderLength({name}.length) + {items_len}")
+            }
+            Self::Map { key, value } => {
+                let entries_len = if let (Some(key_len), Some(value_len)) = (
+                    key.typescript_encoded_fixed_max_len(),
+                    value.typescript_encoded_fixed_max_len(),
+                ) {
+                    format!("{name}.size * {len}", len = key_len + value_len)
+                } else {
+                    format!(
+                        "Array.from({name}).reduce((sum, [key, item]) => sum + {key_len} + {item_len}, 0)",
+                        key_len = key.typescript_encoded_max_len("key"),
+                        item_len = value.typescript_encoded_max_len("item")
+                    )
+                };
+                format!("mapHeaderLength({name}.size) + {entries_len}")
+            }
             Self::Reference { name: type_name } => {
                 format!("TypedpackMaxLength.{type_name}({name})")
             }
         }
     }
 
+    /// Generates TypeScript code which encodes `value_name`, which must not be an array or a map.
+    fn push_typescript_encode(&self, s: &mut String, value_name: &str, indent: &str) {
+        match self {
+            Self::Bool => {
+                s.push_str("offset += encodeBoolean(");
+                s.push_str(value_name);
+                s.push_str(", data, offset);");
+            }
+            Self::U8 => {
+                s.push_str("if (!Number.isSafeInteger(");
+                s.push_str(value_name);
+                s.push_str(") || ");
+                s.push_str(value_name);
+                s.push_str(" < 0 || ");
+                s.push_str(value_name);
+                s.push_str(" >= 256) {\n");
+                s.push_str(indent);
+                s.push_str("    throw new Error(\"invalid uint8\");\n");
+                s.push_str(indent);
+                s.push_str("}\n");
+                s.push_str(indent);
+                s.push_str("offset += encodeUint(");
+                s.push_str(value_name);
+                s.push_str(", data, offset);");
+            }
+            Self::I8 => {
+                s.push_str("if (!Number.isSafeInteger(");
+                s.push_str(value_name);
+                s.push_str(") || ");
+                s.push_str(value_name);
+                s.push_str(" < -128 || ");
+                s.push_str(value_name);
+                s.push_str(" >= 128) {\n");
+                s.push_str(indent);
+                s.push_str("    throw new Error(\"invalid int8\");\n");
+                s.push_str(indent);
+                s.push_str("}\n");
+                s.push_str(indent);
+                s.push_str("offset += encodeInt(");
+                s.push_str(value_name);
+                s.push_str(", data, offset);");
+            }
+            Self::U16 => {
+                s.push_str("if (!Number.isSafeInteger(");
+                s.push_str(value_name);
+                s.push_str(") || ");
+                s.push_str(value_name);
+                s.push_str(" < 0 || ");
+                s.push_str(value_name);
+                s.push_str(" >= 65_536) {\n");
+                s.push_str(indent);
+                s.push_str("    throw new Error(\"invalid uint16\");\n");
+                s.push_str(indent);
+                s.push_str("}\n");
+                s.push_str(indent);
+                s.push_str("offset += encodeUint(");
+                s.push_str(value_name);
+                s.push_str(", data, offset);");
+            }
+            Self::I16 => {
+                s.push_str("if (!Number.isSafeInteger(");
+                s.push_str(value_name);
+                s.push_str(") || ");
+                s.push_str(value_name);
+                s.push_str(" < -32_768 || ");
+                s.push_str(value_name);
+                s.push_str(" >= 32_768) {\n");
+                s.push_str(indent);
+                s.push_str("    throw new Error(\"invalid int16\");\n");
+                s.push_str(indent);
+                s.push_str("}\n");
+                s.push_str(indent);
+                s.push_str("offset += encodeInt(");
+                s.push_str(value_name);
+                s.push_str(", data, offset);");
+            }
+            Self::U32 => {
+                s.push_str("if (!Number.isSafeInteger(");
+                s.push_str(value_name);
+                s.push_str(") || ");
+                s.push_str(value_name);
+                s.push_str(" < 0 || ");
+                s.push_str(value_name);
+                s.push_str(" >= 4_294_967_296) {\n");
+                s.push_str(indent);
+                s.push_str("    throw new Error(\"invalid uint32\");\n");
+                s.push_str(indent);
+                s.push_str("}\n");
+                s.push_str(indent);
+                s.push_str("offset += encodeUint(");
+                s.push_str(value_name);
+                s.push_str(", data, offset);");
+            }
+            Self::I32 => {
+                s.push_str("if (!Number.isSafeInteger(");
+                s.push_str(value_name);
+                s.push_str(") || ");
+                s.push_str(value_name);
+                s.push_str(" < -2_147_483_648 || ");
+                s.push_str(value_name);
+                s.push_str(" >= 2_147_483_648) {\n");
+                s.push_str(indent);
+                s.push_str("    throw new Error(\"invalid int32\");\n");
+                s.push_str(indent);
+                s.push_str("}\n");
+                s.push_str(indent);
+                s.push_str("offset += encodeInt(");
+                s.push_str(value_name);
+                s.push_str(", data, offset);");
+            }
+            Self::U64 => {
+                s.push_str("if (");
+                s.push_str(value_name);
+                s.push_str(" < 0n || ");
+                s.push_str(value_name);
+                s.push_str(" >= 18_446_744_073_709_551_616n) {\n");
+                s.push_str(indent);
+                s.push_str("    throw new Error(\"invalid uint64\");\n");
+                s.push_str(indent);
+                s.push_str("}\n");
+                s.push_str(indent);
+                s.push_str("offset += encodeUint64(");
+                s.push_str(value_name);
+                s.push_str(", data, offset);");
+            }
+            Self::I64 => {
+                s.push_str("if (");
+                s.push_str(value_name);
+                s.push_str(" < -9_223_372_036_854_775_808n || ");
+                s.push_str(value_name);
+                s.push_str(" >= 9_223_372_036_854_775_808n) {\n");
+                s.push_str(indent);
+                s.push_str("    throw new Error(\"invalid int64\");\n");
+                s.push_str(indent);
+                s.push_str("}\n");
+                s.push_str(indent);
+                s.push_str("offset += encodeInt64(");
+                s.push_str(value_name);
+                s.push_str(", data, offset);");
+            }
+            Self::F32 => {
+                s.push_str("offset += encodeFloat32(");
+                s.push_str(value_name);
+                s.push_str(", data, offset);");
+            }
+            Self::F64 => {
+                s.push_str("offset += encodeFloat64(");
+                s.push_str(value_name);
+                s.push_str(", data, offset);");
+            }
+            Self::String => {
+                s.push_str("offset += encodeString(");
+                s.push_str(value_name);
+                s.push_str(", data, offset);");
+            }
+            Self::Bytes { len } => {
+                s.push_str("offset += encodeBytes(");
+                s.push_str(value_name);
+                s.push_str(", data, offset");
+                if let Some(len) = len {
+                    s.push_str(", ");
+                    s.push_str(&len.to_string());
+                }
+                s.push_str(");");
+            }
+            Self::Timestamp => {
+                s.push_str("offset += encodeTimestamp(");
+                s.push_str(value_name);
+                s.push_str(", data, offset);");
+            }
+            Self::Duration => {
+                s.push_str("offset += encodeDuration(");
+                s.push_str(value_name);
+                s.push_str(", data, offset);");
+            }
+            Self::Reference { name } => {
+                s.push_str("{\n");
+                s.push_str(indent);
+                s.push_str("    offset += TypedpackEncodeInternal.");
+                s.push_str(name);
+                s.push('(');
+                s.push_str(value_name);
+                s.push_str(", data, offset);\n");
+                s.push_str(indent);
+                s.push('}');
+            }
+            Self::Array { .. } | Self::Map { .. } => unreachable!(),
+        }
+    }
+
+    /// Generates TypeScript code which decodes a value of this type, which must not be an array or
+    /// a map, and stores it in `target`.
+    fn push_typescript_decode(&self, s: &mut String, indent: &str, target: &DecodeTarget<'_>) {
+        match self {
+            Self::Bool => {
+                if let DecodeTarget::Place(place) = target {
+                    s.push('[');
+                    s.push_str(place);
+                    s.push_str(", offset] = decodeBoolean(data, offset);");
+                } else {
+                    s.push_str("const [value, newOffset] = decodeBoolean(data, offset);\n");
+                    s.push_str(indent);
+                    s.push_str("offset = newOffset;\n");
+                    s.push_str(indent);
+                    s.push_str(&target.store());
+                }
+            }
+            Self::U8 => {
+                s.push_str("const [value, newOffset] = decodeInt(data, offset);\n");
+                s.push_str(indent);
+                s.push_str("offset = newOffset;\n");
+                s.push_str(indent);
+                s.push_str("if (value < 0 || value >= 256) {\n");
+                s.push_str(indent);
+                s.push_str("    throw new Error(\"invalid uint8\");\n");
+                s.push_str(indent);
+                s.push_str("}\n");
+                s.push_str(indent);
+                s.push_str(&target.store());
+            }
+            Self::I8 => {
+                s.push_str("const [value, newOffset] = decodeInt(data, offset);\n");
+                s.push_str(indent);
+                s.push_str("offset = newOffset;\n");
+                s.push_str(indent);
+                s.push_str("if (value < -128 || value >= 128) {\n");
+                s.push_str(indent);
+                s.push_str("    throw new Error(\"invalid int8\");\n");
+                s.push_str(indent);
+                s.push_str("}\n");
+                s.push_str(indent);
+                s.push_str(&target.store());
+            }
+            Self::U16 => {
+                s.push_str("const [value, newOffset] = decodeInt(data, offset);\n");
+                s.push_str(indent);
+                s.push_str("offset = newOffset;\n");
+                s.push_str(indent);
+                s.push_str("if (value < 0 || value >= 65_536) {\n");
+                s.push_str(indent);
+                s.push_str("    throw new Error(\"invalid uint16\");\n");
+                s.push_str(indent);
+                s.push_str("}\n");
+                s.push_str(indent);
+                s.push_str(&target.store());
+            }
+            Self::I16 => {
+                s.push_str("const [value, newOffset] = decodeInt(data, offset);\n");
+                s.push_str(indent);
+                s.push_str("offset = newOffset;\n");
+                s.push_str(indent);
+                s.push_str("if (value < -32_768 || value >= 32_768) {\n");
+                s.push_str(indent);
+                s.push_str("    throw new Error(\"invalid int16\");\n");
+                s.push_str(indent);
+                s.push_str("}\n");
+                s.push_str(indent);
+                s.push_str(&target.store());
+            }
+            Self::U32 => {
+                s.push_str("const [value, newOffset] = decodeInt(data, offset);\n");
+                s.push_str(indent);
+                s.push_str("offset = newOffset;\n");
+                s.push_str(indent);
+                s.push_str("if (value < 0) {\n");
+                s.push_str(indent);
+                s.push_str("    throw new Error(\"invalid uint32\");\n");
+                s.push_str(indent);
+                s.push_str("}\n");
+                s.push_str(indent);
+                s.push_str(&target.store());
+            }
+            Self::I32 => {
+                s.push_str("const [value, newOffset] = decodeInt(data, offset);\n");
+                s.push_str(indent);
+                s.push_str("offset = newOffset;\n");
+                s.push_str(indent);
+                s.push_str("if (value >= 2_147_483_648) {\n");
+                s.push_str(indent);
+                s.push_str("    throw new Error(\"invalid int32\");\n");
+                s.push_str(indent);
+                s.push_str("}\n");
+                s.push_str(indent);
+                s.push_str(&target.store());
+            }
+            Self::U64 => {
+                s.push_str("const [value, newOffset] = decodeInt64(data, offset);\n");
+                s.push_str(indent);
+                s.push_str("offset = newOffset;\n");
+                s.push_str(indent);
+                s.push_str("if (value < 0n) {\n");
+                s.push_str(indent);
+                s.push_str("    throw new Error(\"invalid uint64\");\n");
+                s.push_str(indent);
+                s.push_str("}\n");
+                s.push_str(indent);
+                s.push_str(&target.store());
+            }
+            Self::I64 => {
+                s.push_str("const [value, newOffset] = decodeInt64(data, offset);\n");
+                s.push_str(indent);
+                s.push_str("offset = newOffset;\n");
+                s.push_str(indent);
+                s.push_str("if (value >= 9_223_372_036_854_775_808n) {\n");
+                s.push_str(indent);
+                s.push_str("    throw new Error(\"invalid int64\");\n");
+                s.push_str(indent);
+                s.push_str("}\n");
+                s.push_str(indent);
+                s.push_str(&target.store());
+            }
+            Self::F32 => {
+                if let DecodeTarget::Place(place) = target {
+                    s.push('[');
+                    s.push_str(place);
+                    s.push_str(", offset] = decodeFloat32(data, offset);");
+                } else {
+                    s.push_str("const [value, newOffset] = decodeFloat32(data, offset);\n");
+                    s.push_str(indent);
+                    s.push_str("offset = newOffset;\n");
+                    s.push_str(indent);
+                    s.push_str(&target.store());
+                }
+            }
+            Self::F64 => {
+                if let DecodeTarget::Place(place) = target {
+                    s.push('[');
+                    s.push_str(place);
+                    s.push_str(", offset] = decodeFloat64(data, offset);");
+                } else {
+                    s.push_str("const [value, newOffset] = decodeFloat64(data, offset);\n");
+                    s.push_str(indent);
+                    s.push_str("offset = newOffset;\n");
+                    s.push_str(indent);
+                    s.push_str(&target.store());
+                }
+            }
+            Self::String => {
+                if let DecodeTarget::Place(place) = target {
+                    s.push('[');
+                    s.push_str(place);
+                    s.push_str(", offset] = decodeString(data, offset);");
+                } else {
+                    s.push_str("const [value, newOffset] = decodeString(data, offset);\n");
+                    s.push_str(indent);
+                    s.push_str("offset = newOffset;\n");
+                    s.push_str(indent);
+                    s.push_str(&target.store());
+                }
+            }
+            Self::Bytes { len } => {
+                if let DecodeTarget::Place(place) = target {
+                    s.push('[');
+                    s.push_str(place);
+                    s.push_str(", offset] = decodeBytes(data, offset");
+                    if let Some(len) = len {
+                        s.push_str(", ");
+                        s.push_str(&len.to_string());
+                    }
+                    s.push_str(");");
+                } else {
+                    s.push_str("const [value, newOffset] = decodeBytes(data, offset");
+                    if let Some(len) = len {
+                        s.push_str(", ");
+                        s.push_str(&len.to_string());
+                    }
+                    s.push_str(");\n");
+                    s.push_str(indent);
+                    s.push_str("offset = newOffset;\n");
+                    s.push_str(indent);
+                    s.push_str(&target.store());
+                }
+            }
+            Self::Timestamp => {
+                if let DecodeTarget::Place(place) = target {
+                    s.push('[');
+                    s.push_str(place);
+                    s.push_str(", offset] = decodeTimestamp(data, offset);");
+                } else {
+                    s.push_str("const [value, newOffset] = decodeTimestamp(data, offset);\n");
+                    s.push_str(indent);
+                    s.push_str("offset = newOffset;\n");
+                    s.push_str(indent);
+                    s.push_str(&target.store());
+                }
+            }
+            Self::Duration => {
+                if let DecodeTarget::Place(place) = target {
+                    s.push('[');
+                    s.push_str(place);
+                    s.push_str(", offset] = decodeDuration(data, offset);");
+                } else {
+                    s.push_str("const [value, newOffset] = decodeDuration(data, offset);\n");
+                    s.push_str(indent);
+                    s.push_str("offset = newOffset;\n");
+                    s.push_str(indent);
+                    s.push_str(&target.store());
+                }
+            }
+            Self::Reference { name } => {
+                if let DecodeTarget::Place(place) = target {
+                    s.push('[');
+                    s.push_str(place);
+                    s.push_str(", offset] = TypedpackDecodeInternal.");
+                    s.push_str(name);
+                    s.push_str("(data, offset);");
+                } else {
+                    s.push_str("const [value, newOffset] = TypedpackDecodeInternal.");
+                    s.push_str(name);
+                    s.push_str("(data, offset);\n");
+                    s.push_str(indent);
+                    s.push_str("offset = newOffset;\n");
+                    s.push_str(indent);
+                    s.push_str(&target.store());
+                }
+            }
+            Self::Array { .. } | Self::Map { .. } => unreachable!(),
+        }
+    }
+
+    /// Generates TypeScript code which encodes `value_name`, including the items of arrays and
+    /// maps. Each line is indented by `indent` spaces, plus 4 for each enclosing array or map.
+    fn push_typescript_encode_nested(&self, s: &mut String, value_name: &str, indent: usize) {
+        let get_value_name = |depth| {
+            if depth == 0 {
+                value_name.to_owned()
+            } else {
+                format!("item{depth}", depth = depth - 1)
+            }
+        };
+
+        let mut r#type = self;
+        let mut array_depth = 0;
+        loop {
+            let value_name = get_value_name(array_depth);
+            let indent = " ".repeat(indent + 4 * array_depth);
+            s.push_str(&indent);
+
+            match r#type {
+                Self::Array { items } => {
+                    s.push_str("offset += encodeArrayHeader(");
+                    s.push_str(&value_name);
+                    s.push_str(".length, data, offset);\n");
+                    s.push_str(&indent);
+                    s.push_str("for (const ");
+                    s.push_str(&get_value_name(array_depth + 1));
+                    s.push_str(" of ");
+                    s.push_str(&value_name);
+                    s.push_str(") {\n");
+                    r#type = items;
+                    array_depth += 1;
+                }
+                Self::Map { key, value } => {
+                    s.push_str("offset += encodeMapHeader(");
+                    s.push_str(&value_name);
+                    s.push_str(".size, data, offset);\n");
+                    s.push_str(&indent);
+                    s.push_str("for (const [key");
+                    s.push_str(&array_depth.to_string());
+                    s.push_str(", ");
+                    s.push_str(&get_value_name(array_depth + 1));
+                    s.push_str("] of ");
+                    s.push_str(&value_name);
+                    s.push_str(") {\n");
+                    s.push_str(&indent);
+                    s.push_str("    ");
+                    key.push_typescript_encode(
+                        s,
+                        &format!("key{array_depth}"),
+                        &format!("{indent}    "),
+                    );
+                    s.push('\n');
+                    r#type = value;
+                    array_depth += 1;
+                }
+                _ => {
+                    r#type.push_typescript_encode(s, &value_name, &indent);
+                    break;
+                }
+            }
+        }
+
+        for depth in (0..array_depth).rev() {
+            s.push('\n');
+            s.push_str(&" ".repeat(indent + 4 * depth));
+            s.push('}');
+        }
+    }
+
+    /// Generates TypeScript code which decodes a value of this type, including the items of
+    /// arrays and maps, and assigns it to `place`. Each line is indented by `indent` spaces, plus 4
+    /// for each enclosing array or map.
+    fn push_typescript_decode_nested(&self, s: &mut String, place: &str, indent: usize) {
+        let mut array_depth = 0;
+        // whether each enclosing container is a map rather than an array
+        let mut maps = Vec::new();
+        let mut r#type = self;
+
+        loop {
+            let indent = " ".repeat(indent + 4 * array_depth);
+            s.push_str(&indent);
+
+            match r#type {
+                Self::Array { items } => {
+                    let depth_str = array_depth.to_string();
+                    s.push_str("const array");
+                    s.push_str(&depth_str);
+                    s.push_str(" = [];\n");
+                    s.push_str(&indent);
+                    s.push_str("const [length, newOffset] = decodeArrayLength(data, offset);\n");
+                    s.push_str(&indent);
+                    s.push_str("offset = newOffset;\n");
+                    s.push_str(&indent);
+                    s.push_str("for (let i");
+                    s.push_str(&depth_str);
+                    s.push_str(" = 0; i");
+                    s.push_str(&depth_str);
+                    s.push_str(" < length; i");
+                    s.push_str(&depth_str);
+                    s.push_str(" += 1) {\n");
+                    r#type = items;
+                    array_depth += 1;
+                    maps.push(false);
+                }
+                Self::Map { key, value } => {
+                    let depth_str = array_depth.to_string();
+                    s.push_str("const map");
+                    s.push_str(&depth_str);
+                    s.push_str(" = new Map();\n");
+                    s.push_str(&indent);
+                    s.push_str("const [length, newOffset] = decodeMapLength(data, offset);\n");
+                    s.push_str(&indent);
+                    s.push_str("offset = newOffset;\n");
+                    s.push_str(&indent);
+                    s.push_str("for (let i");
+                    s.push_str(&depth_str);
+                    s.push_str(" = 0; i");
+                    s.push_str(&depth_str);
+                    s.push_str(" < length; i");
+                    s.push_str(&depth_str);
+                    s.push_str(" += 1) {\n");
+                    s.push_str(&indent);
+                    s.push_str("    let key");
+                    s.push_str(&depth_str);
+                    s.push_str(";\n");
+                    s.push_str(&indent);
+                    s.push_str("    {\n");
+                    s.push_str(&indent);
+                    s.push_str("        ");
+                    key.push_typescript_decode(
+                        s,
+                        &format!("{indent}        "),
+                        &DecodeTarget::Statement(format!("key{depth_str} = value;")),
+                    );
+                    s.push('\n');
+                    s.push_str(&indent);
+                    s.push_str("    }\n");
+                    s.push_str(&indent);
+                    s.push_str("    if (map");
+                    s.push_str(&depth_str);
+                    s.push_str(".has(key");
+                    s.push_str(&depth_str);
+                    s.push_str(")) {\n");
+                    s.push_str(&indent);
+                    s.push_str("        throw new Error(\"duplicate map key\");\n");
+                    s.push_str(&indent);
+                    s.push_str("    }\n");
+                    r#type = value;
+                    array_depth += 1;
+                    maps.push(true);
+                }
+                _ => {
+                    let target = if array_depth == 0 {
+                        DecodeTarget::Place(place)
+                    } else {
+                        DecodeTarget::Statement(store(&maps, array_depth - 1, "value"))
+                    };
+                    r#type.push_typescript_decode(s, &indent, &target);
+                    break;
+                }
+            }
+        }
+
+        for depth in (0..array_depth).rev() {
+            let indent = " ".repeat(indent + 4 * depth);
+            s.push('\n');
+            s.push_str(&indent);
+            s.push_str("}\n");
+            s.push_str(&indent);
+
+            let container = container_name(&maps, depth);
+            if depth == 0 {
+                s.push_str(place);
+                s.push_str(" = ");
+                s.push_str(&container);
+                s.push(';');
+            } else {
+                s.push_str(&store(&maps, depth - 1, &container));
+            }
+        }
+    }
+
+    /// Returns the corresponding TypeScript type, where referenced types are in `namespace`.
+    fn typescript_qualified_type(&self, namespace: &str) -> String {
+        match self {
+            Self::Array { items } => format!(
+                "Array<{items}>",
+                items = items.typescript_qualified_type(namespace)
+            ),
+            Self::Map { key, value } => format!(
+                "Map<{key}, {value}>",
+                key = key.typescript_qualified_type(namespace),
+                value = value.typescript_qualified_type(namespace)
+            ),
+            Self::Reference { name } => format!("{namespace}.{name}"),
+            _ => self.typescript_type(),
+        }
+    }
+
     /// Returns the corresponding TypeScript type.
     pub fn typescript_type(&self) -> String {
         match self {
@@ -490,7 +1841,14 @@ impl StructFieldType<'_> {
             Self::U64 | Self::I64 => String::from("bigint"),
             Self::String => String::from("string"),
             Self::Bytes { .. } => String::from("ArrayBuffer"),
+            Self::Timestamp => String::from("Date"),
+            Self::Duration => String::from("{ seconds: bigint; nanos: number }"),
             Self::Array { items } => format!("Array<{items}>", items = items.typescript_type()),
+            Self::Map { key, value } => format!(
+                "Map<{key}, {value}>",
+                key = key.typescript_type(),
+                value = value.typescript_type()
+            ),
             Self::Reference { name } => (*name).to_owned(),
         }
     }
@@ -501,23 +1859,38 @@ impl Struct<'_> {
     pub fn typescript_interface(&self, options: &Options) -> String {
         let mut s = String::from("export namespace ");
         s.push_str(&options.types_namespace);
-        s.push_str(" {\n    export ");
-        if self.fields.is_empty() {
-            s.push_str("type ");
-        } else {
+        s.push_str(" {\n");
+        s.push_str(&typescript_doc_comment(
+            &self.doc,
+            self.deprecated.as_ref(),
+            "    ",
+        ));
+        s.push_str("    export ");
+        let has_members = !self.fields.is_empty() || self.preserve_unknown;
+        if has_members {
             s.push_str("interface ");
+        } else {
+            s.push_str("type ");
         }
         s.push_str(self.name);
-        if self.fields.is_empty() {
-            s.push_str(" = object;");
-        } else {
+        if has_members {
             s.push_str(" {\n");
             for field in &self.fields {
+                s.push_str(&typescript_doc_comment(
+                    &field.doc,
+                    field.deprecated.as_ref(),
+                    "        ",
+                ));
                 s.push_str("        ");
                 s.push_str(&field.typescript_interface_field());
                 s.push('\n');
             }
+            if self.preserve_unknown {
+                s.push_str("        /**\n         * The encoded values of the fields which are not in the schema, which are kept so that\n         * they can be encoded again.\n         */\n        unknown_fields?: Array<[number, Uint8Array]>;\n");
+            }
             s.push_str("    }");
+        } else {
+            s.push_str(" = object;");
         }
         s.push_str("\n}\n\nnamespace TypedpackMapLength {\n    export const ");
         s.push_str(self.name);
@@ -527,7 +1900,8 @@ impl Struct<'_> {
         let required_field_count = self.fields.iter().filter(|field| !field.optional).count();
         let required_field_count_str = required_field_count.to_string();
 
-        if field_count == required_field_count {
+        let is_constant_map_length = field_count == required_field_count && !self.preserve_unknown;
+        if is_constant_map_length {
             s.push('_');
         }
 
@@ -537,7 +1911,7 @@ impl Struct<'_> {
         s.push_str(self.name);
         s.push_str("): number =>");
 
-        if required_field_count == field_count {
+        if is_constant_map_length {
             s.push(' ');
             s.push_str(&required_field_count_str);
         } else {
@@ -548,16 +1922,20 @@ impl Struct<'_> {
                 if !field.optional {
                     continue;
                 }
-                s.push_str("\n        + (Object.hasOwn(value, \"");
-                s.push_str(field.name);
-                s.push_str("\") ? 1 : 0)");
+                s.push_str("\n        + (");
+                s.push_str(&field.typescript_is_present(options));
+                s.push_str(" ? 1 : 0)");
+            }
+            if self.preserve_unknown {
+                s.push_str("\n        + (value.unknown_fields?.length ?? 0)");
             }
         }
 
         s.push_str(";\n}\n\nnamespace TypedpackMaxLength {\n    export const ");
         s.push_str(self.name);
         s.push_str(" = (");
-        if field_count == 0 {
+        let is_empty = field_count == 0 && !self.preserve_unknown;
+        if is_empty {
             s.push('_');
         }
         s.push_str("value: ");
@@ -565,98 +1943,49 @@ impl Struct<'_> {
         s.push('.');
         s.push_str(self.name);
 
-        if field_count == 0 {
+        if is_empty {
             s.push_str("): number => 1");
         } else {
             s.push_str("): number =>\n        mapHeaderLength(TypedpackMapLength.");
             s.push_str(self.name);
             s.push_str("(value))");
 
-            if required_field_count != 0 {
+            let required_keys_len: usize = self
+                .fields
+                .iter()
+                .filter(|field| !field.optional)
+                .map(|field| typescript_uint_len(field.id))
+                .sum();
+            if required_keys_len != 0 {
                 s.push_str("\n            + ");
-                s.push_str(&required_field_count_str);
+                s.push_str(&required_keys_len.to_string());
             }
         }
 
         for field in &self.fields {
             s.push_str("\n            + ");
             if field.optional {
-                s.push_str("(\n                Object.hasOwn(value, \"");
-                s.push_str(field.name);
-                s.push_str("\")\n                ? 1 + ");
+                s.push_str("(\n                ");
+                s.push_str(&field.typescript_is_present(options));
+                s.push_str("\n                ? ");
+                s.push_str(&typescript_uint_len(field.id).to_string());
+                s.push_str(" + ");
             }
             if field.nullable {
                 s.push_str("(value.");
-                s.push_str(field.name);
+                s.push_str(field.typescript_name());
                 s.push_str(" === null ? 1 : ");
             }
 
-            let mut field_type = &field.r#type;
-            let mut array_depth: usize = 0;
-            loop {
-                match field_type {
-                    StructFieldType::Array { items } => {
-                        if array_depth == 0 {
-                            s.push_str("arrayHeaderLength(");
-                            s.push_str("value.");
-                            s.push_str(field.name);
-                            if field.optional || field.nullable {
-                                s.push('!');
-                            }
-                            s.push_str(".length) + value.");
-                            s.push_str(field.name);
-                            if field.optional || field.nullable {
-                                s.push('!');
-                            }
-                        } else {
-                            s.push_str(".reduce((sum, item) => sum + arrayHeaderLength(item.length) + item");
-                        }
-                        field_type = items;
-                        array_depth += 1;
-                    }
-                    StructFieldType::Bool
-                    | StructFieldType::U8
-                    | StructFieldType::I8
-                    | StructFieldType::U16
-                    | StructFieldType::I16
-                    | StructFieldType::U32
-                    | StructFieldType::I32
-                    | StructFieldType::U64
-                    | StructFieldType::I64
-                    | StructFieldType::F32
-                    | StructFieldType::F64 => {
-                        if array_depth != 0 {
-                            s.push_str(".length * ");
-                        }
-                        s.push_str(&field_type.typescript_encoded_max_len(""));
-                        break;
-                    }
-                    StructFieldType::String
-                    | StructFieldType::Bytes { .. }
-                    | StructFieldType::Reference { .. } => {
-                        if array_depth == 0 {
-                            s.push_str(&field_type.typescript_encoded_max_len(&format!(
-                                "value.{name}{assert_non_null}",
-                                name = field.name,
-                                assert_non_null = if field.optional || field.nullable {
-                                    "!"
-                                } else {
-                                    ""
-                                }
-                            )));
-                        } else {
-                            s.push_str(".map((item) => ");
-                            s.push_str(&field_type.typescript_encoded_max_len("item"));
-                            s.push_str(").reduce((sum, item) => sum + item, 0)");
-                        }
-                        break;
-                    }
+            s.push_str(&field.r#type.typescript_encoded_max_len(&format!(
+                "value.{name}{assert_non_null}",
+                name = field.typescript_name(),
+                assert_non_null = if (field.optional && field.default.is_none()) || field.nullable {
+                    "!"
+                } else {
+                    ""
                 }
-            }
-
-            for _ in 1..array_depth {
-                s.push_str(", 0)");
-            }
+            )));
 
             if field.nullable {
                 s.push(')');
@@ -665,8 +1994,17 @@ impl Struct<'_> {
                 s.push_str("\n                : 0\n            )");
             }
         }
+        if self.preserve_unknown {
+            // field IDs take up to 3 bytes
+            s.push_str("\n            + (value.unknown_fields ?? []).reduce((length, [, bytes]) => length + 3 + bytes.byteLength, 0)");
+        }
 
-        s.push_str(";\n}\n\nnamespace TypedpackEncodeInternal {\n    export const ");
+        s.push_str(";\n}\n\nnamespace TypedpackEncodeInternal {\n");
+        s.push_str(&typescript_timestamp_helpers(
+            self.fields.iter().map(|field| &field.r#type),
+            true,
+        ));
+        s.push_str("    export const ");
         s.push_str(self.name);
         s.push_str(" = (value: ");
         s.push_str(&options.types_namespace);
@@ -679,9 +2017,9 @@ impl Struct<'_> {
         for field in &self.fields {
             s.push_str("\n\n");
             if field.optional {
-                s.push_str("        if (Object.hasOwn(value, \"");
-                s.push_str(field.name);
-                s.push_str("\")) {\n    ");
+                s.push_str("        if (");
+                s.push_str(&field.typescript_is_present(options));
+                s.push_str(") {\n    ");
             }
 
             s.push_str("        offset += encodeUint(");
@@ -693,7 +2031,7 @@ impl Struct<'_> {
                     s.push_str("    ");
                 }
                 s.push_str("        if (value.");
-                s.push_str(field.name);
+                s.push_str(field.typescript_name());
                 s.push_str(" === null) {\n            ");
                 if field.optional {
                     s.push_str("    ");
@@ -705,246 +2043,22 @@ impl Struct<'_> {
                 s.push_str("} else {\n");
             }
 
-            let mut field_type = &field.r#type;
-            let mut array_depth = 0;
-            loop {
-                let get_value_name = |depth| {
-                    if depth == 0 {
-                        let mut value_name = String::with_capacity(field.name.len() + 7);
-                        value_name.push_str("value.");
-                        value_name.push_str(field.name);
-                        if field.optional {
-                            value_name.push('!');
-                        }
-                        value_name
-                    } else {
-                        format!("item{depth}", depth = depth - 1)
-                    }
-                };
-                let value_name = get_value_name(array_depth);
-
-                let indent = " ".repeat(
-                    8 + 4 * array_depth
-                        + if field.optional { 4 } else { 0 }
-                        + if field.nullable { 4 } else { 0 },
-                );
-                s.push_str(&indent);
-
-                match field_type {
-                    StructFieldType::Bool => {
-                        s.push_str("offset += encodeBoolean(");
-                        s.push_str(&value_name);
-                        s.push_str(", data, offset);");
-                        break;
-                    }
-                    StructFieldType::U8 => {
-                        s.push_str("if (!Number.isSafeInteger(");
-                        s.push_str(&value_name);
-                        s.push_str(") || ");
-                        s.push_str(&value_name);
-                        s.push_str(" < 0 || ");
-                        s.push_str(&value_name);
-                        s.push_str(" >= 256) {\n");
-                        s.push_str(&indent);
-                        s.push_str("    throw new Error(\"invalid uint8\");\n");
-                        s.push_str(&indent);
-                        s.push_str("}\n");
-                        s.push_str(&indent);
-                        s.push_str("offset += encodeUint(");
-                        s.push_str(&value_name);
-                        s.push_str(", data, offset);");
-                        break;
-                    }
-                    StructFieldType::I8 => {
-                        s.push_str("if (!Number.isSafeInteger(");
-                        s.push_str(&value_name);
-                        s.push_str(") || ");
-                        s.push_str(&value_name);
-                        s.push_str(" < -128 || ");
-                        s.push_str(&value_name);
-                        s.push_str(" >= 128) {\n");
-                        s.push_str(&indent);
-                        s.push_str("    throw new Error(\"invalid int8\");\n");
-                        s.push_str(&indent);
-                        s.push_str("}\n");
-                        s.push_str(&indent);
-                        s.push_str("offset += encodeInt(");
-                        s.push_str(&value_name);
-                        s.push_str(", data, offset);");
-                        break;
-                    }
-                    StructFieldType::U16 => {
-                        s.push_str("if (!Number.isSafeInteger(");
-                        s.push_str(&value_name);
-                        s.push_str(") || ");
-                        s.push_str(&value_name);
-                        s.push_str(" < 0 || ");
-                        s.push_str(&value_name);
-                        s.push_str(" >= 65_536) {\n");
-                        s.push_str(&indent);
-                        s.push_str("    throw new Error(\"invalid uint16\");\n");
-                        s.push_str(&indent);
-                        s.push_str("}\n");
-                        s.push_str(&indent);
-                        s.push_str("offset += encodeUint(");
-                        s.push_str(&value_name);
-                        s.push_str(", data, offset);");
-                        break;
-                    }
-                    StructFieldType::I16 => {
-                        s.push_str("if (!Number.isSafeInteger(");
-                        s.push_str(&value_name);
-                        s.push_str(") || ");
-                        s.push_str(&value_name);
-                        s.push_str(" < -32_768 || ");
-                        s.push_str(&value_name);
-                        s.push_str(" >= 32_768) {\n");
-                        s.push_str(&indent);
-                        s.push_str("    throw new Error(\"invalid int16\");\n");
-                        s.push_str(&indent);
-                        s.push_str("}\n");
-                        s.push_str(&indent);
-                        s.push_str("offset += encodeInt(");
-                        s.push_str(&value_name);
-                        s.push_str(", data, offset);");
-                        break;
-                    }
-                    StructFieldType::U32 => {
-                        s.push_str("if (!Number.isSafeInteger(");
-                        s.push_str(&value_name);
-                        s.push_str(") || ");
-                        s.push_str(&value_name);
-                        s.push_str(" < 0 || ");
-                        s.push_str(&value_name);
-                        s.push_str(" >= 4_294_967_296) {\n");
-                        s.push_str(&indent);
-                        s.push_str("    throw new Error(\"invalid uint32\");\n");
-                        s.push_str(&indent);
-                        s.push_str("}\n");
-                        s.push_str(&indent);
-                        s.push_str("offset += encodeUint(");
-                        s.push_str(&value_name);
-                        s.push_str(", data, offset);");
-                        break;
-                    }
-                    StructFieldType::I32 => {
-                        s.push_str("if (!Number.isSafeInteger(");
-                        s.push_str(&value_name);
-                        s.push_str(") || ");
-                        s.push_str(&value_name);
-                        s.push_str(" < -2_147_483_648 || ");
-                        s.push_str(&value_name);
-                        s.push_str(" >= 2_147_483_648) {\n");
-                        s.push_str(&indent);
-                        s.push_str("    throw new Error(\"invalid int32\");\n");
-                        s.push_str(&indent);
-                        s.push_str("}\n");
-                        s.push_str(&indent);
-                        s.push_str("offset += encodeInt(");
-                        s.push_str(&value_name);
-                        s.push_str(", data, offset);");
-                        break;
-                    }
-                    StructFieldType::U64 => {
-                        s.push_str("if (");
-                        s.push_str(&value_name);
-                        s.push_str(" < 0n || ");
-                        s.push_str(&value_name);
-                        s.push_str(" >= 18_446_744_073_709_551_616n) {\n");
-                        s.push_str(&indent);
-                        s.push_str("    throw new Error(\"invalid uint64\");\n");
-                        s.push_str(&indent);
-                        s.push_str("}\n");
-                        s.push_str(&indent);
-                        s.push_str("offset += encodeUint64(");
-                        s.push_str(&value_name);
-                        s.push_str(", data, offset);");
-                        break;
-                    }
-                    StructFieldType::I64 => {
-                        s.push_str("if (");
-                        s.push_str(&value_name);
-                        s.push_str(" < -9_223_372_036_854_775_808n || ");
-                        s.push_str(&value_name);
-                        s.push_str(" >= 9_223_372_036_854_775_808n) {\n");
-                        s.push_str(&indent);
-                        s.push_str("    throw new Error(\"invalid int64\");\n");
-                        s.push_str(&indent);
-                        s.push_str("}\n");
-                        s.push_str(&indent);
-                        s.push_str("offset += encodeInt64(");
-                        s.push_str(&value_name);
-                        s.push_str(", data, offset);");
-                        break;
-                    }
-                    StructFieldType::F32 => {
-                        s.push_str("offset += encodeFloat32(");
-                        s.push_str(&value_name);
-                        s.push_str(", data, offset);");
-                        break;
-                    }
-                    StructFieldType::F64 => {
-                        s.push_str("offset += encodeFloat64(");
-                        s.push_str(&value_name);
-                        s.push_str(", data, offset);");
-                        break;
-                    }
-                    StructFieldType::String => {
-                        s.push_str("offset += encodeString(");
-                        s.push_str(&value_name);
-                        s.push_str(", data, offset);");
-                        break;
-                    }
-                    StructFieldType::Bytes { len } => {
-                        s.push_str("offset += encodeBytes(");
-                        s.push_str(&value_name);
-                        s.push_str(", data, offset");
-                        if let Some(len) = len {
-                            s.push_str(", ");
-                            s.push_str(&len.to_string());
-                        }
-                        s.push_str(");");
-                        break;
-                    }
-                    StructFieldType::Array { items } => {
-                        s.push_str("offset += encodeArrayHeader(");
-                        s.push_str(&value_name);
-                        s.push_str(".length, data, offset);\n");
-                        s.push_str(&indent);
-                        s.push_str("for (const ");
-                        s.push_str(&get_value_name(array_depth + 1));
-                        s.push_str(" of ");
-                        s.push_str(&value_name);
-                        s.push_str(") {\n");
-                        field_type = items;
-                        array_depth += 1;
-                    }
-                    StructFieldType::Reference { name } => {
-                        s.push_str("{\n");
-                        s.push_str(&indent);
-                        s.push_str("    offset += TypedpackEncodeInternal.");
-                        s.push_str(name);
-                        s.push('(');
-                        s.push_str(&value_name);
-                        s.push_str(", data, offset);\n");
-                        s.push_str(&indent);
-                        s.push('}');
-                        break;
-                    }
-                }
-            }
-
-            for depth in (0..array_depth).rev() {
-                s.push('\n');
-                for _ in 0..4 * depth
-                    + if field.optional { 4 } else { 0 }
-                    + if field.nullable { 4 } else { 0 }
-                    + 8
-                {
-                    s.push(' ');
-                }
-                s.push('}');
+            let mut value_name = String::from("value.");
+            value_name.push_str(field.typescript_name());
+            if field.optional && field.default.is_none() {
+                value_name.push('!');
             }
+            let indent =
+                8 + if field.optional { 4 } else { 0 } + if field.nullable { 4 } else { 0 };
+            field.push_typescript_constraint_checks(
+                &mut s,
+                self.name,
+                &value_name,
+                &" ".repeat(indent),
+            );
+            field
+                .r#type
+                .push_typescript_encode_nested(&mut s, &value_name, indent);
 
             if field.nullable {
                 s.push_str("\n        ");
@@ -959,6 +2073,9 @@ impl Struct<'_> {
             }
         }
 
+        if self.preserve_unknown {
+            s.push_str("\n\n        for (const [key, bytes] of value.unknown_fields ?? []) {\n            offset += encodeUint(key, data, offset);\n            new Uint8Array(data.buffer, data.byteOffset + offset, bytes.byteLength).set(bytes);\n            offset += bytes.byteLength;\n        }");
+        }
         s.push_str("\n\n        return offset - originalOffset;\n    };\n}\n\nexport namespace ");
         s.push_str(&options.encode_namespace);
         s.push_str(" {\n    export const ");
@@ -976,7 +2093,15 @@ impl Struct<'_> {
         if options.export_decode_internal_namespace {
             s.push_str("export ");
         }
-        s.push_str("namespace TypedpackDecodeInternal {\n    export const ");
+        s.push_str("namespace TypedpackDecodeInternal {\n");
+        if self.preserve_unknown {
+            s.push_str(TYPESCRIPT_SKIP_VALUE);
+        }
+        s.push_str(&typescript_timestamp_helpers(
+            self.fields.iter().map(|field| &field.r#type),
+            false,
+        ));
+        s.push_str("    export const ");
         s.push_str(self.name);
         s.push_str(" = (data: DataView, offset: number = 0): [");
         s.push_str(&options.types_namespace);
@@ -986,7 +2111,7 @@ impl Struct<'_> {
         s.push_str(&options.types_namespace);
         s.push('.');
         s.push_str(self.name);
-        s.push_str("> = Object.create(null);\n        const seenKeys = new Set();\n        const [mapLength, newOffset] = decodeMapLength(data, offset);\n        offset = newOffset;\n\n        for (let i = 0; i < mapLength; i += 1) {\n            const key = decodeMapKey(data, offset);\n            offset += 1;\n            if (seenKeys.has(key)) {\n                throw new Error(`duplicate map key '${key}'`);\n            }\n            seenKeys.add(key);\n\n            ");
+        s.push_str("> = Object.create(null);\n        const seenKeys = new Set();\n        const [mapLength, newOffset] = decodeMapLength(data, offset);\n        offset = newOffset;\n\n        for (let i = 0; i < mapLength; i += 1) {\n            const [key, newOffset] = decodeInt(data, offset);\n            offset = newOffset;\n            if (seenKeys.has(key)) {\n                throw new Error(`duplicate map key '${key}'`);\n            }\n            seenKeys.add(key);\n\n            ");
 
         for (i, field) in self.fields.iter().enumerate() {
             if i != 0 {
@@ -996,360 +2121,29 @@ impl Struct<'_> {
             s.push_str(&field.id.to_string());
             s.push_str(") {\n");
 
-            let mut array_depth = 0;
-            let mut field_type = &field.r#type;
-
             if field.nullable {
                 s.push_str("                if (data.getUint8(offset) === 0xc0) {\n                    obj.");
-                s.push_str(field.name);
+                s.push_str(field.typescript_name());
                 s.push_str(
                     " = null;\n                    offset += 1;\n                } else {\n",
                 );
             }
 
-            loop {
-                let indent = " ".repeat(4 * array_depth + if field.nullable { 4 } else { 0 } + 16);
-                s.push_str(&indent);
-
-                match field_type {
-                    StructFieldType::Bool => {
-                        if array_depth == 0 {
-                            s.push_str("[obj.");
-                            s.push_str(field.name);
-                            s.push_str(", offset] = decodeBoolean(data, offset);");
-                        } else {
-                            s.push_str("const [value, newOffset] = decodeBoolean(data, offset);\n");
-                            s.push_str(&indent);
-                            s.push_str("offset = newOffset;\n");
-                            s.push_str(&indent);
-                            s.push_str("array");
-                            s.push_str(&(array_depth - 1).to_string());
-                            s.push_str(".push(value);");
-                        }
-                        break;
-                    }
-                    StructFieldType::U8 => {
-                        s.push_str("const [value, newOffset] = decodeInt(data, offset);\n");
-                        s.push_str(&indent);
-                        s.push_str("offset = newOffset;\n");
-                        s.push_str(&indent);
-                        s.push_str("if (value < 0 || value >= 256) {\n");
-                        s.push_str(&indent);
-                        s.push_str("    throw new Error(\"invalid uint8\");\n");
-                        s.push_str(&indent);
-                        s.push_str("}\n");
-                        s.push_str(&indent);
-                        if array_depth == 0 {
-                            s.push_str("obj.");
-                            s.push_str(field.name);
-                            s.push_str(" = value;");
-                        } else {
-                            s.push_str("array");
-                            s.push_str(&(array_depth - 1).to_string());
-                            s.push_str(".push(value);");
-                        }
-                        break;
-                    }
-                    StructFieldType::I8 => {
-                        s.push_str("const [value, newOffset] = decodeInt(data, offset);\n");
-                        s.push_str(&indent);
-                        s.push_str("offset = newOffset;\n");
-                        s.push_str(&indent);
-                        s.push_str("if (value < -128 || value >= 128) {\n");
-                        s.push_str(&indent);
-                        s.push_str("    throw new Error(\"invalid int8\");\n");
-                        s.push_str(&indent);
-                        s.push_str("}\n");
-                        s.push_str(&indent);
-                        if array_depth == 0 {
-                            s.push_str("obj.");
-                            s.push_str(field.name);
-                            s.push_str(" = value;");
-                        } else {
-                            s.push_str("array");
-                            s.push_str(&(array_depth - 1).to_string());
-                            s.push_str(".push(value);");
-                        }
-                        break;
-                    }
-                    StructFieldType::U16 => {
-                        s.push_str("const [value, newOffset] = decodeInt(data, offset);\n");
-                        s.push_str(&indent);
-                        s.push_str("offset = newOffset;\n");
-                        s.push_str(&indent);
-                        s.push_str("if (value < 0 || value >= 65_536) {\n");
-                        s.push_str(&indent);
-                        s.push_str("    throw new Error(\"invalid uint16\");\n");
-                        s.push_str(&indent);
-                        s.push_str("}\n");
-                        s.push_str(&indent);
-                        if array_depth == 0 {
-                            s.push_str("obj.");
-                            s.push_str(field.name);
-                            s.push_str(" = value;");
-                        } else {
-                            s.push_str("array");
-                            s.push_str(&(array_depth - 1).to_string());
-                            s.push_str(".push(value);");
-                        }
-                        break;
-                    }
-                    StructFieldType::I16 => {
-                        s.push_str("const [value, newOffset] = decodeInt(data, offset);\n");
-                        s.push_str(&indent);
-                        s.push_str("offset = newOffset;\n");
-                        s.push_str(&indent);
-                        s.push_str("if (value < -32_768 || value >= 32_768) {\n");
-                        s.push_str(&indent);
-                        s.push_str("    throw new Error(\"invalid int16\");\n");
-                        s.push_str(&indent);
-                        s.push_str("}\n");
-                        s.push_str(&indent);
-                        if array_depth == 0 {
-                            s.push_str("obj.");
-                            s.push_str(field.name);
-                            s.push_str(" = value;");
-                        } else {
-                            s.push_str("array");
-                            s.push_str(&(array_depth - 1).to_string());
-                            s.push_str(".push(value);");
-                        }
-                        break;
-                    }
-                    StructFieldType::U32 => {
-                        s.push_str("const [value, newOffset] = decodeInt(data, offset);\n");
-                        s.push_str(&indent);
-                        s.push_str("offset = newOffset;\n");
-                        s.push_str(&indent);
-                        s.push_str("if (value < 0) {\n");
-                        s.push_str(&indent);
-                        s.push_str("    throw new Error(\"invalid uint32\");\n");
-                        s.push_str(&indent);
-                        s.push_str("}\n");
-                        s.push_str(&indent);
-                        if array_depth == 0 {
-                            s.push_str("obj.");
-                            s.push_str(field.name);
-                            s.push_str(" = value;");
-                        } else {
-                            s.push_str("array");
-                            s.push_str(&(array_depth - 1).to_string());
-                            s.push_str(".push(value);");
-                        }
-                        break;
-                    }
-                    StructFieldType::I32 => {
-                        s.push_str("const [value, newOffset] = decodeInt(data, offset);\n");
-                        s.push_str(&indent);
-                        s.push_str("offset = newOffset;\n");
-                        s.push_str(&indent);
-                        s.push_str("if (value >= 2_147_483_648) {\n");
-                        s.push_str(&indent);
-                        s.push_str("    throw new Error(\"invalid int32\");\n");
-                        s.push_str(&indent);
-                        s.push_str("}\n");
-                        s.push_str(&indent);
-                        if array_depth == 0 {
-                            s.push_str("obj.");
-                            s.push_str(field.name);
-                            s.push_str(" = value;");
-                        } else {
-                            s.push_str("array");
-                            s.push_str(&(array_depth - 1).to_string());
-                            s.push_str(".push(value);");
-                        }
-                        break;
-                    }
-                    StructFieldType::U64 => {
-                        s.push_str("const [value, newOffset] = decodeInt64(data, offset);\n");
-                        s.push_str(&indent);
-                        s.push_str("offset = newOffset;\n");
-                        s.push_str(&indent);
-                        s.push_str("if (value < 0n) {\n");
-                        s.push_str(&indent);
-                        s.push_str("    throw new Error(\"invalid uint64\");\n");
-                        s.push_str(&indent);
-                        s.push_str("}\n");
-                        s.push_str(&indent);
-                        if array_depth == 0 {
-                            s.push_str("obj.");
-                            s.push_str(field.name);
-                            s.push_str(" = value;");
-                        } else {
-                            s.push_str("array");
-                            s.push_str(&(array_depth - 1).to_string());
-                            s.push_str(".push(value);");
-                        }
-                        break;
-                    }
-                    StructFieldType::I64 => {
-                        s.push_str("const [value, newOffset] = decodeInt64(data, offset);\n");
-                        s.push_str(&indent);
-                        s.push_str("offset = newOffset;\n");
-                        s.push_str(&indent);
-                        s.push_str("if (value >= 9_223_372_036_854_775_808n) {\n");
-                        s.push_str(&indent);
-                        s.push_str("    throw new Error(\"invalid int64\");\n");
-                        s.push_str(&indent);
-                        s.push_str("}\n");
-                        s.push_str(&indent);
-                        if array_depth == 0 {
-                            s.push_str("obj.");
-                            s.push_str(field.name);
-                            s.push_str(" = value;");
-                        } else {
-                            s.push_str("array");
-                            s.push_str(&(array_depth - 1).to_string());
-                            s.push_str(".push(value);");
-                        }
-                        break;
-                    }
-                    StructFieldType::F32 => {
-                        if array_depth == 0 {
-                            s.push_str("[obj.");
-                            s.push_str(field.name);
-                            s.push_str(", offset] = decodeFloat32(data, offset);");
-                        } else {
-                            s.push_str("const [value, newOffset] = decodeFloat32(data, offset);\n");
-                            s.push_str(&indent);
-                            s.push_str("offset = newOffset;\n");
-                            s.push_str(&indent);
-                            s.push_str("array");
-                            s.push_str(&(array_depth - 1).to_string());
-                            s.push_str(".push(value);");
-                        }
-                        break;
-                    }
-                    StructFieldType::F64 => {
-                        if array_depth == 0 {
-                            s.push_str("[obj.");
-                            s.push_str(field.name);
-                            s.push_str(", offset] = decodeFloat64(data, offset);");
-                        } else {
-                            s.push_str("const [value, newOffset] = decodeFloat64(data, offset);\n");
-                            s.push_str(&indent);
-                            s.push_str("offset = newOffset;\n");
-                            s.push_str(&indent);
-                            s.push_str("array");
-                            s.push_str(&(array_depth - 1).to_string());
-                            s.push_str(".push(value);");
-                        }
-                        break;
-                    }
-                    StructFieldType::String => {
-                        if array_depth == 0 {
-                            s.push_str("[obj.");
-                            s.push_str(field.name);
-                            s.push_str(", offset] = decodeString(data, offset);");
-                        } else {
-                            s.push_str("const [value, newOffset] = decodeString(data, offset);\n");
-                            s.push_str(&indent);
-                            s.push_str("offset = newOffset;\n");
-                            s.push_str(&indent);
-                            s.push_str("array");
-                            s.push_str(&(array_depth - 1).to_string());
-                            s.push_str(".push(value);");
-                        }
-                        break;
-                    }
-                    StructFieldType::Bytes { len } => {
-                        if array_depth == 0 {
-                            s.push_str("[obj.");
-                            s.push_str(field.name);
-                            s.push_str(", offset] = decodeBytes(data, offset");
-                            if let Some(len) = len {
-                                s.push_str(", ");
-                                s.push_str(&len.to_string());
-                            }
-                            s.push_str(");");
-                        } else {
-                            s.push_str("const [value, newOffset] = decodeBytes(data, offset");
-                            if let Some(len) = len {
-                                s.push_str(", ");
-                                s.push_str(&len.to_string());
-                            }
-                            s.push_str(");\n");
-                            s.push_str(&indent);
-                            s.push_str("offset = newOffset;\n");
-                            s.push_str(&indent);
-                            s.push_str("array");
-                            s.push_str(&(array_depth - 1).to_string());
-                            s.push_str(".push(value);");
-                        }
-                        break;
-                    }
-                    StructFieldType::Array { items } => {
-                        let depth_str = array_depth.to_string();
-                        s.push_str("const array");
-                        s.push_str(&depth_str);
-                        s.push_str(" = [];\n");
-                        s.push_str(&indent);
-                        s.push_str(
-                            "const [length, newOffset] = decodeArrayLength(data, offset);\n",
-                        );
-                        s.push_str(&indent);
-                        s.push_str("offset = newOffset;\n");
-                        s.push_str(&indent);
-                        s.push_str("for (let i");
-                        s.push_str(&depth_str);
-                        s.push_str(" = 0; i");
-                        s.push_str(&depth_str);
-                        s.push_str(" < length; i");
-                        s.push_str(&depth_str);
-                        s.push_str(" += 1) {\n");
-                        field_type = items;
-                        array_depth += 1;
-                    }
-                    StructFieldType::Reference { name } => {
-                        if array_depth == 0 {
-                            s.push_str("[obj.");
-                            s.push_str(field.name);
-                            s.push_str(", offset] = TypedpackDecodeInternal.");
-                            s.push_str(name);
-                            s.push_str("(data, offset);");
-                        } else {
-                            s.push_str("const [value, newOffset] = TypedpackDecodeInternal.");
-                            s.push_str(name);
-                            s.push_str("(data, offset);\n");
-                            s.push_str(&indent);
-                            s.push_str("offset = newOffset;\n");
-                            s.push_str(&indent);
-                            s.push_str("array");
-                            s.push_str(&(array_depth - 1).to_string());
-                            s.push_str(".push(value);");
-                        }
-                        break;
-                    }
-                }
-            }
-
-            for depth in (0..array_depth).rev() {
+            let place = format!("obj.{name}", name = field.typescript_name());
+            let indent = 16 + if field.nullable { 4 } else { 0 };
+            field
+                .r#type
+                .push_typescript_decode_nested(&mut s, &place, indent);
+            if !field.constraints.is_empty() {
                 s.push('\n');
-                for _ in 0..16 + 4 * depth {
-                    s.push(' ');
-                }
-                if field.nullable {
-                    s.push_str("    ");
-                }
-                s.push_str("}\n");
-
-                if depth == 0 {
-                    if field.nullable {
-                        s.push_str("    ");
-                    }
-                    s.push_str("                obj.");
-                    s.push_str(field.name);
-                    s.push_str(" = array0;");
-                } else {
-                    for _ in 0..16 + 4 * depth {
-                        s.push(' ');
-                    }
-                    s.push_str("array");
-                    s.push_str(&(depth - 1).to_string());
-                    s.push_str(".push(array");
-                    s.push_str(&depth.to_string());
-                    s.push_str(");");
-                }
+                field.push_typescript_constraint_checks(
+                    &mut s,
+                    self.name,
+                    &format!("{place}!"),
+                    &" ".repeat(indent),
+                );
+                // the last line break is added below
+                s.pop();
             }
 
             if field.nullable {
@@ -1357,8 +2151,37 @@ impl Struct<'_> {
             }
             s.push_str("\n            }");
         }
+        if self.preserve_unknown {
+            let keep = "const end = skipValue(data, offset);\n{indent}(obj.unknown_fields ??= []).push([key, new Uint8Array(data.buffer.slice(data.byteOffset + offset, data.byteOffset + end))]);\n{indent}offset = end;";
+            if self.fields.is_empty() {
+                s.push_str(&keep.replace("{indent}", "            "));
+            } else {
+                s.push_str(" else {\n                ");
+                s.push_str(&keep.replace("{indent}", "                "));
+                s.push_str("\n            }");
+            }
+        } else if options.reject_unknown_fields {
+            if self.fields.is_empty() {
+                s.push_str("throw new Error(`unknown map key '${key}'`);");
+            } else {
+                s.push_str(" else {\n                throw new Error(`unknown map key '${key}'`);\n            }");
+            }
+        }
         s.push_str("\n        }\n\n");
 
+        for field in &self.fields {
+            let Some(default) = &field.default else {
+                continue;
+            };
+            s.push_str("        if (!seenKeys.has(");
+            s.push_str(&field.id.to_string());
+            s.push_str(")) {\n            obj.");
+            s.push_str(field.typescript_name());
+            s.push_str(" = ");
+            s.push_str(&default.typescript_value(&field.r#type, options));
+            s.push_str(";\n        }\n\n");
+        }
+
         if required_field_count != 0 {
             s.push_str("        for (const key of [");
             for (i, field) in self
@@ -1414,3 +2237,87 @@ impl Struct<'_> {
         s
     }
 }
+
+impl Module<'_> {
+    /// Generates TypeScript `import` declarations for the types the module imports.
+    ///
+    /// Paths are relative, assuming every module is generated into a `.ts` file according to its
+    /// module path. The imported modules must export their internal namespaces, see
+    /// [`Module::typescript_exports`].
+    pub fn typescript_imports(&self, options: &Options) -> String {
+        let mut s = String::new();
+        for (i, import) in self.imports.iter().enumerate() {
+            if import.names.is_empty() {
+                continue;
+            }
+            let alias = format!("TypedpackImport{i}");
+
+            let common_len = self
+                .module_path
+                .iter()
+                .zip(import.module_path)
+                .take_while(|(a, b)| a == b)
+                .count()
+                .min(self.module_path.len() - 1);
+            s.push_str("import * as ");
+            s.push_str(&alias);
+            s.push_str(" from \"");
+            if common_len + 1 == self.module_path.len() {
+                s.push_str("./");
+            } else {
+                for _ in common_len + 1..self.module_path.len() {
+                    s.push_str("../");
+                }
+            }
+            s.push_str(&import.module_path[common_len..].join("/"));
+            s.push_str(".js\";\n");
+
+            let namespaces = [
+                (true, options.types_namespace.as_str(), "import"),
+                (false, "TypedpackMaxLength", "const"),
+                (false, "TypedpackEncodeInternal", "const"),
+                (
+                    options.export_decode_internal_namespace,
+                    "TypedpackDecodeInternal",
+                    "const",
+                ),
+            ];
+            for (export, namespace, declaration) in namespaces {
+                s.push('\n');
+                if export {
+                    s.push_str("export ");
+                }
+                s.push_str("namespace ");
+                s.push_str(namespace);
+                s.push_str(" {\n");
+                for name in &import.names {
+                    s.push_str("    export ");
+                    s.push_str(declaration);
+                    s.push(' ');
+                    s.push_str(name);
+                    s.push_str(" = ");
+                    s.push_str(&alias);
+                    s.push('.');
+                    s.push_str(namespace);
+                    s.push('.');
+                    s.push_str(name);
+                    s.push_str(";\n");
+                }
+                s.push_str("}\n");
+            }
+        }
+        s
+    }
+
+    /// Generates a TypeScript `export` declaration for the internal namespaces, which is necessary
+    /// for other modules to import types from this module.
+    pub fn typescript_exports(&self, options: &Options) -> String {
+        if options.export_decode_internal_namespace {
+            String::from("export { TypedpackMaxLength, TypedpackEncodeInternal };")
+        } else {
+            String::from(
+                "export { TypedpackMaxLength, TypedpackEncodeInternal, TypedpackDecodeInternal };",
+            )
+        }
+    }
+}
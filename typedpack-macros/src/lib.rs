@@ -0,0 +1,90 @@
+//! Procedural macros for [`typedpack`], which re-exports them when its `macros` feature is
+//! enabled.
+
+use std::path::{Path, PathBuf};
+
+use proc_macro::TokenStream;
+use syn::{LitStr, parse_macro_input};
+use typedpack_codegen::{ResolveError, Sources, rust::OptionsInput, rust_modules};
+
+/// Generates Rust types from a typedpack `.tp` file at compile time.
+///
+/// The path is relative to the directory containing the crate's `Cargo.toml`. The file may
+/// import other files, which are generated as sibling modules, like with
+/// [`rust_modules`](typedpack_codegen::rust_modules). The types declared in the file itself are
+/// re-exported where the macro is invoked.
+///
+/// ```ignore
+/// mod api {
+///     typedpack::include_schema!("schemas/api.tp");
+/// }
+/// ```
+///
+/// Errors in the schema are reported as compiler errors, each pointing at the location in the
+/// schema where it occurred.
+///
+/// The generated code for a `pattern` constraint uses the `regex` crate re-exported by
+/// [`typedpack`], which requires its `pattern` feature.
+#[proc_macro]
+pub fn include_schema(input: TokenStream) -> TokenStream {
+    let path = parse_macro_input!(input as LitStr);
+    match include_schema_code(&path.value()) {
+        Ok(code) => code.parse().expect("generated code is valid Rust"),
+        Err(errors) => errors
+            .into_iter()
+            .map(|error| TokenStream::from(syn::Error::new(path.span(), error).to_compile_error()))
+            .collect(),
+    }
+}
+
+fn include_schema_code(path: &str) -> Result<String, Vec<String>> {
+    let manifest_dir = std::env::var_os("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_default();
+    let path = manifest_dir.join(path);
+
+    let to_errors = |error: ResolveError| match error {
+        ResolveError::Parse { diagnostics, .. } => diagnostics
+            .errors()
+            .iter()
+            .map(|error| {
+                // the compiler adds its own "error: " prefix
+                let rendered = diagnostics.render(error);
+                match rendered.strip_prefix("error: ") {
+                    Some(rendered) => rendered.to_owned(),
+                    None => rendered,
+                }
+            })
+            .collect(),
+        error => vec![error.to_string()],
+    };
+    let sources = Sources::load(&path).map_err(to_errors)?;
+    let modules = sources.parse().map_err(to_errors)?;
+
+    let options = OptionsInput::default()
+        .try_into()
+        .expect("default options are valid");
+    let mut code = rust_modules(&modules, &options);
+
+    let path = path
+        .canonicalize()
+        .map_err(|error| vec![format!("failed to read file {}: {error}", path.display())])?;
+    if let Some(module) = modules.iter().find(|module| module.path() == path) {
+        code.push_str("pub use self::");
+        code.push_str(&module.rust_path());
+        code.push_str("::*;\n");
+    }
+
+    // make the compiler track the files, so that the code is regenerated when they change
+    for path in sources.paths() {
+        code.push_str(&include_bytes_item(path));
+    }
+    Ok(code)
+}
+
+fn include_bytes_item(path: &Path) -> String {
+    format!(
+        "const _: &[::std::primitive::u8] = ::std::include_bytes!({:?});\n",
+        path.to_string_lossy()
+    )
+}
@@ -0,0 +1,80 @@
+//! Compares decoding generated types directly with decoding them through `rmpv::Value`, as the
+//! generated code did before it deserialized fields directly.
+//!
+//! Run with `cargo bench -p typedpack-tests`.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+#[allow(non_camel_case_types)]
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/bench.rs"));
+}
+
+use generated::bench::{BenchDocument, BenchItem, BenchSection, BenchStatus};
+use typedpack::rmpv;
+
+const SECTIONS: u32 = 50;
+const ITEMS_PER_SECTION: u32 = 200;
+const ITERATIONS: u32 = 50;
+
+fn document() -> BenchDocument {
+    BenchDocument {
+        title: String::from("benchmark"),
+        sections: (0..SECTIONS)
+            .map(|section| BenchSection {
+                name: format!("section {section}"),
+                items: (0..ITEMS_PER_SECTION)
+                    .map(|item| BenchItem {
+                        id: section * ITEMS_PER_SECTION + item,
+                        label: format!("item {item} of section {section}"),
+                        payload: vec![item as u8; 64].into(),
+                        values: (0..16).map(|value| i64::from(item) * value).collect(),
+                        status: (item % 3 == 0).then_some(BenchStatus::Archived),
+                    })
+                    .collect(),
+            })
+            .collect(),
+    }
+}
+
+fn measure(name: &str, mut decode: impl FnMut() -> BenchDocument) -> Duration {
+    // warm up
+    black_box(decode());
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(decode());
+    }
+    let elapsed = start.elapsed() / ITERATIONS;
+    println!("{name:<24} {elapsed:>12.3?} per document");
+    elapsed
+}
+
+fn main() {
+    let document = document();
+    let encoded = rmp_serde::to_vec(&document).unwrap();
+    println!(
+        "{SECTIONS} sections of {ITEMS_PER_SECTION} items, {} bytes",
+        encoded.len()
+    );
+
+    let direct = measure("direct", || {
+        rmp_serde::from_slice(black_box(&encoded)).unwrap()
+    });
+    let through_value = measure("through rmpv::Value", || {
+        let value: rmpv::Value = rmp_serde::from_slice(black_box(&encoded)).unwrap();
+        <BenchDocument as typedpack::FromRmpValue>::from(value).unwrap()
+    });
+    assert_eq!(
+        rmp_serde::from_slice::<BenchDocument>(&encoded).unwrap(),
+        document
+    );
+
+    println!(
+        "direct decoding is {:.2}x as fast",
+        through_value.as_secs_f64() / direct.as_secs_f64()
+    );
+}
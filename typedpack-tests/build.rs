@@ -1,26 +1,50 @@
-use std::path::Path;
+use typedpack_codegen::{
+    BuildError, Builder,
+    rust::{OptionsInput, TimeCrate},
+};
 
-use typedpack_codegen::Type;
+fn main() -> Result<(), BuildError> {
+    Builder::new()
+        .file("test.tp")
+        .output_file("types.rs")
+        .compile()?;
 
-fn main() {
-    let out_dir = std::env::var_os("OUT_DIR").unwrap();
-    let dest_path = Path::new(&out_dir).join("types.rs");
-
-    let file = std::fs::read_to_string("test.tp").unwrap();
-    let types = typedpack_codegen::parse(&file).unwrap();
+    let options = OptionsInput {
+        extra_derives: vec![String::from("PartialOrd"), String::from("::std::cmp::Eq")],
+        extra_attributes: vec![String::from("must_use")],
+        field_visibility: Some(String::from("pub(crate)")),
+        vec_arrays: Some(true),
+        primitive_byte_arrays: Some(true),
+        total_order_floats: Some(true),
+        reject_unknown_fields: Some(true),
+        time_crate: Some(TimeCrate::Chrono),
+        module: Some(String::from("with_options")),
+    }
+    .try_into()
+    .unwrap();
+    Builder::new()
+        .file("test.tp")
+        .rust_options(options)
+        .output_file("types_with_options.rs")
+        .compile()?;
 
-    let mut code = String::new();
-    for r#type in types {
-        match r#type {
-            Type::Struct(r#struct) => {
-                code.push_str(&r#struct.rust_struct());
-            }
-            Type::Enum(r#enum) => {
-                code.push_str(&r#enum.rust_enum());
-            }
-        }
-        code.push_str("\n\n");
+    let options = OptionsInput {
+        time_crate: Some(TimeCrate::Time),
+        module: Some(String::from("with_time")),
+        ..Default::default()
     }
-    std::fs::write(&dest_path, code).unwrap();
-    println!("cargo::rerun-if-changed=test.tp");
+    .try_into()
+    .unwrap();
+    Builder::new()
+        .file("test.tp")
+        .rust_options(options)
+        .output_file("types_with_time.rs")
+        .compile()?;
+
+    Builder::new()
+        .file("bench.tp")
+        .output_file("bench.rs")
+        .compile()?;
+
+    Ok(())
 }
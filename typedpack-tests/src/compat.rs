@@ -0,0 +1,235 @@
+use typedpack_codegen::{
+    compat::{self, ChangeKind, Compatibility},
+    parse,
+};
+
+fn changes(old: &str, new: &str) -> Vec<(ChangeKind, Compatibility, String)> {
+    compat::check(&parse(old).unwrap(), &parse(new).unwrap())
+        .changes()
+        .iter()
+        .map(|change| {
+            (
+                change.kind(),
+                change.compatibility(),
+                change.path().to_owned(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_struct_changes() {
+    use ChangeKind::*;
+    use Compatibility::*;
+
+    let old = "struct A {
+    string b = 0;
+    optional string c = 1;
+    uint32 d = 2;
+    optional uint8 e = 3 [default = 1];
+    string f = 4;
+    optional bool g = 5;
+    nullable string h = 6;
+}";
+    let new = "struct A {
+    string renamed = 0;
+    uint64 d = 2;
+    optional uint8 e = 3 [default = 2];
+    string f = 7;
+    bool g = 5;
+    string h = 6;
+    optional string i = 8;
+    string j = 9;
+    reserved 1;
+}";
+    assert_eq!(
+        changes(old, new),
+        [
+            (FieldRenamed, Compatible, String::from("A.renamed")),
+            (FieldRemoved, Compatible, String::from("A.c")),
+            (FieldTypeChanged, Breaking, String::from("A.d")),
+            (FieldDefaultChanged, Breaking, String::from("A.e")),
+            (FieldRenumbered, Breaking, String::from("A.f")),
+            (FieldMadeRequired, Breaking, String::from("A.g")),
+            (FieldNullableChanged, Breaking, String::from("A.h")),
+            (FieldAdded, Compatible, String::from("A.i")),
+            (FieldAdded, Breaking, String::from("A.j")),
+        ],
+    );
+
+    // an ID reused for a different field
+    let report = compat::check(
+        &parse("struct A {\n    optional string b = 0;\n}").unwrap(),
+        &parse("struct A {\n    optional uint8 c = 0;\n}").unwrap(),
+    );
+    assert!(report.is_breaking());
+    assert_eq!(
+        report.changes()[1].message(),
+        "ID 0 was reused for a field of type uint8, but field 'b' had type string",
+    );
+
+    // removed IDs which are not reserved can be reused by accident
+    let report = compat::check(
+        &parse("struct A {\n    optional string b = 0;\n}").unwrap(),
+        &parse("struct A {}").unwrap(),
+    );
+    assert!(!report.is_breaking());
+    assert_eq!(
+        report.to_string(),
+        "compatible: A.b: optional field 'b' = 0 was removed; consider `reserved 0;` so that the ID is not reused\n",
+    );
+}
+
+#[test]
+fn test_constraint_changes() {
+    use ChangeKind::*;
+    use Compatibility::*;
+
+    let old = "struct A {
+    string b = 0 [max_len = 8];
+    uint8 c = 1;
+    uint8 d = 2 [min = 1];
+}";
+    let new = "struct A {
+    string b = 0 [max_len = 16];
+    uint8 c = 1 [max = 9];
+    uint8 d = 2 [min = 1];
+}";
+    // the constraints of `d` moved in the source but did not change
+    assert_eq!(
+        changes(old, new),
+        [
+            (FieldConstraintsChanged, Breaking, String::from("A.b")),
+            (FieldConstraintsChanged, Breaking, String::from("A.c")),
+        ],
+    );
+
+    let report = compat::check(&parse(old).unwrap(), &parse(new).unwrap());
+    assert_eq!(
+        report.changes()[0].message(),
+        "constraints changed from [max_len = 8] to [max_len = 16], so values accepted by one schema can be rejected by the other",
+    );
+    assert_eq!(
+        report.changes()[1].message(),
+        "constraints changed from none to [max = 9], so values accepted by one schema can be rejected by the other",
+    );
+}
+
+#[test]
+fn test_enum_changes() {
+    use ChangeKind::*;
+    use Compatibility::*;
+
+    let old = "enum A {
+    B = 0;
+    C = 1;
+    D = 2;
+}
+
+open enum E {
+    F = 0;
+    G = 1;
+}
+
+enum H {
+    string I = 0;
+    J = 1;
+}";
+    let new = "enum A {
+    B = 0;
+    Renamed = 1;
+    D = 3;
+    K = 4;
+}
+
+open enum E {
+    F = 0;
+    L = 2;
+}
+
+enum H {
+    string[] I = 0;
+    string J = 1;
+}";
+    assert_eq!(
+        changes(old, new),
+        [
+            (VariantRenamed, Compatible, String::from("A::Renamed")),
+            (VariantRenumbered, Breaking, String::from("A::D")),
+            (VariantAdded, Breaking, String::from("A::K")),
+            (VariantRemoved, Compatible, String::from("E::G")),
+            (VariantAdded, Compatible, String::from("E::L")),
+            (VariantTypeChanged, Breaking, String::from("H::I")),
+            (VariantTypeChanged, Breaking, String::from("H::J")),
+        ],
+    );
+
+    // swapped IDs are not renames
+    assert_eq!(
+        changes(
+            "enum A {\n    X = 0;\n    Y = 1;\n}",
+            "enum A {\n    X = 1;\n    Y = 0;\n}",
+        ),
+        [
+            (VariantRenumbered, Breaking, String::from("A::X")),
+            (VariantRenumbered, Breaking, String::from("A::Y")),
+        ],
+    );
+    assert_eq!(
+        changes(
+            "struct A {\n    string x = 0;\n    string y = 1;\n}",
+            "struct A {\n    string x = 1;\n    string y = 0;\n}",
+        ),
+        [
+            (FieldRenumbered, Breaking, String::from("A.x")),
+            (FieldRenumbered, Breaking, String::from("A.y")),
+        ],
+    );
+}
+
+#[test]
+fn test_type_changes() {
+    use ChangeKind::*;
+    use Compatibility::*;
+
+    let old = "struct A {}\nstruct B {}\nenum C {\n    D = 0;\n}";
+    let new = "enum A {\n    D = 0;\n}\nenum C {\n    string D = 0;\n}\nstruct E {}";
+    assert_eq!(
+        changes(old, new),
+        [
+            (TypeKindChanged, Breaking, String::from("A")),
+            (TypeRemoved, Breaking, String::from("B")),
+            (TypeKindChanged, Breaking, String::from("C")),
+            (TypeAdded, Compatible, String::from("E")),
+        ],
+    );
+    assert!(changes(old, old).is_empty());
+}
+
+#[test]
+fn test_report_json() {
+    let report = compat::check(
+        &parse("struct A {\n    string b = 0;\n}").unwrap(),
+        &parse("struct A {\n    string b = 0;\n}\nstruct C {}").unwrap(),
+    );
+    assert_eq!(
+        report.to_json(),
+        r#"{
+  "breaking": false,
+  "changes": [
+    {
+      "kind": "type_added",
+      "compatibility": "compatible",
+      "path": "C",
+      "message": "type 'C' was added"
+    }
+  ]
+}
+"#,
+    );
+    let report = compat::check(&[], &[]);
+    assert_eq!(
+        report.to_json(),
+        "{\n  \"breaking\": false,\n  \"changes\": []\n}\n"
+    );
+}
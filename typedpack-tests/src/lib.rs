@@ -1,14 +1,33 @@
 #[cfg(test)]
+mod compat;
+#[cfg(test)]
+mod macro_types;
+#[cfg(test)]
+mod parse;
+#[cfg(test)]
 mod types;
+#[cfg(test)]
+mod types_with_options;
+#[cfg(test)]
+mod types_with_time;
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+
     use crate::types::{
         TestArrayOfArrayOfString, TestArrayOfBytesLength2, TestArrayOfMaps, TestArrayOfString,
         TestBool, TestBytes, TestBytesLength0, TestBytesLength32, TestBytesLength65536,
-        TestEmptyStruct, TestEnum, TestInt8, TestInt16, TestInt32, TestInt64, TestMultipleFields,
-        TestNestedArray, TestNestedMap, TestNullable, TestOptional, TestOptionalNullable,
-        TestString, TestTaggedEnum, TestUint8, TestUint16, TestUint32, TestUint64, r#if, r#while,
+        TestDefault, TestEmptyStruct, TestEnum, TestExplicitRepr, TestFloat32, TestFloat64,
+        TestFloatDefault, TestFloatEnum, TestImport, TestInt8, TestInt16, TestInt32, TestInt64,
+        TestLargeIdEnum, TestLargeIdTaggedEnum, TestLargeIds, TestMap, TestMapWithEnumKeys,
+        TestMultipleFields, TestNestedArray, TestNestedByteArrays, TestNestedFloats, TestNestedMap,
+        TestNestedMapType, TestNullable, TestOpenEnum, TestOpenEnumMap, TestOpenTaggedEnum,
+        TestOptional, TestOptionalNullable, TestPreserveUnknown, TestPreserveUnknownEmpty,
+        TestSmallTaggedEnum, TestString, TestTaggedEnum, TestTaggedEnumValues,
+        TestTaggedEnumWithImport, TestUint8, TestUint16, TestUint32, TestUint64,
+        common::{TestImportedEnum, TestImportedFloat, TestImportedStruct},
+        r#if, r#while,
     };
 
     #[test]
@@ -495,6 +514,33 @@ mod tests {
             .unwrap(),
             data,
         );
+
+        // unknown fields are skipped, including nested values
+        assert_eq!(
+            rmp_serde::from_slice::<TestMultipleFields>(&[
+                0x83, 0x02, 0xa1, 0x62, 0x01, 0x92, 0x81, 0x00, 0xc0, 0x90, 0x00, 0xa1, 0x61,
+            ])
+            .unwrap(),
+            data,
+        );
+
+        // duplicate fields are rejected, whether they are known or not
+        assert!(
+            rmp_serde::from_slice::<TestMultipleFields>(&[
+                0x83, 0x00, 0xa1, 0x61, 0x02, 0xa1, 0x62, 0x00, 0xa1, 0x63,
+            ])
+            .unwrap_err()
+            .to_string()
+            .contains("duplicate field 0 of TestMultipleFields"),
+        );
+        assert!(
+            rmp_serde::from_slice::<TestMultipleFields>(&[
+                0x84, 0x00, 0xa1, 0x61, 0x01, 0xc0, 0x02, 0xa1, 0x62, 0x01, 0xc0,
+            ])
+            .unwrap_err()
+            .to_string()
+            .contains("duplicate field 1 of TestMultipleFields"),
+        );
     }
 
     #[test]
@@ -554,6 +600,12 @@ mod tests {
             .unwrap(),
             data,
         );
+
+        // binary data is not accepted as an array of integers
+        assert!(
+            rmp_serde::from_slice::<TestNestedArray>(&[0x81, 0x00, 0x91, 0x91, 0xc4, 0x01, 0x01])
+                .is_err()
+        );
     }
 
     #[test]
@@ -617,4 +669,1161 @@ mod tests {
         );
         assert!(rmp_serde::from_slice::<TestTaggedEnum>(&[0x92, 0x01, 0x81, 0x00, 0xc3]).is_err());
     }
+
+    #[test]
+    fn test_tagged_enum_values() {
+        use typedpack::{FromRmpValue, rmpv::Value, serde_bytes::ByteArray};
+
+        let cases = [
+            (
+                TestTaggedEnumValues::Name("a".to_owned()),
+                vec![0x92, 0x00, 0xa1, 0x61],
+            ),
+            (
+                TestTaggedEnumValues::Ids([1, 2].into()),
+                vec![0x92, 0x01, 0x92, 0x01, 0x02],
+            ),
+            (
+                TestTaggedEnumValues::Key(ByteArray::new([1, 2, 3, 4])),
+                vec![0x92, 0x02, 0xc4, 0x04, 0x01, 0x02, 0x03, 0x04],
+            ),
+            (
+                TestTaggedEnumValues::Kinds([("a".to_owned(), TestEnum::Bar)].into()),
+                vec![0x92, 0x03, 0x81, 0xa1, 0x61, 0x01],
+            ),
+            (
+                TestTaggedEnumValues::Kind(TestEnum::Foo),
+                vec![0x92, 0x04, 0x00],
+            ),
+            (TestTaggedEnumValues::None, vec![0x92, 0x05, 0xc0]),
+            (
+                TestTaggedEnumValues::Nested(TestTaggedEnum::B(TestUint8 { foo: 2 })),
+                vec![0x92, 0x06, 0x92, 0x01, 0x81, 0x00, 0x02],
+            ),
+            (
+                TestTaggedEnumValues::Score(1.5),
+                vec![0x92, 0x07, 0xcb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0],
+            ),
+        ];
+        for (data, encoded) in cases {
+            assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+            assert_eq!(
+                rmp_serde::from_slice::<TestTaggedEnumValues>(&encoded).unwrap(),
+                data,
+            );
+        }
+
+        let data = crate::types_with_options::TestTaggedEnumValues::Key([1, 2, 3, 4]);
+        assert_eq!(
+            rmp_serde::to_vec(&data).unwrap(),
+            [0x92, 0x02, 0xc4, 0x04, 0x01, 0x02, 0x03, 0x04],
+        );
+
+        // unit variants only accept `nil`
+        let error = <TestTaggedEnumValues as FromRmpValue>::from(Value::Array(vec![
+            Value::from(5),
+            Value::Boolean(false),
+        ]))
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "TestTaggedEnumValues::None: invalid type: expected nil, found boolean",
+        );
+        let error = <TestTaggedEnumValues as FromRmpValue>::from(Value::Array(vec![
+            Value::from(0),
+            Value::from(1),
+        ]))
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "TestTaggedEnumValues::Name: invalid type: expected string, found integer",
+        );
+    }
+
+    #[test]
+    fn test_import() {
+        let data = TestImport {
+            foo: TestImportedStruct {
+                foo: String::from("a"),
+            },
+            bar: [TestImportedEnum::Bar, TestImportedEnum::Foo].into(),
+        };
+
+        assert_eq!(
+            rmp_serde::to_vec(&data).unwrap(),
+            [0x82, 0x00, 0x81, 0x00, 0xa1, 0x61, 0x01, 0x92, 0x01, 0x00],
+        );
+        assert_eq!(
+            rmp_serde::from_slice::<TestImport>(&[
+                0x82, 0x00, 0x81, 0x00, 0xa1, 0x61, 0x01, 0x92, 0x01, 0x00,
+            ])
+            .unwrap(),
+            data,
+        );
+    }
+
+    #[test]
+    fn test_tagged_enum_with_import() {
+        let data = TestTaggedEnumWithImport::A(TestImportedStruct { foo: String::new() });
+
+        assert_eq!(
+            rmp_serde::to_vec(&data).unwrap(),
+            [0x92, 0x00, 0x81, 0x00, 0xa0],
+        );
+        assert_eq!(
+            rmp_serde::from_slice::<TestTaggedEnumWithImport>(&[0x92, 0x00, 0x81, 0x00, 0xa0])
+                .unwrap(),
+            data,
+        );
+    }
+
+    #[test]
+    fn test_map() {
+        let data = TestMap {
+            foo: BTreeMap::from([(String::from("a"), 1), (String::from("b"), 2)]),
+        };
+
+        assert_eq!(
+            rmp_serde::to_vec(&data).unwrap(),
+            [0x81, 0x00, 0x82, 0xa1, 0x61, 0x01, 0xa1, 0x62, 0x02],
+        );
+        assert_eq!(
+            rmp_serde::from_slice::<TestMap>(&[
+                0x81, 0x00, 0x82, 0xa1, 0x61, 0x01, 0xa1, 0x62, 0x02
+            ])
+            .unwrap(),
+            data,
+        );
+
+        assert!(
+            rmp_serde::from_slice::<TestMap>(&[
+                0x81, 0x00, 0x82, 0xa1, 0x61, 0x01, 0xa1, 0x61, 0x02
+            ])
+            .is_err()
+        );
+        assert!(rmp_serde::from_slice::<TestMap>(&[0x81, 0x00, 0x81, 0x01, 0x01]).is_err());
+    }
+
+    #[test]
+    fn test_map_with_enum_keys() {
+        let data = TestMapWithEnumKeys {
+            foo: BTreeMap::from([
+                (TestEnum::Bar, [String::from("x")].into()),
+                (TestEnum::Foo, [].into()),
+            ]),
+        };
+
+        assert_eq!(
+            rmp_serde::to_vec(&data).unwrap(),
+            [0x81, 0x00, 0x82, 0x00, 0x90, 0x01, 0x91, 0xa1, 0x78],
+        );
+        assert_eq!(
+            rmp_serde::from_slice::<TestMapWithEnumKeys>(&[
+                0x81, 0x00, 0x82, 0x01, 0x91, 0xa1, 0x78, 0x00, 0x90,
+            ])
+            .unwrap(),
+            data,
+        );
+
+        assert!(
+            rmp_serde::from_slice::<TestMapWithEnumKeys>(&[0x81, 0x00, 0x81, 0x02, 0x90]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_nested_map_type() {
+        let data = TestNestedMapType {
+            foo: Some(BTreeMap::from([(
+                -1,
+                [BTreeMap::from([(
+                    String::from("a"),
+                    TestBool { foo: false },
+                )])]
+                .into(),
+            )])),
+        };
+
+        assert_eq!(
+            rmp_serde::to_vec(&data).unwrap(),
+            [
+                0x81, 0x00, 0x81, 0xff, 0x91, 0x81, 0xa1, 0x61, 0x81, 0x00, 0xc2
+            ],
+        );
+        assert_eq!(
+            rmp_serde::from_slice::<TestNestedMapType>(&[
+                0x81, 0x00, 0x81, 0xff, 0x91, 0x81, 0xa1, 0x61, 0x81, 0x00, 0xc2,
+            ])
+            .unwrap(),
+            data,
+        );
+
+        let data = TestNestedMapType { foo: None };
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), [0x80]);
+        assert_eq!(
+            rmp_serde::from_slice::<TestNestedMapType>(&[0x80]).unwrap(),
+            data,
+        );
+    }
+
+    #[test]
+    fn test_default() {
+        let data = TestDefault {
+            retries: 5,
+            name: Some(String::from("anonymous")),
+            kind: TestEnum::Bar,
+            offset: -9000,
+        };
+
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), [0x80]);
+        assert_eq!(rmp_serde::from_slice::<TestDefault>(&[0x80]).unwrap(), data);
+
+        let data = TestDefault {
+            retries: 6,
+            name: None,
+            ..data
+        };
+
+        assert_eq!(
+            rmp_serde::to_vec(&data).unwrap(),
+            [0x82, 0x00, 0x06, 0x01, 0xc0],
+        );
+        assert_eq!(
+            rmp_serde::from_slice::<TestDefault>(&[0x82, 0x00, 0x06, 0x01, 0xc0]).unwrap(),
+            data,
+        );
+    }
+
+    #[test]
+    fn test_float32() {
+        let data = TestFloat32 { foo: 1.5 };
+
+        assert_eq!(
+            rmp_serde::to_vec(&data).unwrap(),
+            [0x81, 0x00, 0xca, 0x3f, 0xc0, 0x00, 0x00],
+        );
+        assert_eq!(
+            rmp_serde::from_slice::<TestFloat32>(&[0x81, 0x00, 0xca, 0x3f, 0xc0, 0x00, 0x00])
+                .unwrap(),
+            data,
+        );
+
+        // float64 is not accepted for float32
+        assert!(
+            rmp_serde::from_slice::<TestFloat32>(&[
+                0x81, 0x00, 0xcb, 0x3f, 0xf8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_float64() {
+        let data = TestFloat64 { foo: 1.5 };
+        let encoded = [
+            0x81, 0x00, 0xcb, 0x3f, 0xf8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+        assert_eq!(
+            rmp_serde::from_slice::<TestFloat64>(&encoded).unwrap(),
+            data
+        );
+
+        let data = TestFloat64 { foo: f64::NAN };
+        let decoded =
+            rmp_serde::from_slice::<TestFloat64>(&rmp_serde::to_vec(&data).unwrap()).unwrap();
+        assert!(decoded.foo.is_nan());
+    }
+
+    #[test]
+    fn test_nested_floats() {
+        let data = TestNestedFloats {
+            foo: [TestFloat32 { foo: 0.5 }].into(),
+            bar: Some(BTreeMap::from([(String::from("a"), [-0.25].into())])),
+            baz: TestImportedFloat { foo: 1.5 },
+        };
+        let encoded = [
+            0x83, 0x00, 0x91, 0x81, 0x00, 0xca, 0x3f, 0x00, 0x00, 0x00, 0x01, 0x81, 0xa1, 0x61,
+            0x91, 0xcb, 0xbf, 0xd0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x81, 0x00, 0xca,
+            0x3f, 0xc0, 0x00, 0x00,
+        ];
+
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+        assert_eq!(
+            rmp_serde::from_slice::<TestNestedFloats>(&encoded).unwrap(),
+            data,
+        );
+    }
+
+    #[test]
+    fn test_float_enum() {
+        let data = TestFloatEnum::Float(TestFloat64 { foo: 1.5 });
+        let encoded = [
+            0x92, 0x00, 0x81, 0x00, 0xcb, 0x3f, 0xf8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+        assert_eq!(
+            rmp_serde::from_slice::<TestFloatEnum>(&encoded).unwrap(),
+            data,
+        );
+    }
+
+    #[test]
+    fn test_float_default() {
+        let data = TestFloatDefault {
+            foo: 1.5,
+            bar: -2500.0,
+        };
+
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), [0x80]);
+        assert_eq!(
+            rmp_serde::from_slice::<TestFloatDefault>(&[0x80]).unwrap(),
+            data,
+        );
+
+        let data = TestFloatDefault { foo: 1.5, bar: 0.5 };
+        let encoded = [0x81, 0x01, 0xca, 0x3f, 0x00, 0x00, 0x00];
+
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+        assert_eq!(
+            rmp_serde::from_slice::<TestFloatDefault>(&encoded).unwrap(),
+            data,
+        );
+    }
+
+    #[test]
+    fn test_total_order_floats() {
+        use std::collections::HashSet;
+
+        use typedpack::{TotalF32, TotalF64};
+
+        use crate::types_with_options;
+
+        let data = types_with_options::TestNestedFloats {
+            foo: vec![types_with_options::TestFloat32 { foo: TotalF32(0.5) }],
+            bar: Some(BTreeMap::from([(String::from("a"), vec![TotalF64(-0.25)])])),
+            baz: types_with_options::with_options::common::TestImportedFloat { foo: TotalF32(1.5) },
+        };
+        let default_data = TestNestedFloats {
+            foo: [TestFloat32 { foo: 0.5 }].into(),
+            bar: Some(BTreeMap::from([(String::from("a"), [-0.25].into())])),
+            baz: TestImportedFloat { foo: 1.5 },
+        };
+        let encoded = rmp_serde::to_vec(&default_data).unwrap();
+
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+        assert_eq!(
+            rmp_serde::from_slice::<types_with_options::TestNestedFloats>(&encoded).unwrap(),
+            data,
+        );
+
+        // every type implements `Eq` and `Hash`, and NaN is equal to itself
+        let nan = types_with_options::TestFloatEnum::Float(types_with_options::TestFloat64 {
+            foo: TotalF64(f64::NAN),
+        });
+        let set = HashSet::from([nan.clone()]);
+        assert!(set.contains(&nan));
+
+        let data = types_with_options::TestFloatDefault {
+            foo: TotalF64(1.5),
+            bar: TotalF32(-2500.0),
+        };
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), [0x80]);
+    }
+
+    #[test]
+    fn test_include_schema() {
+        use crate::macro_types;
+
+        let data = macro_types::TestDefault {
+            retries: 6,
+            name: None,
+            kind: macro_types::TestEnum::Foo,
+            offset: -9000,
+        };
+        let encoded = rmp_serde::to_vec(&data).unwrap();
+
+        assert_eq!(encoded, [0x83, 0x00, 0x06, 0x01, 0xc0, 0x02, 0x00]);
+        assert_eq!(
+            rmp_serde::from_slice::<TestDefault>(&encoded).unwrap(),
+            TestDefault {
+                retries: 6,
+                name: None,
+                kind: TestEnum::Foo,
+                offset: -9000,
+            },
+        );
+        assert_eq!(
+            rmp_serde::from_slice::<macro_types::TestDefault>(&encoded).unwrap(),
+            data,
+        );
+
+        // imported files are generated as sibling modules
+        let data = macro_types::TestImport {
+            foo: macro_types::common::TestImportedStruct {
+                foo: String::from("foo"),
+            },
+            bar: [macro_types::common::TestImportedEnum::Bar].into(),
+        };
+        assert_eq!(
+            rmp_serde::from_slice::<macro_types::TestImport>(&rmp_serde::to_vec(&data).unwrap())
+                .unwrap(),
+            data,
+        );
+    }
+
+    #[test]
+    fn test_rust_options() {
+        use crate::types_with_options;
+
+        let data = types_with_options::TestBytesLength32 { foo: [0x01; 32] };
+        let default_data = TestBytesLength32 {
+            foo: [0x01; 32].into(),
+        };
+        let encoded = rmp_serde::to_vec(&default_data).unwrap();
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+        assert_eq!(
+            rmp_serde::from_slice::<types_with_options::TestBytesLength32>(&encoded).unwrap(),
+            data,
+        );
+
+        let data = types_with_options::TestArrayOfBytesLength2 {
+            foo: vec![[0x01, 0x02], [0x03, 0x04]],
+        };
+        let encoded = [
+            0x81, 0x00, 0x92, 0xc4, 0x02, 0x01, 0x02, 0xc4, 0x02, 0x03, 0x04,
+        ];
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+        assert_eq!(
+            rmp_serde::from_slice::<types_with_options::TestArrayOfBytesLength2>(&encoded).unwrap(),
+            data,
+        );
+
+        let data = types_with_options::TestNestedByteArrays {
+            foo: Some(Some([0x01, 0x02])),
+            bar: BTreeMap::from([(3, vec![[0x04, 0x05]])]),
+        };
+        let default_data = TestNestedByteArrays {
+            foo: Some(Some([0x01, 0x02].into())),
+            bar: BTreeMap::from([(3, [[0x04, 0x05].into()].into())]),
+        };
+        let encoded = rmp_serde::to_vec(&default_data).unwrap();
+        assert_eq!(
+            encoded,
+            [
+                0x82, 0x00, 0xc4, 0x02, 0x01, 0x02, 0x01, 0x81, 0x03, 0x91, 0xc4, 0x02, 0x04, 0x05,
+            ],
+        );
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+        assert_eq!(
+            rmp_serde::from_slice::<types_with_options::TestNestedByteArrays>(&encoded).unwrap(),
+            data,
+        );
+
+        // unknown fields are rejected
+        assert!(
+            rmp_serde::from_slice::<types_with_options::TestMultipleFields>(&[
+                0x83, 0x00, 0xa1, 0x61, 0x01, 0xc0, 0x02, 0xa1, 0x62,
+            ])
+            .unwrap_err()
+            .to_string()
+            .contains("unknown field 1 of TestMultipleFields"),
+        );
+        assert!(
+            rmp_serde::from_slice::<types_with_options::TestEmptyStruct>(&[0x81, 0x00, 0xc0])
+                .is_err(),
+        );
+
+        // `PartialOrd` is derived in addition to the default traits
+        assert!(
+            types_with_options::TestUint8 { foo: 1 } < types_with_options::TestUint8 { foo: 2 }
+        );
+    }
+
+    #[test]
+    fn test_preserve_unknown() {
+        use typedpack::{UnknownFields, rmpv::Value};
+
+        // the unknown fields are written back in the order they were read
+        let encoded = [
+            0x84, 0x03, 0x92, 0x01, 0xc4, 0x01, 0x02, 0x00, 0xa1, 0x61, 0x02, 0xc3, 0x01, 0x05,
+        ];
+        let data = TestPreserveUnknown {
+            foo: String::from("a"),
+            bar: Some(5),
+            unknown_fields: UnknownFields(vec![
+                (
+                    3,
+                    Value::Array(vec![Value::from(1), Value::Binary(vec![0x02])]),
+                ),
+                (2, Value::Boolean(true)),
+            ]),
+        };
+        assert_eq!(
+            rmp_serde::from_slice::<TestPreserveUnknown>(&encoded).unwrap(),
+            data,
+        );
+        assert_eq!(
+            rmp_serde::to_vec(&data).unwrap(),
+            [
+                0x84, 0x00, 0xa1, 0x61, 0x01, 0x05, 0x03, 0x92, 0x01, 0xc4, 0x01, 0x02, 0x02, 0xc3,
+            ],
+        );
+
+        let data = TestPreserveUnknownEmpty {
+            unknown_fields: UnknownFields(vec![(0, Value::Nil)]),
+        };
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), [0x81, 0x00, 0xc0]);
+        assert_eq!(
+            rmp_serde::from_slice::<TestPreserveUnknownEmpty>(&[0x81, 0x00, 0xc0]).unwrap(),
+            data,
+        );
+
+        // duplicates are still rejected
+        assert!(
+            rmp_serde::from_slice::<TestPreserveUnknownEmpty>(&[0x82, 0x00, 0xc0, 0x00, 0xc0])
+                .is_err(),
+        );
+        // unknown fields are kept even when rejecting them is enabled
+        assert_eq!(
+            rmp_serde::from_slice::<crate::types_with_options::TestPreserveUnknownEmpty>(&[
+                0x81, 0x00, 0xc0,
+            ])
+            .unwrap()
+            .unknown_fields
+            .len(),
+            1,
+        );
+    }
+
+    #[test]
+    fn test_open_enums() {
+        use typedpack::{UnknownValue, rmpv::Value};
+
+        assert_eq!(rmp_serde::to_vec(&TestOpenEnum::Bar).unwrap(), [0x01]);
+        assert_eq!(
+            rmp_serde::from_slice::<TestOpenEnum>(&[0x05]).unwrap(),
+            TestOpenEnum::Unknown(5),
+        );
+        assert_eq!(
+            rmp_serde::to_vec(&TestOpenEnum::Unknown(5)).unwrap(),
+            [0x05]
+        );
+
+        let encoded = [0x92, 0x07, 0x92, 0x01, 0xa1, 0x61];
+        let data = TestOpenTaggedEnum::Unknown(
+            7,
+            UnknownValue(Value::Array(vec![Value::from(1), Value::from("a")])),
+        );
+        assert_eq!(
+            rmp_serde::from_slice::<TestOpenTaggedEnum>(&encoded).unwrap(),
+            data,
+        );
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+        assert_eq!(
+            rmp_serde::from_slice::<TestOpenTaggedEnum>(&[0x92, 0x01, 0x81, 0x00, 0x02]).unwrap(),
+            TestOpenTaggedEnum::B(TestUint8 { foo: 2 }),
+        );
+        // unknown variants must still have exactly one value
+        assert!(rmp_serde::from_slice::<TestOpenTaggedEnum>(&[0x93, 0x07, 0xc0, 0xc0]).is_err());
+
+        let encoded = [0x82, 0x00, 0x81, 0x05, 0x92, 0x07, 0xc0, 0x01, 0x03];
+        let data = TestOpenEnumMap {
+            foo: BTreeMap::from([(
+                TestOpenEnum::Unknown(5),
+                TestOpenTaggedEnum::Unknown(7, UnknownValue(Value::Nil)),
+            )]),
+            bar: TestOpenEnum::Unknown(3),
+        };
+        assert_eq!(
+            rmp_serde::from_slice::<TestOpenEnumMap>(&encoded).unwrap(),
+            data,
+        );
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+        assert_eq!(
+            rmp_serde::from_slice::<TestOpenEnumMap>(&[0x81, 0x00, 0x80])
+                .unwrap()
+                .bar,
+            TestOpenEnum::Bar,
+        );
+    }
+
+    #[test]
+    fn test_large_ids() {
+        use typedpack::{UnknownValue, rmpv::Value};
+
+        let encoded = [
+            0x83, 0x00, 0x01, 0xcc, 0xc8, 0xa1, 0x61, 0xcd, 0x01, 0x2c, 0xcd, 0x03, 0xe8,
+        ];
+        let data = TestLargeIds {
+            foo: 1,
+            bar: "a".to_owned(),
+            baz: Some(TestLargeIdEnum::Bar),
+        };
+        assert_eq!(
+            rmp_serde::from_slice::<TestLargeIds>(&encoded).unwrap(),
+            data
+        );
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+        // duplicate keys are detected for IDs of any size
+        assert!(
+            rmp_serde::from_slice::<TestLargeIds>(&[
+                0x83, 0x00, 0x01, 0xcc, 0xc8, 0xa1, 0x61, 0xcc, 0xc8, 0xa1, 0x62,
+            ])
+            .is_err()
+        );
+        assert!(
+            rmp_serde::from_slice::<TestLargeIds>(&[
+                0x83, 0x00, 0x01, 0xcd, 0x01, 0x2c, 0x00, 0xcd, 0x01, 0x2c, 0x00,
+            ])
+            .is_err()
+        );
+
+        let encoded = [0x92, 0xcc, 0x80, 0x81, 0x00, 0xc3];
+        let data = TestLargeIdTaggedEnum::A(TestBool { foo: true });
+        assert_eq!(
+            rmp_serde::from_slice::<TestLargeIdTaggedEnum>(&encoded).unwrap(),
+            data,
+        );
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+        let encoded = [0x92, 0xcd, 0xff, 0xff, 0xc0];
+        assert_eq!(
+            rmp_serde::from_slice::<TestLargeIdTaggedEnum>(&encoded).unwrap(),
+            TestLargeIdTaggedEnum::None,
+        );
+        assert_eq!(
+            rmp_serde::to_vec(&TestLargeIdTaggedEnum::None).unwrap(),
+            encoded
+        );
+
+        assert_eq!(
+            rmp_serde::from_slice::<TestExplicitRepr>(&[0xcd, 0x01, 0x2c]).unwrap(),
+            TestExplicitRepr::Unknown(300),
+        );
+        assert_eq!(
+            rmp_serde::to_vec(&TestExplicitRepr::Unknown(300)).unwrap(),
+            [0xcd, 0x01, 0x2c]
+        );
+
+        // `uint8` enums keep unknown variants up to 255
+        let encoded = [0x92, 0xcc, 0xc8, 0xc0];
+        let data = TestSmallTaggedEnum::Unknown(200, UnknownValue(Value::Nil));
+        assert_eq!(
+            rmp_serde::from_slice::<TestSmallTaggedEnum>(&encoded).unwrap(),
+            data,
+        );
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+        assert_eq!(
+            rmp_serde::from_slice::<TestSmallTaggedEnum>(&[0x92, 0xcd, 0x01, 0x2c, 0xc0])
+                .unwrap_err()
+                .to_string(),
+            "300 is out of range for uint8",
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_deprecated() {
+        use crate::types::{
+            TestDeprecated, TestDeprecatedEnum, TestDeprecatedTaggedEnum, TestUsesDeprecated,
+        };
+
+        // deprecated fields and variants are encoded like any others
+        let encoded = [
+            0x82, 0x00, 0x81, 0x01, 0x82, 0x00, 0x01, 0x02, 0x02, 0x01, 0x92, 0x02, 0xc0,
+        ];
+        let data = TestUsesDeprecated {
+            foo: [(
+                TestDeprecatedEnum::B,
+                TestDeprecated {
+                    foo: 1,
+                    bar: None,
+                    baz: TestDeprecatedEnum::deprecated,
+                },
+            )]
+            .into_iter()
+            .collect(),
+            bar: TestDeprecatedTaggedEnum::None,
+        };
+        assert_eq!(
+            rmp_serde::from_slice::<TestUsesDeprecated>(&encoded).unwrap(),
+            data
+        );
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+
+        let encoded = [0x92, 0x00, 0xa1, 0x61];
+        let data = TestDeprecatedTaggedEnum::A("a".to_owned());
+        assert_eq!(
+            rmp_serde::from_slice::<TestDeprecatedTaggedEnum>(&encoded).unwrap(),
+            data
+        );
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+    }
+
+    #[test]
+    fn test_annotations() {
+        use crate::types::{TestAnnotations, TestAnnotationsEnum};
+
+        // derived with `@rust(derive = "Default")`, `A` is marked with `#[default]`
+        let data = TestAnnotations::default();
+        assert_eq!(data.kind, TestAnnotationsEnum::A);
+        assert_eq!(TestAnnotationsEnum::default(), TestAnnotationsEnum::A);
+
+        let encoded = [0x82, 0x00, 0x00, 0x02, 0x00];
+        assert_eq!(
+            rmp_serde::from_slice::<TestAnnotations>(&encoded).unwrap(),
+            data
+        );
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+    }
+
+    #[test]
+    fn test_constraints() {
+        use typedpack::{DecodeErrorKind, PathSegment, Validate, serde_bytes::ByteBuf};
+
+        use crate::types::{TestConstraints, TestConstraintsEnum};
+
+        let valid = TestConstraints {
+            username: "user_1".to_owned(),
+            age: Some(None),
+            score: 0.5,
+            ids: Box::new([1]),
+            hash: ByteBuf::from([0x01, 0x02]),
+            tags: None,
+            nested: Some(TestConstraintsEnum::None),
+        };
+        assert_eq!(valid.validate(), Ok(()));
+        let encoded = rmp_serde::to_vec(&valid).unwrap();
+        assert_eq!(
+            rmp_serde::from_slice::<TestConstraints>(&encoded).unwrap(),
+            valid
+        );
+
+        // string lengths are counted in characters
+        let data = TestConstraints {
+            username: "äöü".to_owned(),
+            ..valid.clone()
+        };
+        assert_eq!(
+            data.validate().unwrap_err().kind(),
+            &DecodeErrorKind::ConstraintViolation {
+                constraint: r#"pattern = "^\w+$""#,
+            },
+        );
+        // `\w` only matches ASCII characters, as in JavaScript, so not the digit `٣` either
+        let data = TestConstraints {
+            username: "abc٣".to_owned(),
+            ..valid.clone()
+        };
+        assert_eq!(
+            data.validate().unwrap_err().kind(),
+            &DecodeErrorKind::ConstraintViolation {
+                constraint: r#"pattern = "^\w+$""#,
+            },
+        );
+        let data = TestConstraints {
+            username: "ab".to_owned(),
+            ..valid.clone()
+        };
+        assert_eq!(
+            data.validate().unwrap_err().kind(),
+            &DecodeErrorKind::ConstraintViolation {
+                constraint: "min_len = 3",
+            },
+        );
+
+        // serializing does not check the constraints, but decoding does
+        let data = TestConstraints {
+            age: Some(Some(12)),
+            ..valid.clone()
+        };
+        let encoded = rmp_serde::to_vec(&data).unwrap();
+        assert!(
+            rmp_serde::from_slice::<TestConstraints>(&encoded)
+                .unwrap_err()
+                .to_string()
+                .contains("TestConstraints.age: value does not satisfy `min = 13`"),
+        );
+        let data = TestConstraints {
+            score: 1.5,
+            ..valid.clone()
+        };
+        let encoded = rmp_serde::to_vec(&data).unwrap();
+        assert!(
+            rmp_serde::from_slice::<TestConstraints>(&encoded)
+                .unwrap_err()
+                .to_string()
+                .contains("TestConstraints.score: value does not satisfy `max = 1`"),
+        );
+        for data in [
+            TestConstraints {
+                ids: Box::new([]),
+                ..valid.clone()
+            },
+            TestConstraints {
+                hash: ByteBuf::from([0x01]),
+                ..valid.clone()
+            },
+            TestConstraints {
+                tags: Some(
+                    [("a", 1), ("b", 2), ("c", 3)]
+                        .into_iter()
+                        .map(|(key, value)| (key.to_owned(), value))
+                        .collect(),
+                ),
+                ..valid.clone()
+            },
+        ] {
+            let encoded = rmp_serde::to_vec(&data).unwrap();
+            assert_eq!(
+                rmp_serde::from_slice::<TestConstraints>(&encoded)
+                    .unwrap_err()
+                    .to_string(),
+                data.validate().unwrap_err().to_string(),
+            );
+        }
+
+        // nested values are checked with their path
+        let data = TestConstraints {
+            nested: Some(TestConstraintsEnum::Many(Box::new([
+                valid.clone(),
+                TestConstraints {
+                    username: "up-per".to_owned(),
+                    ..valid.clone()
+                },
+            ]))),
+            ..valid.clone()
+        };
+        let error = data.validate().unwrap_err();
+        assert_eq!(
+            error.path(),
+            [
+                PathSegment::Field {
+                    r#type: "TestConstraints",
+                    name: "nested",
+                    id: 6,
+                },
+                PathSegment::Variant {
+                    r#type: "TestConstraintsEnum",
+                    name: "Many",
+                    id: 0,
+                },
+                PathSegment::Index(1),
+                PathSegment::Field {
+                    r#type: "TestConstraints",
+                    name: "username",
+                    id: 0,
+                },
+            ],
+        );
+        let encoded = rmp_serde::to_vec(&data).unwrap();
+        assert!(
+            rmp_serde::from_slice::<TestConstraints>(&encoded)
+                .unwrap_err()
+                .to_string()
+                .contains(&error.to_string()),
+        );
+    }
+
+    #[test]
+    fn test_timestamps() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        use typedpack::{DecodeErrorKind, FromRmpValue, MessagePackType, rmpv::Value};
+
+        use crate::types::{TestTimestamp, TestTimestampEnum};
+
+        let cases = [
+            // 32-bit format
+            (
+                UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+                vec![0xd6, 0xff, 0x65, 0x53, 0xf1, 0x00],
+            ),
+            // 64-bit format
+            (
+                UNIX_EPOCH + Duration::new(1_700_000_000, 500_000_000),
+                vec![0xd7, 0xff, 0x77, 0x35, 0x94, 0x00, 0x65, 0x53, 0xf1, 0x00],
+            ),
+            // 96-bit format, the nanoseconds count forward from the seconds
+            (
+                UNIX_EPOCH - Duration::from_millis(1_500),
+                vec![
+                    0xc7, 0x0c, 0xff, 0x1d, 0xcd, 0x65, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                    0xff, 0xfe,
+                ],
+            ),
+        ];
+        for (time, encoded_time) in cases {
+            let data = TestTimestampEnum::At(time);
+            let encoded = [[0x92, 0x00].as_slice(), &encoded_time].concat();
+            assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+            assert_eq!(
+                rmp_serde::from_slice::<TestTimestampEnum>(&encoded).unwrap(),
+                data,
+            );
+            assert_eq!(
+                <TestTimestampEnum as FromRmpValue>::from(
+                    typedpack::rmpv::decode::read_value(&mut encoded.as_slice()).unwrap()
+                )
+                .unwrap(),
+                data,
+            );
+        }
+
+        let data = TestTimestamp {
+            created: UNIX_EPOCH,
+            timeout: Some(Duration::new(1, 5)),
+            history: [("a".to_owned(), [UNIX_EPOCH].into())].into(),
+        };
+        let encoded = rmp_serde::to_vec(&data).unwrap();
+        assert_eq!(
+            encoded,
+            [
+                0x83, 0x00, 0xd6, 0xff, 0x00, 0x00, 0x00, 0x00, 0x01, 0xd7, 0xff, 0x00, 0x00, 0x00,
+                0x14, 0x00, 0x00, 0x00, 0x01, 0x02, 0x81, 0xa1, 0x61, 0x91, 0xd6, 0xff, 0x00, 0x00,
+                0x00, 0x00,
+            ],
+        );
+        assert_eq!(
+            rmp_serde::from_slice::<TestTimestamp>(&encoded).unwrap(),
+            data
+        );
+
+        // durations cannot be negative
+        let error = <TestTimestampEnum as FromRmpValue>::from(Value::Array(vec![
+            Value::from(1),
+            Value::Ext(
+                -1,
+                vec![0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+            ),
+        ]))
+        .unwrap_err();
+        assert_eq!(
+            error.kind(),
+            &DecodeErrorKind::OutOfRange {
+                r#type: "duration",
+                value: -1,
+            },
+        );
+
+        let error = <TestTimestampEnum as FromRmpValue>::from(Value::Array(vec![
+            Value::from(0),
+            Value::Ext(1, vec![0, 0, 0, 0]),
+        ]))
+        .unwrap_err();
+        assert_eq!(
+            error.kind(),
+            &DecodeErrorKind::InvalidType {
+                expected: MessagePackType::Timestamp,
+                actual: MessagePackType::Extension,
+            },
+        );
+        let error = <TestTimestampEnum as FromRmpValue>::from(Value::Array(vec![
+            Value::from(0),
+            Value::Ext(-1, vec![0, 0]),
+        ]))
+        .unwrap_err();
+        assert_eq!(error.kind(), &DecodeErrorKind::InvalidTimestamp);
+        assert!(
+            rmp_serde::from_slice::<TestTimestampEnum>(&[0x92, 0x00, 0xa1, 0x61])
+                .unwrap_err()
+                .to_string()
+                .contains("invalid type: expected timestamp, found string"),
+        );
+    }
+
+    #[test]
+    fn test_chrono_and_time_timestamps() {
+        use typedpack::{DecodeErrorKind, FromRmpValue, chrono, rmpv::Value, time};
+
+        use crate::{types_with_options, types_with_time};
+
+        // the same values as in `test_timestamps`
+        let cases = [
+            (1_700_000_000, 0, vec![0xd6, 0xff, 0x65, 0x53, 0xf1, 0x00]),
+            (
+                1_700_000_000,
+                500_000_000,
+                vec![0xd7, 0xff, 0x77, 0x35, 0x94, 0x00, 0x65, 0x53, 0xf1, 0x00],
+            ),
+            (
+                -2,
+                500_000_000,
+                vec![
+                    0xc7, 0x0c, 0xff, 0x1d, 0xcd, 0x65, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                    0xff, 0xfe,
+                ],
+            ),
+        ];
+        for (seconds, nanos, encoded_time) in cases {
+            let encoded = [[0x92, 0x00].as_slice(), &encoded_time].concat();
+
+            let data = types_with_options::TestTimestampEnum::At(
+                chrono::DateTime::from_timestamp(seconds, nanos).unwrap(),
+            );
+            assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+            assert_eq!(
+                rmp_serde::from_slice::<types_with_options::TestTimestampEnum>(&encoded).unwrap(),
+                data,
+            );
+
+            let data = types_with_time::TestTimestampEnum::At(
+                time::OffsetDateTime::from_unix_timestamp_nanos(
+                    i128::from(seconds) * 1_000_000_000 + i128::from(nanos),
+                )
+                .unwrap(),
+            );
+            assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+            assert_eq!(
+                rmp_serde::from_slice::<types_with_time::TestTimestampEnum>(&encoded).unwrap(),
+                data,
+            );
+        }
+
+        let encoded = [
+            0x83, 0x00, 0xd6, 0xff, 0x00, 0x00, 0x00, 0x00, 0x01, 0xd7, 0xff, 0x00, 0x00, 0x00,
+            0x14, 0x00, 0x00, 0x00, 0x01, 0x02, 0x81, 0xa1, 0x61, 0x91, 0xd6, 0xff, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+        let data = types_with_options::TestTimestamp {
+            created: chrono::DateTime::UNIX_EPOCH,
+            timeout: Some(chrono::TimeDelta::new(1, 5).unwrap()),
+            history: [("a".to_owned(), vec![chrono::DateTime::UNIX_EPOCH])].into(),
+        };
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+        let data = types_with_time::TestTimestamp {
+            created: time::OffsetDateTime::UNIX_EPOCH,
+            timeout: Some(time::Duration::new(1, 5)),
+            history: [("a".to_owned(), [time::OffsetDateTime::UNIX_EPOCH].into())].into(),
+        };
+        assert_eq!(rmp_serde::to_vec(&data).unwrap(), encoded);
+        assert_eq!(
+            rmp_serde::from_slice::<types_with_time::TestTimestamp>(&encoded).unwrap(),
+            data,
+        );
+
+        // a leap second is encoded as the start of the following second
+        let leap_second = chrono::DateTime::from_timestamp(1_699_999_979, 1_500_000_000).unwrap();
+        let next_second = chrono::DateTime::from_timestamp(1_699_999_980, 500_000_000).unwrap();
+        assert_eq!(
+            rmp_serde::to_vec(&types_with_options::TestTimestampEnum::At(leap_second)).unwrap(),
+            rmp_serde::to_vec(&types_with_options::TestTimestampEnum::At(next_second)).unwrap(),
+        );
+
+        // durations cannot be negative
+        assert!(
+            rmp_serde::to_vec(&types_with_options::TestTimestampEnum::After(
+                chrono::TimeDelta::seconds(-1)
+            ))
+            .is_err()
+        );
+        assert!(
+            rmp_serde::to_vec(&types_with_time::TestTimestampEnum::After(
+                time::Duration::seconds(-1)
+            ))
+            .is_err()
+        );
+        let negative = || {
+            Value::Array(vec![
+                Value::from(1),
+                Value::Ext(
+                    -1,
+                    vec![0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+                ),
+            ])
+        };
+        let out_of_range = DecodeErrorKind::OutOfRange {
+            r#type: "duration",
+            value: -1,
+        };
+        assert_eq!(
+            <types_with_options::TestTimestampEnum as FromRmpValue>::from(negative())
+                .unwrap_err()
+                .kind(),
+            &out_of_range,
+        );
+        assert_eq!(
+            <types_with_time::TestTimestampEnum as FromRmpValue>::from(negative())
+                .unwrap_err()
+                .kind(),
+            &out_of_range,
+        );
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        use typedpack::{
+            Decode, DecodeErrorKind, FromRmpValue, MessagePackType, PathSegment, rmpv::Value,
+        };
+
+        let encoded = [
+            0x81, 0x00, 0x92, 0x81, 0x00, 0xa1, 0x61, 0x81, 0x00, 0xc4, 0x01, 0x62,
+        ];
+        assert!(
+            rmp_serde::from_slice::<TestArrayOfMaps>(&encoded)
+                .unwrap_err()
+                .to_string()
+                .contains(
+                    "TestArrayOfMaps.foo[1].foo: invalid type: expected string, found binary"
+                ),
+        );
+        let mut deserializer = rmp_serde::Deserializer::from_read_ref(&encoded);
+        let error = TestArrayOfMaps::decode(&mut deserializer)
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            error.path(),
+            [
+                PathSegment::Field {
+                    r#type: "TestArrayOfMaps",
+                    name: "foo",
+                    id: 0,
+                },
+                PathSegment::Index(1),
+                PathSegment::Field {
+                    r#type: "TestString",
+                    name: "foo",
+                    id: 0,
+                },
+            ],
+        );
+        assert_eq!(
+            error.kind(),
+            &DecodeErrorKind::InvalidType {
+                expected: MessagePackType::String,
+                actual: MessagePackType::Binary,
+            },
+        );
+
+        assert!(
+            rmp_serde::from_slice::<TestUint8>(&[0x81, 0x00, 0xcd, 0xff, 0xff])
+                .unwrap_err()
+                .to_string()
+                .contains("TestUint8.foo: 65535 is out of range for uint8"),
+        );
+        assert!(
+            rmp_serde::from_slice::<TestMultipleFields>(&[0x81, 0x00, 0xa1, 0x61])
+                .unwrap_err()
+                .to_string()
+                .contains("TestMultipleFields.bar: missing field"),
+        );
+        assert!(
+            rmp_serde::from_slice::<TestEnum>(&[0x05])
+                .unwrap_err()
+                .to_string()
+                .contains("unknown variant 5 of TestEnum"),
+        );
+
+        let error =
+            <TestUint8 as FromRmpValue>::from(Value::Map(vec![(Value::from(0), Value::from(-1))]))
+                .unwrap_err();
+        assert_eq!(
+            error.kind(),
+            &DecodeErrorKind::OutOfRange {
+                r#type: "uint8",
+                value: -1,
+            },
+        );
+    }
 }
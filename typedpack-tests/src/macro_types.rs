@@ -0,0 +1,3 @@
+#![allow(non_camel_case_types)]
+
+typedpack::include_schema!("test.tp");
@@ -0,0 +1,1342 @@
+use typedpack_codegen::{
+    Bound, BuildError, Builder, DefaultValue, Enum, EnumRepr, ParseError, Position, ResolveError,
+    StructFieldType, Type, parse, parse_with_diagnostics,
+};
+
+#[test]
+fn test_unknown_field_type() {
+    let error = parse(
+        "struct Email {
+    string address = 0;
+}
+
+struct User {
+    Emial[] emails = 5;
+}",
+    )
+    .unwrap_err();
+
+    assert!(matches!(
+        &error,
+        ParseError::UnknownType { name, suggestion: Some(suggestion), .. }
+            if name == "Emial" && suggestion == "Email",
+    ));
+    assert_eq!(
+        error.to_string(),
+        "line 6, column 5: unknown type 'Emial'\nhelp: did you mean 'Email'?",
+    );
+}
+
+#[test]
+fn test_unknown_field_type_without_suggestion() {
+    let error = parse("struct User {\n    optional Foo foo = 0;\n}").unwrap_err();
+
+    assert_eq!(error.to_string(), "line 2, column 14: unknown type 'Foo'",);
+}
+
+#[test]
+fn test_forward_reference() {
+    assert!(parse("struct A {\n    B b = 0;\n}\n\nstruct B {}").is_ok());
+}
+
+#[test]
+fn test_map_type() {
+    assert!(
+        parse("enum E {\n    A = 0;\n}\n\nstruct A {\n    map<E, map<string, E[]>>[] a = 0;\n}")
+            .is_ok()
+    );
+}
+
+#[test]
+fn test_invalid_map_key_type() {
+    let error = parse("struct A {\n    map<float32, string> a = 0;\n}").unwrap_err();
+    assert!(matches!(error, ParseError::InvalidMapKeyType { .. }));
+    assert_eq!(
+        error.to_string(),
+        "line 2, column 9: invalid map key type\nnote: map keys must be integers, strings or untagged enums",
+    );
+
+    let error = parse("struct A {\n    map<A, string> a = 0;\n}").unwrap_err();
+    assert!(matches!(error, ParseError::InvalidMapKeyType { .. }));
+}
+
+#[test]
+fn test_default_value() {
+    let types = parse(
+        "enum E {
+    A = 0;
+}
+
+struct A {
+    optional bool a = 0 [default = false];
+    optional int8 b = 1 [default = -128];
+    optional float32 c = 2 [default = 1.5e-3];
+    optional string d = 3 [default = \"\"];
+    optional nullable E e = 4 [default = A];
+    optional nullable string f = 5 [default = \"x\"];
+}",
+    )
+    .unwrap();
+    let Type::Struct(a) = &types[1] else {
+        panic!("expected a struct");
+    };
+
+    let rust_options = typedpack_codegen::rust::OptionsInput::default()
+        .try_into()
+        .unwrap();
+    let rust = a.rust_struct(&rust_options);
+    // string defaults are compared without allocating when serializing
+    assert!(rust.contains("if self.r#d != \"\" {"));
+    assert!(rust.contains("if self.r#f.as_deref() != ::std::option::Option::Some(\"x\") {"));
+    assert!(!rust.contains("!= ::std::string::String::from"));
+    assert!(rust.contains("if self.r#e != ::std::option::Option::Some(r#E::r#A) {"));
+}
+
+#[test]
+fn test_invalid_default_value() {
+    let error = parse("struct A {\n    optional uint8 a = 0 [default = 256];\n}").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "line 2, column 37: invalid default value '256' for the field type",
+    );
+
+    assert!(matches!(
+        parse("struct A {\n    optional float64 a = 0 [default = 1.];\n}"),
+        Err(ParseError::InvalidDefaultValue { .. }),
+    ));
+    assert!(matches!(
+        parse("struct A {\n    optional float64 a = 0 [default = 1 .5];\n}"),
+        Err(ParseError::InvalidDefaultValue { .. }),
+    ));
+    assert!(matches!(
+        parse("struct A {\n    optional bytes a = 0 [default = \"\"];\n}"),
+        Err(ParseError::InvalidDefaultValue { .. }),
+    ));
+    assert!(matches!(
+        parse("enum E {\n    A = 0;\n}\n\nstruct B {\n    optional E e = 0 [default = B];\n}"),
+        Err(ParseError::InvalidDefaultValue { .. }),
+    ));
+}
+
+#[test]
+fn test_default_value_on_required_field() {
+    let error = parse("struct A {\n    uint8 a = 0 [default = 1];\n}").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "line 2, column 18: only optional fields can have a default value",
+    );
+}
+
+#[test]
+fn test_field_options() {
+    assert!(matches!(
+        parse("struct A {\n    optional uint8 a = 0 [foo = 1];\n}"),
+        Err(ParseError::UnknownFieldOption { .. }),
+    ));
+    assert!(matches!(
+        parse("struct A {\n    optional uint8 a = 0 [default = 1, default = 2];\n}"),
+        Err(ParseError::DuplicateFieldOption { .. }),
+    ));
+    assert!(matches!(
+        parse("struct A {\n    optional uint8 a = 0 [default = ];\n}"),
+        Err(ParseError::UnexpectedToken { .. }),
+    ));
+}
+
+#[test]
+fn test_doc_comments() {
+    let types = parse(
+        "## A user.
+##
+## Has a */ in it.
+struct User {
+    # not a doc comment
+    ## The user's name.
+    string name = 0;
+}
+
+enum Role {
+    ## Can do anything.
+    Admin = 0;
+}",
+    )
+    .unwrap();
+
+    let rust_options = typedpack_codegen::rust::OptionsInput::default()
+        .try_into()
+        .unwrap();
+    let Type::Struct(user) = &types[0] else {
+        panic!("expected a struct");
+    };
+    assert!(
+        user.rust_struct(&rust_options)
+            .starts_with("/// A user.\n///\n/// Has a */ in it.\n#[derive(",)
+    );
+    assert!(
+        user.rust_struct(&rust_options)
+            .contains("{\n    /// The user's name.\n    pub r#name: ")
+    );
+
+    let options = typedpack_codegen::typescript::OptionsInput::default()
+        .try_into()
+        .unwrap();
+    let typescript = user.typescript_interface(&options);
+    assert!(typescript.starts_with(
+        "export namespace Types {\n    /**\n     * A user.\n     *\n     * Has a *\\/ in it.\n     */\n    export interface User {\n        /**\n         * The user's name.\n         */\n        name: string;\n",
+    ));
+
+    let Type::Enum(role) = &types[1] else {
+        panic!("expected an enum");
+    };
+    assert!(
+        role.rust_enum(&rust_options)
+            .contains("{\n    /// Can do anything.\n    r#Admin = 0,")
+    );
+    assert!(role.typescript_enum(&options).contains(
+        "export enum Role {\n        /**\n         * Can do anything.\n         */\n        Admin = 0,",
+    ));
+}
+
+#[test]
+fn test_deprecated() {
+    let source = "## A user.
+deprecated(\"use Account instead\") struct User {
+    deprecated optional string name = 0;
+    uint8 age = 1;
+}
+
+deprecated open enum Role {
+    deprecated(\"no */ in notes\") Admin = 0;
+    deprecated = 1;
+}";
+    let types = parse(source).unwrap();
+    let (Type::Struct(user), Type::Enum(role)) = (&types[0], &types[1]) else {
+        panic!("expected a struct and an enum");
+    };
+    assert_eq!(
+        user.deprecated().and_then(|deprecated| deprecated.note()),
+        Some("use Account instead"),
+    );
+    // the span includes the modifier
+    assert!(source[user.span()].starts_with("deprecated(\"use Account instead\") struct User {"));
+    assert!(source[user.fields()[0].span()].starts_with("deprecated optional"));
+    assert_eq!(
+        &source[user.deprecated().unwrap().span()],
+        "deprecated(\"use Account instead\")",
+    );
+    assert!(user.fields()[0].deprecated().unwrap().note().is_none());
+    assert_eq!(
+        &source[user.fields()[0].deprecated().unwrap().span()],
+        "deprecated",
+    );
+    assert!(user.fields()[0].optional());
+    assert!(user.fields()[1].deprecated().is_none());
+    assert!(role.open() && role.deprecated().unwrap().note().is_none());
+    // a unit variant can be named `deprecated`
+    let Enum::Untagged { variants, .. } = role else {
+        panic!("expected an untagged enum");
+    };
+    assert_eq!(
+        variants[0].deprecated().unwrap().note(),
+        Some("no */ in notes")
+    );
+    assert_eq!(variants[1].name(), "deprecated");
+    assert!(variants[1].deprecated().is_none());
+
+    let rust_options = typedpack_codegen::rust::OptionsInput::default()
+        .try_into()
+        .unwrap();
+    let rust = user.rust_struct(&rust_options);
+    assert!(
+        rust.starts_with("/// A user.\n#[deprecated(note = \"use Account instead\")]\n#[derive(")
+    );
+    assert!(rust.contains("{\n    #[deprecated]\n    pub r#name: "));
+    assert!(rust.contains("#[allow(deprecated)]\nimpl ::typedpack::serde::Serialize for r#User {"));
+    assert!(role.rust_enum(&rust_options).contains(
+        "{\n    #[deprecated(note = \"no */ in notes\")]\n    r#Admin,\n    r#deprecated,"
+    ));
+
+    let options = typedpack_codegen::typescript::OptionsInput::default()
+        .try_into()
+        .unwrap();
+    assert!(user.typescript_interface(&options).starts_with(
+        "export namespace Types {\n    /**\n     * A user.\n     *\n     * @deprecated use Account instead\n     */\n    export interface User {\n        /**\n         * @deprecated\n         */\n        name?: string;\n        age: number;\n",
+    ));
+    assert!(role.typescript_enum(&options).contains(
+        "    /**\n     * @deprecated\n     */\n    export enum Role {\n        /**\n         * @deprecated no *\\/ in notes\n         */\n        Admin = 0,\n        deprecated = 1,",
+    ));
+
+    assert!(matches!(
+        parse("struct A {\n    deprecated(note) uint8 a = 0;\n}"),
+        Err(ParseError::UnexpectedToken { unexpected, expected: "a string literal", .. }) if unexpected == "note",
+    ));
+    assert!(matches!(
+        parse("deprecated(\"a\" struct A {}"),
+        Err(ParseError::UnexpectedToken {
+            expected: "`)`",
+            ..
+        }),
+    ));
+    assert!(matches!(
+        parse("deprecated"),
+        Err(ParseError::UnexpectedEnd {
+            expected: "`enum` or `struct`"
+        }),
+    ));
+}
+
+#[test]
+fn test_annotations() {
+    let source = "## A user.
+@rust(derive = \"Default\", attr = '#[doc(alias = \"Account\")]') @docs(hidden = \"true\")
+struct User {
+    @ts(name = \"userId\") @rust(attr = '#[doc(alias = \"id\")]')
+    uint32 user_id = 0;
+    @rust() optional string name = 1;
+}
+
+@rust(derive = \"Default\")
+enum Role {
+    @rust(attr = \"#[default]\") User = 0;
+    Admin = 1;
+}";
+    let types = parse(source).unwrap();
+    let (Type::Struct(user), Type::Enum(role)) = (&types[0], &types[1]) else {
+        panic!("expected a struct and an enum");
+    };
+    assert_eq!(
+        user.annotations()
+            .iter()
+            .map(|annotation| annotation.name())
+            .collect::<Vec<_>>(),
+        ["rust", "docs"],
+    );
+    let args = user.annotations()[0].args();
+    assert_eq!((args[0].key(), args[0].value()), ("derive", "Default"));
+    // single quotes allow double quotes inside the string
+    assert_eq!(
+        (args[1].key(), args[1].value()),
+        ("attr", "#[doc(alias = \"Account\")]"),
+    );
+    // the span includes the annotations
+    assert!(source[user.span()].starts_with("@rust(derive"));
+    assert!(source[user.fields()[0].span()].starts_with("@ts(name"));
+    assert!(user.fields()[1].annotations()[0].args().is_empty());
+    assert_eq!(
+        &source[user.annotations()[1].span()],
+        "@docs(hidden = \"true\")"
+    );
+    assert_eq!(
+        &source[args[1].span()],
+        "attr = '#[doc(alias = \"Account\")]'"
+    );
+    assert_eq!(&source[user.fields()[1].annotations()[0].span()], "@rust()");
+    assert_eq!(user.fields()[0].typescript_name(), "userId");
+    assert_eq!(user.fields()[1].typescript_name(), "name");
+
+    let rust_options = typedpack_codegen::rust::OptionsInput::default()
+        .try_into()
+        .unwrap();
+    let rust = user.rust_struct(&rust_options);
+    assert!(rust.contains(
+        "::std::hash::Hash, Default)]\n#[doc(alias = \"Account\")]\npub struct r#User {\n"
+    ));
+    assert!(rust.contains("\n    #[doc(alias = \"id\")]\n    pub r#user_id: "));
+    assert!(
+        role.rust_enum(&rust_options)
+            .contains("{\n    #[default]\n    r#User = 0,\n")
+    );
+
+    let options = typedpack_codegen::typescript::OptionsInput::default()
+        .try_into()
+        .unwrap();
+    assert!(
+        user.typescript_interface(&options)
+            .contains("        userId: number;\n        name?: string;\n")
+    );
+    // the encode and decode functions use the renamed field as well
+    let code = user.typescript_interface(&options);
+    assert!(code.contains("value.userId"));
+    assert!(!code.contains("user_id"));
+    assert!(typedpack_codegen::rust::annotation_warnings(&types).is_empty());
+    assert!(typedpack_codegen::typescript::annotation_warnings(&types).is_empty());
+
+    let types = parse(
+        "@rust(derive = \"1\", name = \"A\") @ts(name = \"B\")
+struct A {
+    @ts(name = \"not valid\", rename = \"b\") @rust(derive = \"Default\") uint8 a = 0;
+}",
+    )
+    .unwrap();
+    let warnings = |warnings: Vec<typedpack_codegen::Warning>| {
+        warnings.iter().map(ToString::to_string).collect::<Vec<_>>()
+    };
+    assert_eq!(
+        warnings(typedpack_codegen::rust::annotation_warnings(&types)),
+        [
+            "line 1, column 7: invalid value \"1\" for 'derive' in `@rust` annotation; expected a path, e.g. `PartialOrd`",
+            "line 1, column 21: unknown key 'name' in `@rust` annotation on a struct; expected one of `derive`, `attr`",
+            "line 3, column 49: unknown key 'derive' in `@rust` annotation on a struct field; expected `attr`",
+        ],
+    );
+    assert_eq!(
+        warnings(typedpack_codegen::typescript::annotation_warnings(&types)),
+        [
+            "line 1, column 37: unknown key 'name' in `@ts` annotation on a struct; no keys are supported there",
+            "line 3, column 9: invalid value \"not valid\" for 'name' in `@ts` annotation; expected an identifier, e.g. `userId`",
+            "line 3, column 29: unknown key 'rename' in `@ts` annotation on a struct field; expected `name`",
+        ],
+    );
+    // invalid values are ignored
+    let Type::Struct(a) = &types[0] else {
+        panic!("expected a struct");
+    };
+    assert_eq!(a.fields()[0].typescript_name(), "a");
+
+    assert!(matches!(
+        parse("@rust(derive) struct A {}"),
+        Err(ParseError::UnexpectedToken {
+            expected: "`=`",
+            ..
+        }),
+    ));
+    assert!(matches!(
+        parse("@rust(derive = Default) struct A {}"),
+        Err(ParseError::UnexpectedToken { unexpected, expected: "a string literal", .. }) if unexpected == "Default",
+    ));
+    assert!(matches!(
+        parse("@(key = \"value\") struct A {}"),
+        Err(ParseError::UnexpectedToken {
+            expected: "an annotation name",
+            ..
+        }),
+    ));
+    assert!(matches!(
+        parse("struct A {\n    @ts(name = \"b\" uint8 a = 0;\n}"),
+        Err(ParseError::UnexpectedToken {
+            expected: "`,` or `)`",
+            ..
+        }),
+    ));
+}
+
+#[test]
+fn test_string_escapes() {
+    let source = r##"@rust(attr = "#[serde(rename = \"x\")]")
+struct A {
+    optional string b = 0 [default = 'it\'s \\ \d', pattern = "^\w+"];
+    deprecated("use \"b\"") string c = 1;
+}"##;
+    let types = parse(source).unwrap();
+    let Type::Struct(a) = &types[0] else {
+        panic!("expected a struct");
+    };
+    assert_eq!(
+        a.annotations()[0].args()[0].value(),
+        r#"#[serde(rename = "x")]"#,
+    );
+    // backslashes which do not escape a quote or a backslash are kept, e.g. in patterns
+    assert_eq!(
+        a.fields()[0].default(),
+        Some(&DefaultValue::String(r"it's \ \d".into())),
+    );
+    assert_eq!(a.fields()[0].constraints().pattern(), Some(r"^\w+"));
+    assert_eq!(
+        a.fields()[1]
+            .deprecated()
+            .and_then(|deprecated| deprecated.note()),
+        Some(r#"use "b""#),
+    );
+
+    let rust_options = typedpack_codegen::rust::OptionsInput::default()
+        .try_into()
+        .unwrap();
+    let rust = a.rust_struct(&rust_options);
+    assert!(rust.contains("\n#[serde(rename = \"x\")]\n"));
+    assert!(rust.contains(r#"::std::string::String::from("it's \\ \\d")"#));
+
+    assert!(matches!(
+        parse_with_diagnostics("struct A {\n    deprecated(\"\\\") string b = 0;\n}")
+            .unwrap_err()
+            .errors(),
+        [ParseError::UnterminatedString { .. }, ..],
+    ));
+}
+
+#[test]
+fn test_constraints() {
+    let source = "struct User {
+    string name = 0 [min_len = 3, max_len = 32, pattern = '^[a-z]+$'];
+    optional nullable int8 age = 1 [min = -1, max = 100];
+    optional float64 score = 2 [default = 0.5, min = 0, max = 1e3];
+    bytes hash = 3 [len = 4];
+    uint8 plain = 4;
+}";
+    let types = parse(source).unwrap();
+    let Type::Struct(user) = &types[0] else {
+        panic!("expected a struct");
+    };
+    let name = user.fields()[0].constraints();
+    assert_eq!(
+        (name.min_len(), name.max_len(), name.len(), name.pattern()),
+        (Some(3), Some(32), None, Some("^[a-z]+$")),
+    );
+    let age = user.fields()[1].constraints();
+    assert_eq!(
+        (age.min(), age.max()),
+        (Some(&Bound::Integer(-1)), Some(&Bound::Integer(100))),
+    );
+    assert_eq!(
+        user.fields()[2].constraints().to_string(),
+        "min = 0, max = 1e3"
+    );
+    assert_eq!(user.fields()[3].constraints().len(), Some(4));
+    assert!(user.fields()[4].constraints().is_empty());
+    // the span starts at the first constraint, after any default value before it
+    assert_eq!(
+        &source[name.span()],
+        "min_len = 3, max_len = 32, pattern = '^[a-z]+$'",
+    );
+    assert_eq!(
+        &source[user.fields()[2].constraints().span()],
+        "min = 0, max = 1e3",
+    );
+    assert!(user.fields()[4].constraints().span().is_empty());
+
+    let rust_options = typedpack_codegen::rust::OptionsInput::default()
+        .try_into()
+        .unwrap();
+    let rust = user.rust_struct(&rust_options);
+    // decoding checks the constraints of each field
+    assert!(rust.contains("let value = &*field_age.insert(value);\n"));
+    assert!(rust.contains("if !(-1..).contains(value) {\n"));
+    assert!(rust.contains("if !(..=1e3_f64).contains(value) {\n"));
+    assert!(rust.contains("::typedpack::DecodeError::constraint_violation(\"len = 4\").in_field(\"User\", \"hash\", 3)"));
+    assert!(rust.contains("impl ::typedpack::Validate for r#User {\n"));
+    // only patterns need the `pattern` feature of `typedpack`
+    assert_eq!(rust.matches("static PATTERN: ").count(), 2);
+    let Type::Struct(plain) = &parse("struct A {\n    string a = 0 [min_len = 1];\n}").unwrap()[0]
+    else {
+        panic!("expected a struct");
+    };
+    assert!(
+        !plain
+            .rust_struct(&rust_options)
+            .contains("::typedpack::regex")
+    );
+    // classes match what they match in JavaScript, where `\d` only matches ASCII digits
+    let Type::Struct(digits) =
+        &parse("struct A {\n    string a = 0 [pattern = '^\\d+$'];\n}").unwrap()[0]
+    else {
+        panic!("expected a struct");
+    };
+    assert!(
+        digits
+            .rust_struct(&rust_options)
+            .contains(r#"::typedpack::regex::Regex::new("^[0-9]+$")"#)
+    );
+
+    let options = typedpack_codegen::typescript::OptionsInput::default()
+        .try_into()
+        .unwrap();
+    let code = user.typescript_interface(&options);
+    // encoding and decoding both check them
+    assert_eq!(
+        code.matches("if ([...value.name].length < 3) {\n").count()
+            + code.matches("if ([...obj.name!].length < 3) {\n").count(),
+        2,
+    );
+    assert!(code.contains("if (!/^[a-z]+$/u.test(value.name)) {\n"));
+    assert!(code.contains("if (value.hash.byteLength !== 4) {\n"));
+    assert!(code.contains("throw new Error(\"User.age: value does not satisfy `min = -1`\");"));
+
+    let error = parse("struct A {\n    uint8 a = 0 [min = 5, max = 1];\n}").unwrap_err();
+    assert!(matches!(
+        &error,
+        ParseError::InvalidConstraint { name, value, pos: Position { line: 2, column: 33, .. } }
+            if name == "max" && value == "1",
+    ));
+    assert!(
+        error
+            .to_string()
+            .starts_with("line 2, column 33: invalid value '1' for constraint 'max'\n")
+    );
+    for (source, constraint) in [
+        // the value must fit the type
+        ("uint8 a = 0 [max = 256]", "max"),
+        // the default value must satisfy the constraints
+        ("optional uint8 a = 0 [default = 1, min = 2]", "min"),
+        (
+            "optional string a = 0 [default = \"ab\", min_len = 3]",
+            "min_len",
+        ),
+        ("string a = 0 [min_len = 03]", "min_len"),
+        ("string a = 0 [min_len = 4, len = 3]", "len"),
+        ("string a = 0 [pattern = \"(\"]", "pattern"),
+        // the pattern must mean the same in Rust and JavaScript
+        ("string a = 0 [pattern = \"(?i)abc\"]", "pattern"),
+        (r#"string a = 0 [pattern = "\A\d+\z"]"#, "pattern"),
+        ("string a = 0 [pattern = \"a**\"]", "pattern"),
+        ("string a = 0 [pattern = \"[a[b]]\"]", "pattern"),
+    ] {
+        assert!(
+            matches!(
+                parse(&format!("struct A {{\n    {source};\n}}")),
+                Err(ParseError::InvalidConstraint { name, .. }) if name == constraint,
+            ),
+            "{source}",
+        );
+    }
+    for (source, constraint, r#type) in [
+        ("string a = 0 [min = 1]", "min", "string"),
+        ("uint8 a = 0 [max_len = 1]", "max_len", "uint8"),
+        ("bytes4 a = 0 [len = 4]", "len", "bytes4"),
+        ("string[] a = 0 [pattern = \"a\"]", "pattern", "string[]"),
+    ] {
+        assert!(
+            matches!(
+                parse(&format!("struct A {{\n    {source};\n}}")),
+                Err(ParseError::UnsupportedConstraint { name, r#type: actual, .. })
+                    if name == constraint && actual == r#type,
+            ),
+            "{source}",
+        );
+    }
+}
+
+#[test]
+fn test_timestamps() {
+    let types = parse(
+        "struct Event {
+    timestamp at = 0;
+    optional duration[] delays = 1;
+}",
+    )
+    .unwrap();
+    let Type::Struct(event) = &types[0] else {
+        panic!("expected a struct");
+    };
+    assert_eq!(event.fields()[0].r#type(), &StructFieldType::Timestamp);
+    assert_eq!(event.fields()[1].r#type().to_string(), "duration[]");
+
+    let rust_options = typedpack_codegen::rust::OptionsInput::default()
+        .try_into()
+        .unwrap();
+    let rust = event.rust_struct(&rust_options);
+    assert!(rust.contains("pub r#at: ::std::time::SystemTime,\n"));
+    // `SystemTime` and `Duration` serialize as structs on their own
+    assert!(rust.contains("&::typedpack::TimestampRef(&self.r#at)"));
+    assert!(rust.contains(".map(::typedpack::DurationRef)"));
+
+    let rust_options = typedpack_codegen::rust::OptionsInput {
+        time_crate: Some(typedpack_codegen::rust::TimeCrate::Chrono),
+        ..Default::default()
+    }
+    .try_into()
+    .unwrap();
+    let rust = event.rust_struct(&rust_options);
+    assert!(rust.contains("pub r#at: ::typedpack::chrono::DateTime<::typedpack::chrono::Utc>,\n"));
+    assert!(rust.contains("&::typedpack::ChronoTimestampRef(&self.r#at)"));
+    assert!(rust.contains(".map(::typedpack::ChronoDurationRef)"));
+    let rust_options = typedpack_codegen::rust::OptionsInput {
+        time_crate: Some(typedpack_codegen::rust::TimeCrate::Time),
+        ..Default::default()
+    }
+    .try_into()
+    .unwrap();
+    let rust = event.rust_struct(&rust_options);
+    assert!(rust.contains("pub r#at: ::typedpack::time::OffsetDateTime,\n"));
+    assert!(rust.contains(".map(::typedpack::TimeDurationRef)"));
+
+    let options = typedpack_codegen::typescript::OptionsInput::default()
+        .try_into()
+        .unwrap();
+    let code = event.typescript_interface(&options);
+    assert!(code.contains("at: Date;\n"));
+    assert!(code.contains("delays?: Array<{ seconds: bigint; nanos: number }>;\n"));
+    assert!(code.contains("offset += encodeTimestamp(value.at, data, offset);"));
+    // the helpers are only generated for the types which use them
+    assert_eq!(code.matches("const encodeTimestampExtension = ").count(), 1);
+    assert_eq!(code.matches("const decodeDuration = ").count(), 1);
+    let types = parse("struct A {\n    uint8 a = 0;\n}").unwrap();
+    let Type::Struct(r#struct) = &types[0] else {
+        panic!("expected a struct");
+    };
+    assert!(
+        !r#struct
+            .typescript_interface(&options)
+            .contains("TimestampExtension")
+    );
+
+    assert!(matches!(
+        parse("struct A {\n    optional timestamp a = 0 [default = 0];\n}"),
+        Err(ParseError::InvalidDefaultValue { .. }),
+    ));
+    assert!(matches!(
+        parse("struct A {\n    map<timestamp, string> a = 0;\n}"),
+        Err(ParseError::InvalidMapKeyType { .. }),
+    ));
+    assert!(matches!(
+        parse("struct A {\n    duration a = 0 [min = 0];\n}"),
+        Err(ParseError::UnsupportedConstraint { r#type, .. }) if r#type == "duration",
+    ));
+}
+
+#[test]
+fn test_dangling_doc_comment() {
+    // doc comments which do not document anything are ordinary comments
+    let types = parse("struct A {\n    uint8 a = 0;\n    ## nothing\n}\n## nothing").unwrap();
+    let Type::Struct(a) = &types[0] else {
+        panic!("expected a struct");
+    };
+    assert!(a.fields()[0].doc().is_empty());
+    assert!(parse("struct A {}\n## nothing").is_ok());
+}
+
+#[test]
+fn test_trailing_doc_comment() {
+    // `##` after other tokens on the same line starts an ordinary comment
+    let types = parse(
+        "struct A {
+    uint8 a = 0; ## not for `b`
+    ## For `b`.
+    uint8 b = 1;
+    uint8 c = 2; ## not for anything
+}",
+    )
+    .unwrap();
+    let Type::Struct(a) = &types[0] else {
+        panic!("expected a struct");
+    };
+    assert!(a.fields()[0].doc().is_empty());
+    assert_eq!(a.fields()[1].doc(), ["For `b`."]);
+    assert!(a.fields()[2].doc().is_empty());
+}
+
+#[test]
+fn test_ast_accessors() {
+    let source = "## A user.
+struct User {
+    string name = 0;
+    ## The user's role.
+    optional Role role = 1 [default = Guest];
+}
+
+enum Role {
+    Guest = 0;
+    Admin = 1;
+}
+
+enum Entity {
+    User A = 5;
+}";
+    let types = parse(source).unwrap();
+
+    let Type::Struct(user) = &types[0] else {
+        panic!("expected a struct");
+    };
+    assert_eq!(user.name(), "User");
+    assert_eq!(user.doc(), ["A user."]);
+    assert!(source[user.span()].starts_with("struct User {"));
+    assert!(source[user.span()].ends_with("[default = Guest];\n}"));
+
+    let [name, role] = user.fields() else {
+        panic!("expected two fields");
+    };
+    assert_eq!(name.id(), 0);
+    assert_eq!(name.name(), "name");
+    assert_eq!(name.r#type(), &StructFieldType::String);
+    assert!(!name.optional());
+    assert!(!name.nullable());
+    assert_eq!(name.default(), None);
+    assert!(name.doc().is_empty());
+    assert_eq!(&source[name.span()], "string name = 0;");
+
+    assert_eq!(role.r#type(), &StructFieldType::Reference { name: "Role" });
+    assert!(role.optional());
+    assert_eq!(role.default(), Some(&DefaultValue::Variant("Guest")));
+    assert_eq!(role.doc(), ["The user's role."]);
+    assert_eq!(
+        &source[role.span()],
+        "optional Role role = 1 [default = Guest];",
+    );
+
+    let Type::Enum(Enum::Untagged { variants, .. }) = &types[1] else {
+        panic!("expected an untagged enum");
+    };
+    assert_eq!(types[1].name(), "Role");
+    assert!(source[types[1].span()].starts_with("enum Role {"));
+    assert_eq!(variants[1].id(), 1);
+    assert_eq!(variants[1].name(), "Admin");
+    assert_eq!(&source[variants[1].span()], "Admin = 1;");
+
+    let Type::Enum(Enum::Tagged { variants, .. }) = &types[2] else {
+        panic!("expected a tagged enum");
+    };
+    assert_eq!(variants[0].id(), 5);
+    assert_eq!(variants[0].name(), "A");
+    assert_eq!(
+        variants[0].r#type(),
+        Some(&StructFieldType::Reference { name: "User" }),
+    );
+    assert_eq!(&source[variants[0].span()], "User A = 5;");
+}
+
+#[test]
+fn test_tagged_enum_values() {
+    let source = "enum E {
+    string[] A = 0;
+    B = 1;
+    map<string, F> C = 2;
+}
+
+enum F {
+    X = 0;
+}";
+    let types = parse(source).unwrap();
+    let Type::Enum(Enum::Tagged { variants, .. }) = &types[0] else {
+        panic!("expected a tagged enum");
+    };
+    assert_eq!(
+        variants[0].r#type(),
+        Some(&StructFieldType::Array {
+            items: StructFieldType::String.into(),
+        }),
+    );
+    assert_eq!(variants[1].r#type(), None);
+    assert_eq!(&source[variants[1].span()], "B = 1;");
+    assert_eq!(
+        variants[2].r#type(),
+        Some(&StructFieldType::Map {
+            key: StructFieldType::String.into(),
+            value: StructFieldType::Reference { name: "F" }.into(),
+        }),
+    );
+    assert_eq!(&source[variants[2].span()], "map<string, F> C = 2;");
+    // an enum without any values is untagged
+    assert!(matches!(types[1], Type::Enum(Enum::Untagged { .. })));
+
+    let Err(ParseError::UnknownType { name, pos, .. }) = parse("enum E {\n    B A = 0;\n}") else {
+        panic!("expected an unknown type error");
+    };
+    assert_eq!(name, "B");
+    assert_eq!(pos.line, 2);
+    assert!(matches!(
+        parse("enum E {\n    map<bool, string> A = 0;\n}"),
+        Err(ParseError::InvalidMapKeyType { .. }),
+    ));
+}
+
+#[test]
+fn test_error_position() {
+    let Err(ParseError::UnknownType { pos, .. }) = parse("struct A {\n    B b = 0;\n}") else {
+        panic!("expected an unknown type error");
+    };
+    assert_eq!(
+        pos,
+        Position {
+            line: 2,
+            column: 5,
+            offset: 15,
+        },
+    );
+}
+
+#[test]
+fn test_diagnostics() {
+    let source = "struct A {
+    Strin name = 0;
+    string email = 0;
+    uint8 x = 1
+    uint8 y = 2;
+}
+
+enum E {
+    X = 70000;
+}
+
+struct 1B {}
+
+struct C {
+    B b = 0;
+}
+";
+    let diagnostics = parse_with_diagnostics(source).unwrap_err();
+    let errors: Vec<_> = diagnostics
+        .errors()
+        .iter()
+        .map(|error| error.pos().map(|pos| pos.line))
+        .collect();
+    assert_eq!(
+        errors,
+        [Some(3), Some(5), Some(9), Some(12), Some(2), Some(15)],
+    );
+    assert!(matches!(
+        diagnostics.errors()[0],
+        ParseError::DuplicateStructFieldId { id: 0, .. },
+    ));
+    assert!(matches!(
+        &diagnostics.errors()[4],
+        ParseError::UnknownType { name, .. } if name == "Strin",
+    ));
+
+    // `parse` returns the first error
+    assert!(matches!(
+        parse(source),
+        Err(ParseError::DuplicateStructFieldId { id: 0, .. }),
+    ));
+}
+
+#[test]
+fn test_diagnostics_display() {
+    let source = "struct User {\n    string name = 0;\n}\n\nstruct Team {\n    Usr owner = 0;\n}\n";
+    let diagnostics = parse_with_diagnostics(source)
+        .unwrap_err()
+        .with_path("users.tp");
+    assert_eq!(
+        diagnostics.to_string(),
+        "\
+error: unknown type 'Usr'
+ --> users.tp:6:5
+  |
+6 |     Usr owner = 0;
+  |     ^^^
+  = help: did you mean 'User'?",
+    );
+
+    let diagnostics =
+        parse_with_diagnostics("enum E {\n    A = 0;\n    B = 0;\n}\nstruct S {").unwrap_err();
+    assert_eq!(
+        diagnostics.to_string(),
+        "\
+error: duplicate enum variant ID '0'
+ --> line 3, column 9
+  |
+3 |     B = 0;
+  |         ^
+
+error: unexpected end of input: expected a struct field or `}`
+ --> line 5, column 11
+  |
+5 | struct S {
+  |           ^
+
+error: found 2 errors",
+    );
+
+    let source = "struct A {\n    @rust(rename = \"b\") uint8 a = 0;\n}\n";
+    let types = parse(source).unwrap();
+    let warnings = typedpack_codegen::rust::annotation_warnings(&types);
+    let diagnostics = typedpack_codegen::Diagnostics::new(Vec::new(), source).with_path("a.tp");
+    assert_eq!(
+        diagnostics.render_warning(&warnings[0]),
+        "\
+warning: unknown key 'rename' in `@rust` annotation on a struct field; expected `attr`
+ --> a.tp:2:11
+  |
+2 |     @rust(rename = \"b\") uint8 a = 0;
+  |           ^^^^^^",
+    );
+}
+
+#[test]
+fn test_diagnostics_invalid_characters() {
+    let diagnostics =
+        parse_with_diagnostics("struct \u{e4} {\n    string s = \"abc;\n}\n").unwrap_err();
+    assert!(matches!(
+        diagnostics.errors(),
+        [
+            ParseError::NonAsciiCharacter { char: '\u{e4}', .. },
+            ParseError::UnterminatedString { .. },
+        ],
+    ));
+}
+
+#[test]
+fn test_rust_options() {
+    use typedpack_codegen::rust::{Options, OptionsError, OptionsInput};
+
+    let try_options = |input: OptionsInput| Options::try_from(input);
+    assert!(matches!(
+        try_options(OptionsInput {
+            extra_derives: vec![String::from("serde::Serialize()")],
+            ..OptionsInput::default()
+        }),
+        Err(OptionsError::InvalidDerive(_)),
+    ));
+    assert!(matches!(
+        try_options(OptionsInput {
+            extra_attributes: vec![String::from("must_use] #[inline")],
+            ..OptionsInput::default()
+        }),
+        Err(OptionsError::InvalidAttribute(_)),
+    ));
+    assert!(matches!(
+        try_options(OptionsInput {
+            field_visibility: Some(String::from("private")),
+            ..OptionsInput::default()
+        }),
+        Err(OptionsError::InvalidFieldVisibility(_)),
+    ));
+    assert!(matches!(
+        try_options(OptionsInput {
+            module: Some(String::from("1types")),
+            ..OptionsInput::default()
+        }),
+        Err(OptionsError::InvalidModuleName(_)),
+    ));
+
+    let options = try_options(OptionsInput {
+        extra_derives: vec![String::from("Hash"), String::from("PartialOrd")],
+        extra_attributes: vec![String::from("cfg_attr(test, derive(Default))")],
+        field_visibility: Some(String::new()),
+        vec_arrays: Some(true),
+        primitive_byte_arrays: Some(true),
+        total_order_floats: None,
+        reject_unknown_fields: None,
+        time_crate: None,
+        module: None,
+    })
+    .unwrap();
+    let types = parse("struct User {\n    bytes16[] keys = 0;\n}").unwrap();
+    let Type::Struct(user) = &types[0] else {
+        panic!("expected a struct");
+    };
+    assert!(user.rust_struct(&options).starts_with(
+        "#[derive(::std::clone::Clone, ::std::fmt::Debug, ::std::cmp::PartialEq, ::std::cmp::Eq, ::std::hash::Hash, PartialOrd)]\n#[cfg_attr(test, derive(Default))]\npub struct r#User {\n    r#keys: ::std::vec::Vec<[::std::primitive::u8; 16]>,\n}",
+    ));
+}
+
+#[test]
+fn test_reject_unknown_fields() {
+    use typedpack_codegen::typescript::{Options, OptionsInput};
+
+    let types = parse("struct A {\n    uint8 a = 0;\n}\nstruct B {}").unwrap();
+    let (Type::Struct(a), Type::Struct(b)) = (&types[0], &types[1]) else {
+        panic!("expected structs");
+    };
+
+    let options = Options::try_from(OptionsInput::default()).unwrap();
+    assert!(!a.typescript_interface(&options).contains("unknown map key"));
+
+    let options = Options::try_from(OptionsInput {
+        reject_unknown_fields: Some(true),
+        ..OptionsInput::default()
+    })
+    .unwrap();
+    assert!(a.typescript_interface(&options).contains(
+        "            } else {\n                throw new Error(`unknown map key '${key}'`);\n            }\n        }\n",
+    ));
+    assert!(
+        b.typescript_interface(&options)
+            .contains("            throw new Error(`unknown map key '${key}'`);\n        }\n",)
+    );
+}
+
+#[test]
+fn test_preserve_unknown() {
+    let types = parse("preserve_unknown struct A {\n    uint8 a = 0;\n}\nstruct B {}").unwrap();
+    let (Type::Struct(a), Type::Struct(b)) = (&types[0], &types[1]) else {
+        panic!("expected structs");
+    };
+    assert!(a.preserve_unknown());
+    assert!(!b.preserve_unknown());
+    assert_eq!(a.span(), 0..46);
+
+    let options = typedpack_codegen::typescript::OptionsInput::default()
+        .try_into()
+        .unwrap();
+    assert!(
+        a.typescript_interface(&options)
+            .contains("        unknown_fields?: Array<[number, Uint8Array]>;\n    }")
+    );
+    assert!(!b.typescript_interface(&options).contains("unknown_fields"));
+
+    let error = parse("preserve_unknown struct A {\n    uint8 unknown_fields = 0;\n}").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "line 2, column 11: struct field name 'unknown_fields' is reserved\nnote: `preserve_unknown` structs use it for the unknown fields",
+    );
+    assert!(parse("struct A {\n    uint8 unknown_fields = 0;\n}").is_ok());
+    assert!(matches!(
+        parse("preserve_unknown enum A {}"),
+        Err(ParseError::UnexpectedToken { .. }),
+    ));
+}
+
+#[test]
+fn test_open_enum() {
+    let types = parse("open enum A {\n    B = 0;\n}\nenum B {\n    C = 0;\n}").unwrap();
+    let (Type::Enum(a), Type::Enum(b)) = (&types[0], &types[1]) else {
+        panic!("expected enums");
+    };
+    assert!(a.open());
+    assert!(!b.open());
+    assert_eq!(a.span(), 0..26);
+
+    let options = typedpack_codegen::typescript::OptionsInput::default()
+        .try_into()
+        .unwrap();
+    assert!(!a.typescript_enum(&options).contains("is not a variant of"));
+    assert!(b.typescript_enum(&options).contains("is not a variant of"));
+
+    let error = parse("open enum A {\n    Unknown = 0;\n}").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "line 2, column 5: enum variant name 'Unknown' is reserved\nnote: `open` enums use it for the unknown variants",
+    );
+    assert!(parse("enum A {\n    Unknown = 0;\n}").is_ok());
+    assert!(matches!(
+        parse("open struct A {}"),
+        Err(ParseError::UnexpectedToken { .. }),
+    ));
+}
+
+#[test]
+fn test_large_ids() {
+    let types = parse(
+        "struct A {
+    string b = 65535;
+}
+
+enum B {
+    C = 255;
+}
+
+enum C {
+    D = 256;
+}
+
+enum D: uint16 {
+    E = 0;
+}",
+    )
+    .unwrap();
+    let Type::Struct(a) = &types[0] else {
+        panic!("expected a struct");
+    };
+    assert_eq!(a.fields()[0].id(), 65535);
+    let reprs: Vec<_> = types[1..]
+        .iter()
+        .map(|r#type| match r#type {
+            Type::Enum(r#enum) => r#enum.repr(),
+            Type::Struct(_) => panic!("expected an enum"),
+        })
+        .collect();
+    assert_eq!(reprs, [EnumRepr::U8, EnumRepr::U16, EnumRepr::U16]);
+
+    let options = typedpack_codegen::typescript::OptionsInput::default()
+        .try_into()
+        .unwrap();
+    let Type::Enum(c) = &types[2] else {
+        panic!("expected an enum");
+    };
+    assert!(c.typescript_enum(&options).contains("): number => 3;"));
+
+    assert!(matches!(
+        parse("struct A {\n    string b = 65536;\n}"),
+        Err(ParseError::InvalidStructFieldId { .. }),
+    ));
+    assert!(matches!(
+        parse("enum A: uint8 {\n    B = 256;\n}"),
+        Err(ParseError::InvalidEnumVariantId { .. }),
+    ));
+    assert!(matches!(
+        parse("enum A: uint32 {\n    B = 0;\n}"),
+        Err(ParseError::UnexpectedToken {
+            expected: "`uint8` or `uint16`",
+            ..
+        }),
+    ));
+}
+
+#[test]
+fn test_reserved() {
+    let types = parse(
+        "struct A {
+    string b = 0;
+    reserved 1, 3..5, \"c\";
+    reserved \"d\";
+}
+
+enum B {
+    reserved 0;
+    C = 1;
+}",
+    )
+    .unwrap();
+    let (Type::Struct(a), Type::Enum(b)) = (&types[0], &types[1]) else {
+        panic!("expected a struct and an enum");
+    };
+    assert_eq!(a.reserved().ids(), [1..=1, 3..=5]);
+    assert_eq!(a.reserved().names(), ["c", "d"]);
+    assert!(a.reserved().contains_id(5));
+    assert!(!a.reserved().contains_id(6));
+    assert_eq!(b.reserved().ids(), [0..=0]);
+    // from the first `reserved` to the `;` of the last declaration
+    assert_eq!(a.reserved().span(), 33..73);
+
+    // fields declared before the `reserved` declaration are checked as well
+    let error = parse("struct A {\n    string b = 4;\n    reserved 3..5;\n}").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "line 2, column 16: ID '4' is reserved\nnote: it is listed in a `reserved` declaration, usually because a removed field or variant used it",
+    );
+    assert!(matches!(
+        parse("enum A {\n    reserved \"B\";\n    B = 0;\n}"),
+        Err(ParseError::ReservedName { name, pos }) if name == "B" && pos.line == 3,
+    ));
+    assert!(matches!(
+        parse("enum A {\n    string B = 2;\n    reserved 2;\n}"),
+        Err(ParseError::ReservedId { id: 2, .. }),
+    ));
+    assert!(matches!(
+        parse("struct A {\n    reserved 5..3;\n}"),
+        Err(ParseError::InvalidReservedRange { range, .. }) if range == "5..3",
+    ));
+    assert!(matches!(
+        parse("struct A {\n    reserved 1 2;\n}"),
+        Err(ParseError::UnexpectedToken { .. }),
+    ));
+    // reserved declarations of other types are not applied
+    assert!(parse("struct A {\n    reserved 0;\n}\nstruct B {\n    string a = 0;\n}").is_ok());
+
+    // `reserved` is still a valid name
+    let types = parse(
+        "enum A {\n    reserved = 0;\n    reserved 1;\n}\nstruct B {\n    uint8 reserved = 0;\n}",
+    )
+    .unwrap();
+    let Type::Enum(a) = &types[0] else {
+        panic!("expected an enum");
+    };
+    assert_eq!(a.reserved().ids(), [1..=1]);
+    let Enum::Untagged { variants, .. } = a else {
+        panic!("expected an untagged enum");
+    };
+    assert_eq!(variants[0].name(), "reserved");
+    let Type::Struct(b) = &types[1] else {
+        panic!("expected a struct");
+    };
+    assert!(b.reserved().span().is_empty());
+}
+
+#[test]
+fn test_contains_floats() {
+    let types = parse(
+        "struct A {
+    B[] b = 0;
+}
+
+struct B {
+    optional map<string, C> c = 0;
+}
+
+struct C {
+    float32 x = 0;
+}
+
+struct D {
+    D[] children = 0;
+    string name = 1;
+}
+
+enum E {
+    A A = 0;
+    D D = 1;
+}
+
+enum F {
+    D D = 0;
+}",
+    )
+    .unwrap();
+    let contains_floats: Vec<_> = types
+        .iter()
+        .map(|r#type| (r#type.name(), r#type.contains_floats()))
+        .collect();
+    assert_eq!(
+        contains_floats,
+        [
+            ("A", true),
+            ("B", true),
+            ("C", true),
+            ("D", false),
+            ("E", true),
+            ("F", false),
+        ],
+    );
+
+    let options = typedpack_codegen::rust::OptionsInput::default()
+        .try_into()
+        .unwrap();
+    let Type::Struct(a) = &types[0] else {
+        panic!("expected a struct");
+    };
+    assert!(a.rust_struct(&options).starts_with(
+        "#[derive(::std::clone::Clone, ::std::fmt::Debug, ::std::cmp::PartialEq)]\n#[allow(deprecated)]\npub struct r#A {"
+    ));
+}
+
+#[test]
+fn test_builder() {
+    let dir = std::env::temp_dir().join(format!("typedpack-test-builder-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("schemas/users")).unwrap();
+    std::fs::write(
+        dir.join("api.tp"),
+        "import \"schemas/users/email.tp\";\nstruct Api { Email email = 0; }\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("schemas/users/email.tp"),
+        "struct Email { string address = 0; }\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("schemas/status.tp"),
+        "enum Status { Active = 0; }\n",
+    )
+    .unwrap();
+
+    let path = Builder::new()
+        .base_dir(&dir)
+        .file(dir.join("api.tp"))
+        .dir(dir.join("schemas"))
+        .out_dir(&dir)
+        .output_file("generated.rs")
+        .compile()
+        .unwrap();
+    assert_eq!(path, dir.join("generated.rs"));
+    let code = std::fs::read_to_string(&path).unwrap();
+    assert!(code.starts_with("pub mod r#api {\n"));
+    assert!(code.contains("pub mod r#schemas {\npub mod r#status {\n"));
+    assert!(code.contains("pub mod r#users {\npub mod r#email {\n"));
+    assert!(code.contains("use super::r#schemas::r#users::r#email::r#Email;"));
+
+    std::fs::write(
+        dir.join("schemas/status.tp"),
+        "enum Status { Active = 0 }\n",
+    )
+    .unwrap();
+    let result = Builder::new()
+        .base_dir(&dir)
+        .dir(dir.join("schemas"))
+        .out_dir(&dir)
+        .compile();
+    assert!(matches!(
+        result,
+        Err(BuildError::Resolve(ResolveError::Parse { ref diagnostics, .. }))
+            if diagnostics.errors().len() == 1
+    ));
+
+    let result = Builder::new()
+        .base_dir(&dir)
+        .dir(dir.join("missing"))
+        .out_dir(&dir)
+        .compile();
+    assert!(
+        matches!(result, Err(BuildError::ReadDir { ref path, .. }) if *path == dir.join("missing"))
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,3 +1,5 @@
 #![allow(non_camel_case_types)]
 
 include!(concat!(env!("OUT_DIR"), "/types.rs"));
+
+pub use self::test::*;
@@ -0,0 +1,5 @@
+#![allow(dead_code, non_camel_case_types, unused_imports)]
+
+include!(concat!(env!("OUT_DIR"), "/types_with_time.rs"));
+
+pub use self::with_time::test::*;
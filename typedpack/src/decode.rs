@@ -0,0 +1,1035 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, btree_map::Entry},
+    fmt::{self, Formatter},
+    marker::PhantomData,
+};
+
+use serde::{
+    Deserialize, Deserializer,
+    de::{IgnoredAny, MapAccess, SeqAccess, Visitor},
+};
+use serde_bytes::{ByteArray, ByteBuf};
+
+use crate::{TotalF32, TotalF64, UnknownValue, timestamp::TIMESTAMP_EXTENSION};
+
+/// The type of an encoded MessagePack value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MessagePackType {
+    Nil,
+    Boolean,
+    Integer,
+    Float32,
+    Float64,
+    String,
+    Binary,
+    Array,
+    Map,
+    Extension,
+    /// The timestamp extension type, which is predefined by MessagePack.
+    Timestamp,
+}
+
+impl fmt::Display for MessagePackType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Nil => "nil",
+            Self::Boolean => "boolean",
+            Self::Integer => "integer",
+            Self::Float32 => "float32",
+            Self::Float64 => "float64",
+            Self::String => "string",
+            Self::Binary => "binary",
+            Self::Array => "array",
+            Self::Map => "map",
+            Self::Extension => "extension",
+            Self::Timestamp => "timestamp",
+        })
+    }
+}
+
+/// A step in the path to the value a [`DecodeError`] occurred in.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    /// A field of a `struct`.
+    Field {
+        r#type: &'static str,
+        name: &'static str,
+        id: u16,
+    },
+    /// The data of a tagged `enum` variant.
+    Variant {
+        r#type: &'static str,
+        name: &'static str,
+        id: u16,
+    },
+    /// An item of an array.
+    Index(usize),
+    /// The key of the map entry at an index.
+    MapKey(usize),
+    /// The value of the map entry at an index.
+    MapValue(usize),
+}
+
+/// What went wrong when decoding a value.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DecodeErrorKind {
+    /// A value is encoded as a different MessagePack type than its typedpack type.
+    InvalidType {
+        expected: MessagePackType,
+        actual: MessagePackType,
+    },
+    /// An integer does not fit in the declared integer type, e.g. `uint8`, or the seconds of a
+    /// timestamp do not fit in the Rust type of a `timestamp` or `duration`.
+    OutOfRange { r#type: &'static str, value: i128 },
+    /// A timestamp extension value has neither 4, 8 nor 12 bytes of data, or too many nanoseconds.
+    InvalidTimestamp,
+    /// Binary data or a tagged `enum` has the wrong number of elements.
+    InvalidLength { expected: usize, actual: usize },
+    /// A required `struct` field is absent.
+    MissingField,
+    /// A `struct` field occurs more than once.
+    DuplicateField { r#type: &'static str, id: u16 },
+    /// A `struct` has a field which is not in the schema, and unknown fields are rejected.
+    UnknownField { r#type: &'static str, id: u16 },
+    /// A map has the same key more than once.
+    DuplicateMapKey,
+    /// An `enum` variant ID is not in the schema, and the `enum` is not `open`.
+    UnknownVariant { r#type: &'static str, id: u16 },
+    /// A value does not satisfy a constraint of its `struct` field.
+    ConstraintViolation {
+        /// The constraint as written in the schema, e.g. `max_len = 32`.
+        constraint: &'static str,
+    },
+    /// An error reported by the deserializer.
+    Custom(String),
+}
+
+impl fmt::Display for DecodeErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidType { expected, actual } => {
+                write!(f, "invalid type: expected {expected}, found {actual}")
+            }
+            Self::OutOfRange { r#type, value } => write!(f, "{value} is out of range for {type}"),
+            Self::InvalidTimestamp => f.write_str("invalid timestamp"),
+            Self::InvalidLength { expected, actual } => {
+                write!(f, "invalid length: expected {expected}, found {actual}")
+            }
+            Self::MissingField => f.write_str("missing field"),
+            Self::DuplicateField { r#type, id } => write!(f, "duplicate field {id} of {type}"),
+            Self::UnknownField { r#type, id } => write!(f, "unknown field {id} of {type}"),
+            Self::DuplicateMapKey => f.write_str("duplicate map key"),
+            Self::UnknownVariant { r#type, id } => write!(f, "unknown variant {id} of {type}"),
+            Self::ConstraintViolation { constraint } => {
+                write!(f, "value does not satisfy `{constraint}`")
+            }
+            Self::Custom(message) => f.write_str(message),
+        }
+    }
+}
+
+/// The error type for decoding a typedpack value.
+///
+/// Generated [`Deserialize`] implementations report it with [`serde::de::Error::custom`], so
+/// its message is available from the deserializer's error, e.g.
+/// `User.emails[3].address: invalid type: expected string, found binary`. Use [`Decode`]
+/// directly to get the structured error:
+///
+/// ```ignore
+/// let mut deserializer = rmp_serde::Deserializer::from_read_ref(&bytes);
+/// match User::decode(&mut deserializer)? {
+///     Ok(user) => println!("{user:?}"),
+///     Err(error) => println!("{:?} at {:?}", error.kind(), error.path()),
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DecodeError {
+    path: Vec<PathSegment>,
+    kind: DecodeErrorKind,
+}
+
+impl DecodeError {
+    /// Returns an error with an empty path.
+    pub fn new(kind: DecodeErrorKind) -> Self {
+        Self {
+            path: Vec::new(),
+            kind,
+        }
+    }
+
+    /// Returns an error for a value of an unexpected MessagePack type.
+    pub fn invalid_type(expected: MessagePackType, actual: MessagePackType) -> Self {
+        Self::new(DecodeErrorKind::InvalidType { expected, actual })
+    }
+
+    /// Returns an error for a missing `struct` field.
+    pub fn missing_field(r#type: &'static str, name: &'static str, id: u16) -> Self {
+        Self::new(DecodeErrorKind::MissingField).in_field(r#type, name, id)
+    }
+
+    /// Returns an error for a `struct` field which is not in the schema.
+    pub fn unknown_field(r#type: &'static str, id: u16) -> Self {
+        Self::new(DecodeErrorKind::UnknownField { r#type, id })
+    }
+
+    /// Returns an error for an `enum` variant which is not in the schema.
+    pub fn unknown_variant(r#type: &'static str, id: u16) -> Self {
+        Self::new(DecodeErrorKind::UnknownVariant { r#type, id })
+    }
+
+    /// Returns an error for a value which does not satisfy `constraint`.
+    pub fn constraint_violation(constraint: &'static str) -> Self {
+        Self::new(DecodeErrorKind::ConstraintViolation { constraint })
+    }
+
+    /// Returns the path to the value the error occurred in, starting from the outermost value.
+    pub fn path(&self) -> &[PathSegment] {
+        &self.path
+    }
+
+    /// Returns what went wrong.
+    pub fn kind(&self) -> &DecodeErrorKind {
+        &self.kind
+    }
+
+    /// Adds a segment to the start of the path.
+    pub fn in_segment(mut self, segment: PathSegment) -> Self {
+        self.path.insert(0, segment);
+        self
+    }
+
+    /// Adds a `struct` field to the start of the path.
+    pub fn in_field(self, r#type: &'static str, name: &'static str, id: u16) -> Self {
+        self.in_segment(PathSegment::Field { r#type, name, id })
+    }
+
+    /// Adds a tagged `enum` variant to the start of the path.
+    pub fn in_variant(self, r#type: &'static str, name: &'static str, id: u16) -> Self {
+        self.in_segment(PathSegment::Variant { r#type, name, id })
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.path.iter().enumerate() {
+            match segment {
+                PathSegment::Field { r#type, name, .. } => {
+                    if i == 0 {
+                        f.write_str(r#type)?;
+                    }
+                    write!(f, ".{name}")?;
+                }
+                PathSegment::Variant { r#type, name, .. } => {
+                    if i == 0 {
+                        f.write_str(r#type)?;
+                    }
+                    write!(f, "::{name}")?;
+                }
+                PathSegment::Index(index) => write!(f, "[{index}]")?,
+                PathSegment::MapKey(index) => write!(f, "[key {index}]")?,
+                PathSegment::MapValue(index) => write!(f, "[value {index}]")?,
+            }
+        }
+        if !self.path.is_empty() {
+            f.write_str(": ")?;
+        }
+        self.kind.fmt(f)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// A type that can be deserialized from a value of its typedpack type.
+///
+/// Unlike [`Deserialize`], this only accepts the exact MessagePack type the value is encoded as,
+/// e.g. binary data is not accepted for a `string`, and `float64` is not accepted for a
+/// `float32`. Such errors are returned as a [`DecodeError`] inside [`Ok`], so that the path to
+/// the value can be added to them, while errors from the deserializer itself, such as
+/// unexpected end of input, are returned as [`Err`].
+pub trait Decode<'de>: Sized {
+    /// Deserializes a value of this type.
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>;
+}
+
+/// A wrapper which implements [`Deserialize`] using [`Decode`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Decoded<T>(pub Result<T, DecodeError>);
+
+impl<'de, T> Deserialize<'de> for Decoded<T>
+where
+    T: Decode<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        T::decode(deserializer).map(Self)
+    }
+}
+
+/// Deserializes a value with [`Decode`], reporting any [`DecodeError`] with
+/// [`serde::de::Error::custom`]. Generated [`Deserialize`] implementations call this.
+pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Decode<'de>,
+{
+    match T::decode(deserializer) {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(error)) => Err(serde::de::Error::custom(error)),
+        Err(error) => Err(error),
+    }
+}
+
+/// A [`Visitor`]-like trait for implementing [`Decode`], where values of unexpected types result
+/// in a [`DecodeError`].
+///
+/// Use [`decode_any`] to deserialize a value with it.
+pub trait DecodeVisitor<'de>: Sized {
+    type Value;
+
+    /// The type of value the visitor accepts.
+    const EXPECTED: MessagePackType;
+
+    fn visit_nil(self) -> Result<Self::Value, DecodeError> {
+        Err(DecodeError::invalid_type(
+            Self::EXPECTED,
+            MessagePackType::Nil,
+        ))
+    }
+
+    fn visit_bool(self, value: bool) -> Result<Self::Value, DecodeError> {
+        let _ = value;
+        Err(DecodeError::invalid_type(
+            Self::EXPECTED,
+            MessagePackType::Boolean,
+        ))
+    }
+
+    fn visit_u64(self, value: u64) -> Result<Self::Value, DecodeError> {
+        let _ = value;
+        Err(DecodeError::invalid_type(
+            Self::EXPECTED,
+            MessagePackType::Integer,
+        ))
+    }
+
+    fn visit_i64(self, value: i64) -> Result<Self::Value, DecodeError> {
+        let _ = value;
+        Err(DecodeError::invalid_type(
+            Self::EXPECTED,
+            MessagePackType::Integer,
+        ))
+    }
+
+    fn visit_f32(self, value: f32) -> Result<Self::Value, DecodeError> {
+        let _ = value;
+        Err(DecodeError::invalid_type(
+            Self::EXPECTED,
+            MessagePackType::Float32,
+        ))
+    }
+
+    fn visit_f64(self, value: f64) -> Result<Self::Value, DecodeError> {
+        let _ = value;
+        Err(DecodeError::invalid_type(
+            Self::EXPECTED,
+            MessagePackType::Float64,
+        ))
+    }
+
+    fn visit_str(self, value: &str) -> Result<Self::Value, DecodeError> {
+        let _ = value;
+        Err(DecodeError::invalid_type(
+            Self::EXPECTED,
+            MessagePackType::String,
+        ))
+    }
+
+    fn visit_string(self, value: String) -> Result<Self::Value, DecodeError> {
+        self.visit_str(&value)
+    }
+
+    fn visit_bytes(self, value: &[u8]) -> Result<Self::Value, DecodeError> {
+        let _ = value;
+        Err(DecodeError::invalid_type(
+            Self::EXPECTED,
+            MessagePackType::Binary,
+        ))
+    }
+
+    fn visit_byte_buf(self, value: Vec<u8>) -> Result<Self::Value, DecodeError> {
+        self.visit_bytes(&value)
+    }
+
+    /// Visits a value of a MessagePack extension type, given the type and the data.
+    fn visit_ext(self, r#type: i8, data: &[u8]) -> Result<Self::Value, DecodeError> {
+        let _ = data;
+        Err(DecodeError::invalid_type(
+            Self::EXPECTED,
+            if r#type == TIMESTAMP_EXTENSION {
+                MessagePackType::Timestamp
+            } else {
+                MessagePackType::Extension
+            },
+        ))
+    }
+
+    /// Visits a value which is not nil, when deserializing with
+    /// [`Deserializer::deserialize_option`].
+    fn visit_some<D>(self, deserializer: D) -> Result<Result<Self::Value, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        decode_any(deserializer, self)
+    }
+
+    /// Visits an array. The default implementation skips it.
+    fn visit_seq<A>(self, seq: A) -> Result<Result<Self::Value, DecodeError>, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        skip_seq(
+            seq,
+            DecodeError::invalid_type(Self::EXPECTED, MessagePackType::Array),
+        )
+    }
+
+    /// Visits a map. The default implementation skips it.
+    fn visit_map<A>(self, map: A) -> Result<Result<Self::Value, DecodeError>, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        skip_map(
+            map,
+            DecodeError::invalid_type(Self::EXPECTED, MessagePackType::Map),
+        )
+    }
+}
+
+/// Adapts a [`DecodeVisitor`] into a [`Visitor`].
+struct DecodeVisitorAdapter<V>(V);
+
+impl<'de, V> Visitor<'de> for DecodeVisitorAdapter<V>
+where
+    V: DecodeVisitor<'de>,
+{
+    type Value = Result<V::Value, DecodeError>;
+
+    fn expecting(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&V::EXPECTED, formatter)
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E> {
+        Ok(self.0.visit_nil())
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E> {
+        Ok(self.0.visit_nil())
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.0.visit_some(deserializer)
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<Self::Value, E> {
+        Ok(self.0.visit_bool(value))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E> {
+        Ok(self.0.visit_u64(value))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E> {
+        Ok(self.0.visit_i64(value))
+    }
+
+    fn visit_f32<E>(self, value: f32) -> Result<Self::Value, E> {
+        Ok(self.0.visit_f32(value))
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<Self::Value, E> {
+        Ok(self.0.visit_f64(value))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E> {
+        Ok(self.0.visit_str(value))
+    }
+
+    fn visit_string<E>(self, value: String) -> Result<Self::Value, E> {
+        Ok(self.0.visit_string(value))
+    }
+
+    fn visit_bytes<E>(self, value: &[u8]) -> Result<Self::Value, E> {
+        Ok(self.0.visit_bytes(value))
+    }
+
+    fn visit_byte_buf<E>(self, value: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(self.0.visit_byte_buf(value))
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        self.0.visit_seq(seq)
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        self.0.visit_map(map)
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // rmp-serde and rmpv deserialize extension values as newtype structs containing the
+        // extension type and the data
+        let (r#type, data) = <(i8, ByteBuf)>::deserialize(deserializer)?;
+        Ok(self.0.visit_ext(r#type, &data))
+    }
+}
+
+/// Deserializes a value of any type with a [`DecodeVisitor`].
+pub fn decode_any<'de, D, V>(
+    deserializer: D,
+    visitor: V,
+) -> Result<Result<V::Value, DecodeError>, D::Error>
+where
+    D: Deserializer<'de>,
+    V: DecodeVisitor<'de>,
+{
+    // not e.g. `deserialize_seq`, which may accept binary data as an array of integers
+    deserializer.deserialize_any(DecodeVisitorAdapter(visitor))
+}
+
+/// Skips the rest of an array after an error, so that the deserializer can continue after it.
+pub fn skip_seq<'de, A, T>(
+    mut seq: A,
+    error: DecodeError,
+) -> Result<Result<T, DecodeError>, A::Error>
+where
+    A: SeqAccess<'de>,
+{
+    while seq.next_element::<IgnoredAny>()?.is_some() {}
+    Ok(Err(error))
+}
+
+/// Skips the rest of a map after an error, so that the deserializer can continue after it.
+pub fn skip_map<'de, A, T>(
+    mut map: A,
+    error: DecodeError,
+) -> Result<Result<T, DecodeError>, A::Error>
+where
+    A: MapAccess<'de>,
+{
+    while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+    Ok(Err(error))
+}
+
+/// The IDs of the fields of a `struct` which have been read, used to reject duplicate fields.
+#[derive(Clone, Debug, Default)]
+pub struct FieldIds([u64; 4], BTreeSet<u16>);
+
+impl FieldIds {
+    /// Adds an ID, returning `false` if it was already present.
+    pub fn insert(&mut self, id: u16) -> bool {
+        // IDs of 256 or more are rare, so they are kept in a set instead of the bitset
+        let Ok(id) = u8::try_from(id) else {
+            return self.1.insert(id);
+        };
+        let (word, bit) = (usize::from(id / 64), 1 << (id % 64));
+        let is_new = self.0[word] & bit == 0;
+        self.0[word] |= bit;
+        is_new
+    }
+}
+
+/// Reads the next `struct` field ID. If the key is invalid or a duplicate, its value is skipped.
+pub fn next_field_id<'de, A>(
+    map: &mut A,
+    ids: &mut FieldIds,
+    r#type: &'static str,
+) -> Result<Option<Result<u16, DecodeError>>, A::Error>
+where
+    A: MapAccess<'de>,
+{
+    let error = match map.next_key::<Decoded<u16>>()? {
+        Some(Decoded(Ok(id))) if ids.insert(id) => return Ok(Some(Ok(id))),
+        Some(Decoded(Ok(id))) => DecodeError::new(DecodeErrorKind::DuplicateField { r#type, id }),
+        Some(Decoded(Err(error))) => error,
+        None => return Ok(None),
+    };
+    map.next_value::<IgnoredAny>()?;
+    Ok(Some(Err(error)))
+}
+
+/// Reads the tag of a tagged `enum`, which is encoded as a 2-tuple of the tag and the data. The
+/// tag is decoded as `T`, the integer type of the `enum`'s variant IDs.
+pub fn decode_tag<'de, A, T>(seq: &mut A) -> Result<Result<T, DecodeError>, A::Error>
+where
+    A: SeqAccess<'de>,
+    T: Decode<'de>,
+{
+    Ok(match seq.next_element::<Decoded<T>>()? {
+        Some(Decoded(tag)) => tag,
+        None => Err(DecodeError::new(DecodeErrorKind::InvalidLength {
+            expected: 2,
+            actual: 0,
+        })),
+    })
+}
+
+/// Decodes the data of a tagged `enum` variant after its tag, and checks that nothing follows
+/// it.
+pub fn decode_variant<'de, A, T, E>(
+    mut seq: A,
+    r#type: &'static str,
+    name: &'static str,
+    id: u16,
+    variant: impl FnOnce(T) -> E,
+) -> Result<Result<E, DecodeError>, A::Error>
+where
+    A: SeqAccess<'de>,
+    T: Decode<'de>,
+{
+    let value = match seq.next_element::<Decoded<T>>()? {
+        Some(Decoded(Ok(value))) => value,
+        Some(Decoded(Err(error))) => {
+            return skip_seq(seq, error.in_variant(r#type, name, id));
+        }
+        None => {
+            return Ok(Err(DecodeError::new(DecodeErrorKind::InvalidLength {
+                expected: 2,
+                actual: 1,
+            })));
+        }
+    };
+    let mut len = 2;
+    while seq.next_element::<IgnoredAny>()?.is_some() {
+        len += 1;
+    }
+    if len != 2 {
+        return Ok(Err(DecodeError::new(DecodeErrorKind::InvalidLength {
+            expected: 2,
+            actual: len,
+        })));
+    }
+    Ok(Ok(variant(value)))
+}
+
+struct BoolVisitor;
+
+impl DecodeVisitor<'_> for BoolVisitor {
+    type Value = bool;
+    const EXPECTED: MessagePackType = MessagePackType::Boolean;
+
+    fn visit_bool(self, value: bool) -> Result<Self::Value, DecodeError> {
+        Ok(value)
+    }
+}
+
+impl<'de> Decode<'de> for bool {
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        decode_any(deserializer, BoolVisitor)
+    }
+}
+
+struct UnitVisitor;
+
+impl DecodeVisitor<'_> for UnitVisitor {
+    type Value = ();
+    const EXPECTED: MessagePackType = MessagePackType::Nil;
+
+    fn visit_nil(self) -> Result<Self::Value, DecodeError> {
+        Ok(())
+    }
+}
+
+impl<'de> Decode<'de> for () {
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        decode_any(deserializer, UnitVisitor)
+    }
+}
+
+struct IntegerVisitor<T>(&'static str, PhantomData<T>);
+
+impl<T> DecodeVisitor<'_> for IntegerVisitor<T>
+where
+    T: TryFrom<u64> + TryFrom<i64>,
+{
+    type Value = T;
+    const EXPECTED: MessagePackType = MessagePackType::Integer;
+
+    fn visit_u64(self, value: u64) -> Result<Self::Value, DecodeError> {
+        T::try_from(value).map_err(|_| {
+            DecodeError::new(DecodeErrorKind::OutOfRange {
+                r#type: self.0,
+                value: value.into(),
+            })
+        })
+    }
+
+    fn visit_i64(self, value: i64) -> Result<Self::Value, DecodeError> {
+        T::try_from(value).map_err(|_| {
+            DecodeError::new(DecodeErrorKind::OutOfRange {
+                r#type: self.0,
+                value: value.into(),
+            })
+        })
+    }
+}
+
+macro_rules! impl_decode_integer {
+    ($($type:ty => $name:literal),*) => {
+        $(
+            impl<'de> Decode<'de> for $type {
+                fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+                where
+                    D: Deserializer<'de>,
+                {
+                    decode_any(deserializer, IntegerVisitor($name, PhantomData))
+                }
+            }
+        )*
+    };
+}
+
+impl_decode_integer!(
+    u8 => "uint8",
+    i8 => "int8",
+    u16 => "uint16",
+    i16 => "int16",
+    u32 => "uint32",
+    i32 => "int32",
+    u64 => "uint64",
+    i64 => "int64"
+);
+
+struct F32Visitor;
+
+impl DecodeVisitor<'_> for F32Visitor {
+    type Value = f32;
+    const EXPECTED: MessagePackType = MessagePackType::Float32;
+
+    fn visit_f32(self, value: f32) -> Result<Self::Value, DecodeError> {
+        Ok(value)
+    }
+}
+
+impl<'de> Decode<'de> for f32 {
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        decode_any(deserializer, F32Visitor)
+    }
+}
+
+struct F64Visitor;
+
+impl DecodeVisitor<'_> for F64Visitor {
+    type Value = f64;
+    const EXPECTED: MessagePackType = MessagePackType::Float64;
+
+    fn visit_f64(self, value: f64) -> Result<Self::Value, DecodeError> {
+        Ok(value)
+    }
+}
+
+impl<'de> Decode<'de> for f64 {
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        decode_any(deserializer, F64Visitor)
+    }
+}
+
+impl<'de> Decode<'de> for TotalF32 {
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(f32::decode(deserializer)?.map(Self))
+    }
+}
+
+impl<'de> Decode<'de> for TotalF64 {
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(f64::decode(deserializer)?.map(Self))
+    }
+}
+
+impl<'de> Decode<'de> for UnknownValue {
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Ok(Self(rmpv::Value::deserialize(deserializer)?)))
+    }
+}
+
+struct StringVisitor;
+
+impl DecodeVisitor<'_> for StringVisitor {
+    type Value = String;
+    const EXPECTED: MessagePackType = MessagePackType::String;
+
+    fn visit_str(self, value: &str) -> Result<Self::Value, DecodeError> {
+        Ok(value.to_owned())
+    }
+
+    fn visit_string(self, value: String) -> Result<Self::Value, DecodeError> {
+        Ok(value)
+    }
+}
+
+impl<'de> Decode<'de> for String {
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        decode_any(deserializer, StringVisitor)
+    }
+}
+
+struct BytesVisitor;
+
+impl DecodeVisitor<'_> for BytesVisitor {
+    type Value = Vec<u8>;
+    const EXPECTED: MessagePackType = MessagePackType::Binary;
+
+    fn visit_bytes(self, value: &[u8]) -> Result<Self::Value, DecodeError> {
+        Ok(value.to_owned())
+    }
+
+    fn visit_byte_buf(self, value: Vec<u8>) -> Result<Self::Value, DecodeError> {
+        Ok(value)
+    }
+}
+
+impl<'de> Decode<'de> for ByteBuf {
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(decode_any(deserializer, BytesVisitor)?.map(ByteBuf::from))
+    }
+}
+
+// the arrays are only created after deserializing and are filled in place, since large arrays
+// would otherwise be copied through the stack frames of the deserializer and of every `Result`
+impl<'de, const N: usize> Decode<'de> for [u8; N] {
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = match decode_array_bytes::<_, N>(deserializer)? {
+            Ok(value) => value,
+            Err(error) => return Ok(Err(error)),
+        };
+        let mut array = [0; N];
+        array.copy_from_slice(&value);
+        Ok(Ok(array))
+    }
+}
+
+impl<'de, const N: usize> Decode<'de> for ByteArray<N> {
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = match decode_array_bytes::<_, N>(deserializer)? {
+            Ok(value) => value,
+            Err(error) => return Ok(Err(error)),
+        };
+        let mut array = ByteArray::new([0; N]);
+        array.copy_from_slice(&value);
+        Ok(Ok(array))
+    }
+}
+
+fn decode_array_bytes<'de, D, const N: usize>(
+    deserializer: D,
+) -> Result<Result<Vec<u8>, DecodeError>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(decode_any(deserializer, BytesVisitor)?.and_then(|value| {
+        if value.len() == N {
+            Ok(value)
+        } else {
+            Err(DecodeError::new(DecodeErrorKind::InvalidLength {
+                expected: N,
+                actual: value.len(),
+            }))
+        }
+    }))
+}
+
+struct ArrayVisitor<T>(PhantomData<T>);
+
+impl<'de, T> DecodeVisitor<'de> for ArrayVisitor<T>
+where
+    T: Decode<'de>,
+{
+    type Value = Vec<T>;
+    const EXPECTED: MessagePackType = MessagePackType::Array;
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Result<Self::Value, DecodeError>, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        // the size hint comes from the data, so limit it to avoid allocating too much up front
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(Decoded(item)) = seq.next_element()? {
+            match item {
+                Ok(item) => items.push(item),
+                Err(error) => {
+                    let index = items.len();
+                    return skip_seq(seq, error.in_segment(PathSegment::Index(index)));
+                }
+            }
+        }
+        Ok(Ok(items))
+    }
+}
+
+impl<'de, T> Decode<'de> for Vec<T>
+where
+    T: Decode<'de>,
+{
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        decode_any(deserializer, ArrayVisitor(PhantomData))
+    }
+}
+
+impl<'de, T> Decode<'de> for Box<[T]>
+where
+    T: Decode<'de>,
+{
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Vec::decode(deserializer)?.map(Vec::into_boxed_slice))
+    }
+}
+
+struct BTreeMapVisitor<K, V>(PhantomData<(K, V)>);
+
+impl<'de, K, V> DecodeVisitor<'de> for BTreeMapVisitor<K, V>
+where
+    K: Decode<'de> + Ord,
+    V: Decode<'de>,
+{
+    type Value = BTreeMap<K, V>;
+    const EXPECTED: MessagePackType = MessagePackType::Map;
+
+    fn visit_map<A>(self, mut map: A) -> Result<Result<Self::Value, DecodeError>, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut entries = BTreeMap::new();
+        let mut index = 0;
+        while let Some(Decoded(key)) = map.next_key()? {
+            let error = match key {
+                Ok(key) => match entries.entry(key) {
+                    Entry::Vacant(entry) => match map.next_value::<Decoded<V>>()?.0 {
+                        Ok(value) => {
+                            entry.insert(value);
+                            index += 1;
+                            continue;
+                        }
+                        Err(error) => error.in_segment(PathSegment::MapValue(index)),
+                    },
+                    Entry::Occupied(_) => {
+                        map.next_value::<IgnoredAny>()?;
+                        DecodeError::new(DecodeErrorKind::DuplicateMapKey)
+                            .in_segment(PathSegment::MapKey(index))
+                    }
+                },
+                Err(error) => {
+                    map.next_value::<IgnoredAny>()?;
+                    error.in_segment(PathSegment::MapKey(index))
+                }
+            };
+            return skip_map(map, error);
+        }
+        Ok(Ok(entries))
+    }
+}
+
+impl<'de, K, V> Decode<'de> for BTreeMap<K, V>
+where
+    K: Decode<'de> + Ord,
+    V: Decode<'de>,
+{
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        decode_any(deserializer, BTreeMapVisitor(PhantomData))
+    }
+}
+
+struct OptionVisitor<T>(PhantomData<T>);
+
+impl<'de, T> Visitor<'de> for OptionVisitor<T>
+where
+    T: Decode<'de>,
+{
+    type Value = Result<Option<T>, DecodeError>;
+
+    fn expecting(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str("nil or a value")
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E> {
+        Ok(Ok(None))
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E> {
+        Ok(Ok(None))
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(T::decode(deserializer)?.map(Some))
+    }
+}
+
+impl<'de, T> Decode<'de> for Option<T>
+where
+    T: Decode<'de>,
+{
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_option(OptionVisitor(PhantomData))
+    }
+}
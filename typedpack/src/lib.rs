@@ -1,222 +1,329 @@
 //! Helpers for code generated by [`typedpack-codegen`].
 
-use std::fmt::Formatter;
+mod decode;
+mod timestamp;
+
+use std::{
+    cmp::Ordering,
+    fmt::Formatter,
+    hash::{Hash, Hasher},
+    ops::{Deref, DerefMut},
+};
 
 use rmpv::Value;
-use serde::de::{MapAccess, Visitor};
-use serde_bytes::{ByteArray, ByteBuf};
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{MapAccess, Visitor},
+};
 
+#[cfg(feature = "chrono")]
+pub use chrono;
+pub use decode::{
+    Decode, DecodeError, DecodeErrorKind, DecodeVisitor, Decoded, FieldIds, MessagePackType,
+    PathSegment, decode_any, decode_tag, decode_variant, deserialize, next_field_id, skip_map,
+    skip_seq,
+};
+#[cfg(feature = "pattern")]
+pub use regex;
 pub use rmpv;
 pub use serde;
 pub use serde_bytes;
 pub use serde_repr;
+#[cfg(feature = "time")]
+pub use time;
+#[cfg(feature = "chrono")]
+pub use timestamp::{ChronoDurationRef, ChronoTimestampRef};
+pub use timestamp::{DurationRef, TimestampRef};
+#[cfg(feature = "time")]
+pub use timestamp::{TimeDurationRef, TimeTimestampRef};
+#[cfg(feature = "macros")]
+pub use typedpack_macros::include_schema;
 
-/// A type that a suitable variant of [`Value`] can be converted into.
+/// A type that can be converted from a [`Value`] holding a value of its typedpack type.
+///
+/// This is implemented for every type which implements [`Decode`].
 pub trait FromRmpValue {
     /// Converts from [`Value`] to this type.
-    fn from(value: Value) -> Result<Self, &'static str>
+    fn from(value: Value) -> Result<Self, DecodeError>
     where
         Self: Sized;
 }
 
-impl FromRmpValue for bool {
-    fn from(value: Value) -> Result<Self, &'static str> {
-        match value {
-            Value::Boolean(value) => Ok(value),
-            _ => Err("invalid type; expected bool"),
-        }
+impl<T> FromRmpValue for T
+where
+    T: for<'de> Decode<'de>,
+{
+    fn from(value: Value) -> Result<Self, DecodeError> {
+        T::decode(value).unwrap_or_else(|error| {
+            Err(DecodeError::new(DecodeErrorKind::Custom(error.to_string())))
+        })
     }
 }
 
-impl FromRmpValue for u8 {
-    fn from(value: Value) -> Result<Self, &'static str> {
-        match value {
-            Value::Integer(value) => u8::try_from(
-                value
-                    .as_u64()
-                    .ok_or("invalid type; expected an unsigned integer")?,
-            )
-            .map_err(|_| "invalid value; does not fit in u8"),
-            _ => Err("invalid type; expected u8"),
-        }
+/// A type whose values can be checked against the constraints in its typedpack schema.
+///
+/// This is implemented for every generated type. Decoding checks the same constraints and returns
+/// the same errors, so this is only needed for values which were constructed in code.
+pub trait Validate {
+    /// Checks that the value satisfies the constraints in the schema, including those of nested
+    /// values.
+    fn validate(&self) -> Result<(), DecodeError> {
+        Ok(())
     }
 }
 
-impl FromRmpValue for i8 {
-    fn from(value: Value) -> Result<Self, &'static str> {
-        match value {
-            Value::Integer(value) => i8::try_from(
-                value
-                    .as_i64()
-                    .ok_or("invalid type; expected a signed integer")?,
-            )
-            .map_err(|_| "invalid value; does not fit in i8"),
-            _ => Err("invalid type; expected i8"),
-        }
+/// An [`f32`] which implements [`Eq`], [`Ord`] and [`Hash`] using [`f32::total_cmp`].
+///
+/// Generated code uses this instead of [`f32`] when total-order floats are enabled, so that types
+/// with `float32` fields can still derive those traits.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TotalF32(pub f32);
+
+impl PartialEq for TotalF32 {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
     }
 }
 
-impl FromRmpValue for u16 {
-    fn from(value: Value) -> Result<Self, &'static str> {
-        match value {
-            Value::Integer(value) => u16::try_from(
-                value
-                    .as_u64()
-                    .ok_or("invalid type; expected an unsigned integer")?,
-            )
-            .map_err(|_| "invalid value; does not fit in u16"),
-            _ => Err("invalid type; expected u16"),
-        }
+impl Eq for TotalF32 {}
+
+impl PartialOrd for TotalF32 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-impl FromRmpValue for i16 {
-    fn from(value: Value) -> Result<Self, &'static str> {
-        match value {
-            Value::Integer(value) => i16::try_from(
-                value
-                    .as_i64()
-                    .ok_or("invalid type; expected a signed integer")?,
-            )
-            .map_err(|_| "invalid value; does not fit in i16"),
-            _ => Err("invalid type; expected ui16"),
-        }
+impl Ord for TotalF32 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
     }
 }
 
-impl FromRmpValue for u32 {
-    fn from(value: Value) -> Result<Self, &'static str> {
-        match value {
-            Value::Integer(value) => u32::try_from(
-                value
-                    .as_u64()
-                    .ok_or("invalid type; expected an unsigned integer")?,
-            )
-            .map_err(|_| "invalid value; does not fit in u32"),
-            _ => Err("invalid type; expected u32"),
-        }
+impl Hash for TotalF32 {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
     }
 }
 
-impl FromRmpValue for i32 {
-    fn from(value: Value) -> Result<Self, &'static str> {
-        match value {
-            Value::Integer(value) => i32::try_from(
-                value
-                    .as_i64()
-                    .ok_or("invalid type; expected a signed integer")?,
-            )
-            .map_err(|_| "invalid value; does not fit in i32"),
-            _ => Err("invalid type; expected i32"),
-        }
+impl From<f32> for TotalF32 {
+    fn from(value: f32) -> Self {
+        Self(value)
     }
 }
 
-impl FromRmpValue for u64 {
-    fn from(value: Value) -> Result<Self, &'static str> {
-        match value {
-            Value::Integer(value) => value
-                .as_u64()
-                .ok_or("invalid type; expected an unsigned integer"),
-            _ => Err("invalid type; expected u64"),
-        }
+impl Serialize for TotalF32 {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_f32(self.0)
     }
 }
 
-impl FromRmpValue for i64 {
-    fn from(value: Value) -> Result<Self, &'static str> {
-        match value {
-            Value::Integer(value) => value
-                .as_i64()
-                .ok_or("invalid type; expected a signed integer"),
-            _ => Err("invalid type; expected i64"),
-        }
+impl<'de> Deserialize<'de> for TotalF32 {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        f32::deserialize(deserializer).map(Self)
     }
 }
 
-impl FromRmpValue for f32 {
-    fn from(value: Value) -> Result<Self, &'static str> {
-        match value {
-            Value::F32(value) => Ok(value),
-            _ => Err("invalid type; expected f32"),
-        }
+/// An [`f64`] which implements [`Eq`], [`Ord`] and [`Hash`] using [`f64::total_cmp`].
+///
+/// Generated code uses this instead of [`f64`] when total-order floats are enabled, so that types
+/// with `float64` fields can still derive those traits.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TotalF64(pub f64);
+
+impl PartialEq for TotalF64 {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
     }
 }
 
-impl FromRmpValue for f64 {
-    fn from(value: Value) -> Result<Self, &'static str> {
-        match value {
-            Value::F64(value) => Ok(value),
-            _ => Err("invalid type; expected f64"),
-        }
+impl Eq for TotalF64 {}
+
+impl PartialOrd for TotalF64 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-impl FromRmpValue for String {
-    fn from(value: Value) -> Result<Self, &'static str> {
-        match value {
-            Value::String(value) => value.into_str().ok_or("string is not UTF-8"),
-            _ => Err("invalid type; expected a string"),
-        }
+impl Ord for TotalF64 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
     }
 }
 
-impl<const N: usize> FromRmpValue for ByteArray<N> {
-    fn from(value: Value) -> Result<Self, &'static str> {
-        match value {
-            Value::Binary(value) => Ok(ByteArray::new(
-                value.try_into().map_err(|_| "invalid byte array length")?,
-            )),
-            _ => Err("invalid type; expected a byte array"),
-        }
+impl Hash for TotalF64 {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
     }
 }
 
-impl FromRmpValue for ByteBuf {
-    fn from(value: Value) -> Result<Self, &'static str> {
-        match value {
-            Value::Binary(value) => Ok(value.into()),
-            _ => Err("invalid type; expected a byte array"),
-        }
+impl From<f64> for TotalF64 {
+    fn from(value: f64) -> Self {
+        Self(value)
     }
 }
 
-impl<T> FromRmpValue for Box<[T]>
-where
-    T: FromRmpValue,
-{
-    fn from(value: Value) -> Result<Self, &'static str> {
-        match value {
-            Value::Array(value) => {
-                let mut mapped = Vec::with_capacity(value.len());
-                for item in value {
-                    mapped.push(FromRmpValue::from(item)?);
-                }
-                Ok(mapped.into())
-            }
-            _ => Err("invalid type; expected an array"),
-        }
+impl Serialize for TotalF64 {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_f64(self.0)
     }
 }
 
-impl<T> FromRmpValue for Option<T>
-where
-    T: FromRmpValue,
-{
-    fn from(value: Value) -> Result<Self, &'static str> {
-        if matches!(value, Value::Nil) {
-            Ok(None)
-        } else {
-            Ok(Some(FromRmpValue::from(value)?))
-        }
+impl<'de> Deserialize<'de> for TotalF64 {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        f64::deserialize(deserializer).map(Self)
+    }
+}
+
+/// The fields of a `struct` which are not in its schema, as pairs of field ID and value.
+///
+/// Generated `preserve_unknown` structs keep these when decoding and write them back when
+/// encoding, so that fields added in newer versions of the schema are not lost. The values are
+/// compared and hashed by their encoding, so that this implements [`Eq`], [`Ord`] and [`Hash`]
+/// even though [`Value`] does not.
+#[derive(Clone, Debug, Default)]
+pub struct UnknownFields(pub Vec<(u16, Value)>);
+
+impl UnknownFields {
+    fn encoded(&self) -> Vec<(u16, Vec<u8>)> {
+        self.0
+            .iter()
+            .map(|(id, value)| (*id, encode_value(value)))
+            .collect()
+    }
+}
+
+impl PartialEq for UnknownFields {
+    fn eq(&self, other: &Self) -> bool {
+        self.encoded() == other.encoded()
+    }
+}
+
+impl Eq for UnknownFields {}
+
+impl PartialOrd for UnknownFields {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-/// A [`Visitor`] for a map with [`u8`] keys.
+impl Ord for UnknownFields {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.encoded().cmp(&other.encoded())
+    }
+}
+
+impl Hash for UnknownFields {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.encoded().hash(state);
+    }
+}
+
+impl Deref for UnknownFields {
+    type Target = Vec<(u16, Value)>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for UnknownFields {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl From<Vec<(u16, Value)>> for UnknownFields {
+    fn from(value: Vec<(u16, Value)>) -> Self {
+        Self(value)
+    }
+}
+
+/// The data of an unknown variant of a tagged `enum`.
+///
+/// Generated `open` tagged enums keep this when decoding a variant which is not in the schema
+/// and write it back when encoding. Like [`UnknownFields`], the value is compared and hashed by
+/// its encoding.
+#[derive(Clone, Debug)]
+pub struct UnknownValue(pub Value);
+
+impl PartialEq for UnknownValue {
+    fn eq(&self, other: &Self) -> bool {
+        encode_value(&self.0) == encode_value(&other.0)
+    }
+}
+
+impl Eq for UnknownValue {}
+
+impl PartialOrd for UnknownValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for UnknownValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        encode_value(&self.0).cmp(&encode_value(&other.0))
+    }
+}
+
+impl Hash for UnknownValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        encode_value(&self.0).hash(state);
+    }
+}
+
+impl Deref for UnknownValue {
+    type Target = Value;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for UnknownValue {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl From<Value> for UnknownValue {
+    fn from(value: Value) -> Self {
+        Self(value)
+    }
+}
+
+impl Serialize for UnknownValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.0.serialize(serializer)
+    }
+}
+
+fn encode_value(value: &Value) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    rmpv::encode::write_value(&mut bytes, value).expect("writing to a Vec cannot fail");
+    bytes
+}
+
+/// A [`Visitor`] for a map with [`u16`] keys.
 pub struct MapVisitor;
 
 impl<'de> Visitor<'de> for MapVisitor {
-    type Value = Vec<(u8, Value)>;
+    type Value = Vec<(u16, Value)>;
 
     fn expecting(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
         formatter.write_str("a map")
@@ -0,0 +1,347 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserializer, Serialize, Serializer, ser::Error};
+use serde_bytes::Bytes;
+
+use crate::{Decode, DecodeError, DecodeErrorKind, DecodeVisitor, MessagePackType, decode_any};
+
+/// The MessagePack extension type of timestamps.
+pub(crate) const TIMESTAMP_EXTENSION: i8 = -1;
+
+/// The name of the newtype struct which rmp-serde and rmpv serialize as an extension value.
+const EXTENSION_STRUCT_NAME: &str = "_ExtStruct";
+
+/// A [`SystemTime`] which serializes with the MessagePack timestamp extension.
+///
+/// Generated code wraps `timestamp` values in this when serializing, since the [`Serialize`]
+/// implementation of [`SystemTime`] itself produces a struct. This only works with serializers
+/// which support extension types, such as those of rmp-serde and rmpv.
+#[derive(Clone, Copy, Debug)]
+pub struct TimestampRef<'a>(pub &'a SystemTime);
+
+/// A [`Duration`] which serializes with the MessagePack timestamp extension, as the timestamp
+/// which is that long after the Unix epoch.
+///
+/// Generated code wraps `duration` values in this when serializing, like [`TimestampRef`].
+#[derive(Clone, Copy, Debug)]
+pub struct DurationRef<'a>(pub &'a Duration);
+
+/// A [`chrono::DateTime<Utc>`](chrono::DateTime) which serializes with the MessagePack timestamp
+/// extension.
+///
+/// Generated code wraps `timestamp` values in this when using chrono types, like
+/// [`TimestampRef`]. A leap second is serialized as the start of the following second.
+#[cfg(feature = "chrono")]
+#[derive(Clone, Copy, Debug)]
+pub struct ChronoTimestampRef<'a>(pub &'a chrono::DateTime<chrono::Utc>);
+
+/// A non-negative [`chrono::TimeDelta`] which serializes with the MessagePack timestamp
+/// extension, like [`DurationRef`].
+#[cfg(feature = "chrono")]
+#[derive(Clone, Copy, Debug)]
+pub struct ChronoDurationRef<'a>(pub &'a chrono::TimeDelta);
+
+/// A [`time::OffsetDateTime`] which serializes with the MessagePack timestamp extension.
+///
+/// Generated code wraps `timestamp` values in this when using time types, like
+/// [`TimestampRef`]. Decoding produces a value with a UTC offset.
+#[cfg(feature = "time")]
+#[derive(Clone, Copy, Debug)]
+pub struct TimeTimestampRef<'a>(pub &'a time::OffsetDateTime);
+
+/// A non-negative [`time::Duration`] which serializes with the MessagePack timestamp extension,
+/// like [`DurationRef`].
+#[cfg(feature = "time")]
+#[derive(Clone, Copy, Debug)]
+pub struct TimeDurationRef<'a>(pub &'a time::Duration);
+
+impl Serialize for TimestampRef<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let (seconds, nanos) = match self.0.duration_since(UNIX_EPOCH) {
+            Ok(after) => (i64::try_from(after.as_secs()).ok(), after.subsec_nanos()),
+            // the nanoseconds always count forward, so round the seconds down
+            Err(error) => match error.duration() {
+                before if before.subsec_nanos() == 0 => (
+                    i64::try_from(before.as_secs()).ok().map(|seconds| -seconds),
+                    0,
+                ),
+                before => (
+                    i64::try_from(before.as_secs())
+                        .ok()
+                        .map(|seconds| -seconds - 1),
+                    1_000_000_000 - before.subsec_nanos(),
+                ),
+            },
+        };
+        let Some(seconds) = seconds else {
+            return Err(S::Error::custom("timestamp is out of range"));
+        };
+        serialize_timestamp(serializer, seconds, nanos)
+    }
+}
+
+impl Serialize for DurationRef<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let Ok(seconds) = i64::try_from(self.0.as_secs()) else {
+            return Err(S::Error::custom("duration is out of range"));
+        };
+        serialize_timestamp(serializer, seconds, self.0.subsec_nanos())
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl Serialize for ChronoTimestampRef<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let (seconds, nanos) = (self.0.timestamp(), self.0.timestamp_subsec_nanos());
+        // chrono represents a leap second as the nanoseconds going past a second
+        match nanos.checked_sub(1_000_000_000) {
+            Some(nanos) => match seconds.checked_add(1) {
+                Some(seconds) => serialize_timestamp(serializer, seconds, nanos),
+                None => Err(S::Error::custom("timestamp is out of range")),
+            },
+            None => serialize_timestamp(serializer, seconds, nanos),
+        }
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl Serialize for ChronoDurationRef<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if *self.0 < chrono::TimeDelta::zero() {
+            return Err(S::Error::custom("duration is out of range"));
+        }
+        serialize_timestamp(
+            serializer,
+            self.0.num_seconds(),
+            self.0.subsec_nanos().unsigned_abs(),
+        )
+    }
+}
+
+#[cfg(feature = "time")]
+impl Serialize for TimeTimestampRef<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_timestamp(serializer, self.0.unix_timestamp(), self.0.nanosecond())
+    }
+}
+
+#[cfg(feature = "time")]
+impl Serialize for TimeDurationRef<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.0.is_negative() {
+            return Err(S::Error::custom("duration is out of range"));
+        }
+        serialize_timestamp(
+            serializer,
+            self.0.whole_seconds(),
+            self.0.subsec_nanoseconds().unsigned_abs(),
+        )
+    }
+}
+
+/// Serializes a timestamp extension value in the smallest of the 32-, 64- and 96-bit formats
+/// which can hold it.
+fn serialize_timestamp<S>(serializer: S, seconds: i64, nanos: u32) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let data = match (u32::try_from(seconds), u64::try_from(seconds)) {
+        (Ok(seconds), _) if nanos == 0 => seconds.to_be_bytes().to_vec(),
+        // the upper 30 bits are the nanoseconds and the lower 34 bits the seconds
+        (_, Ok(seconds)) if seconds < 1 << 34 => {
+            ((u64::from(nanos) << 34) | seconds).to_be_bytes().to_vec()
+        }
+        _ => [nanos.to_be_bytes().as_slice(), &seconds.to_be_bytes()].concat(),
+    };
+    serializer.serialize_newtype_struct(
+        EXTENSION_STRUCT_NAME,
+        &(TIMESTAMP_EXTENSION, Bytes::new(&data)),
+    )
+}
+
+/// Decodes the data of a timestamp extension value into the seconds and nanoseconds since the
+/// Unix epoch.
+fn decode_timestamp(data: &[u8]) -> Result<(i64, u32), DecodeError> {
+    fn array<const N: usize>(data: &[u8]) -> [u8; N] {
+        data.try_into().expect("the length is checked")
+    }
+
+    let (seconds, nanos) = match data.len() {
+        4 => (u32::from_be_bytes(array(data)).into(), 0),
+        8 => {
+            let value = u64::from_be_bytes(array(data));
+            ((value & 0x3_ffff_ffff) as i64, (value >> 34) as u32)
+        }
+        12 => (
+            i64::from_be_bytes(array(&data[4..])),
+            u32::from_be_bytes(array(&data[..4])),
+        ),
+        _ => return Err(DecodeError::new(DecodeErrorKind::InvalidTimestamp)),
+    };
+    if nanos >= 1_000_000_000 {
+        return Err(DecodeError::new(DecodeErrorKind::InvalidTimestamp));
+    }
+    Ok((seconds, nanos))
+}
+
+struct TimestampVisitor;
+
+impl DecodeVisitor<'_> for TimestampVisitor {
+    type Value = (i64, u32);
+    const EXPECTED: MessagePackType = MessagePackType::Timestamp;
+
+    fn visit_ext(self, r#type: i8, data: &[u8]) -> Result<Self::Value, DecodeError> {
+        if r#type != TIMESTAMP_EXTENSION {
+            return Err(DecodeError::invalid_type(
+                Self::EXPECTED,
+                MessagePackType::Extension,
+            ));
+        }
+        decode_timestamp(data)
+    }
+}
+
+impl<'de> Decode<'de> for SystemTime {
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(
+            decode_any(deserializer, TimestampVisitor)?.and_then(|(seconds, nanos)| {
+                let since_epoch = Duration::from_secs(seconds.unsigned_abs());
+                let time = if seconds >= 0 {
+                    UNIX_EPOCH.checked_add(since_epoch)
+                } else {
+                    UNIX_EPOCH.checked_sub(since_epoch)
+                };
+                time.and_then(|time| time.checked_add(Duration::from_nanos(nanos.into())))
+                    .ok_or_else(|| {
+                        DecodeError::new(DecodeErrorKind::OutOfRange {
+                            r#type: "timestamp",
+                            value: seconds.into(),
+                        })
+                    })
+            }),
+        )
+    }
+}
+
+impl<'de> Decode<'de> for Duration {
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(
+            decode_any(deserializer, TimestampVisitor)?.and_then(|(seconds, nanos)| {
+                match u64::try_from(seconds) {
+                    Ok(seconds) => Ok(Duration::new(seconds, nanos)),
+                    Err(_) => Err(DecodeError::new(DecodeErrorKind::OutOfRange {
+                        r#type: "duration",
+                        value: seconds.into(),
+                    })),
+                }
+            }),
+        )
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl<'de> Decode<'de> for chrono::DateTime<chrono::Utc> {
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(
+            decode_any(deserializer, TimestampVisitor)?.and_then(|(seconds, nanos)| {
+                chrono::DateTime::from_timestamp(seconds, nanos).ok_or_else(|| {
+                    DecodeError::new(DecodeErrorKind::OutOfRange {
+                        r#type: "timestamp",
+                        value: seconds.into(),
+                    })
+                })
+            }),
+        )
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl<'de> Decode<'de> for chrono::TimeDelta {
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(
+            decode_any(deserializer, TimestampVisitor)?.and_then(|(seconds, nanos)| {
+                chrono::TimeDelta::new(seconds, nanos)
+                    .filter(|_| seconds >= 0)
+                    .ok_or_else(|| {
+                        DecodeError::new(DecodeErrorKind::OutOfRange {
+                            r#type: "duration",
+                            value: seconds.into(),
+                        })
+                    })
+            }),
+        )
+    }
+}
+
+#[cfg(feature = "time")]
+impl<'de> Decode<'de> for time::OffsetDateTime {
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(
+            decode_any(deserializer, TimestampVisitor)?.and_then(|(seconds, nanos)| {
+                time::OffsetDateTime::from_unix_timestamp_nanos(
+                    i128::from(seconds) * 1_000_000_000 + i128::from(nanos),
+                )
+                .map_err(|_| {
+                    DecodeError::new(DecodeErrorKind::OutOfRange {
+                        r#type: "timestamp",
+                        value: seconds.into(),
+                    })
+                })
+            }),
+        )
+    }
+}
+
+#[cfg(feature = "time")]
+impl<'de> Decode<'de> for time::Duration {
+    fn decode<D>(deserializer: D) -> Result<Result<Self, DecodeError>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(
+            decode_any(deserializer, TimestampVisitor)?.and_then(|(seconds, nanos)| {
+                if seconds < 0 {
+                    return Err(DecodeError::new(DecodeErrorKind::OutOfRange {
+                        r#type: "duration",
+                        value: seconds.into(),
+                    }));
+                }
+                // the nanoseconds are less than a billion
+                Ok(time::Duration::new(seconds, nanos as i32))
+            }),
+        )
+    }
+}